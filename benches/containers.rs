@@ -0,0 +1,36 @@
+// Indexing, iterating and copying a 100k-element array from Tau. Run with
+// `cargo bench --bench containers`; each case prints its best time of a few.
+use rust_ai_lang::runtime::Runtime;
+use rust_ai_lang::tau;
+use std::time::{Duration, Instant};
+
+const SIZE: usize = 100_000;
+const RUNS: usize = 3;
+
+fn main() {
+    let build = [
+        "xs = []".to_string(),
+        "m = [{0, 0}]".to_string(),
+        format!("for i in 0..{} {{ push(xs, i) }}", SIZE),
+        format!("for i in 0..{} {{ m[i] = i }}", SIZE),
+    ];
+    let cases = [
+        ("index", format!("for i in 0..{} {{ xs[i] }}", SIZE)),
+        ("for-in", "for x in xs { x }".to_string()),
+        ("len", format!("for i in 0..{} {{ len(xs) }}", SIZE)),
+        ("map-get", format!("for i in 0..{} {{ m[i] }}", SIZE)),
+    ];
+    for (name, code) in cases {
+        let mut best = Duration::MAX;
+        for _ in 0..RUNS {
+            let mut runtime = Runtime::new();
+            for setup in &build {
+                tau::parse_tau(setup, &mut runtime).expect(setup);
+            }
+            let start = Instant::now();
+            tau::parse_tau(&code, &mut runtime).expect(name);
+            best = best.min(start.elapsed());
+        }
+        println!("{:<8} {:>10.2?}  ({} elements)", name, best, SIZE);
+    }
+}
@@ -0,0 +1,36 @@
+// Loops whose bodies and conditions are large trees, run from Tau, so the
+// cost of walking the tree each pass shows. Run with `cargo bench --bench
+// loops`; each case prints its best time of a few.
+use rust_ai_lang::runtime::Runtime;
+use rust_ai_lang::tau;
+use std::time::{Duration, Instant};
+
+const PASSES: usize = 100_000;
+const RUNS: usize = 3;
+
+fn main() {
+    // A body of twenty small statements
+    let body = (0..20).map(|n| format!("i * {} + {}", n, n)).collect::<Vec<_>>().join("; ");
+    let build = [
+        "n = [0]".to_string(),
+        format!("f = fn(x) {{ {} }}", body.replace('i', "x")),
+    ];
+    let cases = [
+        ("for", format!("for i in 0..{} {{ {} }}", PASSES, body)),
+        ("while", format!("while n[0] < {} and n[0] > -1 and not (n[0] == -2) {{ n[0] = n[0] + 1; {} }}", PASSES, body.replace('i', "n[0]"))),
+        ("call", format!("for i in 0..{} {{ f(i) }}", PASSES)),
+    ];
+    for (name, code) in cases {
+        let mut best = Duration::MAX;
+        for _ in 0..RUNS {
+            let mut runtime = Runtime::new();
+            for setup in &build {
+                tau::parse_tau(setup, &mut runtime).expect(setup);
+            }
+            let start = Instant::now();
+            tau::parse_tau(&code, &mut runtime).expect(name);
+            best = best.min(start.elapsed());
+        }
+        println!("{:<6} {:>10.2?}  ({} passes)", name, best, PASSES);
+    }
+}
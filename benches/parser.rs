@@ -0,0 +1,96 @@
+// Lexing, parsing and checking generated Pi, Rho and Tau sources of a quarter
+// and a whole megabyte, without running them. Each case prints its best time of a few and
+// its throughput at both sizes; throughput that falls as the input grows means
+// the work is worse than linear. Run with `cargo bench --bench parser`.
+use rust_ai_lang::lexer::tokenize;
+use rust_ai_lang::rho::{check_rho, parse_rho};
+use rust_ai_lang::runtime::Language;
+use rust_ai_lang::{tau, translate};
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 2] = [256 * 1024, 1024 * 1024];
+const RUNS: usize = 3;
+
+// Repeat `piece(i)` for i = 0, 1, ... until the text reaches `size` bytes
+fn generate(size: usize, separator: &str, piece: impl Fn(usize) -> String) -> String {
+    let mut text = String::with_capacity(size + 64);
+    let mut i = 0;
+    while text.len() < size {
+        if i > 0 {
+            text.push_str(separator);
+        }
+        text.push_str(&piece(i));
+        i += 1;
+    }
+    text
+}
+
+// Rho statements, one per line, each a mix of operators, calls and indexing
+fn statements(size: usize) -> String {
+    generate(size, "\n", |i| format!("x{} = (a + {}) * b[{}] - len(\"s{}\") / 2 ** c and not d or e < {}", i % 97, i, i % 7, i, i))
+}
+
+// One expression on one line, a chain of terms
+fn long(size: usize) -> String {
+    generate(size, " + ", |i| format!("{} * x{}", i, i % 7))
+}
+
+// Twenty brackets deep, many times over
+fn nested(size: usize) -> String {
+    let open = "[(".repeat(20);
+    let close = ")]".repeat(20);
+    generate(size, "\n", |i| format!("{}{}{}", open, i, close))
+}
+
+fn array(size: usize) -> String {
+    format!("[{}]", generate(size, ", ", |i| i.to_string()))
+}
+
+fn map(size: usize) -> String {
+    format!("[{}]", generate(size, ", ", |i| format!("{{\"k{}\", {}}}", i, i)))
+}
+
+fn string(size: usize) -> String {
+    format!("\"{}\"", generate(size, "", |i| if i % 64 == 63 { "\\n".to_string() } else { "ab".to_string() }))
+}
+
+// Pi statements, each a short postfix expression bound to a name
+fn postfix(size: usize) -> String {
+    generate(size, "\n", |i| format!("{} 2 + 3 * {} 4 - / \"x{}\" =", i, i, i % 97))
+}
+
+fn pi_array(size: usize) -> String {
+    format!("[{}] len", generate(size, ", ", |i| i.to_string()))
+}
+
+fn main() {
+    type Case = (&'static str, fn(usize) -> String, fn(&str));
+    let cases: [Case; 11] = [
+        ("lex", statements, |source| drop(tokenize(source).unwrap())),
+        ("rho", statements, |source| drop(parse_rho(source).unwrap())),
+        ("check", statements, |source| drop(check_rho(source))),
+        ("tau", statements, |source| drop(tau::check_tau(source))),
+        ("long", long, |source| drop(parse_rho(source).unwrap())),
+        ("nested", nested, |source| drop(parse_rho(source).unwrap())),
+        ("array", array, |source| drop(parse_rho(source).unwrap())),
+        ("map", map, |source| drop(parse_rho(source).unwrap())),
+        ("string", string, |source| drop(parse_rho(source).unwrap())),
+        ("pi", postfix, |source| drop(translate::parse(Language::Pi, source).unwrap())),
+        ("pi-array", pi_array, |source| drop(translate::parse(Language::Pi, source).unwrap())),
+    ];
+    for (name, generate, parse) in cases {
+        let mut line = format!("{:<8}", name);
+        for size in SIZES {
+            let source = generate(size);
+            let mut best = Duration::MAX;
+            for _ in 0..RUNS {
+                let start = Instant::now();
+                parse(&source);
+                best = best.min(start.elapsed());
+            }
+            let rate = source.len() as f64 / best.as_secs_f64() / (1024.0 * 1024.0);
+            line.push_str(&format!("  {:>5} KiB {:>10.2?} {:>7.1} MiB/s", source.len() / 1024, best, rate));
+        }
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,432 @@
+// Zip and tar archives, so packaging scripts need not shell out to zip and
+// tar with fragile quoting. zip_create(path, files), zip_list(path) and
+// zip_extract(path, dest), and the same for tar_*; a tar archive is gzipped
+// when its name ends in .gz or .tgz. A directory among the files adds
+// everything under it.
+//
+// Paths are relative to the workspace. Entries keep the names they were
+// given, so an archive can only hold names inside the destination it is
+// extracted to; entries that would land outside it (absolute, or with ..)
+// are refused rather than written.
+use crate::deflate::{crc32, deflate, inflate};
+use crate::schedule::civil_from_days;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Zip,
+    Tar,
+}
+
+// An entry as read from an archive
+struct Member<'a> {
+    name: String,
+    dir: bool,
+    size: u64,      // Uncompressed
+    method: u16,    // 0 stored, 8 deflated
+    crc: Option<u32>,
+    data: &'a [u8],  // As stored in the archive
+}
+
+impl Member<'_> {
+    fn contents(&self) -> Result<Vec<u8>, String> {
+        let contents = match self.method {
+            0 => self.data.to_vec(),
+            8 => inflate(self.data, self.size as usize)?,
+            other => return Err(format!("{}: unsupported compression method {}", self.name, other)),
+        };
+        if contents.len() as u64 != self.size || self.crc.is_some_and(|crc| crc != crc32(&contents)) {
+            return Err(format!("{}: corrupt archive entry", self.name));
+        }
+        Ok(contents)
+    }
+}
+
+// A file to archive: its entry name and where it is on disk
+struct Source {
+    name: String,
+    path: PathBuf,
+    modified: SystemTime,
+}
+
+// Archive `files` (relative to root) into `archive`, returning how many files went in
+pub fn create(format: Format, root: &Path, archive: &str, files: &[String]) -> Result<usize, String> {
+    let mut sources = Vec::new();
+    for file in files {
+        collect(root, file, &mut sources)?;
+    }
+    let bytes = match format {
+        Format::Zip => write_zip(&sources)?,
+        Format::Tar if is_gzip_name(archive) => gzip(&write_tar(&sources)?),
+        Format::Tar => write_tar(&sources)?,
+    };
+    let path = root.join(archive);
+    fs::write(&path, bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(sources.len())
+}
+
+// Names and sizes of the entries in an archive, in archive order
+pub fn list(format: Format, root: &Path, archive: &str) -> Result<Vec<(String, u64)>, String> {
+    let bytes = read(root, archive)?;
+    let tar;
+    let members = match format {
+        Format::Zip => read_zip(&bytes)?,
+        Format::Tar => {
+            tar = gunzip_if_needed(&bytes)?;
+            read_tar(&tar)?
+        }
+    };
+    Ok(members.into_iter().map(|m| (m.name, m.size)).collect())
+}
+
+// Write every entry under dest, returning the names written
+pub fn extract(format: Format, root: &Path, archive: &str, dest: &str) -> Result<Vec<String>, String> {
+    let bytes = read(root, archive)?;
+    let tar;
+    let members = match format {
+        Format::Zip => read_zip(&bytes)?,
+        Format::Tar => {
+            tar = gunzip_if_needed(&bytes)?;
+            read_tar(&tar)?
+        }
+    };
+    let dest = root.join(dest);
+    // Check every name before writing anything
+    for member in &members {
+        safe_name(&member.name)?;
+    }
+    let mut written = Vec::new();
+    for member in &members {
+        let path = dest.join(&member.name);
+        let parent = if member.dir { path.as_path() } else { path.parent().unwrap_or(&dest) };
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        if !member.dir {
+            fs::write(&path, member.contents()?).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        written.push(member.name.clone());
+    }
+    Ok(written)
+}
+
+fn read(root: &Path, archive: &str) -> Result<Vec<u8>, String> {
+    let path = root.join(archive);
+    fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn is_gzip_name(name: &str) -> bool {
+    name.ends_with(".gz") || name.ends_with(".tgz")
+}
+
+// An entry name must be a relative path that stays below the destination
+fn safe_name(name: &str) -> Result<(), String> {
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("Refusing archive entry outside the destination: {}", name));
+    }
+    Ok(())
+}
+
+// The file, or every file under the directory, in name order
+fn collect(root: &Path, file: &str, sources: &mut Vec<Source>) -> Result<(), String> {
+    let name = file.trim_start_matches("./").trim_end_matches('/').replace('\\', "/");
+    safe_name(&name).map_err(|_| format!("Cannot archive {}: names must be relative and inside the workspace", file))?;
+    let path = root.join(file);
+    let metadata = fs::metadata(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if metadata.is_dir() {
+        let mut children: Vec<String> = fs::read_dir(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.file_name().to_string_lossy().to_string()))
+            .collect();
+        children.sort();
+        for child in children {
+            collect(root, &format!("{}/{}", name, child), sources)?;
+        }
+    } else {
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        sources.push(Source { name, path, modified });
+    }
+    Ok(())
+}
+
+fn contents(source: &Source) -> Result<Vec<u8>, String> {
+    fs::read(&source.path).map_err(|e| format!("Failed to read {}: {}", source.path.display(), e))
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// Zip
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_DIRECTORY: u32 = 0x06054b50;
+const UTF8_NAMES: u16 = 0x0800;
+
+// MS-DOS time and date, as zip records them; 1980 is the earliest they can say
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let secs = unix_seconds(time) as i64;
+    let (year, month, day) = civil_from_days(secs / 86400);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let second_of_day = secs % 86400;
+    let time = ((second_of_day / 3600) << 11) | ((second_of_day / 60 % 60) << 5) | (second_of_day % 60 / 2);
+    let date = ((year - 1980).min(127) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+fn write_zip(sources: &[Source]) -> Result<Vec<u8>, String> {
+    let too_large = || "Archive too large for zip (zip64 is not supported)".to_string();
+    if sources.len() > u16::MAX as usize {
+        return Err(too_large());
+    }
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    for source in sources {
+        let data = contents(source)?;
+        let crc = crc32(&data);
+        let compressed = deflate(&data);
+        let (method, stored) = if compressed.len() < data.len() { (8u16, compressed.as_slice()) } else { (0, data.as_slice()) };
+        let (time, date) = dos_time(source.modified);
+        let offset = u32::try_from(out.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let name = source.name.as_bytes();
+
+        // Fields shared by the local and central headers
+        let mut common = Vec::new();
+        for field in [20u16, UTF8_NAMES, method, time, date] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, stored.len() as u32, size] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());  // Extra field length
+
+        out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name);
+        out.extend_from_slice(stored);
+
+        directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        directory.extend_from_slice(&(3u16 << 8 | 20).to_le_bytes());  // Made by Unix, zip 2.0
+        directory.extend_from_slice(&common);
+        directory.extend_from_slice(&[0; 6]);  // Comment length, disk, internal attributes
+        directory.extend_from_slice(&(0o100644u32 << 16).to_le_bytes());  // A regular file, rw-r--r--
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name);
+    }
+    let directory_offset = u32::try_from(out.len()).map_err(|_| too_large())?;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&END_OF_DIRECTORY.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);  // This disk, directory disk
+    out.extend_from_slice(&(sources.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(sources.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());  // Comment length
+    Ok(out)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<u16, String> {
+    let field = bytes.get(at..at + 2).ok_or("Truncated zip archive")?;
+    Ok(u16::from_le_bytes([field[0], field[1]]))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, String> {
+    let field = bytes.get(at..at + 4).ok_or("Truncated zip archive")?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+// Entries from the central directory, which the end record at the back
+// (before any comment) points to
+fn read_zip(bytes: &[u8]) -> Result<Vec<Member<'_>>, String> {
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(65536 + 22)
+        .find(|&at| u32_at(bytes, at) == Ok(END_OF_DIRECTORY))
+        .ok_or("Not a zip archive")?;
+    let count = u16_at(bytes, end + 10)? as usize;
+    let mut at = u32_at(bytes, end + 16)? as usize;
+    let mut members = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(bytes, at)? != CENTRAL_HEADER {
+            return Err("Corrupt zip central directory".to_string());
+        }
+        let flags = u16_at(bytes, at + 8)?;
+        let method = u16_at(bytes, at + 10)?;
+        let crc = u32_at(bytes, at + 16)?;
+        let compressed = u32_at(bytes, at + 20)? as usize;
+        let size = u32_at(bytes, at + 24)? as u64;
+        let name_length = u16_at(bytes, at + 28)? as usize;
+        let extra_length = u16_at(bytes, at + 30)? as usize;
+        let comment_length = u16_at(bytes, at + 32)? as usize;
+        let local = u32_at(bytes, at + 42)? as usize;
+        let name = bytes.get(at + 46..at + 46 + name_length).ok_or("Truncated zip archive")?;
+        let name = String::from_utf8_lossy(name).to_string();
+        if flags & 1 != 0 {
+            return Err(format!("{}: encrypted zip entries are not supported", name));
+        }
+        if u32_at(bytes, local)? != LOCAL_HEADER {
+            return Err(format!("{}: corrupt zip entry", name));
+        }
+        let start = local + 30 + u16_at(bytes, local + 26)? as usize + u16_at(bytes, local + 28)? as usize;
+        let data = bytes.get(start..start + compressed).ok_or("Truncated zip archive")?;
+        let dir = name.ends_with('/');
+        let name = name.trim_end_matches('/').to_string();
+        members.push(Member { name, dir, size, method, crc: Some(crc), data });
+        at += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(members)
+}
+
+// Tar (ustar) and gzip
+
+const BLOCK: usize = 512;
+
+// A NUL-terminated octal field
+fn octal(field: &mut [u8], value: u64) {
+    let text = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..text.len()].copy_from_slice(text.as_bytes());
+}
+
+fn write_tar(sources: &[Source]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for source in sources {
+        let data = contents(source)?;
+        let mut header = [0u8; BLOCK];
+        // Names past 100 bytes go in the 155-byte prefix, split at a slash
+        let name = source.name.as_bytes();
+        let (prefix, name) = if name.len() <= 100 {
+            (&name[..0], name)
+        } else {
+            let split = (0..name.len())
+                .rev()
+                .find(|&i| name[i] == b'/' && i <= 155 && name.len() - i - 1 <= 100)
+                .ok_or_else(|| format!("Name too long for tar: {}", source.name))?;
+            (&name[..split], &name[split + 1..])
+        };
+        header[..name.len()].copy_from_slice(name);
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        if data.len() as u64 >= 1 << 33 {
+            return Err(format!("File too large for tar: {}", source.name));
+        }
+        octal(&mut header[124..136], data.len() as u64);
+        octal(&mut header[136..148], unix_seconds(source.modified));
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix);
+        // The checksum counts its own field as spaces
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|&b| b as u64).sum();
+        octal(&mut header[148..155], checksum);
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&data);
+        out.resize(out.len().next_multiple_of(BLOCK), 0);
+    }
+    out.extend_from_slice(&[0; 2 * BLOCK]);
+    Ok(out)
+}
+
+fn field_text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, String> {
+    let text = field_text(field);
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| format!("Corrupt tar header field: {:?}", text))
+}
+
+// Files and directories; links and other special entries are left out. GNU
+// long names ('L' entries) name the entry after them.
+fn read_tar(bytes: &[u8]) -> Result<Vec<Member<'_>>, String> {
+    let mut members = Vec::new();
+    let mut long_name = None;
+    let mut at = 0;
+    while at + BLOCK <= bytes.len() {
+        let header = &bytes[at..at + BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let recorded = parse_octal(&header[148..156])?;
+        let actual: u64 = header.iter().enumerate().map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 }).sum();
+        if recorded != actual {
+            return Err(format!("Bad tar header checksum at byte {}", at));
+        }
+        let size = parse_octal(&header[124..136])?;
+        let start = at + BLOCK;
+        let data = bytes.get(start..start + size as usize).ok_or("Truncated tar archive")?;
+        let mut name = field_text(&header[..100]);
+        let prefix = field_text(&header[345..500]);
+        if &header[257..262] == b"ustar" && !prefix.is_empty() {
+            name = format!("{}/{}", prefix, name);
+        }
+        if let Some(long) = long_name.take() {
+            name = long;
+        }
+        match header[156] {
+            b'0' | 0 => members.push(Member { name, dir: false, size, method: 0, crc: None, data }),
+            b'5' => {
+                let name = name.trim_end_matches('/').to_string();
+                members.push(Member { name, dir: true, size: 0, method: 0, crc: None, data: &[] });
+            }
+            b'L' => long_name = Some(field_text(data)),
+            _ => {}
+        }
+        at = start + (size as usize).next_multiple_of(BLOCK);
+    }
+    Ok(members)
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];  // Deflate, no flags or time, Unix
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+// Plain tar passes through; gzip is unpacked (one member)
+fn gunzip_if_needed(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes.to_vec());
+    }
+    let truncated = || "Truncated gzip data".to_string();
+    if bytes.len() < 18 || bytes[2] != 8 {
+        return Err("Unsupported gzip data".to_string());
+    }
+    let flags = bytes[3];
+    let mut at = 10;
+    if flags & 4 != 0 {
+        at += 2 + u16::from_le_bytes([bytes[at], *bytes.get(at + 1).ok_or_else(truncated)?]) as usize;
+    }
+    for flag in [8, 16] {
+        // File name, then comment, each NUL-terminated
+        if flags & flag != 0 {
+            at += bytes.get(at..).and_then(|rest| rest.iter().position(|&b| b == 0)).ok_or_else(truncated)? + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        at += 2;
+    }
+    // The trailer is the CRC and size of the data
+    let trailer = bytes.len() - 8;
+    let crc = u32::from_le_bytes(bytes[trailer..trailer + 4].try_into().unwrap());
+    let size = u32::from_le_bytes(bytes[trailer + 4..].try_into().unwrap()) as usize;
+    let data = inflate(bytes.get(at..trailer).ok_or_else(truncated)?, size)?;
+    if crc != crc32(&data) || data.len() != size {
+        return Err("Corrupt gzip data".to_string());
+    }
+    Ok(data)
+}
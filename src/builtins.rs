@@ -0,0 +1,1115 @@
+// Builtin functions callable as name(args) from Rho, Tau and Pi literals
+use crate::archive::{self, Format};
+use crate::clipboard;
+use crate::capabilities::Capability;
+use crate::config;
+use crate::events::Event;
+use crate::http;
+use crate::json;
+#[cfg(feature = "keyring")]
+use crate::keyring;
+use crate::metrics::MetricKind;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::Client;
+use crate::paths;
+use crate::process;
+use crate::runtime::{self, Expr, Runtime, Site};
+use crate::secret::{self, Secret};
+use crate::serial::Port;
+use crate::shell::{self, Batch, Job};
+use crate::signatures;
+use crate::stream::Stream;
+use crate::tau;
+use crate::transport::Proxy;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+use crate::value::{BlendMode, Color, Function, FutureState, Map, MapKey, TaskError, Value};
+
+pub fn call(runtime: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String> {
+    signatures::check(name, &args)?;
+    run(runtime, name, args)
+}
+
+// The builtin itself, for a caller that has checked the arguments against
+// its signature, see signatures.rs
+pub fn run(runtime: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String> {
+    match name {
+        "color" => color(&args),
+        "to_ansi256" => {
+            let c = color_arg(name, &args, 0)?;
+            Ok(Value::Num(c.to_ansi256() as f64))
+        }
+        "nearest_named" => {
+            let c = color_arg(name, &args, 0)?;
+            Ok(Value::Str(c.nearest_named().into()))
+        }
+        "contrast" => {
+            let a = color_arg(name, &args, 0)?;
+            let b = color_arg(name, &args, 1)?;
+            Ok(Value::Num(a.contrast(&b)))
+        }
+        // with_alpha(c, a) - the same color with alpha a (0 transparent, 255 opaque)
+        "with_alpha" => match args.as_slice() {
+            [Value::Color(c), Value::Num(a)] if (0.0..=255.0).contains(a) && a.fract() == 0.0 => {
+                Ok(Value::Color(c.with_alpha(*a as u8)))
+            }
+            _ => Err("with_alpha expects a color and an alpha from 0 to 255".to_string()),
+        },
+        // scale(c, factor) - each channel times factor, clamped
+        "scale" => match args.as_slice() {
+            [Value::Color(c), Value::Num(factor)] if *factor >= 0.0 && factor.is_finite() => Ok(Value::Color(c.scale(*factor as f32))),
+            _ => Err("scale expects a color and a factor of 0 or more".to_string()),
+        },
+        "show" => show(&args),
+        // mix(c1, c2, ratio[, mode]) and blend(c1, c2[, mode]); mode defaults to the config
+        "mix" | "blend" => {
+            let fixed = if name == "mix" { 3 } else { 2 };
+            let (a, b) = match (&args[0], &args[1]) {
+                (Value::Color(a), Value::Color(b)) => (a, b),
+                _ => return Err(format!("{} expects two colors", name)),
+            };
+            let mode = match args.get(fixed) {
+                Some(Value::Str(mode)) => BlendMode::parse(mode)?,
+                Some(other) => return Err(format!("{} mode must be a string, got {:?}", name, other)),
+                None => runtime.config.blend_mode,
+            };
+            if name == "blend" {
+                return Ok(Value::Color(a.blend_with(b, mode)));
+            }
+            let ratio = args[2].as_num()? as f32;
+            Ok(Value::Color(a.mix_with(b, ratio, mode)))
+        }
+        // gradient(c1, c2, n[, mode]) - n colors from c1 to c2; "hsl" sweeps the hue
+        "gradient" => {
+            let (a, b) = match (&args[0], &args[1]) {
+                (Value::Color(a), Value::Color(b)) => (a, b),
+                _ => return Err("gradient expects two colors and a step count".to_string()),
+            };
+            let steps = count_arg(name, &args[2])?;
+            let mode = match args.get(3) {
+                Some(Value::Str(mode)) => BlendMode::parse(mode)?,
+                Some(other) => return Err(format!("gradient mode must be a string, got {:?}", other)),
+                None => runtime.config.blend_mode,
+            };
+            Ok(colors(a.gradient_with(b, steps, mode)))
+        }
+        "complementary" => Ok(colors(color_arg(name, &args, 0)?.complementary())),
+        "triadic" => Ok(colors(color_arg(name, &args, 0)?.triadic())),
+        "analogous" => {
+            let c = match &args[0] {
+                Value::Color(c) => c,
+                other => return Err(format!("analogous expects a color, got {:?}", other)),
+            };
+            Ok(colors(c.analogous(count_arg(name, &args[1])?)))
+        }
+        // promise() is a pending future settled from code with resolve/reject
+        "promise" => {
+            Ok(Value::Future(runtime.new_future("promise()")))
+        }
+        "resolve" | "reject" => {
+            let mut args = args.into_iter();
+            let future = match args.next() {
+                Some(Value::Future(future)) => future,
+                other => return Err(format!("{} expects a future, got {:?}", name, other.unwrap())),
+            };
+            if !future.is_pending() {
+                return Err(format!("Future #{} is already {}", future.id, future.state().name()));
+            }
+            let value = args.next().unwrap();
+            future.set_state(match (name, value) {
+                ("resolve", value) => FutureState::Resolved(Box::new(value)),
+                (_, Value::Str(e)) => FutureState::Rejected(TaskError::new(&e)),
+                (_, other) => FutureState::Rejected(TaskError::new(&format!("{:?}", other))),
+            });
+            Ok(Value::Unit)
+        }
+        // await f, and f.await(); anything but a future is its own result
+        "await" => match args.as_slice() {
+            [Value::Future(future)] => runtime.await_future(future),
+            [value] => Ok(value.clone()),
+            _ => Err(format!("await expects 1 argument, got {}", args.len())),
+        },
+        // Higher-order array functions taking a lambda
+        "map" | "filter" => {
+            let f = function_arg(name, &args[1])?;
+            let mut out = Vec::new();
+            for item in array_arg(name, &args[0])? {
+                let result = runtime.call_function(&f, vec![item.clone()])?;
+                if name == "map" {
+                    out.push(result);
+                } else if runtime.truthy(&result)? {
+                    out.push(item.clone());
+                }
+            }
+            Ok(Value::Array(out.into()))
+        }
+        // reduce(arr, init, fn(acc, x) ...)
+        "reduce" => {
+            let f = function_arg(name, &args[2])?;
+            let mut acc = args[1].clone();
+            for item in array_arg(name, &args[0])? {
+                acc = runtime.call_function(&f, vec![acc, item.clone()])?;
+            }
+            Ok(acc)
+        }
+        // sort(arr) orders by < (see Value::compare); sort(arr, fn(a, b) ...)
+        // by a comparator giving a number, negative when a goes first, or true
+        // when a goes before b. Both are stable.
+        "sort" => {
+            let mut args = args.into_iter();
+            let items = match args.next() {
+                Some(Value::Array(items)) => items.into_vec(),
+                _ => return Err("sort expects an array and optionally a comparator".to_string()),
+            };
+            let sorted = match (args.next(), args.next()) {
+                (None, _) => merge_sort(items, &mut |a, b| Ok(a.compare(b)? == Ordering::Less))?,
+                (Some(Value::Function(f)), None) if f.params.len() == 2 => merge_sort(items, &mut |a, b| {
+                    match runtime.call_function(&f, vec![a.clone(), b.clone()])? {
+                        Value::Num(n) => Ok(n < 0.0),
+                        Value::Bool(before) => Ok(before),
+                        other => Err(format!("sort comparator must give a number or a bool, got {:?}", other)),
+                    }
+                })?,
+                _ => return Err("sort expects an array and optionally a comparator fn(a, b)".to_string()),
+            };
+            Ok(Value::Array(sorted.into()))
+        }
+        "reverse" => match args.into_iter().next() {
+            Some(Value::Array(mut items)) => {
+                items.make_mut().reverse();
+                Ok(Value::Array(items))
+            }
+            _ => Err("reverse expects an array".to_string()),
+        },
+        // unique(arr) keeps the first of each group of equal items, in order
+        "unique" => match args.into_iter().next() {
+            Some(Value::Array(items)) => {
+                let mut seen = HashSet::new();
+                let mut kept: Vec<Value> = Vec::with_capacity(items.len());
+                for item in items {
+                    // Hashable items are checked in constant time; others (ranges,
+                    // handles) against everything kept so far
+                    let new = match MapKey::from_value(&item) {
+                        Ok(key) => seen.insert(key),
+                        Err(_) => !kept.contains(&item),
+                    };
+                    if new {
+                        kept.push(item);
+                    }
+                }
+                Ok(Value::Array(kept.into()))
+            }
+            _ => Err("unique expects an array".to_string()),
+        },
+        // len(x) - items in an array, entries in a map, characters in a string
+        "len" => match args.as_slice() {
+            [Value::Array(items)] => Ok(Value::Num(items.len() as f64)),
+            [Value::Map(map)] => Ok(Value::Num(map.len() as f64)),
+            [Value::Str(text)] => Ok(Value::Num(text.chars().count() as f64)),
+            [other] => Err(format!("len expects an array, map or string, got {:?}", other)),
+            _ => Err(format!("len expects 1 argument, got {}", args.len())),
+        },
+        // contains(arr, x) by ==; contains(text, part) for substrings
+        "contains" => match args.as_slice() {
+            [Value::Array(items), item] => Ok(Value::Bool(items.contains(item))),
+            [Value::Str(text), Value::Str(part)] => Ok(Value::Bool(text.contains(&**part))),
+            _ => Err("contains expects an array and an item, or two strings".to_string()),
+        },
+        // Text: "a b".upper(), " x ".trim(), "a,b".split(","), "aa".replace("a", "b")
+        "upper" | "lower" | "trim" => match args.as_slice() {
+            [Value::Str(text)] => Ok(Value::Str(match name {
+                "upper" => text.to_uppercase(),
+                "lower" => text.to_lowercase(),
+                _ => text.trim().to_string(),
+            }
+            .into())),
+            _ => Err(format!("{} expects a string", name)),
+        },
+        // split(text, "") gives the characters
+        "split" => match args.as_slice() {
+            [Value::Str(text), Value::Str(separator)] => {
+                let parts: Vec<Value> = match &**separator {
+                    "" => text.chars().map(|c| Value::Str(c.to_string().into())).collect(),
+                    separator => text.split(separator).map(|part| Value::Str(part.into())).collect(),
+                };
+                Ok(Value::Array(parts.into()))
+            }
+            _ => Err("split expects a string and a separator".to_string()),
+        },
+        "replace" => match args.as_slice() {
+            [Value::Str(text), Value::Str(from), Value::Str(to)] if !from.is_empty() => {
+                Ok(Value::Str(text.replace(&**from, to).into()))
+            }
+            _ => Err("replace expects a string, a non-empty string to replace and its replacement".to_string()),
+        },
+        // join(xs, ", ") - the items as print shows them, between separators
+        "join" => match args.as_slice() {
+            [Value::Array(items), Value::Str(separator)] => {
+                let parts: Vec<String> = items.iter().map(Value::print_text).collect();
+                Ok(Value::Str(parts.join(separator).into()))
+            }
+            _ => Err("join expects an array and a separator".to_string()),
+        },
+        // to_json(value) and parse_json(text), see json.rs
+        "to_json" => match args.as_slice() {
+            [value] => Ok(Value::Str(json::to_json(value)?.into())),
+            _ => Err(format!("to_json expects 1 argument, got {}", args.len())),
+        },
+        "parse_json" => match args.as_slice() {
+            [Value::Str(text)] => json::parse_json(text),
+            _ => Err("parse_json expects a string".to_string()),
+        },
+        // get(xs, i) and get(m, k) - as xs[i] and m[k], but none when nothing is there
+        "get" => {
+            let mut args = args.into_iter();
+            let container = args.next().unwrap();
+            runtime::get_or_none(container, args.next().unwrap())
+        }
+        "keys" | "values" => match args.as_slice() {
+            [Value::Map(map)] if name == "keys" => Ok(Value::Array(map.keys().cloned().collect())),
+            [Value::Map(map)] => Ok(Value::Array(map.values().cloned().collect())),
+            _ => Err(format!("{} expects a map", name)),
+        },
+        // A copy with the entries in key order, as sort orders the keys
+        "sort_keys" => match args.as_slice() {
+            [Value::Map(map)] => {
+                let keys = merge_sort(map.keys().cloned().collect(), &mut |a, b| Ok(a.compare(b)? == Ordering::Less))?;
+                let pairs = keys.into_iter().map(|key| (key.clone(), map.get(&key).cloned().unwrap_or(Value::Unit))).collect();
+                Ok(Value::Map(Map::from_pairs(pairs)?))
+            }
+            _ => Err("sort_keys expects a map".to_string()),
+        },
+        // On a literal or expression the change is made to a copy
+        _ if is_mutating(name) => {
+            if args.is_empty() {
+                return Err(format!("{} expects a target argument", name));
+            }
+            let mut args = args;
+            let mut target = args.remove(0);
+            mutate(name, &mut target, args)
+        }
+        // call(agent, "method", args...) on a mock or a proxy, and mock configuration
+        "call" => match args.as_slice() {
+            [Value::Mock(agent), Value::Str(method), rest @ ..] => agent.borrow_mut().call(method, rest.to_vec()),
+            [Value::Proxy(proxy), Value::Str(method), rest @ ..] => {
+                let result = proxy.call(method, rest.to_vec());
+                notify_disconnects(runtime, proxy)?;
+                result
+            }
+            _ => Err("call expects an agent, a method name and its arguments".to_string()),
+        },
+        // connect(agent) reaches an in-process agent over the loopback transport;
+        // connect("file.tsu", "stdio:cmd" or "unix:path") one in another process.
+        // Option "compress" asks the agent for compressed messages.
+        "connect" => match args.as_slice() {
+            [Value::Mock(agent)] => {
+                let proxy = Proxy::loopback(agent.clone());
+                proxy.borrow_mut().tracer = runtime.tracer.clone();
+                Ok(Value::Proxy(proxy))
+            }
+            [Value::Str(file), Value::Str(address), options @ ..] => {
+                let mut features = Vec::new();
+                for option in options {
+                    match option {
+                        Value::Str(option) if &**option == "compress" => features.push("lz"),
+                        other => return Err(format!("Unknown connect option {:?} (expected \"compress\")", other)),
+                    }
+                }
+                let needed = if address.starts_with("stdio:") { Capability::Shell } else { Capability::Net };
+                runtime.config.capabilities.check(needed)?;
+                let interface = tau::read_interface(runtime, file)?;
+                let proxy = Proxy::connect(interface, address, &features)?;
+                proxy.borrow_mut().tracer = runtime.tracer.clone();
+                Ok(Value::Proxy(proxy))
+            }
+            _ => Err("connect expects an agent, or an interface file and an address".to_string()),
+        },
+        // http_get(url) - status, headers and body of a GET; Tau's fetch runs it as a task
+        "http_get" => match args.as_slice() {
+            [Value::Str(url)] => {
+                runtime.config.capabilities.check(Capability::Net)?;
+                http::get(url)?.to_value()
+            }
+            // A URL holding a token; errors would quote it, so they are redacted
+            [Value::Secret(url)] => {
+                runtime.config.capabilities.check(Capability::Net)?;
+                http::get(url.expose()).map_err(|e| url.redact(&e))?.to_value()
+            }
+            _ => Err("http_get expects a URL".to_string()),
+        },
+        // http_get_cached(url, "10m") - http_get answered from disk while the
+        // stored copy is younger than the ttl, see httpcache.rs
+        "http_get_cached" => match args.as_slice() {
+            [url @ (Value::Str(_) | Value::Secret(_)), ttl] => {
+                let ttl = duration_arg(name, ttl)?;
+                runtime.config.capabilities.check(Capability::Net)?;
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                match url {
+                    Value::Secret(url) => runtime.http_get_cached(url.expose(), ttl).map_err(|e| url.redact(&e))?.to_value(),
+                    url => runtime.http_get_cached(&url.print_text(), ttl)?.to_value(),
+                }
+            }
+            _ => Err("http_get_cached expects a URL and a ttl".to_string()),
+        },
+        // http_json("POST", url, {"name": "x"}, {"Authorization": token}) and
+        // graphql(url, query, vars), JSON both ways; see http.rs. The URL and
+        // header values may be secrets.
+        "http_json" => match args.as_slice() {
+            [Value::Str(method), url, rest @ ..] if rest.len() <= 2 => {
+                runtime.config.capabilities.check(Capability::Net)?;
+                let mut secrets = Vec::new();
+                let (url, headers) = request_parts(name, url, rest.get(1), &mut secrets)?;
+                let body = rest.first().unwrap_or(&Value::Unit);
+                http::json_request(method, &url, body, &headers).map_err(|e| secrets.iter().fold(e, |e, s| s.redact(&e)))
+            }
+            _ => Err("http_json expects a method, a URL, and optionally a body and a map of headers".to_string()),
+        },
+        "graphql" => match args.as_slice() {
+            [url, Value::Str(query), rest @ ..] if rest.len() <= 2 => {
+                runtime.config.capabilities.check(Capability::Net)?;
+                let mut secrets = Vec::new();
+                let (url, headers) = request_parts(name, url, rest.get(1), &mut secrets)?;
+                let variables = rest.first().unwrap_or(&Value::Unit);
+                http::graphql(&url, query, variables, &headers).map_err(|e| secrets.iter().fold(e, |e, s| s.redact(&e)))
+            }
+            _ => Err("graphql expects a URL, a query, and optionally a map of variables and a map of headers".to_string()),
+        },
+        // sleep(500) or sleep("2s") blocks; Tau's `sleep 500ms` gives a future instead
+        "sleep" => match args.as_slice() {
+            [duration] => {
+                let duration = duration_arg(name, duration)?;
+                runtime.sleep_until(Instant::now() + duration)
+            }
+            _ => Err(format!("sleep expects 1 argument, got {}", args.len())),
+        },
+        // secret("API key?") - a line read from the terminal without echo,
+        // shown as <secret> from then on (see secret.rs)
+        "secret" => match args.as_slice() {
+            [Value::Str(prompt)] => Ok(Value::Secret(secret::read(prompt)?)),
+            _ => Err("secret expects a prompt".to_string()),
+        },
+        // cred_get(service) gives the credential stored for the service as a
+        // secret; cred_set(service, secret) stores one (see keyring.rs)
+        "cred_get" | "cred_set" => credential(runtime, name, &args),
+        // env(name) gives an environment variable, or none when it is unset
+        "env" => match args.as_slice() {
+            [Value::Str(var)] => {
+                runtime.config.capabilities.check(Capability::Env)?;
+                Ok(std::env::var(&**var).map_or(Value::None, |value| Value::Str(value.into())))
+            }
+            _ => Err("env expects a variable name".to_string()),
+        },
+        // clipboard() gives the system clipboard's text and clipboard_set(text)
+        // replaces it (see clipboard.rs)
+        "clipboard" => match args.as_slice() {
+            [] => {
+                runtime.config.capabilities.check(Capability::Clipboard)?;
+                Ok(Value::Str(clipboard::get()?.into()))
+            }
+            _ => Err(format!("clipboard expects no arguments, got {}", args.len())),
+        },
+        "clipboard_set" => match args.as_slice() {
+            [Value::Str(text)] => {
+                runtime.config.capabilities.check(Capability::Clipboard)?;
+                clipboard::set(text).map(|_| Value::Unit)
+            }
+            _ => Err("clipboard_set expects a string".to_string()),
+        },
+        // sh(cmd), which `cmd` evaluates to - the command's status, stdout and
+        // stderr as a map (see shell.rs)
+        "sh" => match args.as_slice() {
+            [cmd] if let Some(job) = shell::command(cmd) => {
+                runtime.config.capabilities.check(Capability::Shell)?;
+                let output = job.run(runtime.deadline())?;
+                runtime.events.publish(Event::ShellCommandRun { command: cmd.print_text(), status: output.status });
+                Ok(output.into_value())
+            }
+            _ => Err("sh expects a command string".to_string()),
+        },
+        // par_run([cmd, ...][, limit]) runs the commands side by side, at most
+        // `limit` at once, giving their results in order; Tau gives a future
+        "par_run" => commands(runtime, &args)?.wait(runtime.deadline()),
+        // ssh(host, cmd) - the result of running cmd on host; Tau gives a future
+        "ssh" => remote(runtime, &args)?.run(runtime.deadline()).map(shell::CommandOutput::into_value),
+        // processes() - [{"pid", ..}, {"name", ..}, {"cpu", ..}, {"mem", ..}] per
+        // process; kill(pid[, sig]) sends TERM unless told otherwise, and
+        // wait(pid) blocks until it exits (Tau gives a future), see process.rs
+        "processes" => match args.as_slice() {
+            [] => {
+                runtime.config.capabilities.check(Capability::Shell)?;
+                Ok(Value::Array(process::list()?.into_iter().map(process::Process::into_value).collect()))
+            }
+            _ => Err("processes expects no arguments".to_string()),
+        },
+        "kill" => {
+            runtime.config.capabilities.check(Capability::Shell)?;
+            let sig = match args.get(1) {
+                Some(Value::Str(sig)) => sig.to_string(),
+                Some(Value::Num(sig)) => sig.to_string(),
+                Some(other) => return Err(format!("kill expects a signal name or number, got {:?}", other)),
+                None => "TERM".to_string(),
+            };
+            match args.as_slice() {
+                [pid] | [pid, _] => process::kill(pid_arg(name, pid)?, &sig).map(|_| Value::Unit),
+                _ => Err(format!("kill expects 1 or 2 arguments, got {}", args.len())),
+            }
+        }
+        "wait" => match args.as_slice() {
+            [pid] => {
+                runtime.config.capabilities.check(Capability::Shell)?;
+                let pid = pid_arg(name, pid)?;
+                runtime.wait_for(pid)
+            }
+            _ => Err(format!("wait expects 1 argument, got {}", args.len())),
+        },
+        // sh_if(`cmd`, then_fn[, else_fn]) calls then_fn if the command exited
+        // with status 0 and else_fn otherwise, passing the result to a handler
+        // that takes a parameter. A string is run as a command first.
+        "sh_if" => {
+            let (result, then_fn, else_fn) = match args.as_slice() {
+                [result, Value::Function(then_fn)] => (result.clone(), then_fn.clone(), None),
+                [result, Value::Function(then_fn), Value::Function(else_fn)] => {
+                    (result.clone(), then_fn.clone(), Some(else_fn.clone()))
+                }
+                _ => return Err("sh_if expects a command result, a function and an optional else function".to_string()),
+            };
+            let result = match result {
+                Value::Str(_) | Value::Secret(_) => call(runtime, "sh", vec![result])?,
+                other => other,
+            };
+            let ok = match &result {
+                Value::Map(map) => shell::succeeded(map),
+                _ => None,
+            };
+            let Some(ok) = ok else {
+                return Err(format!("sh_if expects a command result, got {:?}", result));
+            };
+            let Some(handler) = (if ok { Some(then_fn) } else { else_fn }) else {
+                return Ok(Value::Unit);
+            };
+            let args = if handler.params.is_empty() { Vec::new() } else { vec![result] };
+            runtime.call_function(&handler, args)
+        }
+        // TCP sockets; Tau's accept and recv run tcp_accept and tcp_recv as tasks
+        "tcp_accept" | "tcp_recv" | "socket_address" | "close" => {
+            // Closing a subscription or MQTT client needs no network
+            match (name, args.as_slice()) {
+                ("close", [Value::Subscription(subscription)]) => {
+                    subscription.close();
+                    return Ok(Value::Unit);
+                }
+                ("close", [Value::Mqtt(client)]) => {
+                    client.close();
+                    return Ok(Value::Unit);
+                }
+                ("close", [Value::Serial(port)]) => {
+                    port.close();
+                    return Ok(Value::Unit);
+                }
+                _ => {}
+            }
+            runtime.config.capabilities.check(Capability::Net)?;
+            match (name, args.as_slice()) {
+                ("tcp_accept", [Value::Socket(socket)]) => Ok(Value::Socket(socket.accept()?)),
+                ("tcp_recv", [Value::Socket(socket)]) => socket.recv(runtime.remaining()),
+                ("socket_address", [Value::Socket(socket)]) => Ok(Value::Str(socket.address()?.into())),
+                ("close", [Value::Socket(socket)]) => {
+                    socket.close();
+                    Ok(Value::Unit)
+                }
+                _ => Err(format!("{} expects a socket", name)),
+            }
+        }
+        // mqtt_connect(url) gives a client; client.publish(topic, payload) and
+        // client.subscribe(filter, handler), see mqtt.rs
+        "mqtt_connect" => mqtt_connect(runtime, &args),
+        "publish" => match args.as_slice() {
+            [Value::Mqtt(client), Value::Str(topic), payload] => {
+                runtime.config.capabilities.check(Capability::Net)?;
+                let payload = match payload {
+                    Value::Str(text) => text.clone(),
+                    other => json::to_json(other)?.into(),
+                };
+                client.publish(topic, payload.as_bytes())?;
+                Ok(Value::Unit)
+            }
+            _ => Err("publish expects an MQTT client, a topic and a payload".to_string()),
+        },
+        "subscribe" => match args.as_slice() {
+            [Value::Mqtt(client), Value::Str(filter), Value::Function(handler)] if (1..=2).contains(&handler.params.len()) => {
+                runtime.config.capabilities.check(Capability::Net)?;
+                client.subscribe(filter, Rc::clone(handler))?;
+                Ok(Value::Unit)
+            }
+            _ => Err("subscribe expects an MQTT client, a topic filter and a function of (topic, payload) or (payload)".to_string()),
+        },
+        // serial_open(path, baud) gives a port; serial_read(port[, timeout])
+        // the next line and serial_write(port, text or bytes), see serial.rs
+        "serial_open" => match args.as_slice() {
+            [Value::Str(path), Value::Num(baud)] if *baud >= 1.0 && baud.fract() == 0.0 && *baud <= u32::MAX as f64 => {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                Ok(Value::Serial(Port::open(path, *baud as u32)?))
+            }
+            _ => Err("serial_open expects a device path and a baud rate".to_string()),
+        },
+        "serial_read" => match args.as_slice() {
+            [Value::Serial(port)] => {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                port.read_line(runtime.remaining()).map(|line| Value::Str(line.into()))
+            }
+            [Value::Serial(port), timeout] => {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                let timeout = duration_arg(name, timeout)?;
+                let limit = runtime.remaining().map_or(timeout, |left| left.min(timeout));
+                port.read_line(Some(limit)).map(|line| Value::Str(line.into()))
+            }
+            _ => Err("serial_read expects a serial port and optionally a timeout".to_string()),
+        },
+        "serial_write" => match args.as_slice() {
+            [Value::Serial(port), Value::Str(text)] => {
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                port.write(text.as_bytes()).map(|_| Value::Unit)
+            }
+            [Value::Serial(port), Value::Array(bytes)] => {
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                let bytes = bytes
+                    .iter()
+                    .map(|b| match b {
+                        Value::Num(n) if (0.0..=255.0).contains(n) && n.fract() == 0.0 => Ok(*n as u8),
+                        other => Err(format!("serial_write expects bytes from 0 to 255, got {:?}", other)),
+                    })
+                    .collect::<Result<Vec<u8>, String>>()?;
+                port.write(&bytes).map(|_| Value::Unit)
+            }
+            _ => Err("serial_write expects a serial port and a string or an array of bytes".to_string()),
+        },
+        // stream(source) or stream(sub, limit); next(s) gives unit once it has
+        // nothing more, see stream.rs
+        "stream" => match args.as_slice() {
+            [source] => Ok(Value::Stream(Stream::new(source.clone(), None)?)),
+            [Value::Subscription(_), Value::Num(limit)] if *limit >= 1.0 => {
+                Ok(Value::Stream(Stream::new(args[0].clone(), Some(*limit as usize))?))
+            }
+            [_, _] => Err("stream takes a limit only for a subscription, and it must be at least 1".to_string()),
+            _ => Err(format!("stream expects a source and an optional limit, got {} arguments", args.len())),
+        },
+        "next" => match args.as_slice() {
+            [Value::Stream(stream)] => Ok(stream.next(runtime)?.unwrap_or(Value::Unit)),
+            _ => Err("next expects a stream".to_string()),
+        },
+        // Tau's recv on a subscription runs subscription_recv as a task;
+        // close(sub) stops it receiving
+        "subscription_recv" => match args.as_slice() {
+            [Value::Subscription(subscription)] => subscription.recv(),
+            _ => Err("subscription_recv expects a subscription".to_string()),
+        },
+        // schedule("*/5 * * * *", fn() ...) runs the handler on a cron spec and
+        // returns its id for cancel_schedule(id)
+        "schedule" => match args.as_slice() {
+            [Value::Str(spec), Value::Function(handler)] if handler.params.is_empty() => {
+                runtime.config.capabilities.check(Capability::Schedule)?;
+                let id = runtime.scheduler.add(spec, Rc::clone(handler), SystemTime::now())?;
+                Ok(Value::Num(id as f64))
+            }
+            _ => Err("schedule expects a cron spec and a function of no arguments".to_string()),
+        },
+        "cancel_schedule" => match args.as_slice() {
+            [Value::Num(id)] => Ok(Value::Bool(runtime.scheduler.cancel(*id as u64))),
+            _ => Err("cancel_schedule expects a schedule id".to_string()),
+        },
+        // counter(name), gauge(name) and timer(name) fetch a metric, creating it on
+        // first use; fetching a timer starts it. Update them with
+        // counter(name).inc(), gauge(name).set(v) and t.stop().
+        "counter" | "gauge" | "timer" => match args.as_slice() {
+            [Value::Str(metric)] => {
+                let kind = match name {
+                    "counter" => MetricKind::Counter,
+                    "gauge" => MetricKind::Gauge,
+                    _ => MetricKind::Timer,
+                };
+                Ok(Value::Metric(runtime.metrics.get(metric, kind)?))
+            }
+            _ => Err(format!("{} expects a name", name)),
+        },
+        "inc" => match args.as_slice() {
+            [Value::Metric(metric)] => metric.inc(1.0),
+            [Value::Metric(metric), Value::Num(by)] => metric.inc(*by),
+            _ => Err("inc expects a counter and an optional amount".to_string()),
+        },
+        "set" => match args.as_slice() {
+            [Value::Metric(metric), Value::Num(value)] => metric.set(*value),
+            _ => Err("set expects a gauge and a number".to_string()),
+        },
+        "stop" => match args.as_slice() {
+            [Value::Metric(metric)] => metric.stop(),
+            _ => Err("stop expects a timer".to_string()),
+        },
+        "metrics_export" => match args.as_slice() {
+            [] => runtime.metrics.export(),
+            _ => Err("metrics_export expects no arguments".to_string()),
+        },
+        // trace(true) records spans for tasks and RPC; trace_export("run.json")
+        // writes them as OTLP/JSON and returns how many there were
+        "trace" => match args.as_slice() {
+            [Value::Bool(enabled)] => {
+                runtime.tracer.set_enabled(*enabled);
+                Ok(Value::Unit)
+            }
+            _ => Err("trace expects true or false".to_string()),
+        },
+        "trace_export" => match args.as_slice() {
+            [Value::Str(path)] => {
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                let json = runtime.tracer.to_otlp_json("rust_ai_lang");
+                std::fs::write(runtime.config.in_workspace(&**path), json)
+                    .map_err(|e| format!("Failed to write trace {}: {}", path, e))?;
+                Ok(Value::Num(runtime.tracer.spans().len() as f64))
+            }
+            _ => Err("trace_export expects a file name".to_string()),
+        },
+        // zip_create(path, files) and tar_create(path, files) give how many files
+        // went in; *_list(path) gives [{"name", ..}, {"size", ..}] per entry and
+        // *_extract(path, dest) the names written, see archive.rs
+        "zip_create" | "zip_list" | "zip_extract" | "tar_create" | "tar_list" | "tar_extract" => {
+            let format = if name.starts_with("zip") { Format::Zip } else { Format::Tar };
+            let root = runtime.config.workspace.clone();
+            match (&name[4..], args.as_slice()) {
+                ("create", [Value::Str(path), Value::Array(files)]) => {
+                    runtime.config.capabilities.check(Capability::FsRead)?;
+                    runtime.config.capabilities.check(Capability::FsWrite)?;
+                    let files = files
+                        .iter()
+                        .map(|file| match file {
+                            Value::Str(file) => Ok(file.to_string()),
+                            other => Err(format!("{} expects file names, got {:?}", name, other)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(Value::Num(archive::create(format, &root, path, &files)? as f64))
+                }
+                ("list", [Value::Str(path)]) => {
+                    runtime.config.capabilities.check(Capability::FsRead)?;
+                    let entries = archive::list(format, &root, path)?.into_iter().map(|(name, size)| {
+                        let pairs = vec![
+                            (Value::Str("name".into()), Value::Str(name.into())),
+                            (Value::Str("size".into()), Value::Num(size as f64)),
+                        ];
+                        Map::from_pairs(pairs).map(Value::Map)
+                    });
+                    Ok(Value::Array(entries.collect::<Result<_, _>>()?))
+                }
+                ("extract", [Value::Str(path), Value::Str(dest)]) => {
+                    runtime.config.capabilities.check(Capability::FsRead)?;
+                    runtime.config.capabilities.check(Capability::FsWrite)?;
+                    let names = archive::extract(format, &root, path, dest)?;
+                    Ok(Value::Array(names.into_iter().map(|name| Value::Str(name.into())).collect()))
+                }
+                ("create", _) => Err(format!("{} expects an archive path and an array of files", name)),
+                ("list", _) => Err(format!("{} expects an archive path", name)),
+                _ => Err(format!("{} expects an archive path and a destination directory", name)),
+            }
+        }
+        // glob("src/**/*.rs") gives matching paths relative to the workspace;
+        // path_join, basename, dirname and ext only look at the text, see paths.rs
+        "glob" => match args.as_slice() {
+            [Value::Str(pattern)] => {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                let found = paths::glob(&runtime.config.workspace, pattern)?;
+                Ok(Value::Array(found.into_iter().map(|path| Value::Str(path.into())).collect()))
+            }
+            _ => Err("glob expects a pattern such as \"src/**/*.rs\"".to_string()),
+        },
+        "path_join" => {
+            let parts = args
+                .iter()
+                .map(|part| match part {
+                    Value::Str(part) => Ok(part.to_string()),
+                    other => Err(format!("path_join expects strings, got {:?}", other)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::Str(paths::join(&parts).into()))
+        }
+        "basename" | "dirname" | "ext" => match args.as_slice() {
+            [Value::Str(path)] => Ok(Value::Str(match name {
+                "basename" => paths::basename(path).into(),
+                "dirname" => paths::dirname(path).into(),
+                _ => paths::ext(path).into(),
+            })),
+            _ => Err(format!("{} expects a path", name)),
+        },
+        "canonicalize" => match args.as_slice() {
+            [Value::Str(path)] => {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                Ok(Value::Str(paths::canonicalize(&runtime.config.workspace, path)?.into()))
+            }
+            _ => Err("canonicalize expects a path".to_string()),
+        },
+        // wire_stats(link) - bytes before and after compression, both directions
+        "wire_stats" => match args.as_slice() {
+            [Value::Proxy(proxy)] => {
+                let stats = proxy.borrow_mut().stats();
+                let mut map = Map::new();
+                for (key, n) in [("raw", stats.raw), ("wire", stats.wire), ("saved", stats.saved())] {
+                    map.insert(Value::Str(key.into()), Value::Num(n as f64))?;
+                }
+                Ok(Value::Map(map))
+            }
+            _ => Err("wire_stats expects a proxy".to_string()),
+        },
+        // Connection health: ping(link) gives the round trip in ms, keepalive(link, ms)
+        // pings before calls after that long idle (0 turns it off), and
+        // reconnect(link, attempts, base_ms) sets the backoff policy
+        "ping" => match args.as_slice() {
+            [Value::Proxy(proxy)] => {
+                let result = proxy.borrow_mut().ping();
+                notify_disconnects(runtime, proxy)?;
+                Ok(Value::Num(result?.as_secs_f64() * 1000.0))
+            }
+            _ => Err("ping expects a proxy".to_string()),
+        },
+        "keepalive" => match args.as_slice() {
+            [Value::Proxy(proxy), Value::Num(ms)] if *ms >= 0.0 => {
+                proxy.borrow_mut().keepalive = (*ms > 0.0).then(|| Duration::from_secs_f64(ms / 1000.0));
+                Ok(Value::Unit)
+            }
+            _ => Err("keepalive expects a proxy and milliseconds".to_string()),
+        },
+        "reconnect" => match args.as_slice() {
+            [Value::Proxy(proxy), Value::Num(attempts), Value::Num(ms)] if *attempts >= 0.0 && *ms >= 0.0 => {
+                let policy = &mut proxy.borrow_mut().reconnect;
+                policy.attempts = *attempts as u32;
+                policy.base = Duration::from_secs_f64(ms / 1000.0);
+                Ok(Value::Unit)
+            }
+            _ => Err("reconnect expects a proxy, a number of attempts and milliseconds".to_string()),
+        },
+        // on_disconnect(link, fn(reason) ...) runs after any call that lost the connection
+        "on_disconnect" => match args.as_slice() {
+            [Value::Proxy(proxy), Value::Function(handler)] => {
+                proxy.borrow_mut().on_disconnect.push(Rc::clone(handler));
+                Ok(Value::Unit)
+            }
+            _ => Err("on_disconnect expects a proxy and a function".to_string()),
+        },
+        "mock_return" => match args.as_slice() {
+            [Value::Mock(agent), Value::Str(method), value] => {
+                agent.borrow_mut().set_return(method, value.clone())?;
+                Ok(Value::Unit)
+            }
+            _ => Err("mock_return expects a mock, a method name and a value".to_string()),
+        },
+        // mock_latency(mock, "method", milliseconds)
+        "mock_latency" => match args.as_slice() {
+            [Value::Mock(agent), Value::Str(method), Value::Num(ms)] if *ms >= 0.0 => {
+                agent.borrow_mut().set_latency(method, Duration::from_secs_f64(ms / 1000.0))?;
+                Ok(Value::Unit)
+            }
+            _ => Err("mock_latency expects a mock, a method name and milliseconds".to_string()),
+        },
+        "mock_calls" => match args.as_slice() {
+            [Value::Mock(agent), Value::Str(method)] => {
+                Ok(Value::Array(agent.borrow().calls(method).into_iter().map(|args| Value::Array(args.into())).collect()))
+            }
+            _ => Err("mock_calls expects a mock and a method name".to_string()),
+        },
+        "print" => {
+            let parts: Vec<String> = args.iter().map(Value::print_text).collect();
+            println!("{}", parts.join(" "));
+            Ok(Value::Unit)
+        }
+        _ => match args.first() {
+            // stub.method(args...) on a proxy - a future for the remote call, made when awaited
+            Some(Value::Proxy(proxy)) if proxy.has_method(name) => {
+                let source = format!("{}.{}", proxy.borrow_mut().interface.name, name);
+                let mut call: Vec<Expr> = args.iter().cloned().map(Expr::Value).collect();
+                call.insert(1, Expr::Value(Value::Str(name.into())));
+                Ok(Value::Future(runtime.spawn(&source, "", Expr::Call("call".to_string(), call, Site::default()))))
+            }
+            _ => Err(format!("Unknown function: {}", name)),
+        },
+    }
+}
+
+// Hand each disconnect since the last call to the proxy's handlers
+fn notify_disconnects(runtime: &mut Runtime, proxy: &Proxy) -> Result<(), String> {
+    let (reasons, handlers) = {
+        let mut client = proxy.borrow_mut();
+        (client.take_disconnects(), client.on_disconnect.clone())
+    };
+    for reason in reasons {
+        for handler in &handlers {
+            runtime.call_function(handler, vec![Value::Str(reason.as_str().into())])?;
+        }
+    }
+    Ok(())
+}
+
+// Builtins that modify their first argument. The runtime passes a variable
+// argument by reference so the change is kept.
+pub fn is_mutating(name: &str) -> bool {
+    matches!(name, "remove" | "push" | "pop" | "insert")
+}
+
+pub fn mutate(name: &str, target: &mut Value, args: Vec<Value>) -> Result<Value, String> {
+    match (name, target) {
+        // remove(map, key) returns the removed value, or unit when the key is absent
+        ("remove", Value::Map(map)) => match args.as_slice() {
+            [key] => Ok(map.remove(key).unwrap_or(Value::Unit)),
+            _ => Err(format!("remove expects 2 arguments, got {}", args.len() + 1)),
+        },
+        // push(arr, v, ...) appends and returns the new length
+        ("push", Value::Array(vec)) => {
+            if args.is_empty() {
+                return Err("push expects at least 2 arguments, got 1".to_string());
+            }
+            vec.make_mut().extend(args);
+            Ok(Value::Num(vec.len() as f64))
+        }
+        // pop(arr) takes the last element, or gives unit when empty
+        ("pop", Value::Array(vec)) => match args.as_slice() {
+            [] => Ok(vec.make_mut().pop().unwrap_or(Value::Unit)),
+            _ => Err(format!("pop expects 1 argument, got {}", args.len() + 1)),
+        },
+        // insert(arr, i, v) puts v before index i; i may be the length, to append
+        ("insert", Value::Array(vec)) => match args.as_slice() {
+            [Value::Num(n), value] => {
+                let len = vec.len();
+                let index = if *n == len as f64 { Some(len) } else { runtime::resolve_index(*n, len) };
+                let index = index.ok_or_else(|| format!("Index {} out of bounds", n))?;
+                vec.make_mut().insert(index, value.clone());
+                Ok(Value::Unit)
+            }
+            [_, _] => Err("Array index must be a number".to_string()),
+            _ => Err(format!("insert expects 3 arguments, got {}", args.len() + 1)),
+        },
+        // remove(arr, i) takes the element at i, shifting the rest down
+        ("remove", Value::Array(vec)) => match args.as_slice() {
+            [Value::Num(n)] => {
+                let index = runtime::resolve_index(*n, vec.len()).ok_or_else(|| format!("Index {} out of bounds", n))?;
+                Ok(vec.make_mut().remove(index))
+            }
+            [_] => Err("Array index must be a number".to_string()),
+            _ => Err(format!("remove expects 2 arguments, got {}", args.len() + 1)),
+        },
+        (_, target) => Err(format!("{} cannot modify {:?}", name, target)),
+    }
+}
+
+fn color(args: &[Value]) -> Result<Value, String> {
+    let channel = |value: &Value, name: &str| -> Result<u8, String> {
+        match value {
+            Value::Num(n) if (0.0..=255.0).contains(n) && n.fract() == 0.0 => Ok(*n as u8),
+            _ => Err(format!("Invalid {} value", name)),
+        }
+    };
+    let alpha = match args.get(3) {
+        Some(a) => channel(a, "a")?,
+        None => 255,
+    };
+    Ok(Value::Color(Color::rgba(
+        channel(&args[0], "r")?,
+        channel(&args[1], "g")?,
+        channel(&args[2], "b")?,
+        alpha,
+    )))
+}
+
+// Fetch argument `index` of a call whose signature has a color there
+fn color_arg(name: &str, args: &[Value], index: usize) -> Result<Color, String> {
+    match &args[index] {
+        Value::Color(c) => Ok(*c),
+        other => Err(format!("{} expects a color, got {:?}", name, other)),
+    }
+}
+
+// Start par_run's commands
+pub fn commands(runtime: &Runtime, args: &[Value]) -> Result<Batch, String> {
+    runtime.config.capabilities.check(Capability::Shell)?;
+    let (commands, limit) = match args {
+        [Value::Array(commands)] => (commands, commands.len()),
+        [Value::Array(commands), Value::Num(limit)] if *limit >= 1.0 => (commands, *limit as usize),
+        _ => return Err("par_run expects an array of commands and an optional limit of at least 1".to_string()),
+    };
+    let commands = commands
+        .iter()
+        .map(|cmd| shell::command(cmd).ok_or_else(|| format!("par_run expects command strings, got {:?}", cmd)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Batch::start(commands, limit))
+}
+
+// Stable merge sort taking a fallible `before(a, b)`, so comparators written
+// in the language can fail (and need not be consistent) without a panic
+fn merge_sort(items: Vec<Value>, before: &mut impl FnMut(&Value, &Value) -> Result<bool, String>) -> Result<Vec<Value>, String> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let mut left = items;
+    let right = left.split_off(left.len() / 2);
+    let (left, right) = (merge_sort(left, before)?, merge_sort(right, before)?);
+    let mut out = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Taking from the right only when strictly before keeps equal items in order
+        let next = if before(b, a)? { right.next() } else { left.next() };
+        out.extend(next);
+    }
+    out.extend(left);
+    out.extend(right);
+    Ok(out)
+}
+
+// The command for ssh(host, cmd), which needs both the shell and the network
+#[cfg(feature = "ssh")]
+pub fn remote(runtime: &Runtime, args: &[Value]) -> Result<Job, String> {
+    runtime.config.capabilities.check(Capability::Shell)?;
+    runtime.config.capabilities.check(Capability::Net)?;
+    match args {
+        [Value::Str(host), Value::Str(cmd)] => {
+            let client = runtime.config.ssh.clone().unwrap_or_else(|| "ssh".into());
+            shell::ssh(&client, host, cmd)
+        }
+        _ => Err("ssh expects a host and a command".to_string()),
+    }
+}
+
+#[cfg(not(feature = "ssh"))]
+pub fn remote(_: &Runtime, _: &[Value]) -> Result<Job, String> {
+    Err("ssh is not available: rebuild with the ssh feature".to_string())
+}
+
+#[cfg(feature = "mqtt")]
+fn mqtt_connect(runtime: &mut Runtime, args: &[Value]) -> Result<Value, String> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    runtime.config.capabilities.check(Capability::Net)?;
+    let client_id = format!("rcl-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+    // A URL carrying a password may be a secret; errors quoting it are redacted
+    let client = match args {
+        [Value::Str(url)] => Client::connect(url, &client_id)?,
+        [Value::Secret(url)] => Client::connect(url.expose(), &client_id).map_err(|e| url.redact(&e))?,
+        _ => return Err("mqtt_connect expects a URL such as mqtt://broker:1883".to_string()),
+    };
+    runtime.mqtt.add(client.clone());
+    Ok(Value::Mqtt(client))
+}
+
+#[cfg(not(feature = "mqtt"))]
+fn mqtt_connect(_: &mut Runtime, _: &[Value]) -> Result<Value, String> {
+    Err("mqtt_connect is not available: rebuild with the mqtt feature".to_string())
+}
+
+#[cfg(feature = "keyring")]
+fn credential(runtime: &Runtime, name: &str, args: &[Value]) -> Result<Value, String> {
+    runtime.config.capabilities.check(Capability::Keyring)?;
+    let tool = runtime.config.keyring.as_deref();
+    match (name, args) {
+        ("cred_get", [Value::Str(service)]) => Ok(Value::Secret(keyring::get(tool, service)?)),
+        ("cred_set", [Value::Str(service), Value::Secret(secret)]) => keyring::set(tool, service, secret.expose()).map(|_| Value::Unit),
+        ("cred_set", [Value::Str(service), Value::Str(secret)]) => keyring::set(tool, service, secret).map(|_| Value::Unit),
+        ("cred_get", _) => Err("cred_get expects a service name".to_string()),
+        _ => Err("cred_set expects a service name and a secret".to_string()),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn credential(_: &Runtime, name: &str, _: &[Value]) -> Result<Value, String> {
+    Err(format!("{} is not available: rebuild with the keyring feature", name))
+}
+
+// The URL and headers of an API request as text; any secrets among them go
+// in `secrets`, so errors can be redacted
+fn request_parts(name: &str, url: &Value, headers: Option<&Value>, secrets: &mut Vec<Secret>) -> Result<(String, Vec<(String, String)>), String> {
+    let mut text = |value: &Value| match value {
+        Value::Str(text) => Ok(text.to_string()),
+        Value::Secret(secret) => {
+            secrets.push(secret.clone());
+            Ok(secret.expose().to_string())
+        }
+        other => Err(format!("{} expects text, got {:?}", name, other)),
+    };
+    let url = text(url)?;
+    let headers = match headers {
+        None | Some(Value::Unit) => Vec::new(),
+        Some(Value::Map(map)) => map.iter().map(|(key, value)| Ok((key.print_text(), text(value)?))).collect::<Result<_, String>>()?,
+        Some(other) => return Err(format!("{} expects headers as a map, got {:?}", name, other)),
+    };
+    Ok((url, headers))
+}
+
+// A duration as milliseconds or text such as "500ms" or "2s"
+pub fn duration_arg(name: &str, value: &Value) -> Result<Duration, String> {
+    match value {
+        Value::Num(ms) if *ms >= 0.0 && ms.is_finite() => Ok(Duration::from_secs_f64(ms / 1000.0)),
+        Value::Str(text) => config::parse_duration(text),
+        other => Err(format!("{} expects milliseconds or a duration like 500ms, got {:?}", name, other)),
+    }
+}
+
+// A process id: a whole number above 0
+pub fn pid_arg(name: &str, value: &Value) -> Result<u32, String> {
+    match value {
+        Value::Num(pid) if *pid >= 1.0 && *pid <= u32::MAX as f64 && pid.fract() == 0.0 => Ok(*pid as u32),
+        other => Err(format!("{} expects a process id, got {:?}", name, other)),
+    }
+}
+
+// Non-negative whole number argument
+fn count_arg(name: &str, value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Num(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        other => Err(format!("{} expects a whole number count, got {:?}", name, other)),
+    }
+}
+
+fn array_arg<'a>(name: &str, value: &'a Value) -> Result<&'a [Value], String> {
+    match value {
+        Value::Array(items) => Ok(items),
+        other => Err(format!("{} expects an array, got {:?}", name, other)),
+    }
+}
+
+fn function_arg(name: &str, value: &Value) -> Result<Rc<Function>, String> {
+    match value {
+        Value::Function(f) => Ok(Rc::clone(f)),
+        other => Err(format!("{} expects a function, got {:?}", name, other)),
+    }
+}
+
+fn colors(list: Vec<Color>) -> Value {
+    Value::Array(list.into_iter().map(Value::Color).collect())
+}
+
+// Print a 256-color swatch for each color, with its hex code and nearest name
+fn show(args: &[Value]) -> Result<Value, String> {
+    let mut colors = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Color(c) => colors.push(*c),
+            Value::Array(items) => {
+                for item in items {
+                    match item {
+                        Value::Color(c) => colors.push(*c),
+                        other => return Err(format!("show expects colors, got {:?}", other)),
+                    }
+                }
+            }
+            other => return Err(format!("show expects colors, got {:?}", other)),
+        }
+    }
+    for c in colors {
+        println!("{}", swatch(&c));
+    }
+    Ok(Value::Unit)
+}
+
+pub fn swatch(c: &Color) -> String {
+    format!(
+        "\x1b[48;5;{}m    \x1b[0m {} ({})",
+        c.to_ansi256(),
+        c.to_hex(),
+        c.nearest_named()
+    )
+}
@@ -0,0 +1,151 @@
+// Capability-based security model
+// Every builtin that touches the outside world checks the session's
+// capability set before doing anything.
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Capability {
+    Shell,      // Backtick commands and process spawning
+    FsRead,     // Reading files
+    FsWrite,    // Creating/writing files (code generation, saves)
+    Net,        // Sockets and HTTP
+    Env,        // Environment variables
+    Clipboard,  // System clipboard
+    Keyring,    // Credentials in the OS keyring
+    Schedule,   // Handlers run later on a cron spec
+}
+
+impl Capability {
+    pub const ALL: [Capability; 8] = [
+        Capability::Shell,
+        Capability::FsRead,
+        Capability::FsWrite,
+        Capability::Net,
+        Capability::Env,
+        Capability::Clipboard,
+        Capability::Keyring,
+        Capability::Schedule,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Shell => "shell",
+            Capability::FsRead => "fs-read",
+            Capability::FsWrite => "fs-write",
+            Capability::Net => "net",
+            Capability::Env => "env",
+            Capability::Clipboard => "clipboard",
+            Capability::Keyring => "keyring",
+            Capability::Schedule => "schedule",
+        }
+    }
+
+    fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+
+    pub fn parse(name: &str) -> Result<Capability, String> {
+        let name = name.trim().to_lowercase().replace('_', "-");
+        Capability::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == name)
+            .ok_or_else(|| format!("Unknown capability: {}", name))
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Set of granted capabilities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    bits: u8,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        let mut caps = Capabilities::none();
+        for cap in Capability::ALL {
+            caps.grant(cap);
+        }
+        caps
+    }
+
+    pub fn none() -> Self {
+        Capabilities { bits: 0 }
+    }
+
+    pub fn grant(&mut self, cap: Capability) {
+        self.bits |= cap.bit();
+    }
+
+    pub fn revoke(&mut self, cap: Capability) {
+        self.bits &= !cap.bit();
+    }
+
+    pub fn has(&self, cap: Capability) -> bool {
+        self.bits & cap.bit() != 0
+    }
+
+    // Called at each builtin boundary
+    pub fn check(&self, cap: Capability) -> Result<(), String> {
+        if self.has(cap) {
+            Ok(())
+        } else {
+            Err(format!("Capability denied: {} (enable with --allow {})", cap, cap))
+        }
+    }
+
+    // Apply a comma separated list such as "shell,fs-read"; "all" matches everything
+    pub fn grant_list(&mut self, list: &str) -> Result<(), String> {
+        for cap in parse_list(list)? {
+            self.grant(cap);
+        }
+        Ok(())
+    }
+
+    pub fn revoke_list(&mut self, list: &str) -> Result<(), String> {
+        for cap in parse_list(list)? {
+            self.revoke(cap);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities::all()
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let granted: Vec<&str> = Capability::ALL
+            .iter()
+            .filter(|c| self.has(**c))
+            .map(|c| c.name())
+            .collect();
+        if granted.is_empty() {
+            write!(f, "(none)")
+        } else {
+            write!(f, "{}", granted.join(", "))
+        }
+    }
+}
+
+fn parse_list(list: &str) -> Result<Vec<Capability>, String> {
+    let mut caps = Vec::new();
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if part == "all" {
+            caps.extend(Capability::ALL);
+        } else {
+            caps.push(Capability::parse(part)?);
+        }
+    }
+    Ok(caps)
+}
@@ -0,0 +1,58 @@
+// The system clipboard. clipboard() gives its text and clipboard_set(text)
+// replaces it, both behind the clipboard capability.
+//
+// macOS goes through pbpaste and pbcopy; elsewhere wl-paste and wl-copy under
+// Wayland, otherwise xclip on the X clipboard selection.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// The program and arguments that read the clipboard, then those that write it
+fn tools() -> ([&'static str; 3], [&'static str; 3]) {
+    if cfg!(target_os = "macos") {
+        (["pbpaste", "", ""], ["pbcopy", "", ""])
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        (["wl-paste", "--no-newline", ""], ["wl-copy", "", ""])
+    } else {
+        (["xclip", "-selection", "clipboard"], ["xclip", "-selection", "clipboard"])
+    }
+}
+
+fn command(tool: &[&str; 3]) -> Command {
+    let mut command = Command::new(tool[0]);
+    command.args(tool[1..].iter().filter(|arg| !arg.is_empty()));
+    command
+}
+
+pub fn get() -> Result<String, String> {
+    let (read, _) = tools();
+    let mut command = command(&read);
+    if read[0] == "xclip" {
+        command.arg("-o");
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Clipboard unavailable ({}): {}", read[0], e))?;
+    if !output.status.success() {
+        return Err(format!("Failed to read the clipboard: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn set(text: &str) -> Result<(), String> {
+    let (_, write) = tools();
+    let mut child = command(&write)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Clipboard unavailable ({}): {}", write[0], e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let output = child.wait_with_output().map_err(|e| format!("Clipboard failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Failed to set the clipboard: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
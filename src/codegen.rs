@@ -0,0 +1,319 @@
+// Code generation from a parsed interface - the same schema mocks and the
+// RPC runtime check calls against (see interface.rs). `proxy "calc.tsu"` and
+// `agent "calc.tsu"` write a C++ header declaring the interface's methods
+// with their types, plus a Tau script that runs the proxy or agent end.
+// `proxy --rust "calc.tsu"` writes a Rust module holding both ends, for
+// include! in crates that depend on this one; Typed below converts its
+// arguments and results.
+use crate::interface::{Interface, Method, Param, Type};
+use crate::value::{Color, Map, Value};
+
+// Tau types in C++. color, map and any come from the Tau runtime header.
+fn cpp_type(ty: &Type) -> String {
+    match ty {
+        Type::Num => "double".to_string(),
+        Type::Str => "std::string".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Color => "tau::Color".to_string(),
+        Type::Map => "tau::Map".to_string(),
+        Type::Any => "tau::Value".to_string(),
+        Type::Unit => "void".to_string(),
+        Type::Array(item) => format!("std::vector<{}>", cpp_type(item)),
+    }
+}
+
+fn uses_runtime_types(ty: &Type) -> bool {
+    match ty {
+        Type::Color | Type::Map | Type::Any => true,
+        Type::Array(item) => uses_runtime_types(item),
+        _ => false,
+    }
+}
+
+fn cpp_params(params: &[Param]) -> String {
+    let params: Vec<String> = params.iter().map(|p| format!("{} {}", cpp_type(&p.ty), p.name)).collect();
+    params.join(", ")
+}
+
+fn cpp_signature(method: &Method) -> String {
+    format!("{} {}({})", cpp_type(&method.returns), method.name, cpp_params(&method.params))
+}
+
+// The opening shared by both headers: banner, include guard and includes
+fn cpp_prelude(interface: &Interface, filename: &str, kind: &str, guard: &str) -> String {
+    let mut out = format!(
+        "// {} header for {}\n// Generated by Tau language from interface {}\n\n#ifndef {}\n#define {}\n\n",
+        kind, filename, interface.name, guard, guard
+    );
+    let types = interface
+        .methods
+        .iter()
+        .flat_map(|m| m.params.iter().map(|p| &p.ty).chain([&m.returns]))
+        .chain(interface.events.iter().flat_map(|e| e.params.iter().map(|p| &p.ty)));
+    let mut includes = vec!["#include <functional>", "#include <string>", "#include <vector>"];
+    if types.into_iter().any(uses_runtime_types) {
+        includes.push("#include \"TauValue.h\"  // tau::Color, tau::Map, tau::Value");
+    }
+    out.push_str(&includes.join("\n"));
+    out.push_str("\n\n");
+    out
+}
+
+// A client class with one blocking call per method and a handler per event
+pub fn cpp_proxy(interface: &Interface, filename: &str, guard: &str) -> String {
+    let name = &interface.name;
+    let mut out = cpp_prelude(interface, filename, "Proxy", guard);
+    out.push_str(&format!("// Calls {} methods on a remote agent\nclass {}Proxy {{\npublic:\n", name, name));
+    out.push_str(&format!("    explicit {}Proxy(const std::string& address);\n", name));
+    for method in &interface.methods {
+        out.push_str(&format!("    {};\n", cpp_signature(method)));
+    }
+    for event in &interface.events {
+        let handler = format!("std::function<void({})>", cpp_params(&event.params));
+        out.push_str(&format!("    void on_{}({} handler);\n", event.name, handler));
+    }
+    out.push_str(&format!("}};\n\n#endif // {}\n", guard));
+    out
+}
+
+// An abstract class to implement, one pure virtual per method; events are
+// raised through protected members
+pub fn cpp_agent(interface: &Interface, filename: &str, guard: &str) -> String {
+    let name = &interface.name;
+    let mut out = cpp_prelude(interface, filename, "Agent", guard);
+    out.push_str(&format!("// Answers {} calls; implement each method\nclass {}Agent {{\npublic:\n", name, name));
+    out.push_str(&format!("    virtual ~{}Agent() = default;\n", name));
+    for method in &interface.methods {
+        out.push_str(&format!("    virtual {} = 0;\n", cpp_signature(method)));
+    }
+    if !interface.events.is_empty() {
+        out.push_str("protected:\n");
+        for event in &interface.events {
+            out.push_str(&format!("    void {}({});  // Raise to connected proxies\n", event.name, cpp_params(&event.params)));
+        }
+    }
+    out.push_str(&format!("}};\n\n#endif // {}\n", guard));
+    out
+}
+
+fn tau_params(params: &[Param]) -> String {
+    let names: Vec<&str> = params.iter().map(|p| p.name.as_str()).collect();
+    names.join(", ")
+}
+
+// name(a: num, b: num) -> num, as written in the interface
+fn described(method: &Method) -> String {
+    let params: Vec<String> = method.params.iter().map(|p| format!("{}: {}", p.name, p.ty)).collect();
+    match method.returns {
+        Type::Unit => format!("{}({})", method.name, params.join(", ")),
+        ref returns => format!("{}({}) -> {}", method.name, params.join(", "), returns),
+    }
+}
+
+// A Rho literal of the type for stub bodies. Rho has no empty map literal,
+// so maps get a placeholder entry.
+fn stub_literal(ty: &Type) -> String {
+    match ty {
+        Type::Num | Type::Any | Type::Unit => "0".to_string(),
+        Type::Str => "\"\"".to_string(),
+        Type::Bool => "false".to_string(),
+        Type::Color => "color(0, 0, 0)".to_string(),
+        Type::Map => "[{\"todo\", 0}]".to_string(),
+        Type::Array(_) => "[]".to_string(),
+    }
+}
+
+// Tau script connecting to a served agent, with each method's call shown
+pub fn tau_proxy(interface: &Interface, filename: &str, variable: &str) -> String {
+    let mut out = format!(
+        "# Proxy for interface {} in {}\n# Generated by Tau language\n\n:tau\n\n\
+         # Connect to an agent started with: agent \"{}\" 9000\n{} = proxy \"{}\" \"127.0.0.1:9000\"\n\n\
+         # Each call returns a future:\n",
+        interface.name, filename, filename, variable, filename
+    );
+    for method in &interface.methods {
+        out.push_str(&format!("#   {}  =>  await {}.{}({})\n", described(method), variable, method.name, tau_params(&method.params)));
+    }
+    out
+}
+
+// Tau script serving the interface, with a stub function per method
+pub fn tau_agent(interface: &Interface, filename: &str) -> String {
+    let mut out = format!(
+        "# Agent for interface {} in {}\n# Generated by Tau language\n\n:tau\n\n\
+         # One function per method; replace each body with the real work\n",
+        interface.name, filename
+    );
+    for method in &interface.methods {
+        out.push_str(&format!("# {}\n", described(method)));
+        out.push_str(&format!("{} = fn({}) {}\n", method.name, tau_params(&method.params), stub_literal(&method.returns)));
+    }
+    out.push_str(&format!("\n# Serve the methods above; run this script with --daemon to keep serving\nagent \"{}\" 9000\n", filename));
+    out
+}
+
+// Tau values as the Rust types of generated modules
+pub trait Typed: Sized {
+    fn from_value(value: Value) -> Result<Self, String>;
+    fn into_value(self) -> Value;
+}
+
+macro_rules! typed {
+    ($rust:ty, $variant:ident, $tau:literal) => {
+        impl Typed for $rust {
+            fn from_value(value: Value) -> Result<Self, String> {
+                match value {
+                    Value::$variant(inner) => Ok(inner),
+                    other => Err(format!("Expected {}, got {:?}", $tau, other)),
+                }
+            }
+
+            fn into_value(self) -> Value {
+                Value::$variant(self)
+            }
+        }
+    };
+}
+
+typed!(f64, Num, "num");
+typed!(bool, Bool, "bool");
+typed!(Color, Color, "color");
+typed!(Map, Map, "map");
+
+impl Typed for String {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Str(inner) => Ok(inner.to_string()),
+            other => Err(format!("Expected str, got {:?}", other)),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Str(self.into())
+    }
+}
+
+impl Typed for Value {
+    fn from_value(value: Value) -> Result<Self, String> {
+        Ok(value)
+    }
+
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl Typed for () {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Unit => Ok(()),
+            other => Err(format!("Expected unit, got {:?}", other)),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Unit
+    }
+}
+
+impl<T: Typed> Typed for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Array(items) => items.iter().cloned().map(T::from_value).collect(),
+            other => Err(format!("Expected an array, got {:?}", other)),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Array(self.into_iter().map(Typed::into_value).collect())
+    }
+}
+
+// The next argument of a call already checked against the interface
+pub fn next_argument<T: Typed>(args: &mut impl Iterator<Item = Value>) -> Result<T, String> {
+    T::from_value(args.next().unwrap_or(Value::Unit))
+}
+
+// Tau types in Rust, as imported by the generated module
+fn rust_type(ty: &Type) -> String {
+    match ty {
+        Type::Num => "f64".to_string(),
+        Type::Str => "String".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Color => "Color".to_string(),
+        Type::Map => "Map".to_string(),
+        Type::Any => "Value".to_string(),
+        Type::Unit => "()".to_string(),
+        Type::Array(item) => format!("Vec<{}>", rust_type(item)),
+    }
+}
+
+// async fn add(&mut self, a: f64, b: f64) -> Result<f64, String>
+fn rust_signature(method: &Method) -> String {
+    let params: Vec<String> = method.params.iter().map(|p| format!(", {}: {}", p.name, rust_type(&p.ty))).collect();
+    format!("async fn {}(&mut self{}) -> Result<{}, String>", method.name, params.concat(), rust_type(&method.returns))
+}
+
+// A module name from a file's base name: calc-v2 becomes calc_v2
+fn module_name(base_name: &str) -> String {
+    let name: String = base_name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) { format!("_{}", name) } else { name }
+}
+
+// One module with the agent trait and its dispatch, and the client struct.
+// Client calls block until the reply; they are async to match the trait.
+pub fn rust_module(interface: &Interface, filename: &str, base_name: &str) -> String {
+    let name = &interface.name;
+    let mut out = format!(
+        "// Rust module for interface {} in {}\n// Generated by Tau language: include!(\"{}.rs\") in a crate depending on rust_ai_lang\n\n",
+        name, filename, base_name
+    );
+    out.push_str(&format!("pub mod {} {{\n", module_name(base_name)));
+    out.push_str("    #![allow(dead_code, unused_imports, async_fn_in_trait)]\n");
+    out.push_str("    use ::rust_ai_lang::codegen::{next_argument, Typed};\n");
+    out.push_str("    use ::rust_ai_lang::interface::{parse_interface, Interface};\n");
+    out.push_str("    use ::rust_ai_lang::transport::Client;\n");
+    out.push_str("    use ::rust_ai_lang::value::{Color, Map, Value};\n\n");
+    out.push_str("    // The interface this module was generated from; both ends check calls against it\n");
+    out.push_str(&format!("    pub const INTERFACE: &str = {:?};\n\n", interface.to_string()));
+    out.push_str("    pub fn interface() -> Interface {\n");
+    out.push_str("        parse_interface(INTERFACE).expect(\"generated from a valid interface\")\n    }\n\n");
+
+    out.push_str(&format!("    // Implement to answer {} calls, and route requests through dispatch\n", name));
+    out.push_str(&format!("    pub trait {}Agent {{\n", name));
+    for method in &interface.methods {
+        out.push_str(&format!("        {};\n", rust_signature(method)));
+    }
+    out.push_str("    }\n\n");
+    out.push_str("    // One call to the agent, with arguments and result checked against the interface\n");
+    out.push_str(&format!(
+        "    pub async fn dispatch<A: {}Agent>(agent: &mut A, method: &str, args: Vec<Value>) -> Result<Value, String> {{\n",
+        name
+    ));
+    out.push_str("        let interface = interface();\n        interface.check_call(method, &args)?;\n");
+    out.push_str("        let mut args = args.into_iter();\n        let result = match method {\n");
+    for method in &interface.methods {
+        let args = vec!["next_argument(&mut args)?"; method.params.len()].join(", ");
+        out.push_str(&format!("            {:?} => agent.{}({}).await?.into_value(),\n", method.name, method.name, args));
+    }
+    out.push_str("            _ => unreachable!(\"check_call rejects unknown methods\"),\n        };\n");
+    out.push_str("        interface.check_return(method, result)\n    }\n\n");
+
+    out.push_str(&format!("    // Calls {} methods on a served agent, such as one started with agent \"{}\" 9000\n", name, filename));
+    out.push_str(&format!("    pub struct {}Client {{\n        client: Client,\n    }}\n\n", name));
+    out.push_str(&format!("    impl {}Client {{\n", name));
+    out.push_str("        // tcp:host:port, unix:path or stdio:command\n");
+    out.push_str("        pub fn connect(address: &str) -> Result<Self, String> {\n");
+    out.push_str(&format!("            Ok({}Client {{ client: Client::connect(interface(), address, &[])? }})\n        }}\n", name));
+    for method in &interface.methods {
+        let args: Vec<String> = method.params.iter().map(|p| format!("{}.into_value()", p.name)).collect();
+        out.push_str(&format!("\n        pub {} {{\n", rust_signature(method)));
+        out.push_str(&format!(
+            "            Typed::from_value(self.client.call({:?}, vec![{}])?)\n        }}\n",
+            method.name,
+            args.join(", ")
+        ));
+    }
+    out.push_str("    }\n}\n");
+    out
+}
@@ -0,0 +1,112 @@
+// Compression for wire messages - a small LZ77 scheme in the spirit of lz4,
+// plus base64 so compressed frames still fit the line protocol.
+//
+// The compressed stream is a series of tokens:
+//   0x00..=0x7f  literal run: the next token+1 bytes are copied as is
+//   0x80..=0xff  match: copy (token & 0x7f) + 4 bytes from `offset` bytes back,
+//                where offset follows as a little-endian u16
+use std::collections::HashMap;
+
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7f + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    let mut recent: HashMap<[u8; MIN_MATCH], usize> = HashMap::new();  // Last position of each 4-byte run
+    let mut literals_from = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= input.len() {
+        let key = [input[pos], input[pos + 1], input[pos + 2], input[pos + 3]];
+        let candidate = recent.insert(key, pos).filter(|&start| pos - start <= MAX_OFFSET);
+        let Some(start) = candidate else {
+            pos += 1;
+            continue;
+        };
+        let mut len = MIN_MATCH;
+        while len < MAX_MATCH && pos + len < input.len() && input[start + len] == input[pos + len] {
+            len += 1;
+        }
+        push_literals(&mut out, &input[literals_from..pos]);
+        out.push(0x80 | (len - MIN_MATCH) as u8);
+        out.extend_from_slice(&((pos - start) as u16).to_le_bytes());
+        pos += len;
+        literals_from = pos;
+    }
+    push_literals(&mut out, &input[literals_from..]);
+    out
+}
+
+fn push_literals(out: &mut Vec<u8>, mut literals: &[u8]) {
+    while !literals.is_empty() {
+        let run = literals.len().min(MAX_LITERALS);
+        out.push((run - 1) as u8);
+        out.extend_from_slice(&literals[..run]);
+        literals = &literals[run..];
+    }
+}
+
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, String> {
+    let corrupt = || "Corrupt compressed data".to_string();
+    let mut out = Vec::with_capacity(input.len() * 2);
+    let mut pos = 0;
+    while let Some(&token) = input.get(pos) {
+        pos += 1;
+        if token < 0x80 {
+            let run = token as usize + 1;
+            out.extend_from_slice(input.get(pos..pos + run).ok_or_else(corrupt)?);
+            pos += run;
+        } else {
+            let len = (token & 0x7f) as usize + MIN_MATCH;
+            let offset = match input.get(pos..pos + 2) {
+                Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+                None => return Err(corrupt()),
+            };
+            pos += 2;
+            if offset == 0 || offset > out.len() {
+                return Err(corrupt());
+            }
+            // Byte by byte: a match may overlap the bytes it produces
+            let start = out.len() - offset;
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn from_base64(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes() {
+        let digit = BASE64.iter().position(|&d| d == c).ok_or_else(|| format!("Invalid base64 character '{}'", c as char))?;
+        bits = bits << 6 | digit as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,324 @@
+// Session configuration
+// Built from defaults, then the config file, then command line flags.
+use crate::capabilities::Capabilities;
+use crate::httpcache;
+use crate::input;
+use crate::output::{MapLayout, Output};
+use crate::value::BlendMode;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub capabilities: Capabilities,
+    pub blend_mode: BlendMode,
+    pub timeout: Option<Duration>,  // Evaluation limit for languages without their own
+    pub workspace: PathBuf,         // Root for files read and written by scripts; empty means the current directory
+    pub trace: bool,                // Record tracing spans from the start, see tracing.rs
+    pub daemon: bool,               // Keep running scheduled handlers after the input ends
+    pub socket: Option<PathBuf>,    // Control socket a daemon listens on for `ctl`, see control.rs
+    pub script: Option<PathBuf>,    // Startup script run instead of reading stdin
+    pub check: bool,                // List the script's syntax errors instead of running it
+    pub output: Output,             // How results are echoed, see output.rs
+    pub map_layout: MapLayout,      // How echoed maps are laid out
+    pub provenance: bool,           // Record where each variable was bound, for :whence
+    pub strict: bool,               // Make loose comparisons, conditions and assignments errors
+    pub ssh: Option<PathBuf>,       // ssh client for ssh(host, cmd); the one on PATH if unset
+    pub keyring: Option<PathBuf>,   // Credential store program for cred_get/cred_set; the platform's if unset
+    pub color: bool,                // Draw swatches beside echoed colors, see output::swatches
+    pub http_rate: Option<Duration>,  // Least gap between http_get_cached fetches to one host
+    pub http_cache: Option<PathBuf>,  // Where http_get_cached keeps responses; .http-cache in the workspace if unset
+    pub stdlib_path: Option<PathBuf>,  // Library files read before the built-in ones, see stdlib.rs
+    pub max_line: Option<usize>,    // Longest line read from stdin or a script; input::MAX_LINE if unset
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config::default()
+    }
+
+    // A config rooted in a fresh directory under the system temp dir, so
+    // file-producing features leave nothing behind in the working tree
+    pub fn with_temp_workspace(name: &str) -> Result<Config, String> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "rust_ai_lang-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        Ok(Config { workspace: dir, ..Config::default() })
+    }
+
+    // Resolve a script path against the workspace; absolute paths are kept
+    pub fn in_workspace(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.workspace.join(path)
+    }
+
+    pub fn max_line(&self) -> usize {
+        self.max_line.unwrap_or(input::MAX_LINE)
+    }
+
+    // Default config file: ~/.rustailangrc
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| Path::new(&home).join(".rustailangrc"))
+    }
+
+    // Config file format is one `key = value` per line, `#` starts a comment:
+    //   allow = shell, fs-read
+    //   deny = net
+    //   blend = linear
+    //   timeout = 2s
+    //   workspace = ~/projects/out
+    //   trace = on
+    //   strict = on
+    //   socket = ~/.rcl.sock
+    //   output = display
+    //   http-rate = 2/s
+    //   max-line = 64k
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        self.apply_str(&content)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn apply_str(&mut self, content: &str) -> Result<(), String> {
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected key = value", line_no + 1))?;
+            self.set(key.trim(), value.trim())
+                .map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "allow" => self.capabilities.grant_list(value),
+            "deny" => self.capabilities.revoke_list(value),
+            "blend" => {
+                self.blend_mode = BlendMode::parse(value)?;
+                Ok(())
+            }
+            "timeout" => {
+                self.timeout = parse_timeout(value)?;
+                Ok(())
+            }
+            "trace" => {
+                self.trace = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(format!("trace must be on or off, got {}", value)),
+                };
+                Ok(())
+            }
+            "provenance" => {
+                self.provenance = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(format!("provenance must be on or off, got {}", value)),
+                };
+                Ok(())
+            }
+            "strict" => {
+                self.strict = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(format!("strict must be on or off, got {}", value)),
+                };
+                Ok(())
+            }
+            "workspace" => {
+                self.workspace = expand_home(value);
+                Ok(())
+            }
+            "socket" => {
+                self.socket = Some(expand_home(value));
+                Ok(())
+            }
+            "ssh" => {
+                self.ssh = Some(expand_home(value));
+                Ok(())
+            }
+            "keyring" => {
+                self.keyring = Some(expand_home(value));
+                Ok(())
+            }
+            "http-rate" => {
+                self.http_rate = httpcache::parse_rate(value)?;
+                Ok(())
+            }
+            "http-cache" => {
+                self.http_cache = Some(expand_home(value));
+                Ok(())
+            }
+            "stdlib-path" => {
+                self.stdlib_path = Some(expand_home(value));
+                Ok(())
+            }
+            "max-line" => {
+                self.max_line = Some(input::parse_size(value)?);
+                Ok(())
+            }
+            "output" => {
+                self.output = Output::parse(value)?;
+                Ok(())
+            }
+            "color" => {
+                self.color = match value {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(format!("color must be on or off, got {}", value)),
+                };
+                Ok(())
+            }
+            // `:set no-color` on its own turns swatches off
+            "no-color" => {
+                self.color = match value {
+                    "" | "on" | "true" => false,
+                    "off" | "false" => true,
+                    _ => return Err(format!("no-color must be on or off, got {}", value)),
+                };
+                Ok(())
+            }
+            _ if key.starts_with("output.") => self.map_layout.set(&key[7..], value),
+            _ => Err(format!("Unknown config key: {}", key)),
+        }
+    }
+
+    // Command line flags:
+    //   --config <path>   read settings from path instead of ~/.rustailangrc
+    //   --no-config       skip the config file
+    //   --allow <list>    grant capabilities
+    //   --deny <list>     revoke capabilities
+    //   --sandbox         revoke every capability
+    //   --workspace <dir> read and write script files under dir
+    //   --daemon          after the input ends, keep running schedule() handlers
+    //   --socket <path>   with --daemon, take `ctl` requests on a Unix socket at path
+    //   --output <mode>   echo results as debug, display or json
+    //   --no-color        no ANSI swatches beside colors (also NO_COLOR=1)
+    //   --stdlib-path <dir>  import library files from dir before the built-in ones
+    //   --max-line <size> skip input lines longer than size, e.g. 64k
+    //   --check           with a script, list every syntax error in it without running it
+    //   --strict          start in strict mode, see :strict
+    //   <script>          run the script file instead of reading stdin
+    pub fn from_args(args: &[String]) -> Result<Config, String> {
+        let mut config = Config::new();
+        // Swatches only make sense on a terminal; NO_COLOR is the usual opt-out
+        config.color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+
+        let mut config_path = Config::default_path().filter(|p| p.exists());
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--config" => {
+                    let path = args.get(i + 1).ok_or("--config requires a path")?;
+                    config_path = Some(PathBuf::from(path));
+                    i += 1;
+                }
+                "--no-config" => config_path = None,
+                _ => {}
+            }
+            i += 1;
+        }
+        if let Some(path) = config_path {
+            config.apply_file(&path)?;
+        }
+
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].as_str() {
+                "--allow" | "--deny" => {
+                    let list = args
+                        .get(i + 1)
+                        .ok_or_else(|| format!("{} requires a capability list", args[i]))?;
+                    config.set(&args[i][2..], list)?;
+                    i += 1;
+                }
+                "--workspace" => {
+                    let dir = args.get(i + 1).ok_or("--workspace requires a directory")?;
+                    config.set("workspace", dir)?;
+                    i += 1;
+                }
+                "--sandbox" => config.capabilities = Capabilities::none(),
+                "--daemon" => config.daemon = true,
+                "--check" => config.check = true,
+                "--strict" => config.strict = true,
+                "--no-color" => config.color = false,
+                "--socket" => {
+                    let path = args.get(i + 1).ok_or("--socket requires a path")?;
+                    config.set("socket", path)?;
+                    i += 1;
+                }
+                "--output" => {
+                    let mode = args.get(i + 1).ok_or("--output requires debug, display or json")?;
+                    config.set("output", mode)?;
+                    i += 1;
+                }
+                "--stdlib-path" => {
+                    let dir = args.get(i + 1).ok_or("--stdlib-path requires a directory")?;
+                    config.set("stdlib-path", dir)?;
+                    i += 1;
+                }
+                "--max-line" => {
+                    let size = args.get(i + 1).ok_or("--max-line requires a size")?;
+                    config.set("max-line", size)?;
+                    i += 1;
+                }
+                "--config" => i += 1,
+                "--no-config" => {}
+                other if other.starts_with('-') || config.script.is_some() => {
+                    return Err(format!("Unknown argument: {}", other));
+                }
+                script => config.script = Some(PathBuf::from(script)),
+            }
+            i += 1;
+        }
+
+        Ok(config)
+    }
+}
+
+// ~/path is relative to the home directory
+fn expand_home(value: &str) -> PathBuf {
+    match value.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(rest),
+        None => PathBuf::from(value),
+    }
+}
+
+// 2s, 500ms, 1m or a bare number of seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let (number, scale) = if let Some(n) = text.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = text.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = text.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        (text, 1.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n * scale)),
+        _ => Err(format!("Invalid duration: {} (expected e.g. 2s, 500ms, 1m)", text)),
+    }
+}
+
+// A duration, or off/none for no limit
+pub fn parse_timeout(text: &str) -> Result<Option<Duration>, String> {
+    match text.trim() {
+        "off" | "none" => Ok(None),
+        other => parse_duration(other).map(Some),
+    }
+}
@@ -0,0 +1,124 @@
+// Control socket - `rcl --daemon --socket path` answers requests on a Unix
+// socket while it runs its schedules and agents, and `rcl ctl eval '...'`
+// sends it one. Each connection carries a single call in the transport line
+// protocol (see transport.rs):
+//
+//   call 1 eval ["counter(\"jobs\").inc()"]
+//   reply 1 ok "Num(1.0)"
+//   call 1 stop []
+//   reply 1 ok unit
+use crate::config::Config;
+use crate::value::Value;
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use crate::transport::{unix_transport, Message, Transport};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::time::Duration;
+
+pub struct ControlSocket {
+    path: PathBuf,
+    #[cfg(unix)]
+    listener: UnixListener,
+}
+
+impl ControlSocket {
+    // A socket file left by a daemon that is no longer running is replaced
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> Result<ControlSocket, String> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!("A daemon is already listening on {}", path.display()));
+            }
+            let _ = std::fs::remove_file(path);
+        }
+        let listener = UnixListener::bind(path).map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(ControlSocket { path: path.to_path_buf(), listener })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(path: &Path) -> Result<ControlSocket, String> {
+        Err(format!("Control socket {} needs Unix domain sockets", path.display()))
+    }
+
+    // Answer the requests waiting on the socket, evaluating each eval with
+    // `eval`. Returns false once a request asks the daemon to stop.
+    #[cfg(unix)]
+    pub fn poll(&mut self, mut eval: impl FnMut(&str) -> Result<String, String>) -> bool {
+        while let Ok((stream, _)) = self.listener.accept() {
+            match answer(stream, &mut eval) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => eprintln!("Control request failed: {}", e),
+            }
+        }
+        true
+    }
+
+    #[cfg(not(unix))]
+    pub fn poll(&mut self, _eval: impl FnMut(&str) -> Result<String, String>) -> bool {
+        true
+    }
+}
+
+// The socket file goes with the daemon
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn answer(stream: UnixStream, eval: &mut impl FnMut(&str) -> Result<String, String>) -> Result<bool, String> {
+    // A client that connects and says nothing must not hang the daemon
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+    let mut transport = unix_transport("control", stream)?;
+    let (id, method, args) = match transport.recv()? {
+        Message::Call { id, method, args } => (id, method, args),
+        other => return Err(format!("Expected a call, got {}", other.kind())),
+    };
+    let (result, running) = match (method.as_str(), args.as_slice()) {
+        ("eval", [Value::Str(input)]) => (eval(input).map(|output| Value::Str(output.into())), true),
+        ("stop", []) => (Ok(Value::Unit), false),
+        _ => (Err(format!("Unknown control request {} (expected eval or stop)", method)), true),
+    };
+    transport.send(Message::Reply { id, result })?;
+    Ok(running)
+}
+
+// Send one request to the daemon listening at `path` and wait for its answer
+#[cfg(unix)]
+pub fn request(path: &Path, method: &str, args: Vec<Value>) -> Result<Value, String> {
+    let stream = UnixStream::connect(path).map_err(|e| format!("No daemon listening on {}: {}", path.display(), e))?;
+    let mut transport = unix_transport(&format!("unix:{}", path.display()), stream)?;
+    transport.send(Message::Call { id: 1, method: method.to_string(), args })?;
+    match transport.recv()? {
+        Message::Reply { result, .. } => result,
+        other => Err(format!("Expected a reply, got {}", other.kind())),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn request(path: &Path, _method: &str, _args: Vec<Value>) -> Result<Value, String> {
+    Err(format!("Control socket {} needs Unix domain sockets", path.display()))
+}
+
+// rcl ctl [--socket <path>] eval '<input>' | stop
+// The socket comes from --socket or `socket` in the config file, as for the daemon.
+pub fn ctl(args: &[String]) -> Result<String, String> {
+    let usage = "Usage: rcl ctl [--socket <path>] eval '<input>' | stop";
+    let at = args.iter().position(|a| a == "eval" || a == "stop").ok_or(usage)?;
+    let config = Config::from_args(&args[..at])?;
+    let path = config.socket.ok_or("No control socket: pass --socket <path> or set socket in ~/.rustailangrc")?;
+    match &args[at..] {
+        [command, input] if command == "eval" => match request(&path, "eval", vec![Value::Str(input.as_str().into())])? {
+            Value::Str(output) => Ok(output.to_string()),
+            other => Err(format!("Expected the printed result, got {:?}", other)),
+        },
+        [command] if command == "stop" => request(&path, "stop", Vec::new()).map(|_| "Stopped".to_string()),
+        _ => Err(usage.to_string()),
+    }
+}
@@ -0,0 +1,42 @@
+// LangError - an interpreter error as a std::error::Error. Inside the crate
+// errors stay the Strings the REPL prints; `?` turns one into a LangError at
+// the embedder's boundary, e.g. in a function returning Result<_, LangError>
+// or Box<dyn Error>.
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangError {
+    message: String,
+}
+
+impl LangError {
+    pub fn new(message: impl Into<String>) -> Self {
+        LangError { message: message.into() }
+    }
+
+    // The text the REPL would print after "Error: "
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for LangError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for LangError {}
+
+impl From<String> for LangError {
+    fn from(message: String) -> Self {
+        LangError { message }
+    }
+}
+
+impl From<&str> for LangError {
+    fn from(message: &str) -> Self {
+        LangError::new(message)
+    }
+}
@@ -0,0 +1,58 @@
+// Events - what happens in a session, published on one bus that features
+// following the session subscribe to, rather than each hooking the REPL
+// loop, the runtime and the executor on its own. Provenance follows it while
+// it is on; embedders subscribe through Runtime::events.
+use crate::runtime::Language;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    // An input, typed or from a module or the control socket (origin says which)
+    EvalStarted { language: Language, source: String, origin: String },
+    EvalFinished { language: Language, error: Option<String>, elapsed: Duration },
+    // A future settled by running its task; error when it was rejected
+    FutureResolved { id: u64, error: Option<String> },
+    // A `cmd` expression or a backtick line; status is None when a signal ended it
+    ShellCommandRun { command: String, status: Option<i32> },
+    // A variable bound, not a let or const inside a block
+    VarAssigned { name: String },
+}
+
+type Handler = Box<dyn FnMut(&Event)>;
+
+#[derive(Default)]
+pub struct Bus {
+    handlers: Vec<(u64, Handler)>,
+    next: u64,
+}
+
+impl Bus {
+    // Call the handler with every event from now on; returns the id that
+    // unsubscribe takes
+    pub fn subscribe(&mut self, handler: impl FnMut(&Event) + 'static) -> u64 {
+        self.next += 1;
+        self.handlers.push((self.next, Box::new(handler)));
+        self.next
+    }
+
+    // Whether the id was subscribed
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        let before = self.handlers.len();
+        self.handlers.retain(|(handler, _)| *handler != id);
+        self.handlers.len() < before
+    }
+
+    // Whether anything would see an event, so a publisher on a busy path can
+    // skip building one
+    pub fn is_followed(&self) -> bool {
+        !self.handlers.is_empty()
+    }
+
+    // Handlers see events in the order they subscribed
+    pub fn publish(&mut self, event: Event) {
+        for (_, handler) in &mut self.handlers {
+            handler(&event);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// Executor - owns every future created this session and the deferred work
+// behind it. Tasks run lazily, the first time something awaits their future.
+use crate::process;
+use crate::runtime::Expr;
+use crate::shell::Batch;
+use crate::tau::Combinator;
+use crate::value::{Function, Future, FutureState, TaskError, Value};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Deferred work behind a future
+pub enum Task {
+    Eval(Expr),
+    Combine(Combinator),  // then/catch/all/race over other futures, see tau.rs
+    Call(Rc<Function>, Vec<Value>),  // A function value applied to arguments
+    Sleep(Instant),  // Resolves to unit once the time comes, see `sleep 500ms`
+    Commands(Batch),  // Shell commands already running, see par_run in shell.rs
+    Exit(u32),  // Resolves to unit once the process exits, see wait(pid) in process.rs
+}
+
+pub struct Executor {
+    futures: Vec<Future>,        // Every future created this session, oldest first
+    tasks: HashMap<u64, Task>,   // Deferred work keyed by future id
+    scopes: Vec<Vec<Future>>,    // Futures spawned inside each open scope, innermost last
+    deadlines: HashMap<u64, (Instant, Duration)>,  // From `timeout fut 5s`: when each must settle by, and the limit
+    next_id: u64,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new()
+    }
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor { futures: Vec::new(), tasks: HashMap::new(), scopes: Vec::new(), deadlines: HashMap::new(), next_id: 1 }
+    }
+
+    // A pending future with no task behind it; something else must settle it
+    pub fn new_future(&mut self, source: &str) -> Future {
+        let future = Future::new(self.next_id, source);
+        self.register(future)
+    }
+
+    // A pending future that settles when its task is driven; `site` is the
+    // line:col of the spawn, reported in failure traces
+    pub fn spawn(&mut self, source: &str, site: &str, task: Expr) -> Future {
+        self.spawn_task(source, site, Task::Eval(task))
+    }
+
+    pub fn spawn_task(&mut self, source: &str, site: &str, task: Task) -> Future {
+        let future = self.register(Future::new(self.next_id, source).at(site));
+        self.tasks.insert(future.id, task);
+        future
+    }
+
+    // spawn inside a scope: the scope joins or cancels the task when it exits
+    pub fn spawn_scoped(&mut self, source: &str, site: &str, task: Expr) -> Future {
+        let future = self.spawn(source, site, task);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(future.clone());
+        }
+        future
+    }
+
+    pub fn open_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    // Futures spawned since the matching open_scope, in spawn order
+    pub fn close_scope(&mut self) -> Vec<Future> {
+        self.scopes.pop().unwrap_or_default()
+    }
+
+    // Drop the task, so it never runs, and mark the future cancelled if it has
+    // not settled yet. Returns whether it was still pending.
+    pub fn cancel(&mut self, future: &Future, reason: &str) -> bool {
+        self.tasks.remove(&future.id);
+        self.deadlines.remove(&future.id);
+        if !future.is_pending() {
+            return false;
+        }
+        let error = TaskError::new(&format!("Cancelled: {}", reason)).with_frame(future.frame());
+        future.set_state(FutureState::Cancelled(error));
+        true
+    }
+
+    // The future is cancelled unless it settles within `limit` from now. A task
+    // still running when the time is up is stopped at its next deadline check.
+    pub fn set_deadline(&mut self, future: &Future, limit: Duration) {
+        if future.is_pending() {
+            self.deadlines.insert(future.id, (Instant::now() + limit, limit));
+        }
+    }
+
+    pub fn deadline(&self, id: u64) -> Option<(Instant, Duration)> {
+        self.deadlines.get(&id).copied()
+    }
+
+    // Cancel every pending future whose time is up, returning how many
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let expired: Vec<(u64, Duration)> =
+            self.deadlines.iter().filter(|(_, (at, _))| now >= *at).map(|(&id, &(_, limit))| (id, limit)).collect();
+        let mut cancelled = 0;
+        for (id, limit) in expired {
+            if let Some(future) = self.futures.iter().find(|f| f.id == id).cloned()
+                && self.cancel(&future, &format!("timed out after {:?}", limit))
+            {
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+
+    // Resolve every sleep whose time has come, and every wait on a process
+    // that has exited, returning their ids. They settle this way between
+    // inputs, so a polling loop sees them finish without awaiting them.
+    pub fn wake(&mut self, now: Instant) -> Vec<u64> {
+        let due: Vec<u64> = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| match task {
+                Task::Sleep(at) => now >= *at,
+                Task::Exit(pid) => !process::alive(*pid),
+                _ => false,
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &due {
+            self.tasks.remove(id);
+            self.deadlines.remove(id);
+            if let Some(future) = self.futures.iter().find(|f| f.id == *id) {
+                future.set_state(FutureState::Resolved(Box::new(Value::Unit)));
+            }
+        }
+        due
+    }
+
+    fn register(&mut self, future: Future) -> Future {
+        self.next_id += 1;
+        self.futures.push(future.clone());
+        future
+    }
+
+    // Remove the task so it runs at most once
+    pub fn take_task(&mut self, id: u64) -> Option<Task> {
+        self.tasks.remove(&id)
+    }
+
+    // Whether awaiting the future would settle it without waiting: true once
+    // it has settled or when its task is an expression to run, false while a
+    // sleep, process or commands are still going, None for a promise that
+    // nothing has settled. A combinator is as ready as the futures it awaits.
+    pub fn is_ready(&mut self, future: &Future, now: Instant) -> Option<bool> {
+        if !future.is_pending() {
+            return Some(true);
+        }
+        let (inner, every) = match self.tasks.get_mut(&future.id)? {
+            Task::Sleep(at) => return Some(now >= *at),
+            Task::Exit(pid) => return Some(!process::alive(*pid)),
+            Task::Commands(batch) => return Some(batch.poll()),
+            Task::Eval(_) | Task::Call(..) => return Some(true),
+            Task::Combine(Combinator::Then(inner, _) | Combinator::Catch(inner, _)) => (vec![inner.clone()], true),
+            Task::Combine(Combinator::All(inner)) => (inner.clone(), true),
+            Task::Combine(Combinator::Race(inner)) => (inner.clone(), false),
+        };
+        let states: Vec<Option<bool>> = inner.iter().map(|future| self.is_ready(future, now)).collect();
+        if every {
+            return states.into_iter().try_fold(true, |ready, state| state.map(|state| ready && state));
+        }
+        states.into_iter().flatten().max()
+    }
+
+    // Whether awaiting the future would run something
+    pub fn has_task(&self, id: u64) -> bool {
+        self.tasks.contains_key(&id)
+    }
+
+    pub fn futures(&self) -> &[Future] {
+        &self.futures
+    }
+}
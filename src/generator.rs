@@ -0,0 +1,194 @@
+// Generators - functions declared with `gen` that produce values lazily.
+// Calling one binds its arguments and returns a suspended Generator; each
+// resume runs the body up to the next `yield` and hands back the value.
+//
+// The suspended state is the continuation of the body, kept as an explicit
+// stack of frames for the control forms a yield can sit inside (blocks, loops,
+// `and`/`or` guards). Everything else is evaluated in one step by the runtime,
+// so a yield nested inside an ordinary expression is an error.
+//
+// Blocks are flattened into frames, so the body has one scope for its
+// parameters and its let and const bindings, kept between resumes and
+// starting with what the generator function captured.
+use crate::runtime::{Expr, Pattern, Runtime, Scope};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+enum Frame {
+    Eval(Expr),
+    Block(VecDeque<Expr>),  // Statements still to run
+    Loop { target: Target, items: Items, body: Expr },
+    While { condition: Expr, body: Expr },
+}
+
+// What a for loop binds on each pass: for x, for k, v or for [a, b]
+enum Target {
+    Name(String),
+    Entry(String, String),
+    Pattern(Pattern),
+}
+
+// What a for loop inside a generator still has to visit
+enum Items {
+    Values(VecDeque<Value>),
+    Entries(VecDeque<(Value, Value)>),
+    Range(i64, i64),
+    Generator(Generator),
+}
+
+struct State {
+    frames: Vec<Frame>,           // Innermost last
+    scope: Scope,                 // The parameters and the body's let and const bindings
+    done: bool,
+}
+
+// Shared handle held by Value::Generator; clones advance the same generator
+#[derive(Clone)]
+pub struct Generator(Rc<RefCell<State>>);
+
+impl Generator {
+    pub fn new(scope: Scope, body: Expr) -> Self {
+        Generator(Rc::new(RefCell::new(State { frames: vec![Frame::Eval(body)], scope, done: false })))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.0.borrow().done
+    }
+
+    // Run to the next yield; None once the body has finished. The body's
+    // scope is in place while it runs, in place of the caller's scopes, then
+    // put back as they were.
+    pub fn next(&self, runtime: &mut Runtime) -> Result<Option<Value>, String> {
+        if self.is_done() {
+            return Ok(None);
+        }
+        let (mut frames, scope) = {
+            let mut state = self.0.borrow_mut();
+            (std::mem::take(&mut state.frames), std::mem::take(&mut state.scope))
+        };
+        let caller = std::mem::replace(&mut runtime.scopes, vec![scope]);
+        let result = step(&mut frames, runtime);
+        let scope = std::mem::replace(&mut runtime.scopes, caller).pop().unwrap_or_default();
+        let mut state = self.0.borrow_mut();
+        state.scope = scope;
+        state.frames = frames;
+        if !matches!(result, Ok(Some(_))) {
+            state.done = true;
+            state.frames.clear();
+        }
+        result
+    }
+}
+
+fn step(frames: &mut Vec<Frame>, runtime: &mut Runtime) -> Result<Option<Value>, String> {
+    while let Some(frame) = frames.pop() {
+        match frame {
+            Frame::Eval(expr) => match expr {
+                Expr::Yield(value) => return Ok(Some(runtime.eval(&value)?)),
+                Expr::Return(_) => return Ok(None),
+                Expr::Block(exprs) => frames.push(Frame::Block(exprs.into())),
+                Expr::And(left, right) => {
+                    let left = runtime.eval(&left)?;
+                    if runtime.truthy(&left)? {
+                        frames.push(Frame::Eval(*right));
+                    }
+                }
+                Expr::Or(left, right) => {
+                    let left = runtime.eval(&left)?;
+                    if !runtime.truthy(&left)? {
+                        frames.push(Frame::Eval(*right));
+                    }
+                }
+                Expr::While(condition, body) => frames.push(Frame::While { condition: *condition, body: *body }),
+                Expr::For(var, iterable, body) => {
+                    let items = items(runtime.eval(&iterable)?)?;
+                    frames.push(Frame::Loop { target: Target::Name(var), items, body: *body });
+                }
+                Expr::ForUnpack(pattern, iterable, body) => {
+                    let items = items(runtime.eval(&iterable)?)?;
+                    frames.push(Frame::Loop { target: Target::Pattern(pattern), items, body: *body });
+                }
+                Expr::ForEntries(var, value_var, iterable, body) => {
+                    let items = match runtime.eval(&iterable)? {
+                        Value::Map(map) => Items::Entries(map.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+                        _ => return Err("for k, v requires a map".to_string()),
+                    };
+                    frames.push(Frame::Loop { target: Target::Entry(var, value_var), items, body: *body });
+                }
+                other => {
+                    runtime.eval(&other)?;
+                }
+            },
+            Frame::Block(mut exprs) => {
+                if let Some(expr) = exprs.pop_front() {
+                    frames.push(Frame::Block(exprs));
+                    frames.push(Frame::Eval(expr));
+                }
+            }
+            Frame::Loop { target, mut items, body } => {
+                runtime.check_deadline()?;
+                let next = match &mut items {
+                    Items::Values(values) => values.pop_front().map(|v| (v, None)),
+                    Items::Entries(entries) => entries.pop_front().map(|(k, v)| (k, Some(v))),
+                    Items::Range(start, end) if *start < *end => {
+                        *start += 1;
+                        Some((Value::Num((*start - 1) as f64), None))
+                    }
+                    Items::Range(..) => None,
+                    Items::Generator(inner) => inner.next(runtime)?.map(|v| (v, None)),
+                };
+                if let Some((item, value)) = next {
+                    match (&target, value) {
+                        (Target::Entry(key_var, value_var), Some(value)) => {
+                            runtime.assign(key_var.clone(), item)?;
+                            runtime.assign(value_var.clone(), value)?;
+                        }
+                        (Target::Pattern(pattern), _) => {
+                            for (name, part) in pattern.unpack(item)? {
+                                runtime.assign(name, part)?;
+                            }
+                        }
+                        (Target::Name(var) | Target::Entry(var, _), _) => runtime.assign(var.clone(), item)?,
+                    }
+                    frames.push(Frame::Loop { target, items, body: body.clone() });
+                    frames.push(Frame::Eval(body));
+                }
+            }
+            Frame::While { condition, body } => {
+                runtime.check_deadline()?;
+                let holds = runtime.eval(&condition)?;
+                if runtime.truthy(&holds)? {
+                    frames.push(Frame::While { condition, body: body.clone() });
+                    frames.push(Frame::Eval(body));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn items(iterable: Value) -> Result<Items, String> {
+    Ok(match iterable {
+        Value::Array(values) => Items::Values(values.into_vec().into()),
+        Value::Range(range) => Items::Range(range.start, range.end),
+        Value::Map(map) => Items::Values(map.keys().cloned().collect()),
+        Value::Generator(generator) => Items::Generator(generator),
+        _ => return Err("For loop requires an array, range, map or generator".to_string()),
+    })
+}
+
+impl PartialEq for Generator {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Generator(suspended) or Generator(done)
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.is_done() { "done" } else { "suspended" })
+    }
+}
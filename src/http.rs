@@ -0,0 +1,209 @@
+// HTTP client - enough HTTP/1.1 for fetch. http:// URLs are spoken directly
+// over a TCP socket; https:// is handed to curl, since TLS is beyond std.
+//
+// For APIs, http_json(method, url, body, headers) sends the body as JSON and
+// gives the reply parsed, failing on statuses outside 2xx with the server's
+// own message; graphql(url, query, vars) posts a query and gives its data,
+// failing with the messages of any errors the server reports.
+use crate::json::{parse_json, to_json};
+use crate::value::{Map, Value};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,  // Names lowercased, in the order received
+    pub body: String,
+}
+
+impl Response {
+    // [{"status", 200}, {"headers", [{"content-type", "text/html"}, ...]}, {"body", "..."}]
+    pub fn to_value(&self) -> Result<Value, String> {
+        let mut headers = Map::new();
+        for (name, value) in &self.headers {
+            headers.insert(Value::Str(name.as_str().into()), Value::Str(value.as_str().into()))?;
+        }
+        let mut map = Map::new();
+        map.insert(Value::Str("status".into()), Value::Num(self.status as f64))?;
+        map.insert(Value::Str("headers".into()), Value::Map(headers))?;
+        map.insert(Value::Str("body".into()), Value::Str(self.body.as_str().into()))?;
+        Ok(Value::Map(map))
+    }
+}
+
+pub fn get(url: &str) -> Result<Response, String> {
+    request("GET", url, &[], None)
+}
+
+// Send a JSON body (none for unit) and parse the JSON reply; an empty reply is unit
+pub fn json_request(method: &str, url: &str, body: &Value, headers: &[(String, String)]) -> Result<Value, String> {
+    let method = method.to_ascii_uppercase();
+    if method.is_empty() || !method.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Err(format!("Invalid HTTP method {:?}", method));
+    }
+    let mut all = vec![("Accept".to_string(), "application/json".to_string())];
+    let body = match body {
+        Value::Unit => None,
+        body => {
+            all.push(("Content-Type".to_string(), "application/json".to_string()));
+            Some(to_json(body)?)
+        }
+    };
+    all.extend(headers.iter().cloned());
+    let response = request(&method, url, &all, body.as_deref())?;
+    if !(200..300).contains(&response.status) {
+        return Err(format!("{} {} failed with status {}: {}", method, url, response.status, error_message(&response.body)));
+    }
+    if response.body.trim().is_empty() {
+        return Ok(Value::Unit);
+    }
+    parse_json(&response.body).map_err(|e| format!("{} {} did not reply with JSON: {}", method, url, e))
+}
+
+// Post a GraphQL query and give its data. Servers report failed queries in
+// "errors", usually with a 200, so those are errors here too.
+pub fn graphql(url: &str, query: &str, variables: &Value, headers: &[(String, String)]) -> Result<Value, String> {
+    let body = Map::from_pairs(vec![
+        (Value::Str("query".into()), Value::Str(query.into())),
+        (Value::Str("variables".into()), variables.clone()),
+    ])?;
+    let Value::Map(reply) = json_request("POST", url, &Value::Map(body), headers)? else {
+        return Err(format!("GraphQL reply from {} is not an object", url));
+    };
+    if let Some(Value::Array(errors)) = reply.get(&Value::Str("errors".into()))
+        && !errors.is_empty()
+    {
+        let messages: Vec<String> = errors.iter().map(|e| field(e, "message").unwrap_or_else(|| e.to_string())).collect();
+        return Err(format!("GraphQL error: {}", messages.join("; ")));
+    }
+    Ok(reply.get(&Value::Str("data".into())).cloned().unwrap_or(Value::Unit))
+}
+
+// What an API said went wrong: the "message" or "error" of a JSON body (or
+// error.message), else the start of the body as text
+fn error_message(body: &str) -> String {
+    if let Ok(Value::Map(reply)) = parse_json(body) {
+        let text = |value: Option<&Value>| match value {
+            Some(Value::Str(text)) => Some(text.to_string()),
+            Some(error @ Value::Map(_)) => field(error, "message"),
+            _ => None,
+        };
+        if let Some(message) = text(reply.get(&Value::Str("message".into()))).or_else(|| text(reply.get(&Value::Str("error".into())))) {
+            return message;
+        }
+    }
+    let body = body.trim();
+    match body.char_indices().nth(200) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None if body.is_empty() => "(empty body)".to_string(),
+        None => body.to_string(),
+    }
+}
+
+// A string field of a JSON object
+fn field(value: &Value, name: &str) -> Option<String> {
+    match value {
+        Value::Map(map) => match map.get(&Value::Str(name.into())) {
+            Some(Value::Str(text)) => Some(text.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn request(method: &str, url: &str, headers: &[(String, String)], body: Option<&str>) -> Result<Response, String> {
+    if url.starts_with("https://") {
+        return curl(method, url, headers, body);
+    }
+    let rest = url.strip_prefix("http://").ok_or_else(|| format!("Unsupported URL {} (expected http:// or https://)", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+    let mut stream = TcpStream::connect(&address).map_err(|e| format!("Failed to connect to {}: {}", authority, e))?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    let mut message = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nUser-Agent: rust_ai_lang\r\n", method, path, authority);
+    for (name, value) in headers {
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some(body) = body {
+        message.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    message.push_str("\r\n");
+    message.push_str(body.unwrap_or_default());
+    stream.write_all(message.as_bytes()).map_err(|e| format!("Failed to send request to {}: {}", authority, e))?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).map_err(|e| format!("Failed to read response from {}: {}", authority, e))?;
+    parse_response(&raw)
+}
+
+// curl -i prints the response head and body just as they arrived on the wire
+fn curl(method: &str, url: &str, headers: &[(String, String)], body: Option<&str>) -> Result<Response, String> {
+    let mut command = Command::new("curl");
+    command.args(["-sS", "-i", "--raw", "--max-time", &TIMEOUT.as_secs().to_string(), "-X", method, url]);
+    for (name, value) in headers {
+        command.args(["-H", &format!("{}: {}", name, value)]);
+    }
+    if let Some(body) = body {
+        command.args(["--data-binary", body]);
+    }
+    let output = command.output().map_err(|e| format!("https needs curl on the PATH: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Request to {} failed: {}", url, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    parse_response(&output.stdout)
+}
+
+pub fn parse_response(raw: &[u8]) -> Result<Response, String> {
+    let malformed = || "Malformed HTTP response".to_string();
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(malformed)?;
+    let head = String::from_utf8_lossy(&raw[..end]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(malformed)?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    let mut body = &raw[end + 4..];
+    let dechunked;
+    if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+        dechunked = dechunk(body)?;
+        body = &dechunked;
+    } else if let Some(length) = header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+        body = &body[..length.min(body.len())];
+    }
+    // An interim 100 Continue comes before the real response
+    if status == 100 {
+        return parse_response(body);
+    }
+    Ok(Response { status, headers, body: String::from_utf8_lossy(body).into_owned() })
+}
+
+// Transfer-Encoding: chunked - hex size line, data, CRLF, ending with size 0
+fn dechunk(mut input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = input.windows(2).position(|w| w == b"\r\n").ok_or("Malformed chunked body")?;
+        let size_text = String::from_utf8_lossy(&input[..line_end]);
+        let size_text = size_text.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_text, 16).map_err(|_| format!("Bad chunk size {:?}", size_text))?;
+        input = &input[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        let chunk = input.get(..size).ok_or("Truncated chunked body")?;
+        out.extend_from_slice(chunk);
+        input = input.get(size + 2..).unwrap_or_default();
+    }
+}
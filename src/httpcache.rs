@@ -0,0 +1,96 @@
+// Cached, rate-limited GETs for exploratory sessions. http_get_cached(url, ttl)
+// answers from .http-cache in the workspace while the stored response is
+// younger than ttl, and otherwise fetches and stores it, so running a script
+// again is fast and leaves the API alone. When the fetch fails and an older
+// copy is on disk, that copy is given instead, so work carries on offline.
+// Only 2xx responses are stored.
+//
+// `http-rate = 2/s` (or n/m) in the config spaces fetches to the same host;
+// one asked for too soon sleeps until the host's next slot. `http-cache = dir`
+// keeps the cache somewhere other than the workspace.
+use crate::http::{self, Response};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const DIR: &str = ".http-cache";
+
+// When each host was last given a slot
+#[derive(Debug, Default)]
+pub struct Limiter {
+    last: HashMap<String, Instant>,
+}
+
+impl Limiter {
+    // How long a request to the host made at `now` must wait; the slot is
+    // taken, so requests queue up one interval apart
+    pub fn reserve(&mut self, host: &str, interval: Duration, now: Instant) -> Duration {
+        let slot = self.last.get(host).map_or(now, |last| (*last + interval).max(now));
+        self.last.insert(host.to_string(), slot);
+        slot - now
+    }
+}
+
+// The host (and port) of a URL, lowercased
+pub fn host(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    authority.to_ascii_lowercase()
+}
+
+// "5/s" or "30/m" as the gap between requests; "off" for no limit
+pub fn parse_rate(text: &str) -> Result<Option<Duration>, String> {
+    let text = text.trim();
+    if text == "off" || text == "none" {
+        return Ok(None);
+    }
+    let invalid = || format!("Invalid rate: {} (expected e.g. 5/s, 30/m or off)", text);
+    let (count, per) = text.split_once('/').ok_or_else(invalid)?;
+    let per = match per.trim() {
+        "s" => 1.0,
+        "m" => 60.0,
+        _ => return Err(invalid()),
+    };
+    match count.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(Some(Duration::from_secs_f64(per / n))),
+        _ => Err(invalid()),
+    }
+}
+
+// The stored response for a URL and how long ago it was fetched
+pub fn load(dir: &Path, url: &str) -> Option<(Response, Duration)> {
+    let path = entry(dir, url);
+    let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().unwrap_or_default();
+    let raw = fs::read(&path).ok()?;
+    Some((http::parse_response(&raw).ok()?, age))
+}
+
+// Keep a response the way it came off the wire, less the framing headers,
+// which no longer describe the body once it has been decoded
+pub fn store(dir: &Path, url: &str, response: &Response) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let mut raw = format!("HTTP/1.1 {}\r\n", response.status);
+    for (name, value) in &response.headers {
+        if name != "transfer-encoding" && name != "content-length" {
+            raw.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    raw.push_str(&format!("content-length: {}\r\n\r\n", response.body.len()));
+    raw.push_str(&response.body);
+    // Written aside and renamed, so a reader never sees half an entry
+    let path = entry(dir, url);
+    let partial = path.with_extension("part");
+    fs::write(&partial, raw)
+        .and_then(|_| fs::rename(&partial, &path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+// One file per URL, named by its FNV-1a hash so the URL (which may hold a
+// token) is not written out
+fn entry(dir: &Path, url: &str) -> PathBuf {
+    let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    dir.join(format!("{:016x}.http", hash))
+}
@@ -0,0 +1,97 @@
+// Lines of input from stdin and script files, read a buffer at a time so a
+// huge piped input is never held whole. Bytes that are not UTF-8 are replaced
+// with U+FFFD, and a line longer than the limit is skipped without being
+// kept, each with a warning, so neither ends the session.
+use std::io::{self, BufRead, ErrorKind};
+
+// Longest line accepted unless `max-line` says otherwise
+pub const MAX_LINE: usize = 1024 * 1024;
+
+// One line, without its \n or \r\n. A skipped line is empty, so the lines
+// after it keep their numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub text: String,
+    pub warning: Option<String>,
+}
+
+pub struct Lines<R> {
+    reader: R,
+    max: usize,
+    number: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+    pub fn new(reader: R, max: usize) -> Self {
+        Lines { reader, max, number: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for Lines<R> {
+    type Item = io::Result<Line>;
+
+    fn next(&mut self) -> Option<io::Result<Line>> {
+        let mut bytes = Vec::new();
+        let mut length = 0;  // The whole line's, kept or not
+        let mut read = false;
+        loop {
+            let buffer = match self.reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            if buffer.is_empty() {
+                if !read {
+                    return None;
+                }
+                break;
+            }
+            read = true;
+            let end = buffer.iter().position(|&b| b == b'\n');
+            let chunk = &buffer[..end.unwrap_or(buffer.len())];
+            length += chunk.len();
+            if length <= self.max {
+                bytes.extend_from_slice(chunk);
+            } else {
+                bytes = Vec::new();
+            }
+            let used = end.map_or(chunk.len(), |end| end + 1);
+            self.reader.consume(used);
+            if end.is_some() {
+                break;
+            }
+        }
+        self.number += 1;
+        if length > self.max {
+            let warning = format!("line {} is {} bytes, over the max-line limit of {}; skipped", self.number, length, self.max);
+            return Some(Ok(Line { text: String::new(), warning: Some(warning) }));
+        }
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+        Some(Ok(match String::from_utf8(bytes) {
+            Ok(text) => Line { text, warning: None },
+            Err(e) => Line {
+                text: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                warning: Some(format!("line {} is not valid UTF-8; its invalid bytes were replaced with U+FFFD", self.number)),
+            },
+        }))
+    }
+}
+
+// 4096, 64k or 1m bytes
+pub fn parse_size(text: &str) -> Result<usize, String> {
+    let text = text.trim();
+    let lower = text.to_ascii_lowercase();
+    let (number, scale) = if let Some(n) = lower.strip_suffix('k') {
+        (n, 1024)
+    } else if let Some(n) = lower.strip_suffix('m') {
+        (n, 1024 * 1024)
+    } else {
+        (lower.as_str(), 1)
+    };
+    match number.trim().parse::<usize>().ok().and_then(|n| n.checked_mul(scale)) {
+        Some(size) if size > 0 => Ok(size),
+        _ => Err(format!("Invalid size: {} (expected e.g. 4096, 64k, 1m)", text)),
+    }
+}
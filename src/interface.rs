@@ -0,0 +1,335 @@
+// Tau interface files (.tsu)
+// One interface per file, declaring the methods a proxy can call and the
+// events an agent can raise:
+//
+//   # Comments run to end of line
+//   interface Calculator {
+//       add(a: num, b: num) -> num
+//       history() -> [num]
+//       reset()
+//       event overflow(value: num)
+//   }
+//
+// Display prints the canonical form, which parses back to an equal Interface.
+// The parsed Interface is the one schema everything shares: mocks and proxies
+// check calls against it, served agents check what they return, and codegen
+// writes typed declarations from it.
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::value::Value;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Num,
+    Str,
+    Bool,
+    Color,
+    Map,
+    Any,
+    Unit,
+    Array(Box<Type>),  // [T]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub returns: Type,  // Unit when no -> is given
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub name: String,
+    pub params: Vec<Param>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    pub methods: Vec<Method>,
+    pub events: Vec<Event>,
+}
+
+impl Interface {
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+
+    pub fn event(&self, name: &str) -> Option<&Event> {
+        self.events.iter().find(|e| e.name == name)
+    }
+
+    // The signature of `method`, once `args` are checked against it
+    pub fn check_call(&self, method: &str, args: &[Value]) -> Result<&Method, String> {
+        let signature = self
+            .method(method)
+            .ok_or_else(|| format!("{} has no method {}", self.name, method))?;
+        if args.len() != signature.params.len() {
+            return Err(format!(
+                "{}.{} expects {} arguments, got {}",
+                self.name, method, signature.params.len(), args.len()
+            ));
+        }
+        for (param, arg) in signature.params.iter().zip(args) {
+            if !param.ty.accepts(arg) {
+                return Err(format!("{}.{}: {} must be {}, got {:?}", self.name, method, param.name, param.ty, arg));
+            }
+        }
+        Ok(signature)
+    }
+
+    // What a call to `method` gives back, once checked against the declared
+    // return type. Methods without one give unit whatever was returned.
+    pub fn check_return(&self, method: &str, value: Value) -> Result<Value, String> {
+        let signature = self
+            .method(method)
+            .ok_or_else(|| format!("{} has no method {}", self.name, method))?;
+        match &signature.returns {
+            Type::Unit => Ok(Value::Unit),
+            returns if returns.accepts(&value) => Ok(value),
+            returns => Err(format!("{}.{} must return {}, got {:?}", self.name, method, returns, value)),
+        }
+    }
+}
+
+impl Type {
+    // Whether a runtime value fits this type
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Any, _) => true,
+            (Type::Num, Value::Num(_)) => true,
+            (Type::Str, Value::Str(_)) => true,
+            (Type::Bool, Value::Bool(_)) => true,
+            (Type::Color, Value::Color(_)) => true,
+            (Type::Map, Value::Map(_)) => true,
+            (Type::Unit, Value::Unit) => true,
+            (Type::Array(item), Value::Array(values)) => values.iter().all(|v| item.accepts(v)),
+            _ => false,
+        }
+    }
+
+    fn named(name: &str) -> Option<Type> {
+        Some(match name {
+            "num" => Type::Num,
+            "str" => Type::Str,
+            "bool" => Type::Bool,
+            "color" => Type::Color,
+            "map" => Type::Map,
+            "any" => Type::Any,
+            "unit" => Type::Unit,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Num => write!(f, "num"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Color => write!(f, "color"),
+            Type::Map => write!(f, "map"),
+            Type::Any => write!(f, "any"),
+            Type::Unit => write!(f, "unit"),
+            Type::Array(item) => write!(f, "[{}]", item),
+        }
+    }
+}
+
+fn write_params(f: &mut fmt::Formatter, params: &[Param]) -> fmt::Result {
+    let params: Vec<String> = params.iter().map(|p| format!("{}: {}", p.name, p.ty)).collect();
+    write!(f, "({})", params.join(", "))
+}
+
+impl fmt::Display for Interface {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "interface {} {{", self.name)?;
+        for method in &self.methods {
+            write!(f, "    {}", method.name)?;
+            write_params(f, &method.params)?;
+            if method.returns != Type::Unit {
+                write!(f, " -> {}", method.returns)?;
+            }
+            writeln!(f)?;
+        }
+        for event in &self.events {
+            write!(f, "    event {}", event.name)?;
+            write_params(f, &event.params)?;
+            writeln!(f)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+pub fn parse_interface(src: &str) -> Result<Interface, String> {
+    let tokens = tokenize(src)?;
+    let mut parser = InterfaceParser { tokens: &tokens, pos: 0 };
+    parser.parse()
+}
+
+struct InterfaceParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl InterfaceParser<'_> {
+    fn parse(&mut self) -> Result<Interface, String> {
+        self.skip_newlines();
+        self.keyword("interface")?;
+        let name = self.ident("interface name")?;
+        self.skip_newlines();
+        self.expect(&TokenKind::LBrace)?;
+
+        let mut interface = Interface { name, methods: Vec::new(), events: Vec::new() };
+        loop {
+            self.skip_newlines();
+            if self.eat(&TokenKind::RBrace) {
+                break;
+            }
+            let start = self.pos;
+            let is_event = matches!(self.peek(), Some(TokenKind::Ident(kw)) if kw == "event")
+                && matches!(self.tokens.get(self.pos + 1).map(|t| &t.kind), Some(TokenKind::Ident(_)));
+            if is_event {
+                self.pos += 1;
+            }
+            let name = self.ident("method, event or }")?;
+            if interface.method(&name).is_some() || interface.event(&name).is_some() {
+                return Err(self.error_at(start, &format!("Duplicate member '{}'", name)));
+            }
+            let params = self.params()?;
+            if is_event {
+                interface.events.push(Event { name, params });
+            } else {
+                let returns = if self.eat(&TokenKind::Minus) {
+                    self.expect(&TokenKind::Greater)?;
+                    self.parse_type()?
+                } else {
+                    Type::Unit
+                };
+                interface.methods.push(Method { name, params, returns });
+            }
+            match self.peek() {
+                None | Some(TokenKind::Newline) | Some(TokenKind::Semicolon) | Some(TokenKind::RBrace) => {
+                    self.eat(&TokenKind::Semicolon);
+                }
+                _ => return Err(self.expected("end of line")),
+            }
+        }
+
+        self.skip_newlines();
+        if self.peek().is_some() {
+            return Err(self.error_at(self.pos, "Only one interface is allowed per file"));
+        }
+        Ok(interface)
+    }
+
+    // (name: type, ...)
+    fn params(&mut self) -> Result<Vec<Param>, String> {
+        self.expect(&TokenKind::LParen)?;
+        let mut params: Vec<Param> = Vec::new();
+        if self.eat(&TokenKind::RParen) {
+            return Ok(params);
+        }
+        loop {
+            let start = self.pos;
+            let name = self.ident("parameter name")?;
+            if params.iter().any(|p| p.name == name) {
+                return Err(self.error_at(start, &format!("Duplicate parameter '{}'", name)));
+            }
+            self.expect(&TokenKind::Colon)?;
+            let type_start = self.pos;
+            let ty = self.parse_type()?;
+            if ty == Type::Unit {
+                return Err(self.error_at(type_start, &format!("Parameter '{}' cannot be unit", name)));
+            }
+            params.push(Param { name, ty });
+            if self.eat(&TokenKind::RParen) {
+                return Ok(params);
+            }
+            self.expect(&TokenKind::Comma)?;
+        }
+    }
+
+    // A type name or [T]
+    fn parse_type(&mut self) -> Result<Type, String> {
+        if self.eat(&TokenKind::LBracket) {
+            let item = self.parse_type()?;
+            self.expect(&TokenKind::RBracket)?;
+            return Ok(Type::Array(Box::new(item)));
+        }
+        let start = self.pos;
+        let name = self.ident("a type")?;
+        Type::named(&name).ok_or_else(|| {
+            self.error_at(start, &format!("Unknown type '{}' (expected num, str, bool, color, map, any, unit or [T])", name))
+        })
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) if name == keyword => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.expected(keyword)),
+        }
+    }
+
+    fn ident(&mut self, what: &str) -> Result<String, String> {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), String> {
+        if self.eat(kind) {
+            Ok(())
+        } else {
+            Err(self.expected(&kind.to_string()))
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek(), Some(TokenKind::Newline) | Some(TokenKind::Indent(_))) {
+            self.pos += 1;
+        }
+    }
+
+    fn expected(&self, what: &str) -> String {
+        match self.tokens.get(self.pos) {
+            Some(token) => format!("Expected {} at {}:{}, found {}", what, token.line, token.col, token.kind),
+            None => format!("Expected {}, found end of input", what),
+        }
+    }
+
+    fn error_at(&self, pos: usize, message: &str) -> String {
+        match self.tokens.get(pos) {
+            Some(token) => format!("{} at {}:{}", message, token.line, token.col),
+            None => format!("{} at end of input", message),
+        }
+    }
+}
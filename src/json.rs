@@ -0,0 +1,383 @@
+// JSON text for values, for tools that read what the REPL prints and for
+// the to_json/parse_json builtins. Unit and none are null, maps are objects keyed by
+// the key's display form, colors are {"r", "g", "b"} objects (plus "a" when
+// not opaque), ranges {"start", "end"} with the end exclusive and structs
+// objects of their fields. Parsing gives plain values back:
+// objects become maps (in document order), numbers Num and null unit.
+use crate::value::{Map, Value};
+
+// How deep arrays and objects may nest when parsing
+const MAX_DEPTH: usize = 256;
+
+pub fn to_json(value: &Value) -> Result<String, String> {
+    Ok(match value {
+        Value::Num(n) if n.is_finite() => n.to_string(),
+        Value::Num(n) => return Err(format!("Cannot represent {} in JSON", n)),
+        Value::Str(s) => quote(s),
+        Value::Bool(b) => b.to_string(),
+        Value::Unit | Value::None => "null".to_string(),
+        Value::Color(c) if c.a == 255 => format!("{{\"r\":{},\"g\":{},\"b\":{}}}", c.r, c.g, c.b),
+        Value::Color(c) => format!("{{\"r\":{},\"g\":{},\"b\":{},\"a\":{}}}", c.r, c.g, c.b, c.a),
+        Value::Range(r) => format!("{{\"start\":{},\"end\":{}}}", r.start, r.end),
+        Value::Array(items) => {
+            let items: Result<Vec<String>, String> = items.iter().map(to_json).collect();
+            format!("[{}]", items?.join(","))
+        }
+        Value::Map(map) => {
+            let mut pairs = Vec::with_capacity(map.len());
+            for (key, value) in map.iter() {
+                let key = match key {
+                    Value::Str(s) => quote(s),
+                    other => quote(&other.to_string()),
+                };
+                pairs.push(format!("{}:{}", key, to_json(value)?));
+            }
+            format!("{{{}}}", pairs.join(","))
+        }
+        Value::Struct(record) => {
+            let fields: Result<Vec<String>, String> =
+                record.fields.iter().map(|(name, value)| Ok(format!("{}:{}", quote(name), to_json(value)?))).collect();
+            format!("{{{}}}", fields?.join(","))
+        }
+        other => return Err(format!("Cannot convert {:?} to JSON", other)),
+    })
+}
+
+// A JSON string literal
+pub fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn parse_json(text: &str) -> Result<Value, String> {
+    let mut parser = JsonParser { text, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error("end of input"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,  // Byte offset
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c @ (' ' | '\t' | '\n' | '\r')) = self.peek() {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    // Invalid JSON at 2:5: expected a value
+    fn error(&self, expected: &str) -> String {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        format!("Invalid JSON at {}:{}: expected {}", line, col, expected)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("'{}'", c)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        self.skip_whitespace();
+        if depth > MAX_DEPTH {
+            return Err(format!("JSON nested deeper than {}", MAX_DEPTH));
+        }
+        match self.peek() {
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => Ok(Value::Str(self.string()?.into())),
+            Some('-' | '0'..='9') => self.number(),
+            _ => {
+                for (word, value) in [("true", Value::Bool(true)), ("false", Value::Bool(false)), ("null", Value::Unit)] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("a value"))
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, String> {
+        self.pos += 1;
+        let mut map = Map::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("a string key"));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            let value = self.value(depth + 1)?;
+            map.insert(Value::Str(key.into()), value)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Map(map));
+                }
+                _ => return Err(self.error("',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Value::Array(items.into()));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items.into()));
+                }
+                _ => return Err(self.error("',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let len = rest.find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')).unwrap_or(rest.len());
+        match rest[..len].parse::<f64>() {
+            Ok(n) if n.is_finite() && !rest.starts_with('+') => {
+                self.pos += len;
+                Ok(Value::Num(n))
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("'\"'"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("an escape"))?;
+                    self.pos += escape.len_utf8();
+                    match escape {
+                        '"' | '\\' | '/' => out.push(escape),
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        'r' => out.push('\r'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => out.push(self.unicode_escape()?),
+                        _ => {
+                            self.pos -= escape.len_utf8();
+                            return Err(self.error("an escape"));
+                        }
+                    }
+                }
+                c if (c as u32) < 0x20 => {
+                    self.pos -= 1;
+                    return Err(self.error("an escaped control character"));
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    // The XXXX of \uXXXX, joining a surrogate pair when one follows
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("a valid \\u escape"));
+        }
+        if !self.text[self.pos..].starts_with("\\u") {
+            return Err(self.error("a low surrogate"));
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error("a low surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).ok_or_else(|| self.error("a valid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()));
+        let code = digits.and_then(|d| u32::from_str_radix(d, 16).ok()).ok_or_else(|| self.error("4 hex digits"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+// serde support behind the `serde` feature, mapping values the same way
+#[cfg(feature = "serde")]
+mod serde_support {
+    use crate::value::{Map, Value};
+    use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+    use serde::ser::{Error as _, Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer};
+    use std::fmt;
+
+    impl Serialize for Value {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Value::Num(n) => serializer.serialize_f64(*n),
+                Value::Str(s) => serializer.serialize_str(s),
+                Value::Bool(b) => serializer.serialize_bool(*b),
+                Value::Unit | Value::None => serializer.serialize_unit(),
+                Value::Color(c) => {
+                    let opaque = c.a == 255;
+                    let mut color = serializer.serialize_struct("Color", if opaque { 3 } else { 4 })?;
+                    color.serialize_field("r", &c.r)?;
+                    color.serialize_field("g", &c.g)?;
+                    color.serialize_field("b", &c.b)?;
+                    if !opaque {
+                        color.serialize_field("a", &c.a)?;
+                    }
+                    color.end()
+                }
+                Value::Range(r) => {
+                    let mut range = serializer.serialize_struct("Range", 2)?;
+                    range.serialize_field("start", &r.start)?;
+                    range.serialize_field("end", &r.end)?;
+                    range.end()
+                }
+                Value::Array(items) => {
+                    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                    for item in items {
+                        seq.serialize_element(item)?;
+                    }
+                    seq.end()
+                }
+                Value::Map(map) => {
+                    let mut entries = serializer.serialize_map(Some(map.len()))?;
+                    for (key, value) in map.iter() {
+                        match key {
+                            Value::Str(key) => entries.serialize_entry(&**key, value)?,
+                            other => entries.serialize_entry(&other.to_string(), value)?,
+                        }
+                    }
+                    entries.end()
+                }
+                Value::Struct(record) => {
+                    let mut fields = serializer.serialize_map(Some(record.fields.len()))?;
+                    for (name, value) in record.fields.iter() {
+                        fields.serialize_entry(name, value)?;
+                    }
+                    fields.end()
+                }
+                other => Err(S::Error::custom(format!("Cannot serialize {:?}", other))),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Value {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+            deserializer.deserialize_any(ValueVisitor)
+        }
+    }
+
+    struct ValueVisitor;
+
+    impl<'de> Visitor<'de> for ValueVisitor {
+        type Value = Value;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a number, string, bool, null, array or map")
+        }
+
+        fn visit_bool<E: de::Error>(self, b: bool) -> Result<Value, E> {
+            Ok(Value::Bool(b))
+        }
+
+        fn visit_i64<E: de::Error>(self, n: i64) -> Result<Value, E> {
+            Ok(Value::Num(n as f64))
+        }
+
+        fn visit_u64<E: de::Error>(self, n: u64) -> Result<Value, E> {
+            Ok(Value::Num(n as f64))
+        }
+
+        fn visit_f64<E: de::Error>(self, n: f64) -> Result<Value, E> {
+            Ok(Value::Num(n))
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<Value, E> {
+            Ok(Value::Str(s.into()))
+        }
+
+        fn visit_string<E: de::Error>(self, s: String) -> Result<Value, E> {
+            Ok(Value::Str(s.into()))
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+            Ok(Value::Unit)
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+            Ok(Value::Unit)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+            Value::deserialize(deserializer)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+            let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(item) = seq.next_element()? {
+                items.push(item);
+            }
+            Ok(Value::Array(items.into()))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut entries: A) -> Result<Value, A::Error> {
+            let mut map = Map::new();
+            while let Some((key, value)) = entries.next_entry::<Value, Value>()? {
+                map.insert(key, value).map_err(de::Error::custom)?;
+            }
+            Ok(Value::Map(map))
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// The OS credential store, so scripts calling http_get or ssh keep tokens out
+// of files. cred_get(service) gives what is stored for the service as a secret
+// (see secret.rs) and cred_set(service, secret) stores one, replacing any
+// there before.
+//
+// Linux and the BSDs go through secret-tool, which talks to whichever keyring
+// the desktop runs (GNOME Keyring, KWallet); macOS through security and the
+// login keychain. `keyring = path` in the config picks a different program
+// taking the same arguments. Built with the keyring feature.
+use crate::secret::Secret;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[cfg(target_os = "macos")]
+const TOOL: &str = "security";
+#[cfg(not(target_os = "macos"))]
+const TOOL: &str = "secret-tool";
+
+pub fn get(tool: Option<&Path>, service: &str) -> Result<Secret, String> {
+    check_service(service)?;
+    let program = tool.unwrap_or(Path::new(TOOL));
+    let mut command = Command::new(program);
+    if cfg!(target_os = "macos") {
+        command.args(["find-generic-password", "-s", service, "-w"]);
+    } else {
+        command.args(["lookup", "service", service]);
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Keyring unavailable ({}): {}", program.display(), e))?;
+    // Both tools exit non-zero, with nothing on stdout, when nothing is stored
+    let text = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || text.is_empty() {
+        return Err(format!("No credential stored for {}", service));
+    }
+    Ok(Secret::new(text.trim_end_matches(['\r', '\n'])))
+}
+
+pub fn set(tool: Option<&Path>, service: &str, secret: &str) -> Result<(), String> {
+    check_service(service)?;
+    let program = tool.unwrap_or(Path::new(TOOL));
+    let mut command = Command::new(program);
+    if cfg!(target_os = "macos") {
+        // security only takes the password as an argument, so it is briefly
+        // visible to ps on the same machine
+        let account = std::env::var("USER").unwrap_or_default();
+        command.args(["add-generic-password", "-U", "-s", service, "-a", &account, "-w", secret]);
+    } else {
+        // secret-tool reads the secret from stdin, keeping it off the command line
+        command.args(["store", &format!("--label={} (rust_ai_lang)", service), "service", service]);
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Keyring unavailable ({}): {}", program.display(), e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(secret.as_bytes());
+    }
+    let output = child.wait_with_output().map_err(|e| format!("Keyring failed: {}", e))?;
+    if !output.status.success() {
+        let stderr = Secret::new(secret).redact(&String::from_utf8_lossy(&output.stderr));
+        return Err(format!("Failed to store credential for {}: {}", service, stderr.trim()));
+    }
+    Ok(())
+}
+
+fn check_service(service: &str) -> Result<(), String> {
+    if service.trim().is_empty() {
+        return Err("Credential service names cannot be empty".to_string());
+    }
+    Ok(())
+}
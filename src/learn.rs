@@ -0,0 +1,203 @@
+// Guided exercises at the REPL. `:learn pi` walks through Pi one idea at a
+// time: each exercise explains a word or two and asks for a line leaving a
+// given stack. The answer runs on an empty stack of its own, so the session's
+// stack is left alone, and is checked against what the exercise's solution
+// leaves. A wrong answer is told what went wrong and given the hint.
+use crate::lexer::{tokenize, TokenKind};
+use crate::pi::{self, format_stack};
+use crate::runtime::Runtime;
+use crate::value::Value;
+
+pub struct Koan {
+    pub title: &'static str,
+    pub lesson: &'static str,
+    pub task: &'static str,
+    pub solution: &'static str,          // Pi leaving the stack to aim for
+    pub uses: &'static [&'static str],   // Words the answer must use
+    pub hint: &'static str,
+}
+
+pub const PI: &[Koan] = &[
+    Koan {
+        title: "The stack",
+        lesson: "Pi keeps values on a stack. Each number you type is pushed on top.",
+        task: "Push 1, 2 and 3.",
+        solution: "1 2 3",
+        uses: &[],
+        hint: "Type the numbers separated by spaces: 1 2 3",
+    },
+    Koan {
+        title: "Operators",
+        lesson: "An operator takes its operands off the stack and pushes the result.",
+        task: "Leave 7 by adding 3 and 4.",
+        solution: "3 4 +",
+        uses: &["+"],
+        hint: "Push both numbers, then the operator: 3 4 +",
+    },
+    Koan {
+        title: "Order matters",
+        lesson: "The value pushed first is the left operand, so 10 4 - is 10 - 4.",
+        task: "Leave 6 by subtracting 4 from 10.",
+        solution: "10 4 -",
+        uses: &["-"],
+        hint: "Push 10 first, then 4, then -",
+    },
+    Koan {
+        title: "No parentheses",
+        lesson: "Each operator works on what is already on the stack, so order replaces parentheses.",
+        task: "Compute (2 + 3) * 4.",
+        solution: "2 3 + 4 *",
+        uses: &["+", "*"],
+        hint: "Add first, then push 4 and multiply: 2 3 + 4 *",
+    },
+    Koan {
+        title: "dup",
+        lesson: "dup pushes a copy of the top value: a -- a a",
+        task: "Leave 81 by multiplying 9 by itself, typing 9 only once.",
+        solution: "9 dup *",
+        uses: &["dup", "*"],
+        hint: "9 dup leaves 9 9 on the stack",
+    },
+    Koan {
+        title: "swap",
+        lesson: "swap exchanges the top two values: a b -- b a",
+        task: "Push 1 and 2, then leave them the other way round.",
+        solution: "1 2 swap",
+        uses: &["swap"],
+        hint: "1 2 swap",
+    },
+    Koan {
+        title: "drop",
+        lesson: "drop throws the top value away: a --",
+        task: "Push 5 and 6, then leave only 5.",
+        solution: "5 6 drop",
+        uses: &["drop"],
+        hint: "5 6 drop",
+    },
+    Koan {
+        title: "over",
+        lesson: "over copies the value under the top: a b -- a b a",
+        task: "Push 3 and 4, then leave 3 4 3.",
+        solution: "3 4 over",
+        uses: &["over"],
+        hint: "3 4 over",
+    },
+    Koan {
+        title: "rot",
+        lesson: "rot brings the third value to the top: a b c -- b c a",
+        task: "Push 1, 2 and 3, then leave 2 3 1.",
+        solution: "1 2 3 rot",
+        uses: &["rot"],
+        hint: "1 2 3 rot",
+    },
+    Koan {
+        title: "Comparisons",
+        lesson: "Comparisons push true or false.",
+        task: "Ask whether 2 is less than 5.",
+        solution: "2 5 <",
+        uses: &["<"],
+        hint: "2 5 < is 2 < 5",
+    },
+    Koan {
+        title: "Arrays",
+        lesson: "Array words such as sort, reverse and len take the array on top.",
+        task: "Sort [3, 1, 2].",
+        solution: "[3, 1, 2] sort",
+        uses: &["sort"],
+        hint: "Push the array, then the word: [3, 1, 2] sort",
+    },
+    Koan {
+        title: "Variables",
+        lesson: "value \"name\" = stores a value and leaves it on top; typing the name pushes it again.",
+        task: "Store 42 as answer, then push answer twice.",
+        solution: "42 \"answer\" = answer answer",
+        uses: &["=", "answer"],
+        hint: "42 \"answer\" = answer answer",
+    },
+];
+
+pub enum Outcome {
+    Correct,
+    Wrong(String),  // What was wrong with the answer
+}
+
+// A run through a set of exercises, one at a time
+pub struct Course {
+    name: &'static str,
+    koans: &'static [Koan],
+    index: usize,
+}
+
+impl Course {
+    pub fn start(language: &str) -> Result<Course, String> {
+        match language {
+            "pi" => Ok(Course { name: "Pi", koans: PI, index: 0 }),
+            _ => Err(format!("No exercises for {} (try :learn pi)", language)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // None once every exercise is done
+    pub fn current(&self) -> Option<&'static Koan> {
+        self.koans.get(self.index)
+    }
+
+    // The current exercise as shown to the learner
+    pub fn prompt(&self) -> Option<String> {
+        let koan = self.current()?;
+        Some(format!(
+            "Exercise {}/{}: {}\n{}\n{}",
+            self.index + 1,
+            self.koans.len(),
+            koan.title,
+            koan.lesson,
+            koan.task
+        ))
+    }
+
+    pub fn skip(&mut self) {
+        self.index = (self.index + 1).min(self.koans.len());
+    }
+
+    // Run an answer on a fresh stack and check it; a correct one moves on
+    pub fn attempt(&mut self, runtime: &mut Runtime, answer: &str) -> Outcome {
+        let Some(koan) = self.current() else {
+            return Outcome::Wrong("There are no exercises left".to_string());
+        };
+        let outcome = check(koan, runtime, answer);
+        if let Outcome::Correct = outcome {
+            self.index += 1;
+        }
+        outcome
+    }
+}
+
+pub fn check(koan: &Koan, runtime: &mut Runtime, answer: &str) -> Outcome {
+    let mut stack = Vec::new();
+    if let Err(e) = pi::parse_pi(answer, runtime, &mut stack) {
+        return Outcome::Wrong(format!("That failed: {}", e));
+    }
+    // Solutions are plain Pi, so a runtime of their own gives the same stack
+    let mut expected = Vec::new();
+    if let Err(e) = pi::parse_pi(koan.solution, &mut Runtime::new(), &mut expected) {
+        return Outcome::Wrong(format!("The exercise's own solution failed: {}", e));
+    }
+    if !same_stack(&stack, &expected) {
+        return Outcome::Wrong(format!("That leaves {} but the goal is {}", format_stack(&stack), format_stack(&expected)));
+    }
+    let words: Vec<String> = match tokenize(answer) {
+        Ok(tokens) => tokens.iter().filter(|t| !matches!(t.kind, TokenKind::Str(_))).map(|t| t.kind.to_string()).collect(),
+        Err(e) => return Outcome::Wrong(e),
+    };
+    if let Some(missing) = koan.uses.iter().find(|word| !words.iter().any(|w| w == *word)) {
+        return Outcome::Wrong(format!("Right stack, but use {} this time", missing));
+    }
+    Outcome::Correct
+}
+
+fn same_stack(a: &[Value], b: &[Value]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b) == Ok(Value::Bool(true)))
+}
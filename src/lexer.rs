@@ -0,0 +1,306 @@
+// Shared lexer for Pi, Rho and Tau
+// Produces a flat token stream with line/column positions.
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Command(String),  // `cmd`, run by the shell
+    // Operators
+    Plus,       // +
+    Minus,      // -
+    Star,       // *
+    Slash,      // /
+    Percent,    // %
+    Power,      // ** or ^
+    Assign,     // =
+    PlusAssign,   // +=
+    MinusAssign,  // -=
+    StarAssign,   // *=
+    SlashAssign,  // /=
+    EqEq,       // ==
+    NotEq,      // !=
+    Less,       // <
+    Greater,    // >
+    Arrow,      // -->
+    FatArrow,   // =>
+    Coalesce,   // ??
+    SafeDot,    // ?.
+    Pipe,       // |
+    Pipeline,   // |>
+    AndAnd,     // &&
+    OrOr,       // ||
+    Bang,       // !
+    Dot,        // .
+    DotDot,     // ..
+    DotDotEq,   // ..=
+    Colon,      // :
+    Comma,      // ,
+    Semicolon,  // ;
+    // Brackets
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    // Layout
+    Newline,
+    Indent(usize),  // Number of leading tabs on a line
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,  // 1-based
+    pub col: usize,   // 1-based, in characters
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Num(n) => write!(f, "{}", n),
+            // Escaped so the text lexes back to the same string
+            TokenKind::Str(s) => write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t")),
+            TokenKind::Ident(s) => write!(f, "{}", s),
+            TokenKind::Command(s) => write!(f, "`{}`", s),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Power => write!(f, "**"),
+            TokenKind::Assign => write!(f, "="),
+            TokenKind::PlusAssign => write!(f, "+="),
+            TokenKind::MinusAssign => write!(f, "-="),
+            TokenKind::StarAssign => write!(f, "*="),
+            TokenKind::SlashAssign => write!(f, "/="),
+            TokenKind::EqEq => write!(f, "=="),
+            TokenKind::NotEq => write!(f, "!="),
+            TokenKind::Less => write!(f, "<"),
+            TokenKind::Greater => write!(f, ">"),
+            TokenKind::Arrow => write!(f, "-->"),
+            TokenKind::FatArrow => write!(f, "=>"),
+            TokenKind::Coalesce => write!(f, "??"),
+            TokenKind::SafeDot => write!(f, "?."),
+            TokenKind::Pipe => write!(f, "|"),
+            TokenKind::Pipeline => write!(f, "|>"),
+            TokenKind::AndAnd => write!(f, "&&"),
+            TokenKind::OrOr => write!(f, "||"),
+            TokenKind::Bang => write!(f, "!"),
+            TokenKind::Dot => write!(f, "."),
+            TokenKind::DotDot => write!(f, ".."),
+            TokenKind::DotDotEq => write!(f, "..="),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Semicolon => write!(f, ";"),
+            TokenKind::LParen => write!(f, "("),
+            TokenKind::RParen => write!(f, ")"),
+            TokenKind::LBracket => write!(f, "["),
+            TokenKind::RBracket => write!(f, "]"),
+            TokenKind::LBrace => write!(f, "{{"),
+            TokenKind::RBrace => write!(f, "}}"),
+            TokenKind::Newline => write!(f, "newline"),
+            TokenKind::Indent(n) => write!(f, "indent({})", n),
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut errors = Vec::new();
+    let tokens = scan(input, &mut errors);
+    match errors.into_iter().next() {
+        Some((_, error)) => Err(error),
+        None => Ok(tokens),
+    }
+}
+
+// Every token that can be read and every error on the way with its line, for
+// diagnostics. A bad character is skipped and an unterminated command resumes
+// at the end of its line; an unterminated string runs to the end of the input.
+pub fn tokenize_all(input: &str) -> (Vec<Token>, Vec<(usize, String)>) {
+    let mut errors = Vec::new();
+    let tokens = scan(input, &mut errors);
+    (tokens, errors)
+}
+
+fn scan(input: &str, errors: &mut Vec<(usize, String)>) -> Vec<Token> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut at_line_start = true;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        let col = i - line_start + 1;
+
+        // Leading tabs of a non-empty line become an Indent token
+        if at_line_start {
+            at_line_start = false;
+            let mut depth = 0;
+            while i + depth < chars.len() && chars[i + depth] == '\t' {
+                depth += 1;
+            }
+            if depth > 0 {
+                tokens.push(Token { kind: TokenKind::Indent(depth), line, col });
+                i += depth;
+                continue;
+            }
+        }
+
+        let push = |tokens: &mut Vec<Token>, kind| tokens.push(Token { kind, line, col });
+
+        match ch {
+            '\n' => {
+                push(&mut tokens, TokenKind::Newline);
+                i += 1;
+                line += 1;
+                line_start = i;
+                at_line_start = true;
+            }
+            c if c.is_whitespace() => i += 1,
+            '#' => {
+                // Comment runs to end of line
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '"' | '\'' => {
+                let quote = ch;
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            errors.push((line, format!("Unterminated string at {}:{}", line, col)));
+                            return tokens;
+                        }
+                        Some(&c) if c == quote => break,
+                        Some('\\') => {
+                            i += 1;
+                            match chars.get(i) {
+                                Some('n') => s.push('\n'),
+                                Some('t') => s.push('\t'),
+                                Some(&c) => s.push(c),
+                                None => {
+                                    errors.push((line, format!("Unterminated string at {}:{}", line, col)));
+                                    return tokens;
+                                }
+                            }
+                        }
+                        Some(&c) => s.push(c),
+                    }
+                    i += 1;
+                }
+                i += 1;
+                push(&mut tokens, TokenKind::Str(s));
+            }
+            '`' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i] != '`' && chars[i] != '\n' {
+                    i += 1;
+                }
+                if chars.get(i) != Some(&'`') {
+                    errors.push((line, format!("Unterminated command at {}:{}", line, col)));
+                    continue;
+                }
+                i += 1;
+                push(&mut tokens, TokenKind::Command(chars[start..i - 1].iter().collect()));
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                // Fraction only when a digit follows the dot
+                if i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit() {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                // Exponent
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                match text.parse::<f64>() {
+                    Ok(n) => push(&mut tokens, TokenKind::Num(n)),
+                    Err(_) => errors.push((line, format!("Invalid number {} at {}:{}", text, line, col))),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                push(&mut tokens, TokenKind::Ident(chars[start..i].iter().collect()));
+            }
+            _ => {
+                let next = chars.get(i + 1).copied();
+                let (kind, len) = match (ch, next) {
+                    ('-', Some('-')) if chars.get(i + 2) == Some(&'>') => (TokenKind::Arrow, 3),
+                    ('.', Some('.')) if chars.get(i + 2) == Some(&'=') => (TokenKind::DotDotEq, 3),
+                    ('.', Some('.')) => (TokenKind::DotDot, 2),
+                    ('=', Some('=')) => (TokenKind::EqEq, 2),
+                    ('=', Some('>')) => (TokenKind::FatArrow, 2),
+                    ('?', Some('?')) => (TokenKind::Coalesce, 2),
+                    ('?', Some('.')) => (TokenKind::SafeDot, 2),
+                    ('!', Some('=')) => (TokenKind::NotEq, 2),
+                    ('&', Some('&')) => (TokenKind::AndAnd, 2),
+                    ('|', Some('|')) => (TokenKind::OrOr, 2),
+                    ('|', Some('>')) => (TokenKind::Pipeline, 2),
+                    ('!', _) => (TokenKind::Bang, 1),
+                    ('*', Some('*')) => (TokenKind::Power, 2),
+                    ('+', Some('=')) => (TokenKind::PlusAssign, 2),
+                    ('-', Some('=')) => (TokenKind::MinusAssign, 2),
+                    ('*', Some('=')) => (TokenKind::StarAssign, 2),
+                    ('/', Some('=')) => (TokenKind::SlashAssign, 2),
+                    ('^', _) => (TokenKind::Power, 1),
+                    ('%', _) => (TokenKind::Percent, 1),
+                    ('+', _) => (TokenKind::Plus, 1),
+                    ('-', _) => (TokenKind::Minus, 1),
+                    ('*', _) => (TokenKind::Star, 1),
+                    ('/', _) => (TokenKind::Slash, 1),
+                    ('=', _) => (TokenKind::Assign, 1),
+                    ('<', _) => (TokenKind::Less, 1),
+                    ('>', _) => (TokenKind::Greater, 1),
+                    ('|', _) => (TokenKind::Pipe, 1),
+                    ('.', _) => (TokenKind::Dot, 1),
+                    (':', _) => (TokenKind::Colon, 1),
+                    (',', _) => (TokenKind::Comma, 1),
+                    (';', _) => (TokenKind::Semicolon, 1),
+                    ('(', _) => (TokenKind::LParen, 1),
+                    (')', _) => (TokenKind::RParen, 1),
+                    ('[', _) => (TokenKind::LBracket, 1),
+                    (']', _) => (TokenKind::RBracket, 1),
+                    ('{', _) => (TokenKind::LBrace, 1),
+                    ('}', _) => (TokenKind::RBrace, 1),
+                    _ => {
+                        errors.push((line, format!("Unexpected character '{}' at {}:{}", ch, line, col)));
+                        i += 1;
+                        continue;
+                    }
+                };
+                push(&mut tokens, kind);
+                i += len;
+            }
+        }
+    }
+
+    tokens
+}
@@ -0,0 +1,57 @@
+pub mod value;
+pub(crate) mod json;
+pub mod lexer;
+pub(crate) mod pi;
+pub mod rho;
+pub mod tau;
+pub mod interface;
+pub mod codegen;
+pub(crate) mod mock;
+pub(crate) mod compress;
+pub(crate) mod deflate;
+pub(crate) mod archive;
+pub(crate) mod paths;
+pub mod wire;
+pub mod transport;
+pub(crate) mod http;
+pub(crate) mod httpcache;
+pub(crate) mod shell;
+pub(crate) mod process;
+pub(crate) mod secret;
+#[cfg(feature = "keyring")]
+pub(crate) mod keyring;
+pub(crate) mod clipboard;
+pub(crate) mod net;
+pub(crate) mod mqtt;
+pub(crate) mod serial;
+pub(crate) mod pubsub;
+pub(crate) mod stream;
+pub(crate) mod rpc;
+pub(crate) mod control;
+pub mod capabilities;
+pub mod config;
+pub(crate) mod executor;
+pub mod runtime;
+pub(crate) mod generator;
+pub(crate) mod tracing;
+pub(crate) mod metrics;
+pub(crate) mod schedule;
+pub(crate) mod modules;
+pub(crate) mod stdlib;
+pub(crate) mod provenance;
+pub mod events;
+pub(crate) mod builtins;
+pub(crate) mod signatures;
+pub(crate) mod output;
+pub(crate) mod learn;
+pub(crate) mod input;
+pub mod translate;
+pub mod repl;
+pub mod error;
+pub mod prelude;
+
+// rcl ctl, the client side of the control socket
+pub use control::ctl;
+
+#[cfg(test)]
+mod tests;
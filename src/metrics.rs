@@ -0,0 +1,167 @@
+// Metrics - named counters, gauges and timers that scripts update so the
+// health of an orchestration run can be read back with :metrics or
+// metrics_export().
+use crate::value::{Map, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,  // Only goes up
+    Gauge,    // Set to the latest reading
+    Timer,    // Durations between start and stop
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetricKind::Counter => write!(f, "counter"),
+            MetricKind::Gauge => write!(f, "gauge"),
+            MetricKind::Timer => write!(f, "timer"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    value: f64,                // Counter total or gauge reading
+    started: Option<Instant>,  // Timer measurement in progress
+    samples: Vec<Duration>,    // Timer measurements so far
+}
+
+// Shared handle held by Value::Metric; the registry holds the same state
+#[derive(Clone)]
+pub struct Metric {
+    pub name: String,
+    pub kind: MetricKind,
+    state: Rc<RefCell<State>>,
+}
+
+impl Metric {
+    fn new(name: &str, kind: MetricKind) -> Self {
+        Metric { name: name.to_string(), kind, state: Rc::new(RefCell::new(State::default())) }
+    }
+
+    pub fn inc(&self, by: f64) -> Result<Value, String> {
+        if self.kind != MetricKind::Counter {
+            return Err(format!("inc needs a counter, {} is a {}", self.name, self.kind));
+        }
+        if by < 0.0 {
+            return Err(format!("Counter {} cannot go down", self.name));
+        }
+        let mut state = self.state.borrow_mut();
+        state.value += by;
+        Ok(Value::Num(state.value))
+    }
+
+    pub fn set(&self, value: f64) -> Result<Value, String> {
+        if self.kind != MetricKind::Gauge {
+            return Err(format!("set needs a gauge, {} is a {}", self.name, self.kind));
+        }
+        self.state.borrow_mut().value = value;
+        Ok(Value::Num(value))
+    }
+
+    // Timers start measuring when fetched with timer(name)
+    fn start(&self) {
+        self.state.borrow_mut().started = Some(Instant::now());
+    }
+
+    // Record the time since the timer was started, in milliseconds
+    pub fn stop(&self) -> Result<Value, String> {
+        if self.kind != MetricKind::Timer {
+            return Err(format!("stop needs a timer, {} is a {}", self.name, self.kind));
+        }
+        let mut state = self.state.borrow_mut();
+        let started = state.started.take().ok_or_else(|| format!("Timer {} is not running", self.name))?;
+        let elapsed = started.elapsed();
+        state.samples.push(elapsed);
+        Ok(Value::Num(millis(elapsed)))
+    }
+
+    // A number for counters and gauges; count, total_ms, min_ms and max_ms for timers
+    pub fn to_value(&self) -> Result<Value, String> {
+        let state = self.state.borrow();
+        if self.kind != MetricKind::Timer {
+            return Ok(Value::Num(state.value));
+        }
+        let total: Duration = state.samples.iter().sum();
+        let min = state.samples.iter().min().copied().unwrap_or_default();
+        let max = state.samples.iter().max().copied().unwrap_or_default();
+        let mut map = Map::new();
+        let fields = [
+            ("count", state.samples.len() as f64),
+            ("total_ms", millis(total)),
+            ("min_ms", millis(min)),
+            ("max_ms", millis(max)),
+        ];
+        for (key, n) in fields {
+            map.insert(Value::Str(key.into()), Value::Num(n))?;
+        }
+        Ok(Value::Map(map))
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl PartialEq for Metric {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+// counter jobs = 3, or timer build (2 samples)
+impl fmt::Debug for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        match self.kind {
+            MetricKind::Timer => write!(f, "timer {} ({} samples)", self.name, state.samples.len()),
+            kind => write!(f, "{} {} = {}", kind, self.name, state.value),
+        }
+    }
+}
+
+// Every metric created this session, in creation order
+#[derive(Default)]
+pub struct Metrics {
+    metrics: Vec<Metric>,
+}
+
+impl Metrics {
+    // The metric called `name`, created on first use. A name keeps the kind
+    // it was created with.
+    pub fn get(&mut self, name: &str, kind: MetricKind) -> Result<Metric, String> {
+        let metric = match self.metrics.iter().find(|m| m.name == name) {
+            Some(metric) if metric.kind != kind => {
+                return Err(format!("{} is a {}, not a {}", name, metric.kind, kind));
+            }
+            Some(metric) => metric.clone(),
+            None => {
+                let metric = Metric::new(name, kind);
+                self.metrics.push(metric.clone());
+                metric
+            }
+        };
+        if kind == MetricKind::Timer {
+            metric.start();
+        }
+        Ok(metric)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Metric> {
+        self.metrics.iter()
+    }
+
+    // name -> value for every metric, see Metric::to_value
+    pub fn export(&self) -> Result<Value, String> {
+        let mut map = Map::new();
+        for metric in &self.metrics {
+            map.insert(Value::Str(metric.name.as_str().into()), metric.to_value()?)?;
+        }
+        Ok(Value::Map(map))
+    }
+}
@@ -0,0 +1,110 @@
+// Mock agents - in-process stand-ins for a Tau interface so client code can be
+// tested without a real server. Every method returns a canned value (the zero
+// value of its return type until configured) after an optional latency, and
+// every call is recorded.
+use crate::interface::{Interface, Method, Type};
+use crate::value::{Array, Color, Map, Value};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+pub struct MockAgent {
+    pub interface: Interface,
+    returns: HashMap<String, Value>,
+    latencies: HashMap<String, Duration>,
+    calls: Vec<(String, Vec<Value>)>,  // Method name and arguments, in call order
+}
+
+impl MockAgent {
+    pub fn new(interface: Interface) -> Self {
+        MockAgent { interface, returns: HashMap::new(), latencies: HashMap::new(), calls: Vec::new() }
+    }
+
+    pub fn set_return(&mut self, method: &str, value: Value) -> Result<(), String> {
+        let returns = &self.method(method)?.returns;
+        if !returns.accepts(&value) {
+            return Err(format!("{}.{} returns {}, got {:?}", self.interface.name, method, returns, value));
+        }
+        self.returns.insert(method.to_string(), value);
+        Ok(())
+    }
+
+    pub fn set_latency(&mut self, method: &str, latency: Duration) -> Result<(), String> {
+        self.method(method)?;
+        self.latencies.insert(method.to_string(), latency);
+        Ok(())
+    }
+
+    // Check the arguments against the interface, wait out the latency, record
+    // the call and hand back the canned value
+    pub fn call(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        let signature = self.interface.check_call(method, &args)?;
+        let result = match self.returns.get(method) {
+            Some(value) => value.clone(),
+            None => zero_value(&signature.returns),
+        };
+        if let Some(latency) = self.latencies.get(method) {
+            thread::sleep(*latency);
+        }
+        self.calls.push((method.to_string(), args));
+        Ok(result)
+    }
+
+    // Arguments of every recorded call to `method`
+    pub fn calls(&self, method: &str) -> Vec<Vec<Value>> {
+        self.calls.iter().filter(|(name, _)| name == method).map(|(_, args)| args.clone()).collect()
+    }
+
+    fn method(&self, name: &str) -> Result<&Method, String> {
+        self.interface
+            .method(name)
+            .ok_or_else(|| format!("{} has no method {}", self.interface.name, name))
+    }
+}
+
+// Shared handle held by Value::Mock; clones refer to the same agent
+#[derive(Clone)]
+pub struct Mock(Rc<RefCell<MockAgent>>);
+
+impl Mock {
+    pub fn new(interface: Interface) -> Self {
+        Mock(Rc::new(RefCell::new(MockAgent::new(interface))))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, MockAgent> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, MockAgent> {
+        self.0.borrow_mut()
+    }
+}
+
+impl PartialEq for Mock {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Mock(Calculator)
+impl fmt::Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.borrow().interface.name)
+    }
+}
+
+// What an unconfigured method returns
+pub fn zero_value(ty: &Type) -> Value {
+    match ty {
+        Type::Num => Value::Num(0.0),
+        Type::Str => Value::Str("".into()),
+        Type::Bool => Value::Bool(false),
+        Type::Color => Value::Color(Color::new(0, 0, 0)),
+        Type::Map => Value::Map(Map::new()),
+        Type::Array(_) => Value::Array(Array::new()),
+        Type::Any | Type::Unit => Value::Unit,
+    }
+}
@@ -0,0 +1,98 @@
+// Modules - script files loaded into the live session with :load. When a
+// module's file changes it is evaluated again and its definitions swapped in
+// all at once: functions take the new version, while values the module had
+// already defined (counters, caches, connections) keep their live state.
+// Reloads happen with :reload name, and automatically between inputs and
+// under --daemon.
+use crate::value::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub struct Module {
+    pub name: String,                // File stem, as in :reload calc
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,  // File time when last evaluated
+    pub names: Vec<String>,          // Variables the module defines, sorted
+    pub loads: u32,
+}
+
+// calc (add, total) from jobs/calc.tsu, loaded 2 times
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let times = if self.loads == 1 { "time" } else { "times" };
+        write!(f, "{} ({}) from {}, loaded {} {}", self.name, self.names.join(", "), self.path.display(), self.loads, times)
+    }
+}
+
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Bind what one evaluation of a module defined. `defined` holds the bindings
+// it made, `previous` the names it defined last time. Functions and new names
+// take the new value, names the module no longer defines are dropped, and
+// other values it defined before keep their live value. Returns the module's
+// names, sorted.
+pub fn swap(live: &mut HashMap<String, Value>, previous: &[String], defined: HashMap<String, Value>) -> Vec<String> {
+    for name in previous.iter().filter(|name| !defined.contains_key(*name)) {
+        live.remove(name);
+    }
+    let mut names: Vec<String> = defined.keys().cloned().collect();
+    names.sort();
+    for (name, value) in defined {
+        let keep = previous.contains(&name) && !matches!(value, Value::Function(_)) && live.contains_key(&name);
+        if !keep {
+            live.insert(name, value);
+        }
+    }
+    names
+}
+
+#[derive(Default)]
+pub struct Modules {
+    modules: Vec<Module>,
+}
+
+impl Modules {
+    pub fn get(&self, name: &str) -> Option<&Module> {
+        self.modules.iter().find(|m| m.name == name)
+    }
+
+    // Record a module after it was evaluated, replacing any earlier load
+    pub fn record(&mut self, name: &str, path: &Path, names: Vec<String>) {
+        let modified = modified(path);
+        match self.modules.iter_mut().find(|m| m.name == name) {
+            Some(module) => {
+                module.path = path.to_path_buf();
+                module.modified = modified;
+                module.names = names;
+                module.loads += 1;
+            }
+            None => self.modules.push(Module { name: name.to_string(), path: path.to_path_buf(), modified, names, loads: 1 }),
+        }
+    }
+
+    // Take the file's current time without evaluating it, so a version that
+    // failed to load is not retried until it changes again
+    pub fn mark_seen(&mut self, name: &str) {
+        if let Some(module) = self.modules.iter_mut().find(|m| m.name == name) {
+            module.modified = modified(&module.path);
+        }
+    }
+
+    // Modules whose file changed since they were last evaluated
+    pub fn changed(&self) -> Vec<String> {
+        self.modules.iter().filter(|m| modified(&m.path) != m.modified).map(|m| m.name.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Module> {
+        self.modules.iter()
+    }
+}
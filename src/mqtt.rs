@@ -0,0 +1,368 @@
+// MQTT 3.1.1 client for IoT scripts. mqtt_connect("mqtt://broker:1883") gives
+// a client; client.publish(topic, payload) sends a message and
+// client.subscribe(filter, fn(topic, payload) ...) runs the handler for every
+// message on a matching topic (`+` matches one level, `#` all below). Handlers
+// run as tasks on the executor, as schedules do: whenever the REPL is between
+// inputs, or continuously under --daemon.
+//
+// Strings are sent as they are and other values as JSON; payloads arrive as
+// strings. Everything goes at QoS 0, at most once. mqtt_connect is built with
+// the mqtt feature.
+use crate::value::Function;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long to wait for the broker to acknowledge a connect or subscribe
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Seconds the broker waits to hear from us before dropping the connection
+const KEEP_ALIVE: u16 = 60;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const DISCONNECT: u8 = 0xe0;
+
+struct Connection {
+    stream: TcpStream,
+    broker: String,
+    pending: Vec<u8>,                    // Bytes of a packet still arriving
+    inbox: VecDeque<(String, String)>,   // Messages read but not yet handled, oldest first
+    acks: Vec<(u16, u8)>,                // Subscribe acknowledgements not yet claimed
+    handlers: Vec<(String, Rc<Function>)>,
+    next_id: u16,
+    last_sent: Instant,
+    open: bool,
+}
+
+// Shared handle held by Value::Mqtt; clones use the same connection
+#[derive(Clone)]
+pub struct Client(Rc<RefCell<Connection>>);
+
+impl Client {
+    // mqtt://[user:password@]host[:port], the port 1883 unless given
+    pub fn connect(url: &str, client_id: &str) -> Result<Client, String> {
+        if url.starts_with("mqtts://") {
+            return Err("mqtts:// needs TLS, which is not supported; use mqtt://".to_string());
+        }
+        let rest = url.strip_prefix("mqtt://").unwrap_or(url);
+        let (login, broker) = match rest.trim_end_matches('/').rsplit_once('@') {
+            Some((login, broker)) => (Some(login), broker),
+            None => (None, rest.trim_end_matches('/')),
+        };
+        let broker = if broker.contains(':') { broker.to_string() } else { format!("{}:1883", broker) };
+        let stream = TcpStream::connect(&broker).map_err(|e| format!("Failed to connect to {}: {}", broker, e))?;
+        stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let mut flags = 0x02;  // Clean session: no state is kept between connections
+        let mut payload = string(client_id);
+        if let Some(login) = login {
+            let (user, password) = login.split_once(':').unwrap_or((login, ""));
+            flags |= 0x80;
+            payload.extend(string(user));
+            if !password.is_empty() {
+                flags |= 0x40;
+                payload.extend(string(password));
+            }
+        }
+        let mut body = string("MQTT");
+        body.extend([4, flags]);
+        body.extend(KEEP_ALIVE.to_be_bytes());
+        body.extend(payload);
+
+        let connection = Connection {
+            stream,
+            broker,
+            pending: Vec::new(),
+            inbox: VecDeque::new(),
+            acks: Vec::new(),
+            handlers: Vec::new(),
+            next_id: 1,
+            last_sent: Instant::now(),
+            open: true,
+        };
+        let client = Client(Rc::new(RefCell::new(connection)));
+        client.send(CONNECT, &body)?;
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let mut connection = client.0.borrow_mut();
+            connection.receive();
+            if let Some(packet) = next_packet(&mut connection.pending) {
+                return match packet {
+                    (CONNACK, body) if body.get(1) == Some(&0) => {
+                        drop(connection);
+                        Ok(client)
+                    }
+                    (CONNACK, body) => Err(format!("{} refused the connection: {}", connection.broker, refusal(body.get(1).copied()))),
+                    _ => Err(format!("{} is not an MQTT broker", connection.broker)),
+                };
+            }
+            if !connection.open || Instant::now() > deadline {
+                return Err(format!("No answer from MQTT broker {}", connection.broker));
+            }
+            drop(connection);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    pub fn publish(&self, topic: &str, payload: &[u8]) -> Result<(), String> {
+        if topic.is_empty() || topic.contains(['+', '#']) {
+            return Err(format!("Cannot publish to {:?}: topics need a name and no wildcards", topic));
+        }
+        let mut body = string(topic);
+        body.extend_from_slice(payload);
+        self.send(PUBLISH, &body)
+    }
+
+    // Ask for messages matching the filter and run the handler on each
+    pub fn subscribe(&self, filter: &str, handler: Rc<Function>) -> Result<(), String> {
+        check_filter(filter)?;
+        let id = {
+            let mut connection = self.0.borrow_mut();
+            let id = connection.next_id;
+            connection.next_id = id.checked_add(1).unwrap_or(1);
+            id
+        };
+        let mut body = id.to_be_bytes().to_vec();
+        body.extend(string(filter));
+        body.push(0);  // QoS 0
+        self.send(SUBSCRIBE, &body)?;
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let mut connection = self.0.borrow_mut();
+            connection.receive();
+            if let Some(i) = connection.acks.iter().position(|(ack, _)| *ack == id) {
+                let (_, code) = connection.acks.remove(i);
+                if code == 0x80 {
+                    return Err(format!("{} refused the subscription to {}", connection.broker, filter));
+                }
+                connection.handlers.push((filter.to_string(), handler));
+                return Ok(());
+            }
+            if !connection.open || Instant::now() > deadline {
+                return Err(format!("No answer from MQTT broker {} to the subscription", connection.broker));
+            }
+            drop(connection);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    pub fn close(&self) {
+        let mut connection = self.0.borrow_mut();
+        if connection.open {
+            connection.write(DISCONNECT, &[]);
+            connection.open = false;
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.0.borrow().open
+    }
+
+    // Messages that have arrived, each with every handler whose filter it
+    // matches; also keeps the connection alive
+    pub fn poll(&self) -> Vec<(Rc<Function>, String, String)> {
+        let mut connection = self.0.borrow_mut();
+        connection.receive();
+        if connection.open && connection.last_sent.elapsed() > Duration::from_secs(KEEP_ALIVE as u64 / 2) {
+            connection.write(PINGREQ, &[]);
+        }
+        let messages: Vec<(String, String)> = connection.inbox.drain(..).collect();
+        let mut calls = Vec::new();
+        for (topic, payload) in messages {
+            for (filter, handler) in &connection.handlers {
+                if matches(filter, &topic) {
+                    calls.push((Rc::clone(handler), topic.clone(), payload.clone()));
+                }
+            }
+        }
+        calls
+    }
+
+    fn send(&self, kind: u8, body: &[u8]) -> Result<(), String> {
+        let mut connection = self.0.borrow_mut();
+        if !connection.open {
+            return Err(format!("MQTT connection to {} is closed", connection.broker));
+        }
+        if !connection.write(kind, body) {
+            connection.open = false;
+            return Err(format!("Lost the MQTT connection to {}", connection.broker));
+        }
+        Ok(())
+    }
+}
+
+impl Connection {
+    // Packets are written blocking, so a slow broker cannot see half of one
+    fn write(&mut self, kind: u8, body: &[u8]) -> bool {
+        let mut packet = vec![kind];
+        let mut length = body.len();
+        loop {
+            let byte = (length % 128) as u8;
+            length /= 128;
+            packet.push(if length > 0 { byte | 0x80 } else { byte });
+            if length == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.last_sent = Instant::now();
+        self.stream.set_nonblocking(false).is_ok()
+            && self.stream.write_all(&packet).and_then(|_| self.stream.flush()).is_ok()
+            && self.stream.set_nonblocking(true).is_ok()
+    }
+
+    // Read whatever has arrived and sort complete packets into the inbox and acks
+    fn receive(&mut self) {
+        let mut buffer = [0u8; 4096];
+        while self.open {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.open = false,
+                Ok(n) => self.pending.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.open = false,
+            }
+        }
+        // A CONNACK is left for connect to read
+        while self.pending.first().is_some_and(|&kind| kind != CONNACK) {
+            let Some((kind, body)) = next_packet(&mut self.pending) else { break };
+            match kind & 0xf0 {
+                PUBLISH => {
+                    let Some(length) = body.get(..2).map(|n| u16::from_be_bytes([n[0], n[1]]) as usize) else { continue };
+                    let Some(topic) = body.get(2..2 + length) else { continue };
+                    // QoS 1 and 2 messages carry a packet id; none are asked for
+                    let start = if kind & 0x06 != 0 { 4 + length } else { 2 + length };
+                    let payload = body.get(start..).unwrap_or_default();
+                    self.inbox.push_back((String::from_utf8_lossy(topic).to_string(), String::from_utf8_lossy(payload).to_string()));
+                }
+                SUBACK if body.len() >= 3 => self.acks.push((u16::from_be_bytes([body[0], body[1]]), body[2])),
+                _ => {}  // PINGRESP and anything else needs nothing
+            }
+        }
+    }
+}
+
+// The first complete packet in the buffer, if one has arrived
+fn next_packet(pending: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let kind = *pending.first()?;
+    let mut length = 0usize;
+    let mut header = 1;
+    loop {
+        let byte = *pending.get(header)?;
+        length += ((byte & 0x7f) as usize) << (7 * (header - 1));
+        header += 1;
+        if byte & 0x80 == 0 || header > 4 {
+            break;
+        }
+    }
+    if pending.len() < header + length {
+        return None;
+    }
+    let body = pending[header..header + length].to_vec();
+    pending.drain(..header + length);
+    Some((kind, body))
+}
+
+// A length-prefixed UTF-8 string
+fn string(text: &str) -> Vec<u8> {
+    let mut out = (text.len() as u16).to_be_bytes().to_vec();
+    out.extend_from_slice(text.as_bytes());
+    out
+}
+
+fn refusal(code: Option<u8>) -> &'static str {
+    match code {
+        Some(1) => "unsupported protocol version",
+        Some(2) => "client id rejected",
+        Some(3) => "server unavailable",
+        Some(4) => "bad user name or password",
+        Some(5) => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+// `#` only as the last level, and wildcards only as whole levels
+fn check_filter(filter: &str) -> Result<(), String> {
+    let levels: Vec<&str> = filter.split('/').collect();
+    let valid = !filter.is_empty()
+        && levels.iter().enumerate().all(|(i, level)| match *level {
+            "#" => i == levels.len() - 1,
+            "+" => true,
+            level => !level.contains(['+', '#']),
+        });
+    if !valid {
+        return Err(format!("Invalid topic filter {:?}", filter));
+    }
+    Ok(())
+}
+
+// Whether a topic matches a filter: sensors/+/temp, sensors/#
+pub fn matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic_level)) if level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+impl PartialEq for Client {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// mqtt client for broker:1883 (2 subscriptions) or (closed)
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let connection = self.0.borrow();
+        if !connection.open {
+            return write!(f, "mqtt client for {} (closed)", connection.broker);
+        }
+        let count = connection.handlers.len();
+        write!(f, "mqtt client for {} ({} subscription{})", connection.broker, count, if count == 1 { "" } else { "s" })
+    }
+}
+
+// Every client connected this session, so their handlers can be run
+#[derive(Default)]
+pub struct Clients {
+    clients: Vec<Client>,
+}
+
+impl Clients {
+    #[cfg(feature = "mqtt")]
+    pub fn add(&mut self, client: Client) {
+        self.clients.push(client);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    // Whether any client has a subscription with a handler to run
+    pub fn is_listening(&self) -> bool {
+        self.clients.iter().any(|client| !client.0.borrow().handlers.is_empty())
+    }
+
+    // Handler calls for the messages every client has received; closed
+    // clients are forgotten
+    pub fn poll(&mut self) -> Vec<(Rc<Function>, String, String)> {
+        let calls = self.clients.iter().flat_map(Client::poll).collect();
+        self.clients.retain(Client::is_open);
+        calls
+    }
+}
@@ -0,0 +1,133 @@
+// TCP sockets for Tau - `listen`, `accept`, `dial`, `send` and `recv` let two
+// sessions exchange Values directly. Each value travels as one line in the
+// transport literal syntax (see transport.rs), so only plain data crosses.
+use crate::transport::{decode_value, encode_value};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::rc::Rc;
+use std::time::Duration;
+
+enum Endpoint {
+    Listening(TcpListener),
+    Connected { reader: BufReader<TcpStream>, writer: TcpStream, peer: SocketAddr, partial: String },
+    Closed,
+}
+
+// Shared handle held by Value::Socket; clones use the same connection
+#[derive(Clone)]
+pub struct Socket(Rc<RefCell<Endpoint>>);
+
+impl Socket {
+    // listen 8080 binds every interface; listen "127.0.0.1:0" a chosen one
+    pub fn listen(address: &str) -> Result<Socket, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+        Ok(Socket(Rc::new(RefCell::new(Endpoint::Listening(listener)))))
+    }
+
+    pub fn dial(address: &str) -> Result<Socket, String> {
+        let stream = TcpStream::connect(address).map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        Socket::connected(stream)
+    }
+
+    fn connected(stream: TcpStream) -> Result<Socket, String> {
+        let peer = stream.peer_addr().map_err(|e| e.to_string())?;
+        let writer = stream.try_clone().map_err(|e| e.to_string())?;
+        let endpoint = Endpoint::Connected { reader: BufReader::new(stream), writer, peer, partial: String::new() };
+        Ok(Socket(Rc::new(RefCell::new(endpoint))))
+    }
+
+    // Wait for the next connection to a listening socket
+    pub fn accept(&self) -> Result<Socket, String> {
+        let stream = match &*self.0.borrow() {
+            Endpoint::Listening(listener) => listener.accept().map_err(|e| format!("Accept failed: {}", e))?.0,
+            _ => return Err("accept needs a listening socket".to_string()),
+        };
+        Socket::connected(stream)
+    }
+
+    pub fn send(&self, value: &Value) -> Result<(), String> {
+        let line = encode_value(value)?;
+        match &mut *self.0.borrow_mut() {
+            Endpoint::Connected { writer, peer, .. } => writeln!(writer, "{}", line)
+                .and_then(|_| writer.flush())
+                .map_err(|e| format!("Failed to send to {}: {}", peer, e)),
+            _ => Err("send needs a connected socket".to_string()),
+        }
+    }
+
+    // Wait for the next value from the peer, for at most `limit` if given. Part
+    // of a line that arrived before the limit is kept for the next recv.
+    pub fn recv(&self, limit: Option<Duration>) -> Result<Value, String> {
+        match self.next(limit)? {
+            Some(value) => Ok(value),
+            None => Err(format!("{} closed the connection", self.peer())),
+        }
+    }
+
+    // Like recv, but None once the peer has closed the connection
+    pub fn next(&self, limit: Option<Duration>) -> Result<Option<Value>, String> {
+        let line = match &mut *self.0.borrow_mut() {
+            Endpoint::Connected { reader, peer, partial, .. } => {
+                // A zero timeout is an error to set_read_timeout, so wait at least 1ms
+                let timeout = limit.map(|limit| limit.max(Duration::from_millis(1)));
+                reader.get_ref().set_read_timeout(timeout).map_err(|e| e.to_string())?;
+                let mut line = std::mem::take(partial);
+                match reader.read_line(&mut line) {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => line,
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                        *partial = line;
+                        return Err(format!("Timed out receiving from {}", peer));
+                    }
+                    Err(e) => return Err(format!("Failed to receive from {}: {}", peer, e)),
+                }
+            }
+            _ => return Err("recv needs a connected socket".to_string()),
+        };
+        decode_value(line.trim_end()).map(Some)
+    }
+
+    fn peer(&self) -> String {
+        match &*self.0.borrow() {
+            Endpoint::Connected { peer, .. } => peer.to_string(),
+            _ => "The peer".to_string(),
+        }
+    }
+
+    // The local address, e.g. to find the port picked for listen "127.0.0.1:0"
+    pub fn address(&self) -> Result<String, String> {
+        let address = match &*self.0.borrow() {
+            Endpoint::Listening(listener) => listener.local_addr(),
+            Endpoint::Connected { writer, .. } => writer.local_addr(),
+            Endpoint::Closed => return Err("Socket is closed".to_string()),
+        };
+        address.map(|a| a.to_string()).map_err(|e| e.to_string())
+    }
+
+    pub fn close(&self) {
+        *self.0.borrow_mut() = Endpoint::Closed;
+    }
+}
+
+impl PartialEq for Socket {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// listening on 127.0.0.1:8080, connected to 127.0.0.1:53122 or closed
+impl fmt::Debug for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &*self.0.borrow() {
+            Endpoint::Listening(listener) => match listener.local_addr() {
+                Ok(address) => write!(f, "listening on {}", address),
+                Err(_) => write!(f, "listening"),
+            },
+            Endpoint::Connected { peer, .. } => write!(f, "connected to {}", peer),
+            Endpoint::Closed => write!(f, "closed"),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// How the REPL echoes results - :output debug|display|json, or `output` in
+// the config file. Display, the default, shows the readable form (7, "hi",
+// [1, 2]), debug the raw structure (Num(7.0)) and json one line of JSON per
+// result, for tools. Maps
+// echoed in debug or display mode can also be sorted, aligned and have long
+// values summarized, see MapLayout.
+//
+// On a terminal a color is echoed after a true-color block showing it, and an
+// array of colors after a row of them; `:set no-color` (or NO_COLOR) turns
+// that off.
+use crate::json;
+use crate::value::{Color, Map, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Output {
+    Debug,
+    #[default]
+    Display,
+    Json,
+}
+
+impl Output {
+    pub fn parse(text: &str) -> Result<Output, String> {
+        match text {
+            "debug" => Ok(Output::Debug),
+            "display" => Ok(Output::Display),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("Unknown output mode: {} (expected debug, display or json)", text)),
+        }
+    }
+
+    // A result as echoed. Values JSON cannot hold are sent as their display
+    // string, and errors as {"error": message}.
+    pub fn format(&self, result: &Result<Value, String>) -> String {
+        match (self, result) {
+            (Output::Debug, Ok(value)) => format!("{:?}", value),
+            (Output::Display, Ok(value)) => value.to_string(),
+            (Output::Json, Ok(value)) => json::to_json(value).unwrap_or_else(|_| json::quote(&value.to_string())),
+            (Output::Json, Err(e)) => format!("{{\"error\":{}}}", json::quote(e)),
+            (_, Err(e)) => format!("Error: {}", e),
+        }
+    }
+
+    // A result as echoed, with maps laid out as `layout` says. JSON keeps
+    // maps as they are, one line each.
+    pub fn echo(&self, result: &Result<Value, String>, layout: &MapLayout) -> String {
+        match (self, result) {
+            (Output::Debug | Output::Display, Ok(Value::Map(map))) if *layout != MapLayout::default() => {
+                layout.render(map, *self == Output::Debug)
+            }
+            _ => self.format(result),
+        }
+    }
+}
+
+// Blocks of each color in the value - one for a color, a row for an array of
+// colors - or None for anything else. Terminals cannot show transparency, so
+// alpha is left out.
+pub fn swatches(value: &Value) -> Option<String> {
+    let block = |c: &Color| format!("\x1b[48;2;{};{};{}m  \x1b[0m", c.r, c.g, c.b);
+    match value {
+        Value::Color(c) => Some(block(c)),
+        Value::Array(items) if !items.is_empty() => {
+            items.iter().map(|item| if let Value::Color(c) = item { Some(block(c)) } else { None }).collect()
+        }
+        _ => None,
+    }
+}
+
+// :output sort on|off, :output align on|off and :output summarize 60|off;
+// output.sort and so on in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MapLayout {
+    pub sort: bool,                // Entries ordered by key rather than insertion
+    pub align: bool,               // One entry per line, values in a column
+    pub summarize: Option<usize>,  // Longest value text shown before it is cut short
+}
+
+impl MapLayout {
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        let switch = || match value {
+            "on" | "true" => Ok(true),
+            "off" | "false" => Ok(false),
+            _ => Err(format!("{} must be on or off, got {}", option, value)),
+        };
+        match option {
+            "sort" => self.sort = switch()?,
+            "align" => self.align = switch()?,
+            "summarize" => {
+                self.summarize = match value {
+                    "off" => None,
+                    _ => match value.parse::<usize>() {
+                        Ok(limit) if limit > 0 => Some(limit),
+                        _ => return Err(format!("summarize expects a length or off, got {}", value)),
+                    },
+                }
+            }
+            _ => return Err(format!("Unknown output option: {} (expected sort, align or summarize)", option)),
+        }
+        Ok(())
+    }
+
+    fn render(&self, map: &Map, debug: bool) -> String {
+        let text = |value: &Value| if debug { format!("{:?}", value) } else { value.to_string() };
+        let mut entries: Vec<(&Value, &Value)> = map.iter().map(|(k, v)| (k, v)).collect();
+        if self.sort {
+            entries.sort_by(|a, b| key_order(a.0, b.0));
+        }
+        let entries: Vec<(String, String)> = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let shown = match self.summarize {
+                    Some(limit) => summarize(value, text(value), limit),
+                    None => text(value),
+                };
+                (text(key), shown)
+            })
+            .collect();
+        if self.align {
+            let width = entries.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(0);
+            let lines: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("  {}:{} {}", key, " ".repeat(width - key.chars().count()), value))
+                .collect();
+            return if lines.is_empty() { "{}".to_string() } else { format!("{{\n{}\n}}", lines.join("\n")) };
+        }
+        if debug {
+            let pairs: Vec<String> = entries.iter().map(|(key, value)| format!("({}, {})", key, value)).collect();
+            format!("Map([{}])", pairs.join(", "))
+        } else {
+            let pairs: Vec<String> = entries.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+            format!("{{{}}}", pairs.join(", "))
+        }
+    }
+}
+
+// sort on, align on, summarize 60
+impl fmt::Display for MapLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let switch = |on: bool| if on { "on" } else { "off" };
+        write!(f, "sort {}, align {}, summarize ", switch(self.sort), switch(self.align))?;
+        match self.summarize {
+            Some(limit) => write!(f, "{}", limit),
+            None => write!(f, "off"),
+        }
+    }
+}
+
+// Numbers first in numeric order, then every other key by its text
+fn key_order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Num(x), Value::Num(y)) => x.total_cmp(y),
+        (Value::Num(_), _) => Ordering::Less,
+        (_, Value::Num(_)) => Ordering::Greater,
+        _ => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+// The first `limit` characters of a long value's text, saying how much there was
+fn summarize(value: &Value, text: String, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text;
+    }
+    let cut: String = text.chars().take(limit).collect();
+    match value {
+        Value::Array(items) => format!("{}... ({} items)", cut, items.len()),
+        Value::Map(map) => format!("{}... ({} entries)", cut, map.len()),
+        Value::Str(s) => format!("{}... ({} chars)", cut, s.chars().count()),
+        _ => format!("{}...", cut),
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Output::Debug => "debug",
+            Output::Display => "display",
+            Output::Json => "json",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// `:silent input` or `input;` evaluates without echoing the result, for
+// side effects whose Unit or handle results would only clutter the screen.
+// Gives the input to evaluate when it is silenced; errors still show.
+pub fn silenced(input: &str) -> Option<&str> {
+    if let Some(rest) = input.strip_prefix(":silent")
+        && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+    {
+        return Some(rest.trim());
+    }
+    if input.starts_with(':') {
+        return None;
+    }
+    input.strip_suffix(';').map(str::trim_end)
+}
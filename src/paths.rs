@@ -0,0 +1,173 @@
+// Paths and globbing for file-oriented scripts. glob("src/**/*.rs") gives the
+// matching paths, sorted, relative to the workspace (or absolute for an
+// absolute pattern). In a pattern `*` matches within a name, `?` one
+// character, `[a-z]` and `[!x]` a character class, `{rs,toml}` any of the
+// alternatives, and a `**` segment any number of directories, including none.
+// Wildcards skip names starting with a dot unless the pattern segment does too.
+//
+// path_join, basename, dirname and ext only work on the text of a path and
+// touch nothing on disk; glob and canonicalize read the file system.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Paths under `root` matching `pattern`, sorted and without duplicates
+pub fn glob(root: &Path, pattern: &str) -> Result<Vec<String>, String> {
+    if pattern.is_empty() {
+        return Err("glob expects a pattern".to_string());
+    }
+    let mut found = Vec::new();
+    for pattern in expand_braces(pattern)? {
+        let (start, prefix) = match pattern.strip_prefix('/') {
+            Some(_) => (PathBuf::from("/"), "/".to_string()),
+            None => (root.to_path_buf(), String::new()),
+        };
+        let segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty() && *s != ".").collect();
+        walk(&start, prefix, &segments, &mut found);
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+// One pattern per alternative: "*.{rs,toml}" is "*.rs" and "*.toml"
+fn expand_braces(pattern: &str) -> Result<Vec<String>, String> {
+    let Some(open) = pattern.find('{') else {
+        return Ok(vec![pattern.to_string()]);
+    };
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut start = open + 1;
+    for (i, c) in pattern.char_indices().skip_while(|&(i, _)| i < open) {
+        match c {
+            '{' => depth += 1,
+            ',' if depth == 1 => {
+                alternatives.push(&pattern[start..i]);
+                start = i + 1;
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    alternatives.push(&pattern[start..i]);
+                    let (head, tail) = (&pattern[..open], &pattern[i + 1..]);
+                    let mut expanded = Vec::new();
+                    for alternative in alternatives {
+                        expanded.extend(expand_braces(&format!("{}{}{}", head, alternative, tail))?);
+                    }
+                    return Ok(expanded);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(format!("Unclosed {{ in glob pattern {}", pattern))
+}
+
+fn walk(dir: &Path, rel: String, segments: &[&str], found: &mut Vec<String>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        if !rel.is_empty() {
+            found.push(rel);
+        }
+        return;
+    };
+    let join = |name: &str| if rel.is_empty() || rel.ends_with('/') { format!("{}{}", rel, name) } else { format!("{}/{}", rel, name) };
+
+    if *segment == "**" {
+        walk(dir, rel.clone(), rest, found);
+        // Symlinked directories are not followed, so a link loop cannot recurse forever
+        for (name, is_dir) in entries(dir) {
+            if is_dir && !name.starts_with('.') {
+                walk(&dir.join(&name), join(&name), segments, found);
+            }
+        }
+    } else if !segment.contains(['*', '?', '[']) {
+        let path = dir.join(segment);
+        if (rest.is_empty() && fs::symlink_metadata(&path).is_ok()) || path.is_dir() {
+            walk(&path, join(segment), rest, found);
+        }
+    } else {
+        for (name, _) in entries(dir) {
+            if (!name.starts_with('.') || segment.starts_with('.')) && matches(segment.as_bytes(), name.as_bytes()) {
+                walk(&dir.join(&name), join(&name), rest, found);
+            }
+        }
+    }
+}
+
+// Names in a directory and whether each is a real (not symlinked) directory
+fn entries(dir: &Path) -> Vec<(String, bool)> {
+    let Ok(read) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    read.filter_map(Result::ok)
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            (entry.file_name().to_string_lossy().to_string(), is_dir)
+        })
+        .collect()
+}
+
+// Whether a name matches one segment of a pattern
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+        Some((b'[', rest)) => {
+            let Some(close) = rest.iter().skip(1).position(|&c| c == b']').map(|i| i + 1) else {
+                return name.first() == Some(&b'[') && matches(rest, &name[1..]);
+            };
+            let Some((&c, name_rest)) = name.split_first() else {
+                return false;
+            };
+            let (negate, class) = match rest[..close].split_first() {
+                Some((b'!' | b'^', class)) => (true, class),
+                _ => (false, &rest[..close]),
+            };
+            let mut hit = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    hit |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    hit |= class[i] == c;
+                    i += 1;
+                }
+            }
+            hit != negate && matches(&rest[close + 1..], name_rest)
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && matches(rest, &name[1..]),
+    }
+}
+
+// The last component, or "" for a path ending in .. or /
+pub fn basename(path: &str) -> String {
+    Path::new(path).file_name().map_or(String::new(), |name| name.to_string_lossy().to_string())
+}
+
+// Everything before the last component; "" for a bare name
+pub fn dirname(path: &str) -> String {
+    Path::new(path).parent().map_or(String::new(), |parent| parent.to_string_lossy().to_string())
+}
+
+// The extension without its dot; "" when there is none
+pub fn ext(path: &str) -> String {
+    Path::new(path).extension().map_or(String::new(), |ext| ext.to_string_lossy().to_string())
+}
+
+// Parts joined with the platform separator. As with Path::join, an absolute
+// part replaces everything before it.
+pub fn join(parts: &[String]) -> String {
+    let mut path = PathBuf::new();
+    for part in parts {
+        path.push(part);
+    }
+    path.to_string_lossy().to_string()
+}
+
+// The absolute path with symlinks and .. resolved; the path must exist
+pub fn canonicalize(root: &Path, path: &str) -> Result<String, String> {
+    fs::canonicalize(root.join(path))
+        .map(|path| path.to_string_lossy().to_string())
+        .map_err(|e| format!("Failed to canonicalize {}: {}", path, e))
+}
@@ -1,67 +1,108 @@
 // Pi Language - Postfix/RPN notation
+use crate::builtins;
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::rho::Parser;
+use crate::runtime::{Expr, Language, Runtime, Site};
 use crate::value::Value;
-use std::collections::HashMap;
 
-pub fn parse_pi(input: &str, variables: &mut HashMap<String, Value>, parse_value_fn: impl Fn(&str) -> Result<Value, String>) -> Result<Value, String> {
-    let tokens: Vec<&str> = input.split_whitespace().collect();
-    let mut stack: Vec<Value> = Vec::new();
+// Runs one line against the persistent data stack and returns the new top of
+// stack (Unit when empty). A failing line leaves the stack untouched.
+pub fn parse_pi(input: &str, runtime: &mut Runtime, data_stack: &mut Vec<Value>) -> Result<Value, String> {
+    let mut undo = Undo::new(data_stack.len());
+    if let Err(e) = run(input, runtime, data_stack, &mut undo) {
+        undo.restore(data_stack);
+        return Err(e);
+    }
+    Ok(data_stack.last().cloned().unwrap_or(Value::Unit))
+}
 
-    for token in tokens {
-        match token {
-            "+" => {
-                if stack.len() < 2 {
-                    return Err("Not enough operands for +".to_string());
-                }
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(a.add(&b)?);
-            }
-            "-" => {
-                if stack.len() < 2 {
-                    return Err("Not enough operands for -".to_string());
-                }
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(a.sub(&b)?);
-            }
-            "*" => {
-                if stack.len() < 2 {
-                    return Err("Not enough operands for *".to_string());
-                }
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(a.mul(&b)?);
-            }
-            "/" => {
-                if stack.len() < 2 {
-                    return Err("Not enough operands for /".to_string());
+// The most values any one word takes off the stack (rot)
+const MAX_OPERANDS: usize = 3;
+
+// What a line has taken from the stack it started with. Everything below
+// `low` is still as it was; `saved` holds the originals from `low` up to the
+// starting length, top first, so a failed line costs only what it touched.
+struct Undo {
+    low: usize,
+    saved: Vec<Value>,
+}
+
+impl Undo {
+    fn new(len: usize) -> Self {
+        Undo { low: len, saved: Vec::new() }
+    }
+
+    // Called before each word, which can reach at most MAX_OPERANDS deep
+    fn save(&mut self, stack: &[Value]) {
+        let reach = stack.len().saturating_sub(MAX_OPERANDS);
+        while self.low > reach {
+            self.low -= 1;
+            self.saved.push(stack[self.low].clone());
+        }
+    }
+
+    fn restore(self, stack: &mut Vec<Value>) {
+        stack.truncate(self.low);
+        stack.extend(self.saved.into_iter().rev());
+    }
+}
+
+fn run(input: &str, runtime: &mut Runtime, stack: &mut Vec<Value>, undo: &mut Undo) -> Result<(), String> {
+    let tokens = tokenize(input)?;
+    let mut pos = 0;
+
+    while pos < tokens.len() {
+        let token = &tokens[pos];
+        pos += 1;
+        undo.save(stack);
+
+        match &token.kind {
+            TokenKind::Plus => binary(stack, "+", |a, b| a.add(b))?,
+            TokenKind::Minus => {
+                // "-5" written without a space is a negative literal
+                if let Some(n) = adjacent(&tokens, pos) {
+                    stack.push(Value::Num(-n));
+                    pos += 1;
+                } else {
+                    binary(stack, "-", |a, b| a.sub(b))?;
                 }
-                let b = stack.pop().unwrap();
-                let a = stack.pop().unwrap();
-                stack.push(a.div(&b)?);
             }
-            "=" => {
+            TokenKind::Star => binary(stack, "*", |a, b| a.mul(b))?,
+            TokenKind::Slash => binary(stack, "/", |a, b| a.div(b))?,
+            TokenKind::Percent => binary(stack, "%", |a, b| a.rem(b))?,
+            TokenKind::Power => binary(stack, "**", |a, b| a.pow(b))?,
+            TokenKind::EqEq => binary(stack, "==", |a, b| runtime.equals(a, b))?,
+            TokenKind::Less => binary(stack, "<", |a, b| a.less_than(b))?,
+            TokenKind::Greater => binary(stack, ">", |a, b| a.greater_than(b))?,
+            // Logical words; both operands are already on the stack so there is no short-circuit
+            TokenKind::AndAnd => binary(stack, "and", |a, b| logical_and(runtime, a, b))?,
+            TokenKind::OrOr => binary(stack, "or", |a, b| logical_or(runtime, a, b))?,
+            TokenKind::Bang => unary(stack, "not", |a| logical_not(runtime, a))?,
+            TokenKind::Ident(name) if name == "and" => binary(stack, "and", |a, b| logical_and(runtime, a, b))?,
+            TokenKind::Ident(name) if name == "or" => binary(stack, "or", |a, b| logical_or(runtime, a, b))?,
+            TokenKind::Ident(name) if name == "not" => unary(stack, "not", |a| logical_not(runtime, a))?,
+            TokenKind::Assign => {
+                // Variable assignment: value name = leaves the value stored
                 if stack.len() < 2 {
                     return Err("Not enough operands for =".to_string());
                 }
                 let name = stack.pop().unwrap();
                 let value = stack.pop().unwrap();
                 if let Value::Str(var_name) = name {
-                    variables.insert(var_name, value.clone());
+                    runtime.assign(var_name.to_string(), value.clone())?;
                     stack.push(value);
                 } else {
                     return Err("Variable name must be a string".to_string());
                 }
             }
-            "-->" => {
-                if stack.is_empty() {
-                    return Err("No value to print".to_string());
-                }
-                let val = stack.pop().unwrap();
+            TokenKind::Arrow => {
+                // Stack print operation: an array prints its items and
+                // becomes Unit; any other value stays on the stack
+                let val = stack.pop().ok_or("No value to print")?;
                 match val {
                     Value::Array(ref arr) => {
                         for item in arr {
-                            print!("{:?} ", item);
+                            print!("{} ", item.print_text());
                         }
                         println!();
                         stack.push(Value::Unit);
@@ -69,21 +110,152 @@ pub fn parse_pi(input: &str, variables: &mut HashMap<String, Value>, parse_value
                     _ => stack.push(val),
                 }
             }
-            _ => {
-                if let Some(var_val) = variables.get(token) {
-                    stack.push(var_val.clone());
-                } else {
-                    stack.push(parse_value_fn(token)?);
+            // .s prints the stack without consuming it
+            TokenKind::Dot if matches!(tokens.get(pos), Some(Token { kind: TokenKind::Ident(s), .. }) if s == "s") => {
+                pos += 1;
+                println!("{}", format_stack(stack));
+            }
+            // "source" suspend - push a Pi continuation; resume runs the top
+            // continuation in its own language and pushes any result
+            TokenKind::Ident(name) if name == "suspend" => match stack.pop() {
+                Some(Value::Str(source)) => runtime.suspend(Language::Pi, &source),
+                Some(other) => return Err(format!("suspend expects source text, got {:?}", other)),
+                None => return Err("No value to suspend".to_string()),
+            },
+            TokenKind::Ident(name) if name == "resume" => match runtime.eval(&Expr::Resume)? {
+                Value::Unit => {}
+                value => stack.push(value),
+            },
+            // Stack shufflers
+            TokenKind::Ident(name) if is_stack_word(name) => stack_word(stack, name)?,
+            // Array words, unless called Rho style as sort(...)
+            TokenKind::Ident(name) if is_array_word(name) && tokens.get(pos).map(|t| &t.kind) != Some(&TokenKind::LParen) => {
+                array_word(runtime, stack, name)?
+            }
+            TokenKind::Num(n) => stack.push(Value::Num(*n)),
+            TokenKind::Str(s) => stack.push(Value::Str(s.as_str().into())),
+            TokenKind::Command(cmd) => stack.push(runtime.eval(&Expr::Call("sh".to_string(), vec![Expr::Value(Value::Str(cmd.as_str().into()))], Site::default()))?),
+            TokenKind::Ident(name) if name == "true" => stack.push(Value::Bool(true)),
+            TokenKind::Ident(name) if name == "none" => stack.push(Value::None),
+            TokenKind::Ident(name) if name == "false" => stack.push(Value::Bool(false)),
+            TokenKind::Ident(name) if tokens.get(pos).map(|t| &t.kind) != Some(&TokenKind::LParen) => {
+                match runtime.get_variable(name) {
+                    Some(value) => stack.push(value.clone()),
+                    None => return Err(format!("Cannot parse value: {}", name)),
                 }
             }
+            // Literals such as [1,2,3], [{k,v}] and color(r,g,b) use the Rho grammar
+            TokenKind::Ident(_) | TokenKind::LBracket => {
+                let mut parser = Parser::at(&tokens, pos - 1);
+                let expr = parser.parse_primary()?;
+                pos = parser.position();
+                stack.push(runtime.eval(&expr)?);
+            }
+            TokenKind::Newline | TokenKind::Indent(_) => {}
+            other => return Err(format!("Unexpected {} at {}:{}", other, token.line, token.col)),
         }
     }
 
-    if stack.len() == 1 {
-        Ok(stack.pop().unwrap())
-    } else if stack.is_empty() {
-        Ok(Value::Unit)
-    } else {
-        Err(format!("Stack has {} values remaining", stack.len()))
+    Ok(())
+}
+
+// Forth style listing: <depth> bottom ... top
+pub fn format_stack(stack: &[Value]) -> String {
+    let mut out = format!("<{}>", stack.len());
+    for value in stack {
+        out.push_str(&format!(" {}", value));
+    }
+    out
+}
+
+pub(crate) fn is_stack_word(name: &str) -> bool {
+    matches!(name, "dup" | "swap" | "drop" | "over" | "rot")
+}
+
+fn stack_word(stack: &mut Vec<Value>, name: &str) -> Result<(), String> {
+    // Going deeper than MAX_OPERANDS would escape the undo record
+    let needed = match name {
+        "dup" | "drop" => 1,
+        "swap" | "over" => 2,
+        _ => 3,
+    };
+    if stack.len() < needed {
+        return Err(format!("Not enough operands for {}", name));
+    }
+    let n = stack.len();
+    match name {
+        "dup" => stack.push(stack[n - 1].clone()),              // a -- a a
+        "drop" => { stack.pop(); }                              // a --
+        "swap" => stack.swap(n - 1, n - 2),                     // a b -- b a
+        "over" => stack.push(stack[n - 2].clone()),             // a b -- a b a
+        _ => {
+            let a = stack.remove(n - 3);                        // a b c -- b c a
+            stack.push(a);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn is_array_word(name: &str) -> bool {
+    matches!(name, "sort" | "reverse" | "unique" | "contains" | "len")
+}
+
+// [3 1 2] sort, [1 2] reverse, [1 1 2] unique, [1 2] 2 contains and "abc" len,
+// through the builtins of the same names. A function on top is sort's comparator.
+fn array_word(runtime: &mut Runtime, stack: &mut Vec<Value>, name: &str) -> Result<(), String> {
+    let needed = match (name, stack.last()) {
+        ("contains", _) | ("sort", Some(Value::Function(_))) => 2,
+        _ => 1,
+    };
+    if stack.len() < needed {
+        return Err(format!("Not enough operands for {}", name));
+    }
+    let args = stack.split_off(stack.len() - needed);
+    stack.push(builtins::call(runtime, name, args)?);
+    Ok(())
+}
+
+fn binary(
+    stack: &mut Vec<Value>,
+    op: &str,
+    f: impl Fn(&Value, &Value) -> Result<Value, String>,
+) -> Result<(), String> {
+    if stack.len() < 2 {
+        return Err(format!("Not enough operands for {}", op));
+    }
+    let b = stack.pop().unwrap();
+    let a = stack.pop().unwrap();
+    stack.push(f(&a, &b)?);
+    Ok(())
+}
+
+fn unary(
+    stack: &mut Vec<Value>,
+    op: &str,
+    f: impl Fn(&Value) -> Result<Value, String>,
+) -> Result<(), String> {
+    let a = stack.pop().ok_or_else(|| format!("Not enough operands for {}", op))?;
+    stack.push(f(&a)?);
+    Ok(())
+}
+
+fn logical_and(runtime: &Runtime, a: &Value, b: &Value) -> Result<Value, String> {
+    Ok(Value::Bool(runtime.truthy(a)? && runtime.truthy(b)?))
+}
+
+fn logical_or(runtime: &Runtime, a: &Value, b: &Value) -> Result<Value, String> {
+    Ok(Value::Bool(runtime.truthy(a)? || runtime.truthy(b)?))
+}
+
+fn logical_not(runtime: &Runtime, a: &Value) -> Result<Value, String> {
+    Ok(Value::Bool(!runtime.truthy(a)?))
+}
+
+// Number token directly following the previous token with no gap
+pub(crate) fn adjacent(tokens: &[Token], pos: usize) -> Option<f64> {
+    let prev = &tokens[pos - 1];
+    match tokens.get(pos) {
+        Some(Token { kind: TokenKind::Num(n), line, col }) if *line == prev.line && *col == prev.col + 1 => Some(*n),
+        _ => None,
     }
 }
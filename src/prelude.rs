@@ -0,0 +1,15 @@
+// What an embedder needs, in one import:
+//   use rust_ai_lang::prelude::*;
+//   let mut interpreter = Interpreter::with_config(Config::default());
+// Interpreter is the Repl. Its errors are the Strings the REPL prints, which
+// `?` turns into a LangError.
+// These names are the stable surface: within a major version they keep
+// their meaning and the signatures recorded in the public_api snapshot,
+// and only gain methods, and variants of the enums marked non_exhaustive.
+pub use crate::capabilities::{Capabilities, Capability};
+pub use crate::config::Config;
+pub use crate::error::LangError;
+pub use crate::events::{Bus, Event};
+pub use crate::repl::{Interpreter, Repl};
+pub use crate::runtime::{Language, Runtime};
+pub use crate::value::{Color, Map, Value};
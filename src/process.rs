@@ -0,0 +1,168 @@
+// Processes on this machine, so administration scripts need not parse ps.
+// processes() gives a map per process - [{"pid", 812}, {"name", "nginx"},
+// {"cpu", 1.5}, {"mem", 0.4}] - with cpu and mem as percentages the way ps
+// reports them: cpu averaged over the process's life, mem of physical memory.
+// kill(pid[, sig]) signals one, and wait(pid) waits for it to exit (a future
+// in Tau).
+//
+// Linux reads /proc directly; other systems ask ps, or tasklist on Windows,
+// where cpu and mem are not reported.
+use crate::value::{Map, Value};
+use std::process::{Command, Stdio};
+
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    pub cpu: Option<f64>,
+    pub mem: Option<f64>,
+}
+
+impl Process {
+    pub fn into_value(self) -> Value {
+        let percent = |p: Option<f64>| p.map_or(Value::Unit, |p| Value::Num((p * 10.0).round() / 10.0));
+        let pairs = vec![
+            (Value::Str("pid".into()), Value::Num(self.pid as f64)),
+            (Value::Str("name".into()), Value::Str(self.name.into())),
+            (Value::Str("cpu".into()), percent(self.cpu)),
+            (Value::Str("mem".into()), percent(self.mem)),
+        ];
+        Value::Map(Map::from_pairs(pairs).unwrap_or_default())
+    }
+}
+
+// Signals kill accepts by name
+const SIGNALS: [&str; 9] = ["HUP", "INT", "QUIT", "KILL", "USR1", "USR2", "TERM", "CONT", "STOP"];
+
+// A signal name ("TERM", "SIGKILL") or number, as kill -s takes it
+pub fn signal(sig: &str) -> Result<String, String> {
+    let name = sig.trim().to_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    if SIGNALS.contains(&name) || name.parse::<u8>().is_ok_and(|n| n <= 64) {
+        return Ok(name.to_string());
+    }
+    Err(format!("Unknown signal: {} (expected a name such as TERM or KILL, or a number)", sig))
+}
+
+// Every process we can see, in pid order
+#[cfg(target_os = "linux")]
+pub fn list() -> Result<Vec<Process>, String> {
+    use std::fs;
+    // Clock ticks per second; 100 on every mainstream Linux build
+    const TICKS: f64 = 100.0;
+    let uptime: f64 = fs::read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|text| text.split_whitespace().next()?.parse().ok())
+        .ok_or("Failed to read /proc/uptime")?;
+    let total_kb: f64 = fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|text| text.lines().find_map(|line| line.strip_prefix("MemTotal:")?.split_whitespace().next()?.parse().ok()))
+        .ok_or("Failed to read /proc/meminfo")?;
+    let mut processes = Vec::new();
+    for entry in fs::read_dir("/proc").map_err(|e| format!("Failed to read /proc: {}", e))?.filter_map(Result::ok) {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+        // A process can exit while we read it; skip it rather than fail
+        let Ok(stat) = fs::read_to_string(entry.path().join("stat")) else { continue };
+        // pid (name) state ppid ...; the name may itself contain ") "
+        let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else { continue };
+        let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+        let field = |i: usize| fields.get(i).and_then(|f| f.parse::<f64>().ok());
+        // utime, stime and starttime are fields 14, 15 and 22 of stat, counted from 1
+        let cpu = match (field(11), field(12), field(19)) {
+            (Some(user), Some(system), Some(start)) if uptime > start / TICKS => {
+                Some((user + system) / TICKS / (uptime - start / TICKS) * 100.0)
+            }
+            _ => None,
+        };
+        let rss_kb = fs::read_to_string(entry.path().join("status"))
+            .ok()
+            .and_then(|text| text.lines().find_map(|line| line.strip_prefix("VmRSS:")?.split_whitespace().next()?.parse::<f64>().ok()))
+            .unwrap_or(0.0);
+        let name = stat[open + 1..close].to_string();
+        processes.push(Process { pid, name, cpu, mem: Some(rss_kb / total_kb * 100.0) });
+    }
+    processes.sort_by_key(|p| p.pid);
+    Ok(processes)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn list() -> Result<Vec<Process>, String> {
+    let text = output(Command::new("ps").args(["-axo", "pid=,pcpu=,pmem=,comm="]))?;
+    let mut processes: Vec<Process> = text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse().ok()?;
+            let cpu = fields.next()?.parse().ok();
+            let mem = fields.next()?.parse().ok();
+            let command = fields.collect::<Vec<_>>().join(" ");
+            let name = command.rsplit('/').next().unwrap_or(&command).to_string();
+            Some(Process { pid, name, cpu, mem })
+        })
+        .collect();
+    processes.sort_by_key(|p| p.pid);
+    Ok(processes)
+}
+
+#[cfg(windows)]
+pub fn list() -> Result<Vec<Process>, String> {
+    // "name.exe","1234","Console","1","12,345 K"
+    let text = output(Command::new("tasklist").args(["/fo", "csv", "/nh"]))?;
+    let mut processes: Vec<Process> = text
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split("\",\"").map(|f| f.trim_matches('"')).collect();
+            Some(Process { pid: fields.get(1)?.parse().ok()?, name: fields[0].to_string(), cpu: None, mem: None })
+        })
+        .collect();
+    processes.sort_by_key(|p| p.pid);
+    Ok(processes)
+}
+
+// Send the signal to the process. Pids 0 and below, which would signal whole
+// process groups, are refused.
+pub fn kill(pid: u32, sig: &str) -> Result<(), String> {
+    if pid == 0 {
+        return Err("kill expects a process id above 0".to_string());
+    }
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("taskkill");
+        command.args(["/PID", &pid.to_string(), "/F"]);
+        command
+    } else {
+        let mut command = Command::new("kill");
+        command.args(["-s", &signal(sig)?, &pid.to_string()]);
+        command
+    };
+    output(&mut command).map(|_| ()).map_err(|e| format!("Failed to signal process {}: {}", pid, e))
+}
+
+// Whether a process with this pid is still running; zombies count as exited
+pub fn alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        return std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .is_ok_and(|stat| stat.rfind(')').is_some_and(|close| !stat[close + 1..].trim_start().starts_with('Z')));
+    }
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("tasklist");
+        command.args(["/fi", &format!("PID eq {}", pid), "/nh"]);
+        command
+    } else {
+        let mut command = Command::new("ps");
+        command.args(["-p", &pid.to_string()]);
+        command
+    };
+    match output(&mut command) {
+        Ok(text) if cfg!(windows) => text.contains(&pid.to_string()),
+        Ok(_) => true,
+        Err(_) => false,
+    }
+}
+
+// Stdout of a command that must succeed; its stderr otherwise
+fn output(command: &mut Command) -> Result<String, String> {
+    let output = command.stdin(Stdio::null()).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
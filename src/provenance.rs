@@ -0,0 +1,67 @@
+// Where values came from - with `provenance = on` (or :set provenance on)
+// every binding records the input that produced it, so `:whence x` can say
+// where a variable came from long after that was forgotten. Off by default,
+// since it keeps a copy of the source text for every variable. While on, it
+// follows the session's events, see events.rs.
+use crate::events::Event;
+use crate::runtime::Language;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub source: String,               // The input being evaluated when the value was bound
+    pub language: Option<Language>,   // None for bindings made outside any input
+    pub origin: String,               // input 3, module calc, ctl eval
+    pub at: SystemTime,
+}
+
+// "x = async 1 + 1" in Tau, input 3, 12.4s ago
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(language) = self.language {
+            write!(f, "{:?} in {}, ", self.source, language)?;
+        }
+        write!(f, "{}, {:.1}s ago", self.origin, self.at.elapsed().unwrap_or_default().as_secs_f64())
+    }
+}
+
+#[derive(Default)]
+pub struct Provenances {
+    current: Option<Provenance>,  // The input now being evaluated
+    bindings: HashMap<String, Provenance>,
+}
+
+impl Provenances {
+    pub fn notify(&mut self, event: &Event) {
+        match event {
+            Event::EvalStarted { language, source, origin } => {
+                let language = Some(*language);
+                self.current = Some(Provenance { source: source.clone(), language, origin: origin.clone(), at: SystemTime::now() });
+            }
+            Event::EvalFinished { .. } => self.current = None,
+            Event::VarAssigned { name } => self.record(name),
+            _ => {}
+        }
+    }
+
+    // Bindings made between inputs, by a scheduled handler or an agent call,
+    // are recorded as coming from outside any input
+    fn record(&mut self, name: &str) {
+        let provenance = match &self.current {
+            Some(current) => Provenance { at: SystemTime::now(), ..current.clone() },
+            None => Provenance {
+                source: String::new(),
+                language: None,
+                origin: "outside any input".to_string(),
+                at: SystemTime::now(),
+            },
+        };
+        self.bindings.insert(name.to_string(), provenance);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Provenance> {
+        self.bindings.get(name)
+    }
+}
@@ -0,0 +1,125 @@
+// Publish/subscribe for Tau - `subscribe "prices"` gives a subscription that
+// queues every value later sent with `publish "prices" value`. `recv sub` is
+// a future for the next value and `for v in sub` drains what has arrived, so
+// Tau programs can react to events rather than poll for them. The broker is
+// in-process; publish is its only way in, so anything that can call it (an
+// agent relaying values it receives over TCP, say) can bridge topics between
+// sessions.
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+struct Queue {
+    topic: String,
+    values: VecDeque<Value>,  // Published but not yet received, oldest first
+    open: bool,
+    limit: Option<usize>,     // Most values to queue; later ones are dropped until some are read
+    dropped: usize,
+}
+
+// Shared handle held by Value::Subscription; clones read the same queue
+#[derive(Clone)]
+pub struct Subscription(Rc<RefCell<Queue>>);
+
+impl Subscription {
+    pub fn topic(&self) -> String {
+        self.0.borrow().topic.clone()
+    }
+
+    // The oldest value not yet received
+    pub fn recv(&self) -> Result<Value, String> {
+        let mut queue = self.0.borrow_mut();
+        match queue.values.pop_front() {
+            Some(value) => Ok(value),
+            None if !queue.open => Err(format!("Subscription to {} is closed", queue.topic)),
+            None => Err(format!("Nothing published on {} yet", queue.topic)),
+        }
+    }
+
+    // Every value waiting, oldest first
+    pub fn drain(&self) -> Vec<Value> {
+        self.0.borrow_mut().values.drain(..).collect()
+    }
+
+    // The oldest value waiting, if any
+    pub fn try_next(&self) -> Option<Value> {
+        self.0.borrow_mut().values.pop_front()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.0.borrow().open
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.0.borrow_mut().limit = Some(limit);
+    }
+
+    // Stop receiving; values already queued can still be read
+    pub fn close(&self) {
+        self.0.borrow_mut().open = false;
+    }
+}
+
+impl PartialEq for Subscription {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// subscription to prices (2 pending), (2 pending, 5 dropped) or (closed)
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let queue = self.0.borrow();
+        if !queue.open {
+            return write!(f, "subscription to {} (closed)", queue.topic);
+        }
+        write!(f, "subscription to {} ({} pending", queue.topic, queue.values.len())?;
+        if queue.dropped > 0 {
+            write!(f, ", {} dropped", queue.dropped)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Topics and their subscribers. Subscribers are held weakly, so one nothing
+// refers to any more stops receiving like a closed one.
+#[derive(Default)]
+pub struct Broker {
+    topics: HashMap<String, Vec<Weak<RefCell<Queue>>>>,
+}
+
+impl Broker {
+    pub fn subscribe(&mut self, topic: &str) -> Subscription {
+        let queue = Queue { topic: topic.to_string(), values: VecDeque::new(), open: true, limit: None, dropped: 0 };
+        let queue = Rc::new(RefCell::new(queue));
+        self.topics.entry(topic.to_string()).or_default().push(Rc::downgrade(&queue));
+        Subscription(queue)
+    }
+
+    // Queue the value for every open subscriber to the topic, returning how
+    // many took it. A subscriber whose queue is at its limit drops the value,
+    // so a publisher can tell its readers are falling behind.
+    pub fn publish(&mut self, topic: &str, value: &Value) -> usize {
+        let Some(subscribers) = self.topics.get_mut(topic) else {
+            return 0;
+        };
+        subscribers.retain(|queue| queue.upgrade().is_some_and(|queue| queue.borrow().open));
+        if subscribers.is_empty() {
+            self.topics.remove(topic);
+            return 0;
+        }
+        let mut delivered = 0;
+        for queue in subscribers.iter().filter_map(Weak::upgrade) {
+            let mut queue = queue.borrow_mut();
+            if queue.limit.is_some_and(|limit| queue.values.len() >= limit) {
+                queue.dropped += 1;
+            } else {
+                queue.values.push_back(value.clone());
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+}
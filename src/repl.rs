@@ -0,0 +1,1016 @@
+use crate::capabilities::{Capabilities, Capability};
+use crate::config::{self, Config};
+use crate::control::ControlSocket;
+use crate::events::{Bus, Event};
+use crate::input::{Line, Lines};
+use crate::learn::{Course, Outcome};
+use crate::lexer::tokenize_all;
+use crate::modules::{self, Modules};
+use crate::output::{self, Output};
+use crate::runtime::Runtime;
+pub use crate::runtime::Language;
+use crate::value::{FutureState, Value};
+use crate::{pi, rho, shell, stdlib, tau, translate};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::thread;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often agents, the control socket and module files are checked while
+// the session is otherwise idle
+const POLL: Duration = Duration::from_millis(10);
+
+// Stdin lines, read on a thread of their own so the session can wait for
+// input and network requests at once
+fn read_lines(max: usize) -> Receiver<String> {
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in Lines::new(io::stdin().lock(), max) {
+            let Ok(Line { text, warning }) = line else { break };
+            if let Some(warning) = warning {
+                eprintln!("Warning: {}", warning);
+            }
+            if sender.send(text).is_err() {
+                break;
+            }
+        }
+    });
+    lines
+}
+
+// A script's lines, read as they are used. Paths such as examples/primes.tsu
+// that are not on disk are looked up among the built-in files.
+fn open_script(path: &Path, max: usize) -> Result<Lines<Box<dyn BufRead>>, String> {
+    let reader: Box<dyn BufRead> = match fs::File::open(path) {
+        Ok(file) => Box::new(BufReader::new(file)),
+        Err(e) => match path.to_str().and_then(stdlib::file) {
+            Some(source) => Box::new(source.as_bytes()),
+            None => return Err(format!("Failed to read file {}: {}", path.display(), e)),
+        },
+    };
+    Ok(Lines::new(reader, max))
+}
+
+// The next line of a script, reporting what was wrong with it
+fn script_line(path: &Path, line: io::Result<Line>) -> Result<String, String> {
+    let Line { text, warning } = line.map_err(|e| format!("Failed to read file {}: {}", path.display(), e))?;
+    if let Some(warning) = warning {
+        eprintln!("Warning: {}: {}", path.display(), warning);
+    }
+    Ok(text)
+}
+
+// REPL - Multi-language Read-Eval-Print Loop
+// Supports: Pi (postfix), Rho (infix+tabs), Tau (network+futures)
+pub struct Repl {
+    runtime: Runtime,
+    current_lang: Language,
+    pi_stack: Vec<Value>,  // Pi data stack, kept across inputs
+    timeouts: HashMap<Language, Duration>,  // :timeout per language, overriding config.timeout
+    modules: Modules,      // Files loaded with :load, reloaded when they change
+    inputs: usize,         // Inputs evaluated so far, numbering them for :whence
+    origin: Option<String>,  // Where inputs come from while not typed in, e.g. module calc
+    watches: Vec<(Language, String)>,  // :watch expressions, shown after every input
+    course: Option<Course>,  // :learn exercises; while running, inputs are answers
+}
+
+// What embedders call the REPL: the same session, driven through eval and run_file
+pub type Interpreter = Repl;
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        Repl {
+            runtime: Runtime::with_config(config),
+            current_lang: Language::Pi,  // Default to Pi (postfix)
+            pi_stack: Vec::new(),
+            timeouts: HashMap::new(),
+            modules: Modules::default(),
+            inputs: 0,
+            origin: None,
+            watches: Vec::new(),
+            course: None,
+        }
+    }
+
+    // Embedding API: restrict what scripts may touch
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        Repl::with_config(Config { capabilities, ..Config::default() })
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.runtime.config.capabilities
+    }
+
+    pub fn capabilities_mut(&mut self) -> &mut Capabilities {
+        &mut self.runtime.config.capabilities
+    }
+
+    pub fn pi_stack(&self) -> &[Value] {
+        &self.pi_stack
+    }
+
+    pub fn clear_pi_stack(&mut self) {
+        self.pi_stack.clear();
+    }
+
+    pub fn language(&self) -> Language {
+        self.current_lang
+    }
+
+    // Switch the language later inputs are read in. Pending continuations stay
+    // on the stack and each resumes in the language it was suspended in, so
+    // the warning returned when there are any says which.
+    pub fn set_language(&mut self, lang: Language) -> Option<String> {
+        self.current_lang = lang;
+        let pending = self.runtime.cont_stack.languages();
+        if pending.is_empty() {
+            return None;
+        }
+        let languages: Vec<String> = pending.iter().map(|l| l.map_or("native".to_string(), |l| l.to_string())).collect();
+        let plural = if pending.len() == 1 { "" } else { "s" };
+        Some(format!(
+            "{} pending continuation{} ({}, top last); resume runs each in the language it was suspended in",
+            pending.len(),
+            plural,
+            languages.join(", ")
+        ))
+    }
+
+    fn switch_language(&mut self, lang: Language) {
+        let warning = self.set_language(lang);
+        let description = match lang {
+            Language::Pi => "Pi (postfix/RPN notation)",
+            Language::Rho => "Rho (infix with tab indentation)",
+            Language::Tau => "Tau (network language with futures)",
+        };
+        println!("Switched to {}", description);
+        if let Some(warning) = warning {
+            println!("Warning: {}", warning);
+        }
+    }
+
+    // Limit for a single evaluation in `lang`; None removes the language's own
+    // limit so the config default applies again
+    pub fn set_timeout(&mut self, lang: Language, limit: Option<Duration>) {
+        match limit {
+            Some(limit) => self.timeouts.insert(lang, limit),
+            None => self.timeouts.remove(&lang),
+        };
+    }
+
+    pub fn timeout(&self, lang: Language) -> Option<Duration> {
+        self.timeouts.get(&lang).copied().or(self.runtime.config.timeout)
+    }
+
+    pub fn run(&mut self) {
+        println!("Multi-Language REPL v0.2.0");
+        println!("Languages: Pi (postfix), Rho (infix+tabs), Tau (network+futures)");
+        println!("Commands: :quit, :help, :pi, :rho, :tau");
+        println!("Use `command` to execute bash commands\n");
+        println!("Current language: {:?}\n", self.current_lang);
+
+        let lines = read_lines(self.runtime.config.max_line());
+        loop {
+            print!("> ");
+            io::stdout().flush().unwrap();
+
+            // End of input (or a read error) ends the session
+            let Some(input) = self.next_line(&lines) else {
+                break;
+            };
+            self.reload_changed();
+            self.run_due_schedules();
+            self.serve_agents();
+            self.runtime.expire_futures();
+
+            if !self.handle_line(&input) {
+                break;
+            }
+        }
+    }
+
+    // Run a script line by line as if typed, without the banner or prompts.
+    // The language comes from the extension (.pi, .rho or .tsu).
+    pub fn run_file(&mut self, path: &Path) -> Result<(), String> {
+        let lines = open_script(path, self.runtime.config.max_line())?;
+        if let Some(lang) = path.extension().and_then(|e| e.to_str()).and_then(Language::from_extension) {
+            self.current_lang = lang;
+        }
+        self.run_lines(path, lines)
+    }
+
+    // Run piped input the way run_file runs a script, in the current
+    // language; --daemon without a script starts from this, never the REPL
+    pub fn run_input(&mut self, input: impl BufRead) -> Result<(), String> {
+        let lines = Lines::new(input, self.runtime.config.max_line());
+        self.run_lines(Path::new("stdin"), lines)
+    }
+
+    fn run_lines(&mut self, path: &Path, lines: Lines<impl BufRead>) -> Result<(), String> {
+        for line in lines {
+            if !self.handle_line(&script_line(path, line)?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // One line of input; false when it ends the session
+    fn handle_line(&mut self, input: &str) -> bool {
+        let input = input.trim();
+
+        // Skip empty lines and comments
+        if input.is_empty() || input.starts_with('#') {
+            return true;
+        }
+
+        // :silent input or input; evaluates without echoing the result
+        let (input, silent) = match output::silenced(input) {
+            Some(rest) => (rest, true),
+            None => (input, false),
+        };
+        if input.is_empty() {
+            return true;
+        }
+
+        // :force-assign input may bind keyword names, and builtin names quietly
+        let (input, forced) = match input.strip_prefix(":force-assign ") {
+            Some(rest) => (rest.trim(), true),
+            None => (input, false),
+        };
+
+        // Handle special commands
+        if input.starts_with(':') {
+            match input {
+                ":quit" | ":q" => {
+                    println!("Goodbye!");
+                    return false;
+                }
+                ":help" | ":h" => {
+                    self.print_help();
+                    return true;
+                }
+                ":pi" => {
+                    self.switch_language(Language::Pi);
+                    return true;
+                }
+                ":rho" => {
+                    self.switch_language(Language::Rho);
+                    return true;
+                }
+                ":tau" => {
+                    self.switch_language(Language::Tau);
+                    return true;
+                }
+                ":lang" => {
+                    println!("Current language: {}", self.current_lang);
+                    return true;
+                }
+                _ if input.starts_with(":lang ") => {
+                    match Language::from_name(input[6..].trim()) {
+                        Some(lang) => self.switch_language(lang),
+                        None => println!("Error: Unknown language {} (expected pi, rho or tau)", input[6..].trim()),
+                    }
+                    return true;
+                }
+                ":caps" => {
+                    println!("Capabilities: {}", self.runtime.config.capabilities);
+                    return true;
+                }
+                ":clear-stack" => {
+                    self.clear_pi_stack();
+                    println!("Pi stack cleared");
+                    return true;
+                }
+                ":futures" => {
+                    self.print_futures();
+                    return true;
+                }
+                ":metrics" => {
+                    self.print_metrics();
+                    return true;
+                }
+                ":schedules" => {
+                    self.print_schedules();
+                    return true;
+                }
+                ":modules" => {
+                    self.print_modules();
+                    return true;
+                }
+                ":output" => {
+                    let config = &self.runtime.config;
+                    println!("Output: {} ({})", config.output, config.map_layout);
+                    return true;
+                }
+                _ if input.starts_with(":output ") => {
+                    let result = match input[8..].split_once(char::is_whitespace) {
+                        Some((option, value)) => self.runtime.config.map_layout.set(option, value.trim()),
+                        None => Output::parse(input[8..].trim()).map(|output| self.runtime.config.output = output),
+                    };
+                    if let Err(e) = result {
+                        println!("Error: {}", e);
+                    }
+                    return true;
+                }
+                _ if input.starts_with(":load ") => {
+                    match self.load_module(input[6..].trim()) {
+                        Ok(name) => println!("Loaded {}", name),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                    return true;
+                }
+                _ if input.starts_with(":reload ") => {
+                    let name = input[8..].trim();
+                    match self.reload_module(name) {
+                        Ok(()) => println!("Reloaded {}", name),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                    return true;
+                }
+                _ if input == ":timeout" || input.starts_with(":timeout ") => {
+                    // :timeout 2s | :timeout off - applies to the current language
+                    let arg = input[":timeout".len()..].trim();
+                    if !arg.is_empty() {
+                        match config::parse_timeout(arg) {
+                            Ok(limit) => self.set_timeout(self.current_lang, limit),
+                            Err(e) => {
+                                println!("Error: {}", e);
+                                return true;
+                            }
+                        }
+                    }
+                    match self.timeout(self.current_lang) {
+                        Some(limit) => println!("{:?} timeout: {:?}", self.current_lang, limit),
+                        None => println!("{:?} timeout: off", self.current_lang),
+                    }
+                    return true;
+                }
+                _ if input == ":strict" || input.starts_with(":strict ") => {
+                    // :strict on | :strict off - the same as :set strict
+                    let arg = input[":strict".len()..].trim();
+                    if !arg.is_empty()
+                        && let Err(e) = self.runtime.config.set("strict", arg)
+                    {
+                        println!("Error: {}", e);
+                        return true;
+                    }
+                    println!("Strict mode: {}", if self.runtime.config.strict { "on" } else { "off" });
+                    return true;
+                }
+                ":watch" => {
+                    if self.watches.is_empty() {
+                        println!("No watches");
+                    }
+                    for (n, (lang, expr)) in self.watches.iter().enumerate() {
+                        println!("[{}] {} ({})", n + 1, expr, lang);
+                    }
+                    return true;
+                }
+                _ if input.starts_with(":watch ") => {
+                    let n = self.watch(input[7..].trim());
+                    println!("Watching [{}]", n);
+                    return true;
+                }
+                _ if input.starts_with(":unwatch") => {
+                    if let Err(e) = self.unwatch(input[8..].trim()) {
+                        println!("Error: {}", e);
+                    }
+                    return true;
+                }
+                _ if input.starts_with(":whence ") => {
+                    println!("{}", self.whence(input[8..].trim()));
+                    return true;
+                }
+                _ if input.starts_with(":translate ") => {
+                    println!("{}", self.translate(input[":translate".len()..].trim()));
+                    return true;
+                }
+                _ if input == ":learn" || input.starts_with(":learn ") => {
+                    println!("{}", self.learn(input[":learn".len()..].trim()));
+                    return true;
+                }
+                _ if input.starts_with(":set ") => {
+                    // :set key value - same keys as the config file
+                    let rest = input[5..].trim();
+                    let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    match self.runtime.config.set(key, value.trim()) {
+                        Ok(()) => println!("{} = {}", key, value.trim()),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                    self.runtime.follow_provenance();
+                    return true;
+                }
+                _ => {
+                    println!("Unknown command: {}", input);
+                    return true;
+                }
+            }
+        }
+
+        if self.course.is_some() {
+            println!("{}", self.answer(input));
+            return true;
+        }
+
+        // A line starting with a backtick runs as at a shell prompt; backticks
+        // elsewhere are `cmd` expressions in the current language
+        if input.starts_with('`') {
+            match self.process_bash(input) {
+                Ok(_) if silent => {}
+                Ok(result) => println!("{}", result),
+                Err(e) => println!("Bash error: {}", e),
+            }
+            return true;
+        }
+
+        // Parse and evaluate based on current language
+        let result = if forced { self.force_assign(input) } else { self.eval(input) };
+        if !silent || result.is_err() {
+            let config = &self.runtime.config;
+            let text = config.output.echo(&result, &config.map_layout);
+            match &result {
+                Ok(value) if config.color && config.output != Output::Json => match output::swatches(value) {
+                    Some(swatches) => println!("{} {}", swatches, text),
+                    None => println!("{}", text),
+                },
+                _ => println!("{}", text),
+            }
+        }
+        for warning in std::mem::take(&mut self.runtime.warnings) {
+            eprintln!("Warning: {}", warning);
+        }
+        for line in self.watch_lines() {
+            println!("{}", line);
+        }
+        true
+    }
+
+    fn print_futures(&self) {
+        let futures = self.runtime.futures();
+        if futures.is_empty() {
+            println!("No futures");
+        }
+        for future in futures {
+            println!("{}", future);
+            if let FutureState::Rejected(error) | FutureState::Cancelled(error) = future.state() {
+                for frame in &error.trace {
+                    println!("    at {}", frame);
+                }
+            }
+        }
+    }
+
+    // Run the scheduled handlers that are due, reporting any that fail
+    pub fn run_due_schedules(&mut self) {
+        self.runtime.set_timeout(self.timeout(Language::Tau));
+        for (id, result) in self.runtime.run_due_schedules(SystemTime::now()) {
+            if let Err(e) = result {
+                eprintln!("Schedule #{} failed: {}", id, e);
+            }
+        }
+    }
+
+    // The next input line; agents keep being served and modules reloaded while
+    // it is awaited
+    fn next_line(&mut self, lines: &Receiver<String>) -> Option<String> {
+        loop {
+            if self.runtime.agents.is_empty() && self.runtime.mqtt.is_empty() && self.modules.is_empty() {
+                return lines.recv().ok();
+            }
+            match lines.recv_timeout(POLL) {
+                Ok(line) => return Some(line),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.serve_agents();
+                    self.reload_changed();
+                }
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    // :load path - evaluate a script file as a module named after the file,
+    // see modules.rs. Returns the module name.
+    pub fn load_module(&mut self, path: &str) -> Result<String, String> {
+        self.runtime.config.capabilities.check(Capability::FsRead)?;
+        let path = self.runtime.config.in_workspace(path);
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => stem.to_string(),
+            None => return Err(format!("No module name in {}", path.display())),
+        };
+        self.evaluate_module(&name, &path)?;
+        Ok(name)
+    }
+
+    // import "std/strings" - bind a library's definitions, once per session.
+    // Libraries come from --stdlib-path or the binary, see stdlib.rs; other
+    // names are script files in the workspace. The module is named by the
+    // import, so :reload std/strings works too. Returns the module name.
+    pub fn import(&mut self, target: &str) -> Result<String, String> {
+        let name = target.rsplit_once('.').filter(|(_, ext)| Language::from_extension(ext).is_some()).map_or(target, |(stem, _)| stem);
+        if self.modules.get(name).is_some() {
+            return Ok(name.to_string());
+        }
+        let path = match stdlib::resolve(target, self.runtime.config.stdlib_path.as_deref()) {
+            Some(path) => path,
+            None => {
+                self.runtime.config.capabilities.check(Capability::FsRead)?;
+                let path = self.runtime.config.in_workspace(target);
+                if !path.is_file() {
+                    return Err(format!("No library or file named {}", target));
+                }
+                path
+            }
+        };
+        self.evaluate_module(name, &path)?;
+        Ok(name.to_string())
+    }
+
+    // :reload name - evaluate a module's file again and swap in its definitions
+    pub fn reload_module(&mut self, name: &str) -> Result<(), String> {
+        let path = self.modules.get(name).ok_or_else(|| format!("No module named {}", name))?.path.clone();
+        self.runtime.config.capabilities.check(Capability::FsRead)?;
+        self.evaluate_module(name, &path)
+    }
+
+    // Reload the modules whose files changed, reporting each
+    pub fn reload_changed(&mut self) {
+        for name in self.modules.changed() {
+            match self.reload_module(&name) {
+                Ok(()) => println!("Reloaded {}", name),
+                Err(e) => {
+                    self.modules.mark_seen(&name);
+                    eprintln!("Reload of {} failed, keeping the previous version: {}", name, e);
+                }
+            }
+        }
+    }
+
+    // Evaluate a module against the live session minus its own previous
+    // definitions. Nothing changes unless the whole file succeeds.
+    fn evaluate_module(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        let source = match stdlib::built_in(path) {
+            Some(source) => source.to_string(),
+            None => fs::read_to_string(path).map_err(|e| format!("Failed to read file {}: {}", path.display(), e))?,
+        };
+        let lang = path.extension().and_then(|e| e.to_str()).and_then(Language::from_extension).unwrap_or(self.current_lang);
+        let previous = self.modules.get(name).map(|m| m.names.clone()).unwrap_or_default();
+        let live = self.runtime.variables.clone();
+        let mut base = live.clone();
+        base.retain(|name, _| !previous.contains(name));
+        self.runtime.variables = base.clone();
+        // Its consts may be declared again
+        let constants = self.runtime.constants.clone();
+        self.runtime.constants.retain(|name| !previous.contains(name));
+        let outer = std::mem::replace(&mut self.current_lang, lang);
+        let outer_origin = self.origin.replace(format!("module {}", name));
+        let result = self.eval_source(&source);
+        self.origin = outer_origin;
+        self.current_lang = outer;
+        let after = std::mem::replace(&mut self.runtime.variables, live);
+        if result.is_err() {
+            self.runtime.constants = constants;
+        }
+        result?;
+        let defined = after.into_iter().filter(|(name, value)| base.get(name) != Some(value)).collect();
+        let names = modules::swap(&mut self.runtime.variables, &previous, defined);
+        self.modules.record(name, path, names);
+        Ok(())
+    }
+
+    // A whole file: Tau takes one statement per line, Pi and Rho the text at
+    // once after its import lines
+    fn eval_source(&mut self, source: &str) -> Result<Value, String> {
+        if self.current_lang != Language::Tau {
+            let (imports, rest): (Vec<&str>, Vec<&str>) = source.lines().partition(|line| stdlib::import_target(line).is_some());
+            for line in imports {
+                self.eval(line)?;
+            }
+            return self.eval(&rest.join("\n"));
+        }
+        let mut last = Value::Unit;
+        for line in source.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            last = self.eval(line)?;
+        }
+        Ok(last)
+    }
+
+    // Every syntax error in a script, found without running it. Commands and
+    // imports are left to the REPL; Pi is only lexed, since its operands are
+    // checked as it runs.
+    pub fn check(&self, lang: Language, source: &str) -> Vec<String> {
+        let lines: Vec<&str> = source
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                let input = output::silenced(trimmed).unwrap_or(line);
+                if input.trim_start().starts_with(':') || stdlib::import_target(input).is_some() {
+                    ""
+                } else {
+                    input
+                }
+            })
+            .collect();
+        let source = lines.join("\n");
+        match lang {
+            Language::Pi => tokenize_all(&source).1.into_iter().map(|(_, error)| error).collect(),
+            Language::Rho => rho::check_rho(&source),
+            Language::Tau => tau::check_tau(&source),
+        }
+    }
+
+    // check for a script file, in the language of its extension
+    pub fn check_file(&self, path: &Path) -> Result<Vec<String>, String> {
+        let lines = open_script(path, self.runtime.config.max_line())?;
+        let source = lines.map(|line| script_line(path, line)).collect::<Result<Vec<_>, _>>()?.join("\n");
+        let lang = path.extension().and_then(|e| e.to_str()).and_then(Language::from_extension).unwrap_or(self.current_lang);
+        Ok(self.check(lang, &source))
+    }
+
+    // The session's events, for embedders to subscribe to, see events.rs
+    pub fn events(&mut self) -> &mut Bus {
+        self.runtime.events()
+    }
+
+    // Modules loaded with :load or import
+    pub(crate) fn modules(&self) -> &Modules {
+        &self.modules
+    }
+
+    fn print_modules(&self) {
+        if self.modules().is_empty() {
+            println!("No modules");
+        }
+        for module in self.modules().iter() {
+            println!("{}", module);
+        }
+    }
+
+    // Answer requests to this session's agents, and run MQTT handlers for
+    // messages, that arrived meanwhile
+    pub fn serve_agents(&mut self) {
+        self.runtime.set_timeout(self.timeout(Language::Tau));
+        self.runtime.serve_agents();
+        for (topic, result) in self.runtime.serve_mqtt() {
+            if let Err(e) = result {
+                eprintln!("MQTT handler for {} failed: {}", topic, e);
+            }
+        }
+    }
+
+    // --daemon: once the input is exhausted, keep running scheduled handlers
+    // on time and serving agents until neither is left. With a control socket
+    // it runs until `rcl ctl stop` instead.
+    pub fn run_daemon(&mut self) {
+        let mut control = match self.runtime.config.socket.clone().map(|path| ControlSocket::bind(&path)).transpose() {
+            Ok(control) => control,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        };
+        loop {
+            let next = self.runtime.scheduler.next_due();
+            if next.is_none() && self.runtime.agents.is_empty() && !self.runtime.mqtt.is_listening() && control.is_none() {
+                break;
+            }
+            // Agents, MQTT clients, the control socket and module files are
+            // polled every few milliseconds; schedules alone can sleep until due
+            let mut wait = next.map_or(Duration::MAX, |next| next.duration_since(SystemTime::now()).unwrap_or_default());
+            if !self.runtime.agents.is_empty() || !self.runtime.mqtt.is_empty() || control.is_some() || !self.modules.is_empty() {
+                wait = wait.min(POLL);
+            }
+            thread::sleep(wait);
+            self.reload_changed();
+            self.run_due_schedules();
+            self.serve_agents();
+            self.runtime.expire_futures();
+            if let Some(control) = &mut control
+                && !control.poll(|input| self.control_eval(input))
+            {
+                println!("Stopped");
+                return;
+            }
+        }
+        println!("No schedules or agents left");
+    }
+
+    // A ctl eval: one input line, answered with what the REPL would print
+    fn control_eval(&mut self, input: &str) -> Result<String, String> {
+        if input.starts_with('`') {
+            return self.process_bash(input);
+        }
+        let (output, layout) = (self.runtime.config.output, self.runtime.config.map_layout);
+        let outer = self.origin.replace("ctl eval".to_string());
+        let result = self.eval(input);
+        self.origin = outer;
+        result.map(|value| output.echo(&Ok(value), &layout))
+    }
+
+    fn print_schedules(&self) {
+        let schedules = self.runtime.scheduler.schedules();
+        if schedules.is_empty() {
+            println!("No schedules");
+        }
+        for schedule in schedules {
+            println!("{}", schedule);
+        }
+    }
+
+    fn print_metrics(&self) {
+        let mut metrics = self.runtime.metrics.iter().peekable();
+        if metrics.peek().is_none() {
+            println!("No metrics");
+        }
+        for metric in metrics {
+            match metric.to_value() {
+                Ok(Value::Num(n)) => println!("{} {} = {}", metric.kind, metric.name, n),
+                Ok(Value::Map(map)) => {
+                    let fields: Vec<String> = map
+                        .iter()
+                        .filter_map(|(k, v)| match (k, v) {
+                            (Value::Str(k), Value::Num(n)) => Some(format!("{}={:.3}", k, n)),
+                            _ => None,
+                        })
+                        .collect();
+                    println!("{} {} {}", metric.kind, metric.name, fields.join(" "));
+                }
+                _ => println!("{:?}", metric),
+            }
+        }
+    }
+
+    fn print_help(&self) {
+        println!("Multi-Language REPL Help:");
+        println!("\nLanguages:");
+        println!("  :pi  - Switch to Pi (postfix/RPN): 3 4 +");
+        println!("  :rho - Switch to Rho (infix+tabs): 3 + 4");
+        println!("  :tau - Switch to Tau (network+futures): async operations");
+        println!("\nPi (Postfix):");
+        println!("  3 4 +        # 7");
+        println!("  [1,2,3] \"arr\" =  arr -->  # prints: 1 2 3");
+        println!("  dup swap drop over rot  # stack shufflers");
+        println!("  .s           # show the stack (kept between inputs, :clear-stack resets it)");
+        println!("\nRho (Infix):");
+        println!("  3 + 4        # 7");
+        println!("  if a == 1    # uses tabs for blocks");
+        println!("\nTau (Network):");
+        println!("  async fetch  # returns Future");
+        println!("  await val    # resolves Future");
+        println!("  scope {{ spawn a; spawn b }}  # join both, cancel on error");
+        println!("  cancel f / timeout f 5s  # stop a future's task");
+        println!("  s = stream(conn); for x in s {{ x }}  # values as they arrive; next(s) reads one");
+        println!("  s = subscribe \"t\"; publish \"t\" 1; recv s  # pub/sub within the session");
+        println!("\nCommon:");
+        println!("  Bash: `ls`, `echo hello`, `pwd`");
+        println!("  suspend x + 1, then resume  # runs later in the language it was suspended in (Pi: \"1 2 +\" suspend)");
+        let libraries: Vec<&str> = stdlib::files().filter_map(|file| file.strip_suffix(".tsu")).filter(|file| file.starts_with("std/")).collect();
+        println!("  import \"std/strings\"  # built-in libraries: {}", libraries.join(", "));
+        println!("  Commands: :quit, :help, :pi, :rho, :tau, :lang [name], :output debug|display|json, :output sort|align on|off, :output summarize 60|off, :silent input (or end it with ;), :force-assign input, :caps, :futures, :metrics, :schedules, :modules, :load path, :reload name, :clear-stack, :timeout 2s|off, :strict on|off, :set key value, :whence name, :watch [expr], :unwatch [n], :learn pi|hint|skip|stop, :translate expr");
+    }
+
+    // Evaluate one input in the current language
+    pub fn eval(&mut self, input: &str) -> Result<Value, String> {
+        if let Some(target) = stdlib::import_target(input) {
+            return self.import(target).map(|_| Value::Unit);
+        }
+        if self.origin.is_none() {
+            self.inputs += 1;
+        }
+        let language = self.current_lang;
+        let started = Instant::now();
+        if self.runtime.events.is_followed() {
+            let origin = self.origin.clone().unwrap_or_else(|| format!("input {}", self.inputs));
+            self.runtime.events.publish(Event::EvalStarted { language, source: input.to_string(), origin });
+        }
+        let result = match language {
+            Language::Pi => self.parse_pi(input),
+            Language::Rho => self.parse_rho(input),
+            Language::Tau => self.parse_tau(input),
+        };
+        if self.runtime.events.is_followed() {
+            let error = result.as_ref().err().cloned();
+            self.runtime.events.publish(Event::EvalFinished { language, error, elapsed: started.elapsed() });
+        }
+        result
+    }
+
+    // Evaluate one input letting variables take builtin and keyword names,
+    // as :force-assign map = fn(xs, f) ... does
+    pub fn force_assign(&mut self, input: &str) -> Result<Value, String> {
+        self.runtime.force_assign = true;
+        let result = self.eval(input);
+        self.runtime.force_assign = false;
+        result
+    }
+
+    // :watch expr - evaluated in the current language after every input;
+    // returns its number for :unwatch
+    pub fn watch(&mut self, expr: &str) -> usize {
+        self.watches.push((self.current_lang, expr.to_string()));
+        self.watches.len()
+    }
+
+    // :unwatch n removes one watch, :unwatch alone every one
+    pub fn unwatch(&mut self, which: &str) -> Result<(), String> {
+        if which.is_empty() {
+            self.watches.clear();
+            return Ok(());
+        }
+        match which.parse::<usize>() {
+            Ok(n) if (1..=self.watches.len()).contains(&n) => {
+                self.watches.remove(n - 1);
+                Ok(())
+            }
+            _ => Err(format!("No watch {} (:watch lists them)", which)),
+        }
+    }
+
+    // The watches as shown after an input: [1] total = Num(5.0). Each runs in
+    // the language it was added in, and a failing one shows its error.
+    pub fn watch_lines(&mut self) -> Vec<String> {
+        let watches = self.watches.clone();
+        watches
+            .iter()
+            .enumerate()
+            .map(|(n, (lang, expr))| {
+                let result = match lang {
+                    Language::Pi => self.parse_pi_isolated(expr),
+                    Language::Rho => self.parse_rho(expr),
+                    Language::Tau => self.parse_tau(expr),
+                };
+                let config = &self.runtime.config;
+                format!("[{}] {} = {}", n + 1, expr, config.output.echo(&result, &config.map_layout))
+            })
+            .collect()
+    }
+
+    // Pi on a copy of the stack, so a watch cannot push or pop the real one
+    fn parse_pi_isolated(&mut self, input: &str) -> Result<Value, String> {
+        self.runtime.set_timeout(self.timeout(Language::Pi));
+        let mut stack = self.pi_stack.clone();
+        pi::parse_pi(input, &mut self.runtime, &mut stack)
+    }
+
+    // :learn pi starts the exercises, :learn shows the current one, and hint,
+    // skip and stop do what they say
+    pub fn learn(&mut self, arg: &str) -> String {
+        match (arg, &mut self.course) {
+            ("", None) => "No exercises running; :learn pi starts them".to_string(),
+            ("hint", Some(course)) => course.current().map_or(String::new(), |koan| format!("Hint: {}", koan.hint)),
+            ("skip", Some(course)) => {
+                course.skip();
+                self.next_exercise()
+            }
+            ("stop", Some(_)) => {
+                self.course = None;
+                "Stopped the exercises".to_string()
+            }
+            ("" | "hint" | "skip" | "stop", _) => self.next_exercise(),
+            (language, _) => match Course::start(language) {
+                Ok(course) => {
+                    self.course = Some(course);
+                    format!("Answers run on a stack of their own; :learn hint, :learn skip or :learn stop at any time\n\n{}", self.next_exercise())
+                }
+                Err(e) => format!("Error: {}", e),
+            },
+        }
+    }
+
+    // Check an input against the current exercise
+    pub fn answer(&mut self, input: &str) -> String {
+        self.runtime.set_timeout(self.timeout(Language::Pi));
+        let Some(course) = self.course.as_mut() else {
+            return "No exercises running; :learn pi starts them".to_string();
+        };
+        match course.attempt(&mut self.runtime, input) {
+            Outcome::Correct => format!("Correct!\n\n{}", self.next_exercise()),
+            Outcome::Wrong(reason) => {
+                let hint = course.current().map_or("", |koan| koan.hint);
+                format!("{}\nHint: {}", reason, hint)
+            }
+        }
+    }
+
+    // The exercise to do now, ending the course when none are left
+    fn next_exercise(&mut self) -> String {
+        let Some(course) = &self.course else {
+            return "No exercises running; :learn pi starts them".to_string();
+        };
+        match course.prompt() {
+            Some(prompt) => prompt,
+            None => {
+                let name = course.name();
+                self.course = None;
+                format!("That was the last of the {} exercises", name)
+            }
+        }
+    }
+
+    // :translate expr - the input, read in the current language, as the other
+    // two would write it: "Pi:  3 4 +" and "Tau: 3 + 4"
+    pub fn translate(&self, input: &str) -> String {
+        match translate::translate(self.current_lang, input) {
+            Ok(translations) => translations
+                .into_iter()
+                .map(|(lang, text)| format!("{:<4} {}", format!("{}:", lang), text.unwrap_or_else(|e| format!("({})", e))))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    // :whence name - where the variable's value came from
+    pub fn whence(&self, name: &str) -> String {
+        if self.runtime.get_variable(name).is_none() {
+            return format!("Variable {} not found", name);
+        }
+        match self.runtime.provenance.borrow().get(name) {
+            Some(provenance) => format!("{}: {}", name, provenance),
+            None if !self.runtime.config.provenance => "Provenance is off; :set provenance on to record it".to_string(),
+            None => format!("{} was bound before provenance was turned on", name),
+        }
+    }
+
+    // Pi language parser (Postfix/RPN notation)
+    pub fn parse_pi(&mut self, input: &str) -> Result<Value, String> {
+        self.runtime.set_timeout(self.timeout(Language::Pi));
+        pi::parse_pi(input, &mut self.runtime, &mut self.pi_stack)
+    }
+
+    // Rho language parser (Infix with tab indentation)
+    pub fn parse_rho(&mut self, input: &str) -> Result<Value, String> {
+        self.runtime.set_timeout(self.timeout(Language::Rho));
+        let expr = rho::parse_rho(input)?;
+        self.runtime.eval(&expr)
+    }
+
+    // Tau language parser (Network with futures)
+    pub fn parse_tau(&mut self, input: &str) -> Result<Value, String> {
+        self.runtime.set_timeout(self.timeout(Language::Tau));
+        tau::parse_tau(input, &mut self.runtime)
+    }
+
+    pub fn process_bash(&mut self, input: &str) -> Result<String, String> {
+        // One deadline covers every command on the line
+        let deadline = self.timeout(self.current_lang).map(|limit| (Instant::now() + limit, limit));
+        let mut result = String::new();
+        let mut chars = input.chars().peekable();
+        let mut current = String::new();
+
+        while let Some(ch) = chars.next() {
+            if ch == '`' {
+                // Found backtick, collect command
+                let mut cmd = String::new();
+                while let Some(&next_ch) = chars.peek() {
+                    if next_ch == '`' {
+                        chars.next(); // consume closing backtick
+                        break;
+                    }
+                    cmd.push(chars.next().unwrap());
+                }
+
+                // Execute bash command
+                if !cmd.is_empty() {
+                    match self.execute_bash(&cmd, deadline) {
+                        Ok(output) => result.push_str(&output),
+                        Err(e) => return Err(e),
+                    }
+                }
+            } else {
+                current.push(ch);
+            }
+        }
+
+        if !current.is_empty() {
+            result.push_str(&current);
+        }
+
+        Ok(result)
+    }
+
+    fn execute_bash(&mut self, cmd: &str, deadline: Option<(Instant, Duration)>) -> Result<String, String> {
+        self.runtime.config.capabilities.check(Capability::Shell)?;
+
+        let output = shell::run(cmd, deadline)?;
+        self.runtime.events.publish(Event::ShellCommandRun { command: cmd.to_string(), status: output.status });
+        if !output.stderr.is_empty() {
+            Err(output.stderr)
+        } else {
+            Ok(output.stdout.trim_end().to_string())
+        }
+    }
+}
@@ -1,7 +1,821 @@
 // Rho Language - Infix notation with tab indentation
+use crate::lexer::{tokenize, tokenize_all, Token, TokenKind};
+use crate::runtime::{Case, Expr, Pattern, Site};
 use crate::value::Value;
 
-pub fn parse_rho(input: &str, parse_eval_fn: impl Fn(&str) -> Result<Value, String>) -> Result<Value, String> {
-    // For now, delegate to the main parser
-    parse_eval_fn(input)
+// Builds a binary operator node, e.g. Expr::Add
+pub(crate) type Binary = fn(Box<Expr>, Box<Expr>) -> Expr;
+
+// Words the grammar reads itself, which a variable cannot stand in for
+const KEYWORDS: &[&str] = &[
+    "true", "false", "none", "and", "or", "not", "in", "let", "const", "fn", "gen", "yield", "for", "while", "try", "catch",
+    "finally", "return", "throw", "break", "suspend", "scope", "spawn", "async", "struct", "enum", "match",
+];
+
+pub fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+pub fn parse_rho(input: &str) -> Result<Expr, String> {
+    let tokens = tokenize(input)?;
+    Parser::new(&tokens).parse_program()
+}
+
+// Every problem in the input rather than only the first, in line order. A
+// statement starting on a line the lexer already faulted is not reported as
+// well, since its parse error would only follow from the missing token.
+pub fn check_rho(input: &str) -> Vec<String> {
+    let (tokens, mut errors) = tokenize_all(input);
+    let faulted: Vec<usize> = errors.iter().map(|(line, _)| *line).collect();
+    let parsed = Parser::new(&tokens).diagnostics();
+    errors.extend(parsed.into_iter().filter(|(line, _)| !faulted.contains(line)));
+    errors.sort_by_key(|(line, _)| *line);
+    errors.into_iter().map(|(_, error)| error).collect()
+}
+
+// Recursive descent parser over the shared token stream
+// Precedence (lowest first): |, ??, or, and, not, comparisons, |>, .. ..=, + -, * / %, unary -, ** (right assoc), postfix [ ] and [a:b]
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Parser { tokens, pos: 0 }
+    }
+
+    pub fn at(tokens: &'a [Token], pos: usize) -> Self {
+        Parser { tokens, pos }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    // Statements separated by newlines or semicolons
+    pub fn parse_program(&mut self) -> Result<Expr, String> {
+        let mut exprs = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.peek().is_none() {
+                break;
+            }
+            exprs.push(self.parse_statement()?);
+            match self.peek() {
+                None | Some(TokenKind::Newline) | Some(TokenKind::Semicolon) => {}
+                Some(_) => return Err(self.error("end of statement")),
+            }
+        }
+        // A lone block keeps a block around it, since the runtime runs a
+        // program's statements in the scope it was given, see Runtime::eval
+        Ok(match exprs.len() {
+            0 => Expr::Value(Value::Unit),
+            1 if matches!(exprs[0], Expr::Block(_)) => Expr::Block(exprs),
+            1 => exprs.pop().unwrap(),
+            _ => Expr::Block(exprs),
+        })
+    }
+
+    // Parse the whole program, reporting each statement that fails with the
+    // line it starts on and carrying on after it; see recover
+    pub fn diagnostics(&mut self) -> Vec<(usize, String)> {
+        let mut errors = Vec::new();
+        let mut recovering = false;
+        loop {
+            self.skip_separators();
+            // The closer of a block whose opening line failed
+            while recovering && matches!(self.peek(), Some(TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace)) {
+                self.pos += 1;
+                self.skip_separators();
+            }
+            let Some(start) = self.tokens.get(self.pos) else {
+                return errors;
+            };
+            let line = start.line;
+            let outcome = self.parse_statement().and_then(|_| match self.peek() {
+                None | Some(TokenKind::Newline) | Some(TokenKind::Semicolon) => Ok(()),
+                Some(_) => Err(self.error("end of statement")),
+            });
+            recovering = outcome.is_err();
+            if let Err(error) = outcome {
+                errors.push((line, error));
+                self.recover();
+            }
+        }
+    }
+
+    // Skip to the end of the statement the parser stopped in: the next
+    // newline or semicolon outside brackets opened since. Closers with no
+    // opener there belong to brackets opened before, and are passed over.
+    fn recover(&mut self) {
+        let mut depth = 0usize;
+        while let Some(kind) = self.peek() {
+            match kind {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth = depth.saturating_sub(1),
+                TokenKind::Newline | TokenKind::Semicolon if depth == 0 => return,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+
+    // An expression, or an assignment: x = value or map[key] = value. The
+    // compound forms x += value and map[key] -= value read the target first.
+    fn parse_statement(&mut self) -> Result<Expr, String> {
+        // let x = value, or const x = value for a binding that cannot change
+        for keyword in ["let", "const"] {
+            if self.eat_keyword(keyword) {
+                let name = self.name(&format!("a name after {}", keyword))?;
+                self.expect(&TokenKind::Assign)?;
+                let value = self.parse_expr()?;
+                return Ok(Expr::Let(name, Box::new(value), keyword == "const"));
+            }
+        }
+        // [a, b] = array or {x, y} = map
+        let start = self.pos;
+        if let Some(pattern) = self.pattern()
+            && self.eat(&TokenKind::Assign)
+        {
+            let value = self.parse_expr()?;
+            return Ok(Expr::Unpack(pattern, Box::new(value)));
+        }
+        self.pos = start;
+        let expr = self.parse_expr()?;
+        let compound: Option<Binary> = match self.peek() {
+            Some(TokenKind::Assign) => None,
+            Some(TokenKind::PlusAssign) => Some(Expr::Add),
+            Some(TokenKind::MinusAssign) => Some(Expr::Sub),
+            Some(TokenKind::StarAssign) => Some(Expr::Mul),
+            Some(TokenKind::SlashAssign) => Some(Expr::Div),
+            _ => return Ok(expr),
+        };
+        let name = match &expr {
+            Expr::Var(name) => name.clone(),
+            Expr::Get(target, _) if let Expr::Var(name) = target.as_ref() => name.clone(),
+            _ => return Err(self.error("end of statement")),
+        };
+        self.pos += 1;
+        let value = self.parse_expr()?;
+        let value = Box::new(match compound {
+            Some(make) => make(Box::new(expr.clone()), Box::new(value)),
+            None => value,
+        });
+        Ok(match expr {
+            Expr::Get(_, key) => Expr::SetIndex(name, key, value),
+            _ => Expr::Assign(name, value),
+        })
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_default()?;
+        while self.eat(&TokenKind::Pipe) {
+            let right = self.parse_default()?;
+            left = Expr::Choice(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // a ?? b, b only when a is none
+    fn parse_default(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_or()?;
+        while self.eat(&TokenKind::Coalesce) {
+            let right = self.parse_or()?;
+            left = Expr::Default(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // a or b / a || b
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while self.eat(&TokenKind::OrOr) || self.eat_keyword("or") {
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // a and b / a && b
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_not()?;
+        while self.eat(&TokenKind::AndAnd) || self.eat_keyword("and") {
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // not a / !a
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.eat(&TokenKind::Bang) || self.eat_keyword("not") {
+            let operand = self.parse_not()?;
+            return Ok(Expr::Not(Box::new(operand)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_pipeline()?;
+        loop {
+            let make: fn(Box<Expr>, Box<Expr>) -> Expr = match self.peek() {
+                Some(TokenKind::Less) => Expr::Less,
+                Some(TokenKind::Greater) => Expr::Greater,
+                Some(TokenKind::EqEq) => Expr::Equal,
+                Some(TokenKind::NotEq) => |l, r| Expr::Not(Box::new(Expr::Equal(l, r))),
+                _ => break,
+            };
+            self.pos += 1;
+            let right = self.parse_pipeline()?;
+            left = make(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // x |> f is f(x) and x |> g(2) is g(x, 2), so x |> f |> g(2) is g(f(x), 2)
+    fn parse_pipeline(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_range()?;
+        while self.eat(&TokenKind::Pipeline) {
+            let at = self.pos;
+            left = match self.parse_range()? {
+                Expr::Var(name) => Expr::Call(name, vec![left], self.site(at)),
+                Expr::Call(name, mut args, site) => {
+                    args.insert(0, left);
+                    Expr::Call(name, args, site)
+                }
+                _ => return Err(self.site(at).locate("Expected a function name or call after |>".to_string())),
+            };
+        }
+        Ok(left)
+    }
+
+    // a..b (end excluded) or a..=b (end included)
+    fn parse_range(&mut self) -> Result<Expr, String> {
+        let start = self.parse_additive()?;
+        let inclusive = match self.peek() {
+            Some(TokenKind::DotDot) => false,
+            Some(TokenKind::DotDotEq) => true,
+            _ => return Ok(start),
+        };
+        self.pos += 1;
+        let end = self.parse_additive()?;
+        Ok(Expr::Range(Box::new(start), Box::new(end), inclusive))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_term()?;
+        loop {
+            let make: fn(Box<Expr>, Box<Expr>) -> Expr = match self.peek() {
+                Some(TokenKind::Plus) => Expr::Add,
+                Some(TokenKind::Minus) => Expr::Sub,
+                _ => break,
+            };
+            self.pos += 1;
+            let right = self.parse_term()?;
+            left = make(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let make: fn(Box<Expr>, Box<Expr>) -> Expr = match self.peek() {
+                Some(TokenKind::Star) => Expr::Mul,
+                Some(TokenKind::Slash) => Expr::Div,
+                Some(TokenKind::Percent) => Expr::Mod,
+                _ => break,
+            };
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = make(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.eat(&TokenKind::Minus) {
+            return Ok(match self.parse_unary()? {
+                Expr::Value(Value::Num(n)) => Expr::Value(Value::Num(-n)),
+                operand => Expr::Sub(Box::new(Expr::Value(Value::Num(0.0))), Box::new(operand)),
+            });
+        }
+        self.parse_power()
+    }
+
+    // Binds tighter than unary minus on the left: -2 ** 2 == -4, 2 ** -1 == 0.5
+    fn parse_power(&mut self) -> Result<Expr, String> {
+        let primary = self.parse_primary()?;
+        let base = self.parse_postfix(primary)?;
+        if self.eat(&TokenKind::Power) {
+            let exponent = self.parse_unary()?;
+            return Ok(Expr::Pow(Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_postfix(&mut self, mut expr: Expr) -> Result<Expr, String> {
+        loop {
+            // value.name(args) is name(value, args)
+            if self.peek() == Some(&TokenKind::Dot)
+                && let Some(TokenKind::Ident(name)) = self.tokens.get(self.pos + 1).map(|t| &t.kind)
+                && self.tokens.get(self.pos + 2).map(|t| &t.kind) == Some(&TokenKind::LParen)
+            {
+                let name = name.clone();
+                let site = self.site(self.pos + 1);
+                self.pos += 3;
+                let mut args = vec![expr];
+                args.extend(self.parse_list(&TokenKind::RParen)?);
+                expr = Expr::Call(name, args, site);
+                continue;
+            }
+            // value.field, or value?.field for none rather than an error when
+            // the value is none or the field is missing
+            if self.eat(&TokenKind::Dot) {
+                let field = self.name("a field name after .")?;
+                expr = Expr::Field(Box::new(expr), field);
+                continue;
+            }
+            if self.eat(&TokenKind::SafeDot) {
+                let field = self.name("a field name after ?.")?;
+                expr = Expr::SafeField(Box::new(expr), field);
+                continue;
+            }
+            if !self.eat(&TokenKind::LBracket) {
+                break;
+            }
+            self.skip_newlines();
+            let start = match self.peek() {
+                Some(TokenKind::Colon) => None,
+                _ => Some(Box::new(self.parse_expr()?)),
+            };
+            self.skip_newlines();
+            // arr[start:end] with either bound optional
+            if self.eat(&TokenKind::Colon) {
+                self.skip_newlines();
+                let end = match self.peek() {
+                    Some(TokenKind::RBracket) => None,
+                    _ => Some(Box::new(self.parse_expr()?)),
+                };
+                self.skip_newlines();
+                self.expect(&TokenKind::RBracket)?;
+                expr = Expr::Slice(Box::new(expr), start, end);
+                continue;
+            }
+            self.expect(&TokenKind::RBracket)?;
+            // start is always present when there is no colon
+            expr = Expr::Get(Box::new(expr), start.unwrap());
+        }
+        Ok(expr)
+    }
+
+    pub fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token.kind.clone(),
+            None => return Err("Unexpected end of input".to_string()),
+        };
+        self.pos += 1;
+
+        match token {
+            TokenKind::Num(n) => Ok(Expr::Value(Value::Num(n))),
+            TokenKind::Str(s) => Ok(Expr::Value(Value::Str(s.into()))),
+            // `cmd` is sh("cmd")
+            TokenKind::Command(cmd) => Ok(Expr::Call("sh".to_string(), vec![Expr::Value(Value::Str(cmd.into()))], self.site(self.pos - 1))),
+            TokenKind::LParen => {
+                self.skip_newlines();
+                let expr = self.parse_expr()?;
+                self.skip_newlines();
+                self.expect(&TokenKind::RParen)?;
+                Ok(expr)
+            }
+            TokenKind::LBracket => self.parse_array_or_map(),
+            TokenKind::LBrace => self.parse_block_body(),
+            TokenKind::Ident(name) => match name.as_str() {
+                "true" => Ok(Expr::Value(Value::Bool(true))),
+                "none" => Ok(Expr::Value(Value::None)),
+                "false" => Ok(Expr::Value(Value::Bool(false))),
+                // resume(g) advances a generator; bare resume runs the top continuation
+                "resume" if self.peek() != Some(&TokenKind::LParen) => Ok(Expr::Resume),
+                "break" => Ok(Expr::Break),
+                "suspend" => {
+                    let start = self.pos;
+                    self.parse_expr()?;
+                    Ok(Expr::Suspend(source_text(&self.tokens[start..self.pos])))
+                }
+                "for" => self.parse_for(),
+                "while" => self.parse_while(),
+                "try" => self.parse_try(),
+                "fn" => self.parse_lambda(),
+                "gen" => self.parse_generator(),
+                "struct" => self.parse_struct(),
+                "enum" => self.parse_enum(),
+                "match" => self.parse_match(),
+                "yield" => Ok(Expr::Yield(Box::new(self.parse_expr()?))),
+                "scope" => Ok(Expr::Scope(Box::new(self.parse_block()?))),
+                // A bare return gives unit
+                "return" => match self.peek() {
+                    None | Some(TokenKind::Newline) | Some(TokenKind::Semicolon) | Some(TokenKind::RBrace) => {
+                        Ok(Expr::Return(Box::new(Expr::Value(Value::Unit))))
+                    }
+                    _ => Ok(Expr::Return(Box::new(self.parse_expr()?))),
+                },
+                "throw" => Ok(Expr::Throw(Box::new(self.parse_expr()?))),
+                // spawn joins the enclosing scope; async is Tau's future, left
+                // to whoever awaits it
+                "spawn" | "async" => {
+                    let keyword = &self.tokens[self.pos - 1];
+                    let site = format!("{}:{}", keyword.line, keyword.col);
+                    let start = self.pos;
+                    let task = self.parse_expr()?;
+                    let source = source_text(&self.tokens[start..self.pos]);
+                    Ok(if name == "spawn" { Expr::Spawn(source, site, Box::new(task)) } else { Expr::Async(source, site, Box::new(task)) })
+                }
+                // await takes one operand, method calls included, so
+                // await f.then(g) + 1 adds to the chain's result
+                "await" => {
+                    let site = self.site(self.pos - 1);
+                    Ok(Expr::Call(name, vec![self.parse_unary()?], site))
+                }
+                _ if self.peek() == Some(&TokenKind::LParen) => {
+                    let site = self.site(self.pos - 1);
+                    self.pos += 1;
+                    let args = self.parse_list(&TokenKind::RParen)?;
+                    Ok(Expr::Call(name, args, site))
+                }
+                _ => Ok(Expr::Var(name)),
+            },
+            other => {
+                self.pos -= 1;
+                Err(self.error_found("an expression", &other))
+            }
+        }
+    }
+
+    // After '[': either [a, b, c] or a map [{k, v}, {k, v}]
+    fn parse_array_or_map(&mut self) -> Result<Expr, String> {
+        self.skip_newlines();
+        if self.peek() == Some(&TokenKind::LBrace) {
+            let mut pairs = Vec::new();
+            loop {
+                self.skip_newlines();
+                self.expect(&TokenKind::LBrace)?;
+                let key = self.parse_expr()?;
+                self.expect(&TokenKind::Comma)?;
+                let value = self.parse_expr()?;
+                self.expect(&TokenKind::RBrace)?;
+                pairs.push((key, value));
+                self.skip_newlines();
+                if !self.eat(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.skip_newlines();
+            self.expect(&TokenKind::RBracket)?;
+            return Ok(Expr::Map(pairs));
+        }
+        Ok(Expr::Array(self.parse_list(&TokenKind::RBracket)?))
+    }
+
+    // Comma separated expressions up to (and including) the closing token
+    fn parse_list(&mut self, close: &TokenKind) -> Result<Vec<Expr>, String> {
+        let mut items = Vec::new();
+        self.skip_newlines();
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            self.skip_newlines();
+            items.push(self.parse_expr()?);
+            self.skip_newlines();
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(&TokenKind::Comma)?;
+        }
+    }
+
+    // After '{': statements until the matching '}'
+    fn parse_block_body(&mut self) -> Result<Expr, String> {
+        let mut exprs = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.eat(&TokenKind::RBrace) {
+                return Ok(Expr::Block(exprs));
+            }
+            if self.peek().is_none() {
+                return Err(self.error("}"));
+            }
+            exprs.push(self.parse_statement()?);
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Expr, String> {
+        self.skip_newlines();
+        self.expect(&TokenKind::LBrace)?;
+        self.parse_block_body()
+    }
+
+    // fn(a, b) body - the body is any expression, including a { block }
+    fn parse_lambda(&mut self) -> Result<Expr, String> {
+        let params = self.parse_params()?;
+        let body = self.parse_expr()?;
+        Ok(Expr::Lambda(params, Box::new(body)))
+    }
+
+    // gen name(a, b) body, or gen(a, b) body for an anonymous generator
+    fn parse_generator(&mut self) -> Result<Expr, String> {
+        let name = match self.peek() {
+            Some(TokenKind::Ident(name)) => Some(name.clone()),
+            _ => None,
+        };
+        if name.is_some() {
+            self.pos += 1;
+        }
+        let params = self.parse_params()?;
+        let body = self.parse_expr()?;
+        Ok(Expr::Generator(name, params, Box::new(body)))
+    }
+
+    // struct Point(x, y), declaring Point(1, 2) and its fields p.x and p.y
+    fn parse_struct(&mut self) -> Result<Expr, String> {
+        let name = self.name("a struct name")?;
+        let fields = self.fields(&format!("struct {}", name))?;
+        Ok(Expr::Struct(name, fields))
+    }
+
+    // enum Shape = Circle(r) | Rect(w, h) | Empty
+    fn parse_enum(&mut self) -> Result<Expr, String> {
+        let name = self.name("an enum name")?;
+        self.expect(&TokenKind::Assign)?;
+        let mut variants: Vec<(String, Option<Vec<String>>)> = Vec::new();
+        loop {
+            self.skip_newlines();
+            let variant = self.name("a variant name")?;
+            if variants.iter().any(|(other, _)| *other == variant) {
+                return Err(format!("enum {} has two variants named {}", name, variant));
+            }
+            let fields = match self.peek() {
+                Some(TokenKind::LParen) => Some(self.fields(&variant)?),
+                _ => None,
+            };
+            variants.push((variant, fields));
+            if !self.eat(&TokenKind::Pipe) {
+                return Ok(Expr::Enum(name, variants));
+            }
+        }
+    }
+
+    // The field names of a struct or variant, in parentheses
+    fn fields(&mut self, owner: &str) -> Result<Vec<String>, String> {
+        let fields = self.parse_params()?;
+        if let Some(field) = fields.iter().enumerate().find_map(|(i, field)| fields[..i].contains(field).then_some(field)) {
+            return Err(format!("{} has two fields named {}", owner, field));
+        }
+        Ok(fields)
+    }
+
+    // match value { Circle(r) => body, _ => other }, the arms separated by
+    // commas or newlines
+    fn parse_match(&mut self) -> Result<Expr, String> {
+        let value = self.parse_expr()?;
+        self.skip_newlines();
+        self.expect(&TokenKind::LBrace)?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.eat(&TokenKind::RBrace) {
+                return Ok(Expr::Match(Box::new(value), arms));
+            }
+            let case = self.case()?;
+            self.expect(&TokenKind::FatArrow)?;
+            let body = self.parse_expr()?;
+            arms.push((case, body));
+            self.eat(&TokenKind::Comma);
+        }
+    }
+
+    // A match arm's pattern: _, a literal, Circle(r, _) or a bare Circle
+    fn case(&mut self) -> Result<Case, String> {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) if name == "_" => {
+                self.pos += 1;
+                Ok(Case::Any)
+            }
+            Some(TokenKind::Ident(name)) if name != "true" && name != "false" => {
+                let name = name.clone();
+                self.pos += 1;
+                let names = match self.peek() {
+                    Some(TokenKind::LParen) => Some(self.parse_params()?),
+                    _ => None,
+                };
+                Ok(Case::Variant(name, names))
+            }
+            Some(TokenKind::Ident(_) | TokenKind::Num(_) | TokenKind::Str(_) | TokenKind::Minus) => match self.parse_unary()? {
+                Expr::Value(value) => Ok(Case::Literal(value)),
+                _ => Err(self.error("=>")),
+            },
+            _ => Err(self.error("a pattern")),
+        }
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<String>, String> {
+        self.expect(&TokenKind::LParen)?;
+        let mut params = Vec::new();
+        while !self.eat(&TokenKind::RParen) {
+            match self.peek() {
+                Some(TokenKind::Ident(name)) => params.push(name.clone()),
+                _ => return Err(self.error("parameter name")),
+            }
+            self.pos += 1;
+            if !self.eat(&TokenKind::Comma) {
+                self.expect(&TokenKind::RParen)?;
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    // for var in iterable { body }, for key, value in map { body } or
+    // for [a, b] in items { body }
+    fn parse_for(&mut self) -> Result<Expr, String> {
+        if matches!(self.peek(), Some(TokenKind::LBracket | TokenKind::LBrace)) {
+            let pattern = self.pattern().ok_or_else(|| self.error("loop variables"))?;
+            if !self.eat_keyword("in") {
+                return Err(self.error("in"));
+            }
+            let iterable = self.parse_expr()?;
+            let body = self.parse_block()?;
+            return Ok(Expr::ForUnpack(pattern, Box::new(iterable), Box::new(body)));
+        }
+        let var = self.name("loop variable")?;
+        let value_var = if self.eat(&TokenKind::Comma) { Some(self.name("loop variable")?) } else { None };
+        if !self.eat_keyword("in") {
+            return Err(self.error("in"));
+        }
+        let iterable = self.parse_expr()?;
+        let body = self.parse_block()?;
+        Ok(match value_var {
+            Some(value_var) => Expr::ForEntries(var, value_var, Box::new(iterable), Box::new(body)),
+            None => Expr::For(var, Box::new(iterable), Box::new(body)),
+        })
+    }
+
+    fn name(&mut self, what: &str) -> Result<String, String> {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error(what)),
+        }
+    }
+
+    // while condition { body }
+    fn parse_while(&mut self) -> Result<Expr, String> {
+        let condition = self.parse_expr()?;
+        let body = self.parse_block()?;
+        Ok(Expr::While(Box::new(condition), Box::new(body)))
+    }
+
+    // try { body } catch e { handler } finally { cleanup }, with either clause
+    // left out but not both; each may start on a line of its own
+    fn parse_try(&mut self) -> Result<Expr, String> {
+        let body = self.parse_block()?;
+        let catch = if self.eat_clause("catch") {
+            let name = self.name("error variable")?;
+            Some((name, Box::new(self.parse_block()?)))
+        } else {
+            None
+        };
+        let finally = if self.eat_clause("finally") { Some(Box::new(self.parse_block()?)) } else { None };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error("catch or finally"));
+        }
+        Ok(Expr::Try(Box::new(body), catch, finally))
+    }
+
+    // [a, b] or {x, y}: names in brackets or braces. None when the tokens
+    // here are not one, leaving pos where it stopped.
+    fn pattern(&mut self) -> Option<Pattern> {
+        let close = match self.peek()? {
+            TokenKind::LBracket => TokenKind::RBracket,
+            TokenKind::LBrace => TokenKind::RBrace,
+            _ => return None,
+        };
+        self.pos += 1;
+        let mut names = Vec::new();
+        loop {
+            match self.peek()? {
+                TokenKind::Ident(name) => names.push(name.clone()),
+                _ => return None,
+            }
+            self.pos += 1;
+            if self.eat(&close) {
+                break;
+            }
+            if !self.eat(&TokenKind::Comma) {
+                return None;
+            }
+        }
+        Some(match close {
+            TokenKind::RBracket => Pattern::Array(names),
+            _ => Pattern::Map(names),
+        })
+    }
+
+    // The keyword, even after line breaks; the breaks stay if it is not there
+    fn eat_clause(&mut self, keyword: &str) -> bool {
+        let start = self.pos;
+        self.skip_newlines();
+        if self.eat_keyword(keyword) {
+            return true;
+        }
+        self.pos = start;
+        false
+    }
+
+    // Where the token at index is, for a call's errors
+    fn site(&self, index: usize) -> Site {
+        self.tokens.get(index).map_or_else(Site::default, |token| Site::new(token.line, token.col))
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(TokenKind::Ident(name)) if name == keyword => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), String> {
+        if self.eat(kind) {
+            Ok(())
+        } else {
+            Err(self.error(&kind.to_string()))
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek(), Some(TokenKind::Newline) | Some(TokenKind::Indent(_))) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(
+            self.peek(),
+            Some(TokenKind::Newline) | Some(TokenKind::Indent(_)) | Some(TokenKind::Semicolon)
+        ) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, expected: &str) -> String {
+        match self.tokens.get(self.pos) {
+            Some(token) => self.error_found(expected, &token.kind),
+            None => format!("Expected {}, found end of input", expected),
+        }
+    }
+
+    fn error_found(&self, expected: &str, found: &TokenKind) -> String {
+        let token = &self.tokens[self.pos];
+        format!("Expected {} at {}:{}, found {}", expected, token.line, token.col, found)
+    }
+}
+
+// Rebuild readable source from tokens, e.g. for naming spawned tasks
+fn source_text(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&TokenKind> = None;
+    for token in tokens {
+        let tight = matches!(prev, None | Some(TokenKind::LParen) | Some(TokenKind::LBracket))
+            || matches!(
+                token.kind,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::Comma | TokenKind::LBracket
+            )
+            || (token.kind == TokenKind::LParen && matches!(prev, Some(TokenKind::Ident(_))));
+        if !tight {
+            out.push(' ');
+        }
+        out.push_str(&token.kind.to_string());
+        prev = Some(&token.kind);
+    }
+    out
 }
@@ -0,0 +1,128 @@
+// Agents served over TCP - `agent "calc.tsu" 9000` answers calls to the
+// interface's methods with the session's functions of the same names, and
+// `proxy "calc.tsu" "host:9000"` is the other end (see transport.rs). Agents
+// never block the session: pending requests are answered whenever the REPL
+// is between inputs, or continuously under --daemon.
+use crate::interface::Interface;
+use crate::transport::{decode_message, dispatch, encode_message, Message, Service};
+use crate::value::Value;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+struct Connection {
+    stream: TcpStream,
+    pending: Vec<u8>,  // Bytes of a line still arriving
+}
+
+pub struct Agent {
+    pub interface: Interface,
+    pub address: SocketAddr,
+    listener: TcpListener,
+    connections: Vec<Connection>,
+}
+
+// Adapts the session's call handler to the transport's Service
+struct Handler<'a, F>(&'a Interface, &'a mut F);
+
+impl<F: FnMut(&Interface, &str, Vec<Value>) -> Result<Value, String>> Service for Handler<'_, F> {
+    fn handle(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.0.check_call(method, &args)?;
+        let value = (self.1)(self.0, method, args)?;
+        self.0.check_return(method, value)
+    }
+}
+
+impl Agent {
+    // Answer every complete request that has arrived, returning how many
+    fn poll<F>(&mut self, handle: &mut F) -> usize
+    where
+        F: FnMut(&Interface, &str, Vec<Value>) -> Result<Value, String>,
+    {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.connections.push(Connection { stream, pending: Vec::new() });
+            }
+        }
+        let mut answered = 0;
+        let interface = &self.interface;
+        self.connections.retain_mut(|connection| {
+            let mut open = read_available(connection);
+            while let Some(end) = connection.pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = connection.pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let reply = match decode_message(line.trim_end()) {
+                    Ok(message) => dispatch(&mut Handler(interface, handle), message),
+                    Err(_) => None,  // Nothing to answer a line that is not a message
+                };
+                if let Some(reply) = reply {
+                    answered += 1;
+                    open = open && write_line(&connection.stream, &reply);
+                }
+            }
+            open
+        });
+        answered
+    }
+}
+
+// Move whatever has arrived into the pending buffer; false once the peer is gone
+fn read_available(connection: &mut Connection) -> bool {
+    let mut buffer = [0u8; 4096];
+    loop {
+        match connection.stream.read(&mut buffer) {
+            Ok(0) => return false,
+            Ok(n) => connection.pending.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return true,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+// Replies are written blocking, so a slow reader cannot see half a line
+fn write_line(mut stream: &TcpStream, reply: &Message) -> bool {
+    let Ok(line) = encode_message(reply) else {
+        return false;
+    };
+    stream.set_nonblocking(false).is_ok()
+        && writeln!(stream, "{}", line).and_then(|_| stream.flush()).is_ok()
+        && stream.set_nonblocking(true).is_ok()
+}
+
+// Every agent this session serves
+#[derive(Default)]
+pub struct Agents {
+    agents: Vec<Agent>,
+}
+
+impl Agents {
+    // Start serving `interface` on `address`, returning the address bound
+    pub fn serve(&mut self, interface: Interface, address: &str) -> Result<SocketAddr, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let address = listener.local_addr().map_err(|e| e.to_string())?;
+        self.agents.push(Agent { interface, address, listener, connections: Vec::new() });
+        Ok(address)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Agent> {
+        self.agents.iter()
+    }
+
+    // Answer what has arrived for every agent, without waiting for more
+    pub fn poll<F>(&mut self, mut handle: F) -> usize
+    where
+        F: FnMut(&Interface, &str, Vec<Value>) -> Result<Value, String>,
+    {
+        self.agents.iter_mut().map(|agent| agent.poll(&mut handle)).sum()
+    }
+
+    // Agents started while others were being polled join the rest
+    pub fn append(&mut self, other: &mut Agents) {
+        self.agents.append(&mut other.agents);
+    }
+}
@@ -0,0 +1,1573 @@
+use crate::builtins;
+use crate::config::Config;
+use crate::executor::{Executor, Task};
+use crate::generator::Generator;
+use crate::http::{self, Response};
+use crate::httpcache::{self, Limiter};
+use crate::interface::Interface;
+use crate::metrics::Metrics;
+use crate::mqtt::Clients;
+use crate::process;
+use crate::provenance::Provenances;
+use crate::events::{Bus, Event};
+use crate::pubsub::Broker;
+use crate::rpc::Agents;
+use crate::schedule::Scheduler;
+use crate::shell::Batch;
+use crate::signatures;
+use crate::tau::{self, Combinator};
+use crate::{pi, rho};
+use crate::tracing::Tracer;
+use crate::value::{Continuation, Function, Future, FutureState, Map, Range, Struct, TaskError, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Expression types
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Value(Value),
+    Var(String),                   // Variable lookup
+    Array(Vec<Expr>),              // [a, b, c]
+    Map(Vec<(Expr, Expr)>),        // [{k, v}, ...]
+    Range(Box<Expr>, Box<Expr>, bool),  // a..b, or a..=b when the flag is set
+    Call(String, Vec<Expr>, Site),  // Builtin or lambda call: name(args)
+    Lambda(Vec<String>, Box<Expr>), // fn(a, b) body
+    Generator(Option<String>, Vec<String>, Box<Expr>),  // gen name(a, b) body, binding name if given
+    Yield(Box<Expr>),              // yield expr, only inside a generator body
+    Struct(String, Vec<String>),   // struct Point(x, y), binding Point to its constructor
+    Enum(String, Vec<(String, Option<Vec<String>>)>),  // enum Shape = Circle(r) | Empty, binding each variant
+    Record(String, Option<String>, Vec<String>),  // A struct, or a variant of the named enum, of the variables named by its fields; a constructor's body
+    Match(Box<Expr>, Vec<(Case, Expr)>),  // match value { Circle(r) => body, _ => other }
+    Field(Box<Expr>, String),      // value.field
+    SafeField(Box<Expr>, String),  // value?.field, none when there is no such field
+    Default(Box<Expr>, Box<Expr>), // a ?? b
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Mod(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    // Comparisons
+    Less(Box<Expr>, Box<Expr>),
+    Greater(Box<Expr>, Box<Expr>),
+    Equal(Box<Expr>, Box<Expr>),
+    // Logical operators (And/Or short-circuit)
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    // Color operations
+    Blend(Box<Expr>, Box<Expr>),   // Blend two colors
+    Scale(Box<Expr>, f32),          // Scale a color
+    // Array/Map operations
+    Get(Box<Expr>, Box<Expr>),     // Array/Map indexing: arr[index] or map[key]
+    Assign(String, Box<Expr>),     // name = value
+    Unpack(Pattern, Box<Expr>),    // [a, b] = array or {x, y} = map
+    Let(String, Box<Expr>, bool),  // let name = value, or const name = value when the flag is set
+    SetIndex(String, Box<Expr>, Box<Expr>),  // map[key] = value or arr[i] = value on a variable
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),  // arr[start:end], either bound optional
+    // Continuation algebra operations
+    Compose(Box<Expr>, Box<Expr>),  // Continuation composition: c1 ; c2
+    Choice(Box<Expr>, Box<Expr>),   // Continuation choice: c1 | c2
+    Resume,                         // Run the top continuation
+    Suspend(String),                // suspend expr - push its source as a Rho continuation
+    Break,                          // Drop the continuation stack
+    // Loop constructs
+    For(String, Box<Expr>, Box<Expr>),     // for var in iterable { body }
+    ForEntries(String, String, Box<Expr>, Box<Expr>),  // for k, v in map { body }
+    ForUnpack(Pattern, Box<Expr>, Box<Expr>),  // for [k, v] in pairs { body }
+    While(Box<Expr>, Box<Expr>),           // while condition { body }
+    Block(Vec<Expr>),                      // { expr1; expr2; ... }
+    Return(Box<Expr>),                     // return expr, unwinds to the enclosing call
+    Throw(Box<Expr>),                      // throw expr, unwinds to the nearest catch
+    Try(Box<Expr>, Option<(String, Box<Expr>)>, Option<Box<Expr>>),  // try { body } catch e { handler } finally { cleanup }
+    // Structured concurrency
+    Spawn(String, String, Box<Expr>),      // spawn expr (source text, line:col, task)
+    Async(String, String, Box<Expr>),      // async expr, a future not tied to any scope
+    Scope(Box<Expr>),                      // scope { spawn a; spawn b }
+}
+
+// The names a destructuring assignment binds: [a, b] takes an array's items
+// in order, {x, y} takes a map's values for the keys "x" and "y"
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Array(Vec<String>),
+    Map(Vec<String>),
+}
+
+impl Pattern {
+    // Each name with its part of the value
+    pub fn unpack(&self, value: Value) -> Result<Vec<(String, Value)>, String> {
+        match (self, value) {
+            (Pattern::Array(names), Value::Array(items)) if names.len() == items.len() => {
+                Ok(names.iter().cloned().zip(items).collect())
+            }
+            (Pattern::Array(names), Value::Array(items)) => {
+                Err(format!("Expected {} items to unpack, got {}", names.len(), items.len()))
+            }
+            (Pattern::Map(names), Value::Map(map)) => names
+                .iter()
+                .map(|name| match map.get(&Value::Str(name.as_str().into())) {
+                    Some(value) => Ok((name.clone(), value.clone())),
+                    None => Err(format!("Map has no key {}", name)),
+                })
+                .collect(),
+            (Pattern::Array(_), other) => Err(format!("Cannot unpack {:?} as an array", other)),
+            (Pattern::Map(_), other) => Err(format!("Cannot unpack {:?} as a map", other)),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Array(names) => write!(f, "[{}]", names.join(", ")),
+            Pattern::Map(names) => write!(f, "{{{}}}", names.join(", ")),
+        }
+    }
+}
+
+// One arm's test in a match: _ takes anything, a literal an equal value,
+// Circle(r) a Circle, binding its fields in order, and a bare Circle any Circle
+#[derive(Debug, Clone, PartialEq)]
+pub enum Case {
+    Any,
+    Literal(Value),
+    Variant(String, Option<Vec<String>>),
+}
+
+impl Case {
+    // The names to bind when the value fits, or None
+    pub fn bind(&self, value: &Value) -> Result<Option<Vec<(String, Value)>>, String> {
+        match (self, value) {
+            (Case::Any, _) => Ok(Some(Vec::new())),
+            (Case::Literal(literal), value) => Ok((literal == value).then(Vec::new)),
+            (Case::Variant(name, names), Value::Struct(record)) if *record.name == **name => match names {
+                None => Ok(Some(Vec::new())),
+                Some(names) if names.len() == record.fields.len() => Ok(Some(
+                    names
+                        .iter()
+                        .zip(record.fields.iter())
+                        .filter(|(name, _)| *name != "_")
+                        .map(|(name, (_, value))| (name.clone(), value.clone()))
+                        .collect(),
+                )),
+                Some(names) => Err(format!("Pattern {}({}) does not fit {}", name, names.join(", "), value)),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+// Where a call was written, as line:col in its input. Calls the runtime
+// makes itself have the default, line 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Site {
+    pub line: u32,
+    pub col: u32,
+}
+
+impl Site {
+    pub fn new(line: usize, col: usize) -> Site {
+        Site { line: line as u32, col: col as u32 }
+    }
+
+    // The error, saying where the call is when that is known
+    pub fn locate(self, error: String) -> String {
+        match self.line {
+            0 => error,
+            _ => format!("{} at {}:{}", error, self.line, self.col),
+        }
+    }
+}
+
+// Language modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Pi,   // Postfix/RPN notation
+    Rho,  // Infix with tab indentation
+    Tau,  // Network language with futures
+}
+
+impl Language {
+    // pi, rho or tsu
+    pub fn from_extension(extension: &str) -> Option<Language> {
+        match extension {
+            "pi" => Some(Language::Pi),
+            "rho" => Some(Language::Rho),
+            "tsu" => Some(Language::Tau),
+            _ => None,
+        }
+    }
+
+    // pi, rho or tau, as in :lang rho
+    pub fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "pi" => Some(Language::Pi),
+            "rho" => Some(Language::Rho),
+            "tau" => Some(Language::Tau),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Language::Pi => "Pi",
+            Language::Rho => "Rho",
+            Language::Tau => "Tau",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Continuation stack - holds suspended computations
+pub struct ContinuationStack {
+    stack: Vec<Continuation>,
+}
+
+impl Default for ContinuationStack {
+    fn default() -> Self {
+        ContinuationStack::new()
+    }
+}
+
+impl ContinuationStack {
+    pub fn new() -> Self {
+        ContinuationStack { stack: Vec::new() }
+    }
+
+    pub fn push(&mut self, cont: Continuation) {
+        self.stack.push(cont);
+    }
+
+    pub fn pop(&mut self) -> Option<Continuation> {
+        self.stack.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    // The language each pending continuation was suspended in, bottom first;
+    // continuations pushed from Rust have none
+    pub fn languages(&self) -> Vec<Option<Language>> {
+        self.stack.iter().map(Continuation::language).collect()
+    }
+}
+
+// Control flow operations
+pub enum ControlFlow {
+    Resume,           // Execute what's on the continuation stack
+    Break,            // Drop continuation stack and resume next
+    Continue(Value),  // Takes a continuation as argument
+}
+
+// Why evaluation stopped early: an error, a value thrown by the script, a
+// return unwinding to the nearest function call, or an escape unwinding to
+// the callcc that captured it
+#[derive(Debug)]
+pub enum Signal {
+    Error(String),
+    Throw(Value),
+    Return(Value),
+    Escape(u64, Value),
+}
+
+impl From<String> for Signal {
+    fn from(e: String) -> Self {
+        Signal::Error(e)
+    }
+}
+
+// The let and const bindings of a running block: name to value, and
+// whether it is a const
+pub type Scope = HashMap<String, (Value, bool)>;
+
+// Runtime context
+pub struct Runtime {
+    pub(crate) cont_stack: ContinuationStack,
+    pub(crate) variables: HashMap<String, Value>,  // Bound outside any block
+    pub(crate) scopes: Vec<Scope>,  // Blocks running that declare with let or const, innermost last
+    pub(crate) constants: HashSet<String>,  // Variables declared with const outside any block
+    pub(crate) force_assign: bool,  // Let variables take builtin and keyword names, see :force-assign
+    pub(crate) warnings: Vec<String>,  // For the REPL to print after the input, e.g. a function taking a builtin's name
+    pub config: Config,
+    pub(crate) executor: Executor,
+    deadline: Option<(Instant, Duration)>,  // When the current evaluation must stop, and its limit
+    escapes: Vec<u64>,           // Continuations captured by callcc calls still running, innermost last
+    next_escape: u64,
+    unwinding: Option<Signal>,   // An escape crossing a builtin, which only passes Strings
+    pub(crate) tracer: Tracer,   // Spans for task spawn/resolve, shared with connected proxies
+    pub(crate) metrics: Metrics, // counter/gauge/timer values, see metrics.rs
+    pub(crate) scheduler: Scheduler,    // Recurring handlers from schedule(), see schedule.rs
+    pub(crate) agents: Agents,   // Interfaces served over TCP, see rpc.rs
+    pub(crate) broker: Broker,   // Topics for publish/subscribe, see pubsub.rs
+    pub(crate) events: Bus,      // Session events and their subscribers, see events.rs
+    pub(crate) provenance: Rc<RefCell<Provenances>>,  // Where each variable was bound, following events while config.provenance is on
+    following: Option<u64>,      // Provenance's subscription
+    pub(crate) limiter: Limiter, // Per-host spacing for http_get_cached, see httpcache.rs
+    pub(crate) mqtt: Clients,    // Connections whose subscription handlers run between inputs, see mqtt.rs
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime::new()
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Runtime::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        let tracer = Tracer::default();
+        tracer.set_enabled(config.trace);
+        let mut runtime = Runtime {
+            cont_stack: ContinuationStack::new(),
+            variables: HashMap::new(),
+            config,
+            executor: Executor::new(),
+            deadline: None,
+            escapes: Vec::new(),
+            next_escape: 1,
+            unwinding: None,
+            scopes: Vec::new(),
+            constants: HashSet::new(),
+            force_assign: false,
+            warnings: Vec::new(),
+            tracer,
+            metrics: Metrics::default(),
+            scheduler: Scheduler::default(),
+            agents: Agents::default(),
+            broker: Broker::default(),
+            events: Bus::default(),
+            provenance: Rc::default(),
+            following: None,
+            limiter: Limiter::default(),
+            mqtt: Clients::default(),
+        };
+        runtime.follow_provenance();
+        runtime
+    }
+
+    // Subscribe provenance to the events while config.provenance is on, and
+    // unsubscribe it when it is turned off
+    pub fn follow_provenance(&mut self) {
+        match (self.config.provenance, self.following) {
+            (true, None) => {
+                let provenance = Rc::clone(&self.provenance);
+                self.following = Some(self.events.subscribe(move |event| provenance.borrow_mut().notify(event)));
+            }
+            (false, Some(id)) => {
+                self.events.unsubscribe(id);
+                self.following = None;
+            }
+            _ => {}
+        }
+    }
+
+    // resume - does what's on the continuation stack. Errors give unit, as an
+    // empty stack does; evaluate `resume` to see them.
+    pub fn resume(&mut self) -> Value {
+        match self.cont_stack.pop() {
+            Some(cont) => self.run_continuation(&cont).unwrap_or(Value::Unit),
+            None => Value::Unit,
+        }
+    }
+
+    // suspend - push source to run later, in `language`, when it is resumed
+    pub fn suspend(&mut self, language: Language, source: &str) {
+        self.cont_stack.push(Continuation::Source(language, source.to_string()));
+    }
+
+    // break - drop continuation stack and resume next
+    pub fn break_flow(&mut self) -> Value {
+        self.cont_stack.clear();
+        Value::Unit
+    }
+
+    // continue - takes a continuation argument (fun f(a,))
+    pub fn continue_with(&mut self, cont: Value) -> Value {
+        match cont {
+            Value::Continuation(c) => {
+                self.cont_stack.push(*c);
+                self.resume()
+            }
+            _ => Value::Unit,
+        }
+    }
+
+    // Limit the evaluations that follow, counted from now; None removes the limit
+    pub fn set_timeout(&mut self, limit: Option<Duration>) {
+        self.deadline = limit.map(|limit| (Instant::now() + limit, limit));
+    }
+
+    // The current evaluation's deadline and limit, for work that must stop at it
+    pub fn deadline(&self) -> Option<(Instant, Duration)> {
+        self.deadline
+    }
+
+    // Time left before the deadline, for work that waits outside the evaluator
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    // Checked wherever evaluation can run for unbounded time (loops, calls, awaits)
+    pub fn check_deadline(&self) -> Result<(), String> {
+        match self.deadline {
+            Some((deadline, limit)) if Instant::now() >= deadline => {
+                Err(format!("Evaluation timed out after {:?}", limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Evaluate expressions. The statements of a program run in the scope the
+    // runtime is in, so a let or const at the top level outlives the input.
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        let outcome = match expr {
+            Expr::Block(exprs) => exprs.iter().try_fold(Value::Unit, |_, expr| self.exec(expr)),
+            expr => self.exec(expr),
+        };
+        settle(outcome)
+    }
+
+    // Evaluate, letting control flow such as return unwind through the caller
+    fn exec(&mut self, expr: &Expr) -> Result<Value, Signal> {
+        match expr {
+            Expr::Value(v) => Ok(v.clone()),
+            Expr::Var(name) => Ok(self
+                .get_variable(name)
+                .cloned()
+                .ok_or_else(|| format!("Undefined variable: {}", name))?),
+            Expr::Array(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.exec(item)?);
+                }
+                Ok(Value::Array(values.into()))
+            }
+            Expr::Map(pairs) => {
+                let mut map = Map::new();
+                for (key, value) in pairs {
+                    let key = self.exec(key)?;
+                    map.insert(key, self.exec(value)?)?;
+                }
+                Ok(Value::Map(map))
+            }
+            Expr::Range(start, end, inclusive) => {
+                // i64::MAX as f64 rounds up to 2^63, which is already out
+                let bound = |value: Value| match value {
+                    Value::Num(n) if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 => Ok(n as i64),
+                    Value::Num(n) if n.fract() == 0.0 => Err(format!("Range bound out of bounds: {}", n)),
+                    other => Err(format!("Range bounds must be whole numbers, got {:?}", other)),
+                };
+                let start = bound(self.exec(start)?)?;
+                let end = bound(self.exec(end)?)?.checked_add(*inclusive as i64).ok_or_else(|| "Range end out of bounds".to_string())?;
+                Ok(Value::Range(Range { start, end }))
+            }
+            Expr::Call(name, arg_exprs, site) => {
+                // A function variable is called ahead of any builtin of the same name
+                let function = match self.get_variable(name) {
+                    Some(Value::Function(f)) => Some(Rc::clone(f)),
+                    _ => None,
+                };
+                // Mutating builtins change a variable argument in place
+                let target = match arg_exprs.first() {
+                    Some(Expr::Var(var)) if function.is_none() && builtins::is_mutating(name) => Some(var.clone()),
+                    _ => None,
+                };
+                let mut args = Vec::with_capacity(arg_exprs.len());
+                for arg in arg_exprs {
+                    args.push(self.exec(arg)?);
+                }
+                if let Some(f) = function {
+                    return self.invoke(&f, args);
+                }
+                signatures::check(name, &args).map_err(|e| site.locate(e))?;
+                if let Some(var) = target {
+                    // Drop the evaluated copy of the target first, so changing
+                    // the variable's elements does not copy them
+                    let rest = args.split_off(1);
+                    drop(args);
+                    return Ok(builtins::mutate(name, self.variable_mut(&var)?, rest)?);
+                }
+                // Continuation forms need to raise signals, so they live here
+                match name.as_str() {
+                    "callcc" => self.callcc(args),
+                    "continue" => self.continue_form(args),
+                    "resume" => self.resume_generator(args),
+                    "then" | "catch" | "all" | "race" => Ok(self.combinator(name, args, *site)?),
+                    _ => builtins::run(self, name, args).map_err(|e| self.unwinding.take().unwrap_or(Signal::Error(e))),
+                }
+            }
+            Expr::Lambda(params, body) => {
+                let captured = self.captures(params);
+                Ok(Value::Function(Rc::new(Function { params: params.clone(), body: (**body).clone(), generator: false, captured })))
+            }
+            Expr::Generator(name, params, body) => {
+                let captured = self.captures(params);
+                let f = Value::Function(Rc::new(Function { params: params.clone(), body: (**body).clone(), generator: true, captured }));
+                if let Some(name) = name {
+                    self.assign(name.clone(), f.clone())?;
+                }
+                Ok(f)
+            }
+            Expr::Struct(name, fields) => {
+                let f = constructor(name, None, fields);
+                self.assign(name.clone(), f.clone())?;
+                Ok(f)
+            }
+            // A variant with fields is bound to its constructor, one without to itself
+            Expr::Enum(name, variants) => {
+                for (variant, fields) in variants {
+                    let value = match fields {
+                        Some(fields) => constructor(variant, Some(name), fields),
+                        None => Value::Struct(Struct { name: variant.as_str().into(), fields: Rc::new(Vec::new()), enumeration: Some(name.as_str().into()) }),
+                    };
+                    self.assign(variant.clone(), value)?;
+                }
+                Ok(Value::Unit)
+            }
+            Expr::Record(name, enumeration, fields) => {
+                let mut values = Vec::with_capacity(fields.len());
+                for field in fields {
+                    let value = self.get_variable(field).cloned().ok_or_else(|| format!("Undefined variable: {}", field))?;
+                    values.push((field.clone(), value));
+                }
+                let enumeration = enumeration.as_deref().map(Rc::from);
+                Ok(Value::Struct(Struct { name: name.as_str().into(), fields: Rc::new(values), enumeration }))
+            }
+            // The first arm that fits runs, with what it binds in a scope of its own
+            Expr::Match(value, arms) => {
+                let value = self.exec(value)?;
+                for (case, body) in arms {
+                    let Some(bindings) = case.bind(&value)? else {
+                        continue;
+                    };
+                    if bindings.is_empty() {
+                        return self.exec(body);
+                    }
+                    self.scopes.push(bindings.into_iter().map(|(name, value)| (name, (value, false))).collect());
+                    let result = self.exec(body);
+                    self.scopes.pop();
+                    return result;
+                }
+                Err(format!("No match arm for {}", value).into())
+            }
+            Expr::Field(value, field) => match self.exec(value)? {
+                Value::Struct(record) => match record.get(field) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(format!("{} has no field {}", record.name, field).into()),
+                },
+                other => Err(format!("Cannot read field {} of {:?}", field, other).into()),
+            },
+            // A map's field is the entry under the field's name
+            Expr::SafeField(value, field) => match self.exec(value)? {
+                Value::None => Ok(Value::None),
+                Value::Struct(record) => Ok(record.get(field).cloned().unwrap_or(Value::None)),
+                Value::Map(map) => Ok(map.get(&Value::Str(field.as_str().into())).cloned().unwrap_or(Value::None)),
+                other => Err(format!("Cannot read field {} of {:?}", field, other).into()),
+            },
+            Expr::Default(value, fallback) => match self.exec(value)? {
+                Value::None => self.exec(fallback),
+                value => Ok(value),
+            },
+            // Generator bodies are stepped by generator.rs, which handles yield itself
+            Expr::Yield(_) => Err("yield outside a generator".to_string().into()),
+            Expr::Add(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.add(&r)?)
+            }
+            Expr::Sub(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.sub(&r)?)
+            }
+            Expr::Mul(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.mul(&r)?)
+            }
+            Expr::Div(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.div(&r)?)
+            }
+            Expr::Mod(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.rem(&r)?)
+            }
+            Expr::Pow(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.pow(&r)?)
+            }
+            Expr::Less(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.less_than(&r)?)
+            }
+            Expr::Greater(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.greater_than(&r)?)
+            }
+            Expr::Equal(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(self.equals(&l, &r)?)
+            }
+            Expr::And(left, right) => {
+                let l = self.exec(left)?;
+                if !self.truthy(&l)? {
+                    return Ok(Value::Bool(false));
+                }
+                let r = self.exec(right)?;
+                Ok(Value::Bool(self.truthy(&r)?))
+            }
+            Expr::Or(left, right) => {
+                let l = self.exec(left)?;
+                if self.truthy(&l)? {
+                    return Ok(Value::Bool(true));
+                }
+                let r = self.exec(right)?;
+                Ok(Value::Bool(self.truthy(&r)?))
+            }
+            Expr::Not(operand) => {
+                let v = self.exec(operand)?;
+                Ok(Value::Bool(!self.truthy(&v)?))
+            }
+            Expr::Blend(left, right) => {
+                let l = self.exec(left)?;
+                let r = self.exec(right)?;
+                Ok(l.blend_with(&r, self.config.blend_mode)?)
+            }
+            Expr::Scale(expr, factor) => {
+                let v = self.exec(expr)?;
+                Ok(v.scale(*factor)?)
+            }
+            Expr::Get(arr_expr, idx_expr) => {
+                let arr = self.exec(arr_expr)?;
+                let idx = self.exec(idx_expr)?;
+                Ok(index_value(arr, idx)?)
+            }
+            Expr::Assign(name, value) => {
+                let value = self.exec(value)?;
+                self.update(name.clone(), value.clone())?;
+                Ok(value)
+            }
+            Expr::Unpack(pattern, value) => {
+                let value = self.exec(value)?;
+                for (name, part) in pattern.unpack(value.clone())? {
+                    self.update(name, part)?;
+                }
+                Ok(value)
+            }
+            Expr::Let(name, value, constant) => {
+                let value = self.exec(value)?;
+                self.define(name.clone(), value.clone(), *constant)?;
+                Ok(value)
+            }
+            Expr::SetIndex(name, key, value) => {
+                let key = self.exec(key)?;
+                let value = self.exec(value)?;
+                match self.variable_mut(name)? {
+                    Value::Map(map) => {
+                        map.insert(key, value.clone())?;
+                        Ok(value)
+                    }
+                    // Arrays do not grow by assignment; push or insert does that
+                    Value::Array(vec) => {
+                        let Value::Num(n) = key else {
+                            return Err("Array index must be a number".to_string().into());
+                        };
+                        let index = resolve_index(n, vec.len()).ok_or_else(|| format!("Index {} out of bounds", n))?;
+                        vec.make_mut()[index] = value.clone();
+                        Ok(value)
+                    }
+                    other => Err(format!("Cannot assign into {:?}", other).into()),
+                }
+            }
+            Expr::Slice(target, start, end) => {
+                let target = self.exec(target)?;
+                let mut bound = |expr: &Option<Box<Expr>>| -> Result<Option<f64>, Signal> {
+                    match expr {
+                        Some(expr) => Ok(Some(self.exec(expr)?.as_num()?)),
+                        None => Ok(None),
+                    }
+                };
+                let (start, end) = (bound(start)?, bound(end)?);
+                match target {
+                    Value::Array(vec) => {
+                        let (from, to) = slice_bounds(start, end, vec.len());
+                        Ok(Value::Array(vec[from..to].iter().cloned().collect()))
+                    }
+                    Value::Str(s) => {
+                        let chars: Vec<char> = s.chars().collect();
+                        let (from, to) = slice_bounds(start, end, chars.len());
+                        Ok(Value::Str(chars[from..to].iter().collect::<String>().into()))
+                    }
+                    other => Err(format!("Cannot slice {:?}", other).into()),
+                }
+            }
+            Expr::Compose(left, right) => {
+                // Continuation composition: a continuation that runs left, then right
+                let l_val = self.exec(left)?;
+                let r_val = self.exec(right)?;
+
+                match (l_val, r_val) {
+                    (Value::Continuation(c1), Value::Continuation(c2)) => {
+                        let composed = Continuation::Sequence(Rc::new(*c1), Rc::new(*c2));
+                        Ok(Value::Continuation(Box::new(composed)))
+                    }
+                    _ => Err("Compose requires two continuations".to_string().into()),
+                }
+            }
+            Expr::Choice(left, right) => {
+                // Continuation choice: try left, if it fails/returns Unit, use right
+                let l_val = self.exec(left)?;
+
+                match l_val {
+                    Value::Unit => self.exec(right),
+                    v => Ok(v),
+                }
+            }
+            Expr::Resume => match self.cont_stack.pop() {
+                Some(cont) => self.run_continuation(&cont),
+                None => Ok(Value::Unit),
+            },
+            Expr::Suspend(source) => {
+                self.suspend(Language::Rho, source);
+                Ok(Value::Unit)
+            }
+            Expr::Break => Ok(self.break_flow()),
+            Expr::While(condition, body) => {
+                let mut last_val = Value::Unit;
+                loop {
+                    self.check_deadline()?;
+                    let cond_val = self.exec(condition)?;
+                    if !self.truthy(&cond_val)? {
+                        break;
+                    }
+                    last_val = self.exec(body)?;
+                }
+                Ok(last_val)
+            }
+            Expr::For(var_name, iterable_expr, body) => {
+                let iterable = self.exec(iterable_expr)?;
+                self.each(iterable, body, |runtime, item| runtime.assign(var_name.clone(), item))
+            }
+            Expr::ForUnpack(pattern, iterable_expr, body) => {
+                let iterable = self.exec(iterable_expr)?;
+                self.each(iterable, body, |runtime, item| {
+                    for (name, part) in pattern.unpack(item)? {
+                        runtime.assign(name, part)?;
+                    }
+                    Ok(())
+                })
+            }
+            Expr::ForEntries(key_name, value_name, map_expr, body) => {
+                let mut last_val = Value::Unit;
+                match self.exec(map_expr)? {
+                    Value::Map(map) => {
+                        for (key, value) in map.iter() {
+                            self.check_deadline()?;
+                            self.assign(key_name.clone(), key.clone())?;
+                            self.assign(value_name.clone(), value.clone())?;
+                            last_val = self.exec(body)?;
+                        }
+                    }
+                    _ => return Err("for k, v requires a map".to_string().into()),
+                }
+                Ok(last_val)
+            }
+            Expr::Block(exprs) => {
+                // Only a block that declares something needs a scope of its own
+                let scoped = exprs.iter().any(|expr| matches!(expr, Expr::Let(..)));
+                if scoped {
+                    self.scopes.push(Scope::new());
+                }
+                let result = exprs.iter().try_fold(Value::Unit, |_, expr| self.exec(expr));
+                if scoped {
+                    self.scopes.pop();
+                }
+                result
+            }
+            Expr::Spawn(source, site, task) => {
+                let future = self.executor.spawn_scoped(source, site, (**task).clone());
+                self.trace_spawn(&future);
+                Ok(Value::Future(future))
+            }
+            Expr::Async(source, site, task) => Ok(Value::Future(self.spawn(source, site, (**task).clone()))),
+            Expr::Scope(body) => self.run_scope(body),
+            Expr::Return(value) => Err(Signal::Return(self.exec(value)?)),
+            Expr::Throw(value) => Err(Signal::Throw(self.exec(value)?)),
+            Expr::Try(body, catch, finally) => {
+                let mut outcome = self.exec(body);
+                // A timeout is not the script's to handle, or a catch in a
+                // loop could run past it forever
+                if let Some((name, handler)) = catch
+                    && let Some(error) = caught(&outcome)
+                {
+                    match self.check_deadline() {
+                        // The error is bound only while the handler runs
+                        Ok(()) => {
+                            self.scopes.push(Scope::from([(name.clone(), (error, false))]));
+                            outcome = self.exec(handler);
+                            self.scopes.pop();
+                        }
+                        // The deadline passed as the body failed, so report it and not the error
+                        Err(timeout) => outcome = Err(Signal::Error(timeout)),
+                    }
+                }
+                // Runs however the body ended, even by return; its own error wins
+                if let Some(finally) = finally {
+                    self.exec(finally)?;
+                }
+                outcome
+            }
+        }
+    }
+
+    // A for loop's iterations, binding each item with bind before the body
+    fn each(
+        &mut self,
+        iterable: Value,
+        body: &Expr,
+        bind: impl Fn(&mut Runtime, Value) -> Result<(), String>,
+    ) -> Result<Value, Signal> {
+        let mut last_val = Value::Unit;
+
+        match iterable {
+            Value::Array(ref arr) => {
+                for item in arr {
+                    self.check_deadline()?;
+                    // Store loop variable
+                    bind(self, item.clone())?;
+                    last_val = self.exec(body)?;
+                }
+            }
+            // Ranges are iterated without building an array
+            Value::Range(range) => {
+                for n in range.start..range.end {
+                    self.check_deadline()?;
+                    bind(self, Value::Num(n as f64))?;
+                    last_val = self.exec(body)?;
+                }
+            }
+            // Maps iterate their keys in insertion order
+            Value::Map(ref map) => {
+                for key in map.keys() {
+                    self.check_deadline()?;
+                    bind(self, key.clone())?;
+                    last_val = self.exec(body)?;
+                }
+            }
+            // Generators are pulled one value at a time
+            Value::Generator(generator) => {
+                while let Some(item) = generator.next(self)? {
+                    self.check_deadline()?;
+                    bind(self, item)?;
+                    last_val = self.exec(body)?;
+                }
+            }
+            // Subscriptions give the values published so far
+            Value::Subscription(subscription) => {
+                for item in subscription.drain() {
+                    self.check_deadline()?;
+                    bind(self, item)?;
+                    last_val = self.exec(body)?;
+                }
+            }
+            // Streams until they end
+            Value::Stream(stream) => {
+                while let Some(item) = stream.next(self)? {
+                    self.check_deadline()?;
+                    bind(self, item)?;
+                    last_val = self.exec(body)?;
+                }
+            }
+            _ => {
+                return Err("For loop requires an array, range, map, generator, subscription or stream".to_string().into());
+            }
+        }
+
+        Ok(last_val)
+    }
+
+    // Create and register a pending future for the given source text
+    pub fn new_future(&mut self, source: &str) -> Future {
+        self.executor.new_future(source)
+    }
+
+    // Defer `task` until the returned future is awaited
+    pub fn spawn(&mut self, source: &str, site: &str, task: Expr) -> Future {
+        let future = self.executor.spawn(source, site, task);
+        self.trace_spawn(&future);
+        future
+    }
+
+    // A future resolved once `duration` has passed; awaiting it waits out the rest
+    pub fn spawn_sleep(&mut self, source: &str, site: &str, duration: Duration) -> Future {
+        let future = self.executor.spawn_task(source, site, Task::Sleep(Instant::now() + duration));
+        self.trace_spawn(&future);
+        future
+    }
+
+    // Block until `until`, or the evaluation's deadline if that comes first
+    pub fn sleep_until(&mut self, until: Instant) -> Result<Value, String> {
+        let wait = until.saturating_duration_since(Instant::now());
+        thread::sleep(self.remaining().map_or(wait, |left| wait.min(left)));
+        self.check_deadline()?;
+        Ok(Value::Unit)
+    }
+
+    // GET through the on-disk cache: a copy younger than ttl is used as is,
+    // otherwise the URL is fetched, once the host's rate limit allows
+    pub(crate) fn http_get_cached(&mut self, url: &str, ttl: Duration) -> Result<Response, String> {
+        let dir = self.config.in_workspace(self.config.http_cache.clone().unwrap_or_else(|| httpcache::DIR.into()));
+        let cached = httpcache::load(&dir, url);
+        if let Some((response, age)) = &cached && *age <= ttl {
+            return Ok(response.clone());
+        }
+        if let Some(interval) = self.config.http_rate {
+            let now = Instant::now();
+            let wait = self.limiter.reserve(&httpcache::host(url), interval, now);
+            self.sleep_until(now + wait)?;
+        }
+        match http::get(url) {
+            Ok(response) => {
+                if (200..300).contains(&response.status) {
+                    httpcache::store(&dir, url, &response)?;
+                }
+                Ok(response)
+            }
+            // Offline, a stale copy beats none
+            Err(e) => cached.map(|(response, _)| response).ok_or(e),
+        }
+    }
+
+    // A future resolved once the process exits
+    pub fn spawn_wait(&mut self, source: &str, site: &str, pid: u32) -> Future {
+        let future = self.executor.spawn_task(source, site, Task::Exit(pid));
+        self.trace_spawn(&future);
+        future
+    }
+
+    // Block until the process exits, or the evaluation's deadline passes
+    pub fn wait_for(&mut self, pid: u32) -> Result<Value, String> {
+        while process::alive(pid) {
+            self.check_deadline()?;
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(Value::Unit)
+    }
+
+    // A future for shell commands started now; awaiting it collects their results
+    pub(crate) fn spawn_commands(&mut self, source: &str, site: &str, batch: Batch) -> Future {
+        let future = self.executor.spawn_task(source, site, Task::Commands(batch));
+        self.trace_spawn(&future);
+        future
+    }
+
+    // A future settled by combining others when it is awaited
+    pub fn spawn_combinator(&mut self, source: &str, site: &str, combinator: Combinator) -> Future {
+        let future = self.executor.spawn_task(source, site, Task::Combine(combinator));
+        self.trace_spawn(&future);
+        future
+    }
+
+    // then(f, handler), catch(f, handler), all(futures) and race(futures):
+    // a future that combines others once awaited, see tau::combine
+    fn combinator(&mut self, name: &str, args: Vec<Value>, site: Site) -> Result<Value, String> {
+        let mut args = args.into_iter();
+        let (source, combinator) = match (name, args.next(), args.next()) {
+            ("then" | "catch", Some(Value::Future(future)), Some(Value::Function(f))) => {
+                let source = format!("#{}.{}({:?})", future.id, name, f);
+                (source, if name == "then" { Combinator::Then(future, f) } else { Combinator::Catch(future, f) })
+            }
+            ("all" | "race", Some(Value::Array(items)), None) => {
+                let futures = items
+                    .iter()
+                    .map(|item| match item {
+                        Value::Future(future) => Ok(future.clone()),
+                        other => Err(format!("{} expects an array of futures, got {:?}", name, other)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let ids: Vec<String> = futures.iter().map(|future| format!("#{}", future.id)).collect();
+                let source = format!("{}([{}])", name, ids.join(", "));
+                (source, if name == "all" { Combinator::All(futures) } else { Combinator::Race(futures) })
+            }
+            _ => return Err(format!("{} expects a future and a function, or an array of futures", name)),
+        };
+        let site = if site.line == 0 { String::new() } else { format!("{}:{}", site.line, site.col) };
+        Ok(Value::Future(self.spawn_combinator(&source, &site, combinator)))
+    }
+
+    // Run every scheduled handler due at `now` as a task of its own, recording
+    // how each one went. Returns the ids and outcomes of the handlers run.
+    pub fn run_due_schedules(&mut self, now: SystemTime) -> Vec<(u64, Result<Value, String>)> {
+        let mut outcomes = Vec::new();
+        for (id, spec, handler) in self.scheduler.take_due(now) {
+            let source = format!("schedule #{} {}", id, spec);
+            let future = self.executor.spawn_task(&source, "", Task::Call(handler, Vec::new()));
+            self.trace_spawn(&future);
+            let result = self.await_future(&future);
+            self.scheduler.record(id, result.as_ref().err().map(String::as_str));
+            outcomes.push((id, result));
+        }
+        outcomes
+    }
+
+    // Run the subscription handlers for every MQTT message that has arrived,
+    // each as a task of its own. Returns the topics and outcomes.
+    pub fn serve_mqtt(&mut self) -> Vec<(String, Result<Value, String>)> {
+        let mut outcomes = Vec::new();
+        for (handler, topic, payload) in self.mqtt.poll() {
+            let args = match handler.params.len() {
+                1 => vec![Value::Str(payload.into())],
+                _ => vec![Value::Str(topic.as_str().into()), Value::Str(payload.into())],
+            };
+            let future = self.executor.spawn_task(&format!("mqtt {}", topic), "", Task::Call(handler, args));
+            self.trace_spawn(&future);
+            outcomes.push((topic, self.await_future(&future)));
+        }
+        outcomes
+    }
+
+    // Answer the requests that have reached this session's agents, each run as
+    // a task calling the session function named after the method. Returns how
+    // many were answered.
+    pub fn serve_agents(&mut self) -> usize {
+        let mut agents = std::mem::take(&mut self.agents);
+        let answered = agents.poll(|interface, method, args| self.serve_call(interface, method, args));
+        agents.append(&mut self.agents);
+        self.agents = agents;
+        answered
+    }
+
+    fn serve_call(&mut self, interface: &Interface, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        let handler = match self.variables.get(method) {
+            Some(Value::Function(f)) => Rc::clone(f),
+            _ => return Err(format!("{}.{} is not implemented by this agent", interface.name, method)),
+        };
+        let source = format!("agent {}.{}", interface.name, method);
+        let future = self.executor.spawn_task(&source, "", Task::Call(handler, args));
+        self.trace_spawn(&future);
+        self.await_future(&future)
+    }
+
+    fn trace_spawn(&self, future: &Future) {
+        self.tracer.event("task.spawn", &task_attributes(future));
+    }
+
+    pub fn has_task(&self, future: &Future) -> bool {
+        self.executor.has_task(future.id)
+    }
+
+    // Drive a future to completion. The first await runs its task and caches the
+    // outcome in the future, so later awaits (through any clone) see the same result.
+    // A failure keeps the original error and gains a trace frame for this task.
+    // A task given a timeout runs under that deadline too, and is cancelled
+    // rather than failed when it runs out of time.
+    pub fn await_future(&mut self, future: &Future) -> Result<Value, String> {
+        self.check_deadline()?;
+        self.expire_futures();
+        if let Some(task) = self.executor.take_task(future.id) {
+            let limit = self.executor.deadline(future.id);
+            let outer = self.deadline;
+            if let Some((at, _)) = limit
+                && outer.is_none_or(|(outer_at, _)| at < outer_at)
+            {
+                self.deadline = limit;
+            }
+            let span = self.tracer.start("task.resolve", &task_attributes(future));
+            let outcome = match task {
+                // A task is an expression, so a block in it is scoped as usual
+                Task::Eval(expr) => settle(self.exec(&expr)),
+                Task::Combine(combinator) => tau::combine(self, combinator),
+                Task::Call(f, args) => self.call_function(&f, args),
+                Task::Sleep(until) => self.sleep_until(until),
+                Task::Commands(batch) => batch.wait(self.deadline),
+                Task::Exit(pid) => self.wait_for(pid),
+            };
+            self.tracer.end(span, outcome.as_ref().err().map(String::as_str));
+            self.deadline = outer;
+            match outcome {
+                Ok(value) => {
+                    future.set_state(FutureState::Resolved(Box::new(value)));
+                    self.events.publish(Event::FutureResolved { id: future.id, error: None });
+                }
+                Err(_) if limit.is_some_and(|(at, _)| Instant::now() >= at) => {
+                    self.expire_futures();
+                }
+                Err(e) => {
+                    future.set_state(FutureState::Rejected(TaskError::from_error(&e).with_frame(future.frame())));
+                    self.events.publish(Event::FutureResolved { id: future.id, error: Some(e) });
+                }
+            }
+        }
+        match future.state() {
+            FutureState::Resolved(v) => Ok(*v),
+            FutureState::Pending => Err(format!("Future still pending: {}", future)),
+            FutureState::Rejected(e) | FutureState::Cancelled(e) => Err(e.to_string()),
+        }
+    }
+
+    // cancel fut: the task never runs, and awaiting gives the reason.
+    // Returns whether the future was still pending.
+    pub fn cancel(&mut self, future: &Future, reason: &str) -> bool {
+        self.executor.cancel(future, reason)
+    }
+
+    pub fn set_future_timeout(&mut self, future: &Future, limit: Duration) {
+        self.executor.set_deadline(future, limit);
+    }
+
+    // Cancel the futures whose timeout has passed and resolve the sleeps that
+    // are done, returning how many futures settled
+    pub fn expire_futures(&mut self) -> usize {
+        let now = Instant::now();
+        let expired = self.executor.expire(now);
+        let woken = self.executor.wake(now);
+        for &id in &woken {
+            self.events.publish(Event::FutureResolved { id, error: None });
+        }
+        expired + woken.len()
+    }
+
+    // Run the body with the parameters bound in a scope of its own
+    pub fn call_function(&mut self, f: &Function, args: Vec<Value>) -> Result<Value, String> {
+        match self.invoke(f, args) {
+            Ok(value) => Ok(value),
+            Err(Signal::Error(e)) => Err(e),
+            // Kept aside while the caller's String error passes back up to exec
+            Err(signal) => {
+                self.unwinding = Some(signal);
+                Err("Continuation escaped".to_string())
+            }
+        }
+    }
+
+    fn invoke(&mut self, f: &Function, args: Vec<Value>) -> Result<Value, Signal> {
+        self.check_deadline()?;
+        if args.len() != f.params.len() {
+            return Err(format!("{:?} expects {} arguments, got {}", f, f.params.len(), args.len()).into());
+        }
+        // Parameters are bound in a scope of their own over what the body
+        // captured, so they are gone once it returns
+        let mut scope = f.captured.clone();
+        scope.extend(f.params.iter().cloned().zip(args).map(|(param, arg)| (param, (arg, false))));
+        // A generator's body waits for the first resume
+        if f.generator {
+            return Ok(Value::Generator(Generator::new(scope, f.body.clone())));
+        }
+        // The body sees its own scope, not the caller's let bindings
+        let caller = std::mem::replace(&mut self.scopes, vec![scope]);
+        // A return anywhere in the body ends the call with its value
+        let result = match self.exec(&f.body) {
+            Err(Signal::Return(value)) => Ok(value),
+            other => other,
+        };
+        self.scopes = caller;
+        result
+    }
+
+    // callcc(f) calls f with the continuation of the callcc itself; continuing
+    // it from anywhere inside f makes callcc return that value at once
+    fn callcc(&mut self, args: Vec<Value>) -> Result<Value, Signal> {
+        let f = match args.as_slice() {
+            [Value::Function(f)] => Rc::clone(f),
+            _ => return Err("callcc expects a function of one argument".to_string().into()),
+        };
+        let id = self.next_escape;
+        self.next_escape += 1;
+        self.escapes.push(id);
+        let result = self.invoke(&f, vec![Value::Continuation(Box::new(Continuation::Escape(id)))]);
+        self.escapes.pop();
+        match result {
+            Err(Signal::Escape(target, value)) if target == id => Ok(value),
+            other => other,
+        }
+    }
+
+    // continue(k) or continue(k, value)
+    fn continue_form(&mut self, args: Vec<Value>) -> Result<Value, Signal> {
+        let mut args = args.into_iter();
+        match (args.next(), args.next(), args.next()) {
+            (Some(Value::Continuation(k)), value, None) => match *k {
+                Continuation::Escape(id) => self.escape(id, value.unwrap_or(Value::Unit)),
+                other => self.run_continuation(&other),
+            },
+            _ => Err("continue expects a continuation and an optional value".to_string().into()),
+        }
+    }
+
+    // resume(g) runs a generator to its next yield, giving unit once it is done
+    fn resume_generator(&mut self, args: Vec<Value>) -> Result<Value, Signal> {
+        match args.as_slice() {
+            [Value::Generator(generator)] => Ok(generator.next(self)?.unwrap_or(Value::Unit)),
+            _ => Err("resume expects a generator".to_string().into()),
+        }
+    }
+
+    // Like Continuation::run, but escapes can unwind
+    fn run_continuation(&mut self, cont: &Continuation) -> Result<Value, Signal> {
+        match cont {
+            Continuation::Escape(id) => self.escape(*id, Value::Unit),
+            Continuation::Sequence(first, second) => {
+                self.run_continuation(first)?;
+                self.run_continuation(second)
+            }
+            Continuation::Source(language, source) => self.run_source(*language, source),
+            other => Ok(other.run()),
+        }
+    }
+
+    // Re-enter the frontend a continuation was suspended in, whichever
+    // language is current. Pi source runs on a stack of its own and gives its top.
+    fn run_source(&mut self, language: Language, source: &str) -> Result<Value, Signal> {
+        match language {
+            Language::Pi => Ok(pi::parse_pi(source, self, &mut Vec::new())?),
+            Language::Rho => self.exec(&rho::parse_rho(source)?),
+            Language::Tau => Ok(tau::parse_tau(source, self)?),
+        }
+    }
+
+    // Unwind to the callcc that captured `id`; once it has returned there is
+    // nothing left to re-enter
+    fn escape(&mut self, id: u64, value: Value) -> Result<Value, Signal> {
+        if self.escapes.contains(&id) {
+            Err(Signal::Escape(id, value))
+        } else {
+            Err(format!("Continuation #{} is no longer active", id).into())
+        }
+    }
+
+    // Run a scope body, then join every task it spawned in spawn order. If the
+    // body or any task fails, or a return or escape leaves the body, the tasks still
+    // outstanding are cancelled so nothing outlives the scope. Returns the task
+    // results as an array.
+    fn run_scope(&mut self, body: &Expr) -> Result<Value, Signal> {
+        self.executor.open_scope();
+        let outcome = self.exec(body);
+        let spawned = self.executor.close_scope();
+        let mut results = Vec::with_capacity(spawned.len());
+        let mut exit = outcome.err();
+        for future in &spawned {
+            match exit {
+                Some(Signal::Error(_) | Signal::Throw(_)) => {
+                    self.executor.cancel(future, "scope exited with an error");
+                }
+                Some(_) => {
+                    self.executor.cancel(future, "scope exited early");
+                }
+                None => match self.await_future(future) {
+                    Ok(value) => results.push(value),
+                    Err(e) => exit = Some(Signal::Error(e)),
+                },
+            }
+        }
+        match exit {
+            Some(signal) => Err(signal),
+            None => Ok(Value::Array(results.into())),
+        }
+    }
+
+    pub fn futures(&self) -> &[Future] {
+        self.executor.futures()
+    }
+
+    // The variables bound outside any block, in no particular order
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.variables.iter().map(|(name, value)| (name.as_str(), value))
+    }
+
+    // The interfaces this session serves over TCP, with the address of each
+    pub fn agents(&self) -> impl Iterator<Item = (&Interface, std::net::SocketAddr)> {
+        self.agents.iter().map(|agent| (&agent.interface, agent.address))
+    }
+
+    pub fn events(&mut self) -> &mut Bus {
+        &mut self.events
+    }
+
+    // The innermost let or const binding of the name, else the variable
+    pub fn get_variable(&self, name: &str) -> Option<&Value> {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some((value, _)) => Some(value),
+            None => self.variables.get(name),
+        }
+    }
+
+    // Overwrite the innermost let binding of the name, or else set the
+    // variable. Consts are not checked; assign is the checked form.
+    pub fn set_variable(&mut self, name: String, value: Value) {
+        if let Some((slot, _)) = self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(&name)) {
+            *slot = value;
+            return;
+        }
+        if self.events.is_followed() {
+            self.events.publish(Event::VarAssigned { name: name.clone() });
+        }
+        self.variables.insert(name, value);
+    }
+
+    // name = value from a script, which a const refuses
+    pub fn assign(&mut self, name: String, value: Value) -> Result<(), String> {
+        if self.is_constant(&name) {
+            return Err(format!("Cannot assign to constant {}", name));
+        }
+        self.check_reserved(&name, &value)?;
+        self.set_variable(name, value);
+        Ok(())
+    }
+
+    // name = value as written in Rho or Tau; strict mode wants the name
+    // bound first, so a typo is not a new variable
+    pub fn update(&mut self, name: String, value: Value) -> Result<(), String> {
+        if self.config.strict && self.get_variable(&name).is_none() {
+            return Err(format!("Strict mode: {} is not declared; use let {} = ...", name, name));
+        }
+        self.assign(name, value)
+    }
+
+    // A keyword cannot name a variable, and strict mode keeps builtin names
+    // free too. Otherwise a function bound to a builtin's name takes over its
+    // calls, which earns a warning; any other value leaves the builtin
+    // callable. :force-assign lifts all of this.
+    fn check_reserved(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        if self.force_assign {
+            return Ok(());
+        }
+        if rho::is_keyword(name) {
+            return Err(format!("{} is a keyword; :force-assign {} = ... binds it anyway", name, name));
+        }
+        if !signatures::is_builtin(name) {
+            return Ok(());
+        }
+        if self.config.strict {
+            return Err(format!("Strict mode: {} is a builtin", name));
+        }
+        if matches!(value, Value::Function(_)) {
+            self.warnings.push(format!("{} is a builtin; calls to {} now run this function", name, name));
+        }
+        Ok(())
+    }
+
+    // == in every language. Strict mode makes values of different kinds an
+    // error rather than unequal.
+    pub fn equals(&self, left: &Value, right: &Value) -> Result<Value, String> {
+        if self.config.strict && left.kind() != right.kind() {
+            return Err(format!("Strict mode: cannot compare {} with {}", left.kind(), right.kind()));
+        }
+        left.equals(right)
+    }
+
+    // Whether a condition holds. Strict mode will not guess for a string or
+    // collection, which is true even when empty.
+    pub fn truthy(&self, value: &Value) -> Result<bool, String> {
+        match value {
+            Value::Str(_) | Value::Array(_) | Value::Map(_) | Value::Range(_) if self.config.strict => {
+                Err(format!("Strict mode: {} used as a condition", value.kind()))
+            }
+            _ => Ok(value.is_truthy()),
+        }
+    }
+
+    // let or const: bound in the innermost block, or as a variable outside
+    // any. A const cannot be declared again where it is in sight.
+    fn define(&mut self, name: String, value: Value, constant: bool) -> Result<(), String> {
+        self.check_reserved(&name, &value)?;
+        if self.scopes.last().is_some_and(|scope| scope.get(&name).is_some_and(|(_, constant)| *constant))
+            || (self.scopes.is_empty() && self.constants.contains(&name))
+        {
+            return Err(format!("{} is already a constant", name));
+        }
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name, (value, constant));
+            }
+            None => {
+                if constant {
+                    self.constants.insert(name.clone());
+                }
+                self.set_variable(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_constant(&self, name: &str) -> bool {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some((_, constant)) => *constant,
+            None => self.constants.contains(name),
+        }
+    }
+
+    // A variable to change in place, as map[key] = value and push do
+    fn variable_mut(&mut self, name: &str) -> Result<&mut Value, String> {
+        if self.is_constant(name) {
+            return Err(format!("Cannot assign to constant {}", name));
+        }
+        match self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name)) {
+            Some((value, _)) => Ok(value),
+            None => self.variables.get_mut(name).ok_or_else(|| format!("Undefined variable: {}", name)),
+        }
+    }
+
+    // The let and const bindings a function made here can see, minus its
+    // own parameters; inner blocks shadow outer ones
+    fn captures(&self, params: &[String]) -> Scope {
+        let mut captured = Scope::new();
+        for scope in &self.scopes {
+            for (name, binding) in scope {
+                if !params.contains(name) {
+                    captured.insert(name.clone(), binding.clone());
+                }
+            }
+        }
+        captured
+    }
+}
+
+fn task_attributes(future: &Future) -> [(&'static str, String); 3] {
+    [("task.id", future.id.to_string()), ("task.source", future.source.clone()), ("task.site", future.site.clone())]
+}
+
+// An evaluation's outcome as its caller sees it, with any signal still
+// unwinding at the top turned into an error
+fn settle(outcome: Result<Value, Signal>) -> Result<Value, String> {
+    match outcome {
+        Ok(value) => Ok(value),
+        Err(Signal::Error(e)) => Err(e),
+        Err(Signal::Throw(value)) => Err(thrown_message(&value)),
+        Err(Signal::Return(_)) => Err("return outside a function".to_string()),
+        Err(Signal::Escape(id, _)) => Err(format!("Continuation #{} escaped its callcc", id)),
+    }
+}
+
+// What catch binds: a thrown value as it was thrown, an error as a map.
+// Returns and escapes pass through.
+fn caught(outcome: &Result<Value, Signal>) -> Option<Value> {
+    match outcome {
+        Err(Signal::Throw(value)) => Some(value.clone()),
+        Err(Signal::Error(message)) => Some(error_value(message)),
+        _ => None,
+    }
+}
+
+// An error as catch sees it: [{"message", "Division by zero"}]
+fn error_value(message: &str) -> Value {
+    let mut map = Map::new();
+    // A string key always hashes
+    let _ = map.insert(Value::Str("message".into()), Value::Str(message.into()));
+    Value::Map(map)
+}
+
+// A throw nothing caught, as an error: the "message" of a thrown map,
+// or else the value itself
+fn thrown_message(value: &Value) -> String {
+    if let Value::Map(map) = value
+        && let Some(Value::Str(message)) = map.get(&Value::Str("message".into()))
+    {
+        return message.to_string();
+    }
+    value.print_text()
+}
+
+// The function a struct or enum variant declaration binds: its parameters are
+// the fields, and its body builds the value from them
+fn constructor(name: &str, enumeration: Option<&str>, fields: &[String]) -> Value {
+    let body = Expr::Record(name.to_string(), enumeration.map(str::to_string), fields.to_vec());
+    Value::Function(Rc::new(Function { params: fields.to_vec(), body, generator: false, captured: Scope::new() }))
+}
+
+// get(container, key): as container[key], but none for an index out of range
+// or a missing key
+pub(crate) fn get_or_none(container: Value, key: Value) -> Result<Value, String> {
+    let present = match (&container, &key) {
+        (Value::Array(items), Value::Num(n)) => resolve_index(*n, items.len()).is_some(),
+        (Value::Range(range), Value::Num(n)) => resolve_index(*n, range.len()).is_some(),
+        (Value::Str(s), Value::Num(n)) => resolve_index(*n, s.chars().count()).is_some(),
+        (Value::Map(map), key) => map.get(key).is_some(),
+        // Left to index_value to refuse
+        _ => true,
+    };
+    if present { index_value(container, key) } else { Ok(Value::None) }
+}
+
+// arr[index], str[index], range[index] or map[key]
+fn index_value(arr: Value, idx: Value) -> Result<Value, String> {
+    match arr {
+        Value::Array(ref vec) => {
+            match idx {
+                // Negative indices count from the end: arr[-1] is the last element
+                Value::Num(n) => resolve_index(n, vec.len())
+                    .map(|i| vec[i].clone())
+                    .ok_or_else(|| format!("Index {} out of bounds", n)),
+                _ => Err("Array index must be a number".to_string()),
+            }
+        }
+        Value::Range(range) => match idx {
+            Value::Num(n) => resolve_index(n, range.len())
+                .and_then(|i| range.get(i))
+                .map(|n| Value::Num(n as f64))
+                .ok_or_else(|| format!("Index {} out of bounds", n)),
+            _ => Err("Range index must be a number".to_string()),
+        },
+        Value::Str(ref s) => {
+            let chars: Vec<char> = s.chars().collect();
+            match idx {
+                Value::Num(n) => resolve_index(n, chars.len())
+                    .map(|i| Value::Str(chars[i].to_string().into()))
+                    .ok_or_else(|| format!("Index {} out of bounds", n)),
+                _ => Err("String index must be a number".to_string()),
+            }
+        }
+        Value::Map(ref map) => map
+            .get(&idx)
+            .cloned()
+            .ok_or_else(|| format!("Key {:?} not found in map", idx)),
+        _ => Err("Get requires an array, string or map".to_string()),
+    }
+}
+
+// Index into a sequence of `len` items; negative indices count from the end
+pub(crate) fn resolve_index(n: f64, len: usize) -> Option<usize> {
+    let index = if n < 0.0 { len as f64 + n } else { n };
+    if index >= 0.0 && index < len as f64 {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+// Bounds of a [start:end] slice; missing bounds mean the ends, negative bounds
+// count from the end and everything is clamped, so slicing never fails
+fn slice_bounds(start: Option<f64>, end: Option<f64>, len: usize) -> (usize, usize) {
+    let clamp = |n: f64| {
+        let n = if n < 0.0 { len as f64 + n } else { n };
+        n.clamp(0.0, len as f64) as usize
+    };
+    let from = start.map_or(0, clamp);
+    let to = end.map_or(len, clamp);
+    (from, to.max(from))
+}
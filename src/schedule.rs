@@ -0,0 +1,204 @@
+// Scheduler - cron-style recurring handlers. schedule("*/5 * * * *", fn() ...)
+// registers a handler; the REPL runs the ones that are due before each input,
+// and `--daemon` keeps running them on time once the input is exhausted.
+//
+// Specs have the five standard fields, evaluated in UTC:
+//   minute (0-59) hour (0-23) day-of-month (1-31) month (1-12) day-of-week (0-7, 0 and 7 are Sunday)
+// Each field is `*`, a number, a range `a-b`, any of those with a step `/n`,
+// or a comma separated list of them.
+use crate::value::Function;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: u64,  // Bit n set when n matches
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,      // Day-of-month was `*`
+    any_weekday: bool,  // Day-of-week was `*`
+}
+
+impl Cron {
+    pub fn parse(spec: &str) -> Result<Cron, String> {
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("Cron spec {:?} needs 5 fields: minute hour day month weekday", spec));
+        };
+        let mut weekdays = field(weekday, 0, 7, "day-of-week")?;
+        // 7 is another name for Sunday
+        if weekdays & 1 << 7 != 0 {
+            weekdays = weekdays & !(1 << 7) | 1;
+        }
+        Ok(Cron {
+            minutes: field(minute, 0, 59, "minute")?,
+            hours: field(hour, 0, 23, "hour")?,
+            days: field(day, 1, 31, "day-of-month")?,
+            months: field(month, 1, 12, "month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    // The first matching minute strictly after `after`
+    pub fn next_after(&self, after: SystemTime) -> Result<SystemTime, String> {
+        let secs = after.duration_since(UNIX_EPOCH).map_err(|_| "Time before 1970".to_string())?.as_secs() as i64;
+        let mut t = (secs / 60 + 1) * 60;
+        // Five years covers every combination that can ever match (Feb 29 included)
+        let limit = t + 5 * 366 * 86400;
+        while t < limit {
+            let days = t.div_euclid(86400);
+            let (_, month, day) = civil_from_days(days);
+            let weekday = (days + 4).rem_euclid(7);  // 1970-01-01 was a Thursday
+            let minute_of_day = t.rem_euclid(86400) / 60;
+            if self.months & 1 << month == 0 {
+                t = (days + 1) * 86400;  // Months are checked a day at a time
+            } else if !self.day_matches(day, weekday) {
+                t = (days + 1) * 86400;
+            } else if self.hours & 1 << (minute_of_day / 60) == 0 {
+                t = (t / 3600 + 1) * 3600;
+            } else if self.minutes & 1 << (minute_of_day % 60) == 0 {
+                t += 60;
+            } else {
+                return Ok(UNIX_EPOCH + Duration::from_secs(t as u64));
+            }
+        }
+        Err("Cron spec never matches".to_string())
+    }
+
+    // When both day fields are restricted, cron fires on either
+    fn day_matches(&self, day: i64, weekday: i64) -> bool {
+        let by_day = self.days & 1 << day != 0;
+        let by_weekday = self.weekdays & 1 << weekday != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => by_day || by_weekday,
+            _ => by_day && by_weekday,
+        }
+    }
+}
+
+// Bitmask of the values a field allows
+fn field(text: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid cron {} field {:?}", name, text);
+    let mut mask = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&s| s > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (from.parse().map_err(|_| invalid())?, to.parse().map_err(|_| invalid())?),
+            None => {
+                let n = range.parse().map_err(|_| invalid())?;
+                // n/step runs from n to the end of the field
+                (n, if step > 1 { max } else { n })
+            }
+        };
+        if from < min || to > max || from > to {
+            return Err(invalid());
+        }
+        for n in (from..=to).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+// Days since 1970-01-01 to (year, month, day), after Howard Hinnant's algorithm
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+// 2026-10-16 12:05 UTC
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let minute_of_day = secs.rem_euclid(86400) / 60;
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minute_of_day / 60, minute_of_day % 60)
+}
+
+pub struct Schedule {
+    pub id: u64,
+    pub spec: String,
+    cron: Cron,
+    pub handler: Rc<Function>,
+    pub next: SystemTime,
+    pub runs: u64,
+    pub last_error: Option<String>,
+}
+
+// #1 */5 * * * * next 2026-10-16 12:05 UTC, 3 runs
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {} next {}, {} runs", self.id, self.spec, format_utc(self.next), self.runs)?;
+        if let Some(error) = &self.last_error {
+            write!(f, " (last failed: {})", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    schedules: Vec<Schedule>,
+    next_id: u64,
+}
+
+impl Scheduler {
+    pub fn add(&mut self, spec: &str, handler: Rc<Function>, now: SystemTime) -> Result<u64, String> {
+        let cron = Cron::parse(spec)?;
+        let next = cron.next_after(now)?;
+        self.next_id += 1;
+        let id = self.next_id;
+        self.schedules.push(Schedule { id, spec: spec.to_string(), cron, handler, next, runs: 0, last_error: None });
+        Ok(id)
+    }
+
+    // false when there was no such schedule
+    pub fn cancel(&mut self, id: u64) -> bool {
+        let before = self.schedules.len();
+        self.schedules.retain(|s| s.id != id);
+        self.schedules.len() != before
+    }
+
+    pub fn schedules(&self) -> &[Schedule] {
+        &self.schedules
+    }
+
+    // The soonest time any schedule fires
+    pub fn next_due(&self) -> Option<SystemTime> {
+        self.schedules.iter().map(|s| s.next).min()
+    }
+
+    // Handlers due at `now`, each moved on to its following time. A handler
+    // that fell behind runs once, not once per missed slot.
+    pub fn take_due(&mut self, now: SystemTime) -> Vec<(u64, String, Rc<Function>)> {
+        let mut due = Vec::new();
+        for schedule in self.schedules.iter_mut().filter(|s| s.next <= now) {
+            // next_after only fails for specs that never match, which add rejects
+            schedule.next = schedule.cron.next_after(now).unwrap_or(now + Duration::from_secs(366 * 86400));
+            due.push((schedule.id, schedule.spec.clone(), Rc::clone(&schedule.handler)));
+        }
+        due
+    }
+
+    pub fn record(&mut self, id: u64, error: Option<&str>) {
+        if let Some(schedule) = self.schedules.iter_mut().find(|s| s.id == id) {
+            schedule.runs += 1;
+            schedule.last_error = error.map(str::to_string);
+        }
+    }
+}
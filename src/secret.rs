@@ -0,0 +1,87 @@
+// Secrets - secret("API key?") reads a line from the terminal without echoing
+// it, and gives a value that shows as <secret> wherever values are printed:
+// echoed results, print, watches, JSON, error messages and exported traces.
+// Joining a secret and a string with + gives another secret, so a command or
+// URL built around a credential stays hidden as well.
+//
+// sh, sh_if, par_run and http_get use the text itself. Agents are never sent
+// one, since nothing here controls what they log.
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, PartialEq)]
+pub struct Secret(Rc<str>);
+
+impl Secret {
+    pub fn new(text: &str) -> Secret {
+        Secret(Rc::from(text))
+    }
+
+    // The text itself, for builtins that must hand it on
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    // The text with this secret, wherever it appears, replaced by <secret>
+    pub fn redact(&self, text: &str) -> String {
+        if self.0.is_empty() {
+            return text.to_string();
+        }
+        text.replace(&*self.0, "<secret>")
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<secret>")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<secret>")
+    }
+}
+
+// Prompt on the terminal and read a line with echo off. This reads the
+// terminal rather than stdin, so it works when the session's input is piped,
+// and fails rather than blocks when there is no terminal at all.
+#[cfg(unix)]
+pub fn read(prompt: &str) -> Result<Secret, String> {
+    use std::fs::OpenOptions;
+    use std::io::{BufRead, BufReader, Write};
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| "secret needs a terminal to read from".to_string())?;
+    stty(&tty, "-echo")?;
+    let _ = write!(tty, "{} ", prompt);
+    let _ = tty.flush();
+    let mut line = String::new();
+    let read = tty.try_clone().map_err(|e| e.to_string()).and_then(|tty| {
+        BufReader::new(tty).read_line(&mut line).map_err(|e| format!("Failed to read secret: {}", e))
+    });
+    // Echo comes back on even if the read failed
+    let restored = stty(&tty, "echo");
+    let _ = writeln!(tty);
+    read?;
+    restored?;
+    Ok(Secret::new(line.trim_end_matches(['\r', '\n'])))
+}
+
+#[cfg(unix)]
+fn stty(tty: &std::fs::File, setting: &str) -> Result<(), String> {
+    use std::process::{Command, Stdio};
+    let tty = tty.try_clone().map_err(|e| e.to_string())?;
+    match Command::new("stty").arg(setting).stdin(Stdio::from(tty)).status() {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err("secret could not turn terminal echo off and on".to_string()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn read(_: &str) -> Result<Secret, String> {
+    Err("secret needs a terminal to read from".to_string())
+}
@@ -0,0 +1,121 @@
+// Serial ports, for driving microcontrollers from the REPL.
+// serial_open("/dev/ttyUSB0", 115200) opens the device raw at that baud rate;
+// serial_write(port, text) sends text as it is (or an array of byte values),
+// and serial_read(port) gives the next line the device sends, without its
+// line ending. In Tau, `recv port` is a future for the next line, and
+// stream(port) reads one line after another.
+//
+// The port is set up with stty, so this works on Unix only.
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+struct Device {
+    path: String,
+    baud: u32,
+    file: Option<File>,  // None once closed
+    partial: Vec<u8>,    // Bytes of a line still arriving
+}
+
+// Shared handle held by Value::Serial; clones use the same device
+#[derive(Clone)]
+pub struct Port(Rc<RefCell<Device>>);
+
+impl Port {
+    #[cfg(unix)]
+    pub fn open(path: &str, baud: u32) -> Result<Port, String> {
+        use std::process::{Command, Stdio};
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open serial port {}: {}", path, e))?;
+        // Raw bytes both ways, no modem control lines, and reads that give up
+        // after a tenth of a second so a read can be abandoned on time
+        let input = file.try_clone().map_err(|e| e.to_string())?;
+        let output = Command::new("stty")
+            .args([&baud.to_string(), "raw", "-echo", "clocal", "min", "0", "time", "1"])
+            .stdin(Stdio::from(input))
+            .output()
+            .map_err(|e| format!("Failed to run stty for {}: {}", path, e))?;
+        if !output.status.success() {
+            let reason = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("Failed to set {} to {} baud: {}", path, baud, reason.trim()));
+        }
+        let device = Device { path: path.to_string(), baud, file: Some(file), partial: Vec::new() };
+        Ok(Port(Rc::new(RefCell::new(device))))
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(_: &str, _: u32) -> Result<Port, String> {
+        Err("Serial ports are only supported on Unix".to_string())
+    }
+
+    pub fn write(&self, bytes: &[u8]) -> Result<(), String> {
+        let mut device = self.0.borrow_mut();
+        let path = device.path.clone();
+        let file = device.file.as_mut().ok_or_else(|| format!("Serial port {} is closed", path))?;
+        file.write_all(bytes).and_then(|_| file.flush()).map_err(|e| format!("Failed to write to {}: {}", path, e))
+    }
+
+    // Wait for the next line, for at most `limit` if given. Part of a line
+    // that arrived before the limit is kept for the next read.
+    pub fn read_line(&self, limit: Option<Duration>) -> Result<String, String> {
+        match self.next(limit)? {
+            Some(line) => Ok(line),
+            None => Err(format!("Serial port {} is closed", self.0.borrow().path)),
+        }
+    }
+
+    // Like read_line, but None once the port is closed
+    pub fn next(&self, limit: Option<Duration>) -> Result<Option<String>, String> {
+        let deadline = limit.map(|limit| Instant::now() + limit);
+        let mut device = self.0.borrow_mut();
+        let mut buffer = [0u8; 256];
+        loop {
+            if let Some(end) = device.partial.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = device.partial.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()));
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(format!("Timed out reading from {}", device.path));
+            }
+            let path = device.path.clone();
+            let Some(file) = device.file.as_mut() else {
+                return Ok(None);
+            };
+            // With stty's time 1 a read gives nothing after 0.1s of quiet
+            match file.read(&mut buffer) {
+                Ok(n) => device.partial.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(format!("Failed to read from {}: {}", path, e)),
+            }
+        }
+    }
+
+    pub fn close(&self) {
+        self.0.borrow_mut().file = None;
+    }
+}
+
+impl PartialEq for Port {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// serial port /dev/ttyUSB0 at 115200 baud, or (closed)
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let device = self.0.borrow();
+        write!(f, "serial port {} at {} baud", device.path, device.baud)?;
+        if device.file.is_none() {
+            write!(f, " (closed)")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+// Shell commands. A line starting with a backtick is run for its output, as
+// at a shell prompt (see Repl::process_bash); elsewhere `cmd` is an expression
+// giving the command's result as a map - [{"status", 0}, {"ok", true},
+// {"stdout", ..}, {"stderr", ..}] - so code can branch on success without
+// reading stderr, e.g. sh_if(`test -f foo`, then_fn, else_fn).
+//
+// par_run([cmd, ...]) runs commands side by side on worker threads and gives
+// their results in the order given; in Tau it is a future, so the commands
+// run while other statements do. ssh(host, cmd) runs one on another machine
+// and gives the same kind of result (built with the ssh feature).
+use crate::value::{Map, Value};
+use std::collections::VecDeque;
+use std::io::Read;
+#[cfg(feature = "ssh")]
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct CommandOutput {
+    pub status: Option<i32>,  // None when a signal ended the command
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+
+    // Output keeps everything but the trailing newline
+    pub fn into_value(self) -> Value {
+        let status = self.status.map_or(Value::Unit, |code| Value::Num(code as f64));
+        let pairs = vec![
+            (Value::Str("status".into()), status),
+            (Value::Str("ok".into()), Value::Bool(self.success())),
+            (Value::Str("stdout".into()), Value::Str(self.stdout.trim_end().into())),
+            (Value::Str("stderr".into()), Value::Str(self.stderr.trim_end().into())),
+        ];
+        Value::Map(Map::from_pairs(pairs).unwrap_or_default())
+    }
+}
+
+// Whether a command result map (as `cmd` gives) succeeded
+pub fn succeeded(result: &Map) -> Option<bool> {
+    match result.get(&Value::Str("ok".into())) {
+        Some(Value::Bool(ok)) => Some(*ok),
+        _ => None,
+    }
+}
+
+// A command ready to run, and the text its errors name it by
+pub struct Job {
+    command: Command,
+    label: String,
+}
+
+// A command line for the system shell
+pub fn sh(cmd: &str) -> Job {
+    let command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", cmd]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    };
+    Job { command, label: cmd.to_string() }
+}
+
+// The command line a value holds: a string, or a secret whose text errors
+// name only as <secret>
+pub fn command(value: &Value) -> Option<Job> {
+    match value {
+        Value::Str(cmd) => Some(sh(cmd)),
+        Value::Secret(secret) => Some(Job { label: secret.to_string(), ..sh(secret.expose()) }),
+        _ => None,
+    }
+}
+
+// A command line run on `host` by an ssh client (config ssh, or ssh on PATH).
+// BatchMode makes ssh fail rather than prompt for a password, so only hosts
+// set up for key authentication work.
+#[cfg(feature = "ssh")]
+pub fn ssh(client: &Path, host: &str, cmd: &str) -> Result<Job, String> {
+    if host.is_empty() || host.starts_with('-') || host.contains(char::is_whitespace) {
+        return Err(format!("Invalid ssh host: {:?}", host));
+    }
+    let mut command = Command::new(client);
+    command.args(["-o", "BatchMode=yes", host, "--", cmd]).stdin(Stdio::null());
+    Ok(Job { command, label: format!("ssh {}: {}", host, cmd) })
+}
+
+// Run a command through the system shell, killing it at the deadline
+pub fn run(cmd: &str, deadline: Option<(Instant, Duration)>) -> Result<CommandOutput, String> {
+    sh(cmd).run(deadline)
+}
+
+impl Job {
+    pub fn run(self, deadline: Option<(Instant, Duration)>) -> Result<CommandOutput, String> {
+        self.run_until(deadline, &AtomicBool::new(false))
+    }
+
+    // As run, also killing the command once `stop` is set
+    fn run_until(self, deadline: Option<(Instant, Duration)>, stop: &AtomicBool) -> Result<CommandOutput, String> {
+        let Job { mut command, label: cmd } = self;
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+        // Drain the pipes on threads so a chatty command cannot block while we poll
+        let drain = |pipe: Option<Box<dyn Read + Send>>| {
+            thread::spawn(move || {
+                let mut text = String::new();
+                if let Some(mut pipe) = pipe {
+                    let mut bytes = Vec::new();
+                    let _ = pipe.read_to_end(&mut bytes);
+                    text = String::from_utf8_lossy(&bytes).to_string();
+                }
+                text
+            })
+        };
+        let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+        let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => {}
+                Err(e) => return Err(format!("Failed to execute command: {}", e)),
+            }
+            if let Some((deadline, limit)) = deadline
+                && Instant::now() >= deadline
+            {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Command timed out after {:?}: {}", limit, cmd));
+            }
+            if stop.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Command stopped: {}", cmd));
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+
+        Ok(CommandOutput {
+            status: status.code(),
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+// Commands running on up to `limit` worker threads. Dropping the batch, as
+// cancelling its future does, kills whatever is still running.
+pub struct Batch {
+    count: usize,
+    single: bool,  // Give the lone command's result rather than an array
+    results: Receiver<(usize, Result<CommandOutput, String>)>,
+    received: Vec<(usize, Result<CommandOutput, String>)>,  // Taken by poll before wait
+    stop: Arc<AtomicBool>,
+}
+
+impl Batch {
+    pub fn start(jobs: Vec<Job>, limit: usize) -> Batch {
+        let count = jobs.len();
+        let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, results) = mpsc::channel();
+        for _ in 0..limit.clamp(1, count.max(1)) {
+            let (queue, stop, sender) = (queue.clone(), stop.clone(), sender.clone());
+            thread::spawn(move || {
+                loop {
+                    let Some((index, job)) = queue.lock().unwrap().pop_front() else { break };
+                    if stop.load(Ordering::Relaxed) || sender.send((index, job.run_until(None, &stop))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Batch { count, single: false, results, received: Vec::new(), stop }
+    }
+
+    // One command in the background, as Tau's ssh(host, cmd)
+    pub fn one(job: Job) -> Batch {
+        let mut batch = Batch::start(vec![job], 1);
+        batch.single = true;
+        batch
+    }
+
+    // Whether every command has finished, without waiting for any
+    pub fn poll(&mut self) -> bool {
+        while let Ok(result) = self.results.try_recv() {
+            self.received.push(result);
+        }
+        self.received.len() == self.count
+    }
+
+    // Every command's result map, in the order given. The first command that
+    // could not run fails the lot, and nothing is kept running past the deadline.
+    pub fn wait(mut self, deadline: Option<(Instant, Duration)>) -> Result<Value, String> {
+        let mut outputs: Vec<Option<CommandOutput>> = (0..self.count).map(|_| None).collect();
+        let polled = std::mem::take(&mut self.received);
+        let remaining = self.count - polled.len();
+        for (index, output) in polled {
+            outputs[index] = Some(output?);
+        }
+        for _ in 0..remaining {
+            let received = match deadline {
+                Some((at, limit)) => self.results.recv_timeout(at.saturating_duration_since(Instant::now())).map_err(|e| match e {
+                    RecvTimeoutError::Timeout => format!("Commands timed out after {:?}", limit),
+                    RecvTimeoutError::Disconnected => "Command workers stopped".to_string(),
+                }),
+                None => self.results.recv().map_err(|_| "Command workers stopped".to_string()),
+            };
+            let (index, output) = received?;
+            outputs[index] = Some(output?);
+        }
+        let mut results: Vec<Value> = outputs.into_iter().flatten().map(CommandOutput::into_value).collect();
+        if self.single && results.len() == 1 {
+            return Ok(results.remove(0));
+        }
+        Ok(Value::Array(results.into()))
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,240 @@
+// What each builtin takes, checked before it runs so that every builtin
+// answers a wrong call the same way:
+//   len expects 1 argument, got 2 arguments (array, number) at 1:5
+// Builtins still check what a kind cannot say, such as a number's range.
+// A name is a builtin exactly when it has a signature here.
+use crate::value::Value;
+use Kind::*;
+
+// What one parameter accepts
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Any,
+    Num,
+    Str,
+    Text,  // A string or a secret
+    Bool,
+    Color,
+    Array,
+    Map,
+    Function,
+    Future,
+    Agent,  // A mock or a proxy
+    Mock,
+    Proxy,
+    Metric,
+    Socket,
+    Subscription,
+    Stream,
+    Mqtt,
+    Serial,
+    Continuation,
+    Generator,
+}
+
+impl Kind {
+    fn accepts(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (Kind::Any, _)
+                | (Kind::Num, Value::Num(_))
+                | (Kind::Str, Value::Str(_))
+                | (Kind::Text, Value::Str(_) | Value::Secret(_))
+                | (Kind::Bool, Value::Bool(_))
+                | (Kind::Color, Value::Color(_))
+                | (Kind::Array, Value::Array(_))
+                | (Kind::Map, Value::Map(_))
+                | (Kind::Function, Value::Function(_))
+                | (Kind::Future, Value::Future(_))
+                | (Kind::Agent, Value::Mock(_) | Value::Proxy(_))
+                | (Kind::Mock, Value::Mock(_))
+                | (Kind::Proxy, Value::Proxy(_))
+                | (Kind::Metric, Value::Metric(_))
+                | (Kind::Socket, Value::Socket(_))
+                | (Kind::Subscription, Value::Subscription(_))
+                | (Kind::Stream, Value::Stream(_))
+                | (Kind::Mqtt, Value::Mqtt(_))
+                | (Kind::Serial, Value::Serial(_))
+                | (Kind::Continuation, Value::Continuation(_))
+                | (Kind::Generator, Value::Generator(_))
+        )
+    }
+
+    // As Value::kind names the values it accepts
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Any => "any",
+            Kind::Num => "number",
+            Kind::Str | Kind::Text => "string",
+            Kind::Bool => "bool",
+            Kind::Color => "color",
+            Kind::Array => "array",
+            Kind::Map => "map",
+            Kind::Function => "function",
+            Kind::Future => "future",
+            Kind::Agent => "agent",
+            Kind::Mock => "mock",
+            Kind::Proxy => "proxy",
+            Kind::Metric => "metric",
+            Kind::Socket => "socket",
+            Kind::Subscription => "subscription",
+            Kind::Stream => "stream",
+            Kind::Mqtt => "mqtt client",
+            Kind::Serial => "serial port",
+            Kind::Continuation => "continuation",
+            Kind::Generator => "generator",
+        }
+    }
+}
+
+// The parameters' kinds; the first `required` must be given, and when
+// `variadic` the last may repeat
+pub struct Signature {
+    params: &'static [Kind],
+    required: usize,
+    variadic: bool,
+}
+
+const fn fixed(params: &'static [Kind]) -> Signature {
+    Signature { params, required: params.len(), variadic: false }
+}
+
+const fn optional(required: usize, params: &'static [Kind]) -> Signature {
+    Signature { params, required, variadic: false }
+}
+
+const fn variadic(required: usize, params: &'static [Kind]) -> Signature {
+    Signature { params, required, variadic: true }
+}
+
+// Every builtin, including the ones the runtime runs itself
+pub fn signature(name: &str) -> Option<Signature> {
+    Some(match name {
+        "color" => optional(3, &[Num, Num, Num, Num]),
+        "to_ansi256" | "nearest_named" | "complementary" | "triadic" => fixed(&[Color]),
+        "contrast" => fixed(&[Color, Color]),
+        "with_alpha" | "analogous" | "scale" => fixed(&[Color, Num]),
+        "show" => variadic(0, &[Any]),
+        "mix" | "gradient" => optional(3, &[Color, Color, Num, Str]),
+        "blend" => optional(2, &[Color, Color, Str]),
+        "promise" | "processes" | "metrics_export" | "clipboard" => fixed(&[]),
+        "resolve" | "reject" => fixed(&[Future, Any]),
+        "await" | "len" | "to_json" | "sleep" | "sh" | "wait" | "close" | "pop" => fixed(&[Any]),
+        "map" | "filter" => fixed(&[Array, Function]),
+        "reduce" => fixed(&[Array, Any, Function]),
+        "sort" => optional(1, &[Array, Function]),
+        "reverse" | "unique" => fixed(&[Array]),
+        "contains" | "remove" | "get" => fixed(&[Any, Any]),
+        "upper" | "lower" | "trim" => fixed(&[Str]),
+        "split" => fixed(&[Str, Str]),
+        "replace" => fixed(&[Str, Str, Str]),
+        "join" => fixed(&[Array, Str]),
+        "push" => variadic(2, &[Any, Any]),
+        "insert" => fixed(&[Any, Any, Any]),
+        "parse_json" | "secret" | "cred_get" | "env" | "clipboard_set" | "counter" | "gauge" | "timer" | "trace_export" | "glob" | "basename"
+        | "dirname" | "ext" | "canonicalize" | "zip_list" | "tar_list" => fixed(&[Str]),
+        "keys" | "values" | "sort_keys" => fixed(&[Map]),
+        "call" => variadic(2, &[Agent, Str, Any]),
+        "connect" => variadic(1, &[Any, Str, Str]),
+        "http_get" | "mqtt_connect" => fixed(&[Text]),
+        "http_get_cached" => fixed(&[Text, Any]),
+        "http_json" => optional(2, &[Str, Text, Any, Any]),
+        "graphql" => optional(2, &[Text, Str, Any, Any]),
+        "cred_set" => fixed(&[Str, Text]),
+        "par_run" => optional(1, &[Array, Num]),
+        "ssh" | "zip_extract" | "tar_extract" => fixed(&[Str, Str]),
+        "kill" => optional(1, &[Any, Any]),
+        "sh_if" => optional(2, &[Any, Function, Function]),
+        "tcp_accept" | "tcp_recv" | "socket_address" => fixed(&[Socket]),
+        "publish" => fixed(&[Mqtt, Str, Any]),
+        "subscribe" => fixed(&[Mqtt, Str, Function]),
+        "serial_open" => fixed(&[Str, Num]),
+        "serial_read" => optional(1, &[Serial, Any]),
+        "serial_write" => fixed(&[Serial, Any]),
+        "stream" => optional(1, &[Any, Num]),
+        "next" => fixed(&[Stream]),
+        "subscription_recv" => fixed(&[Subscription]),
+        "schedule" => fixed(&[Str, Function]),
+        "cancel_schedule" => fixed(&[Num]),
+        "inc" => optional(1, &[Metric, Num]),
+        "set" => fixed(&[Metric, Num]),
+        "stop" => fixed(&[Metric]),
+        "trace" => fixed(&[Bool]),
+        "zip_create" | "tar_create" => fixed(&[Str, Array]),
+        "path_join" => variadic(0, &[Str]),
+        "wire_stats" | "ping" => fixed(&[Proxy]),
+        "keepalive" => fixed(&[Proxy, Num]),
+        "reconnect" => fixed(&[Proxy, Num, Num]),
+        "on_disconnect" => fixed(&[Proxy, Function]),
+        "mock_return" => fixed(&[Mock, Str, Any]),
+        "mock_latency" => fixed(&[Mock, Str, Num]),
+        "mock_calls" => fixed(&[Mock, Str]),
+        "print" => variadic(0, &[Any]),
+        "then" | "catch" => fixed(&[Future, Function]),
+        "all" | "race" => fixed(&[Array]),
+        "callcc" => fixed(&[Function]),
+        "continue" => optional(1, &[Continuation, Any]),
+        "resume" => fixed(&[Generator]),
+        _ => return None,
+    })
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    signature(name).is_some()
+}
+
+// A call's arguments against the builtin's signature; anything that is not a
+// builtin passes, for the caller to report
+pub fn check(name: &str, args: &[Value]) -> Result<(), String> {
+    match signature(name) {
+        Some(signature) if !signature.admits(args) => Err(format!("{} expects {}, got {}", name, signature, given(args))),
+        _ => Ok(()),
+    }
+}
+
+impl Signature {
+    fn admits(&self, args: &[Value]) -> bool {
+        let Some(last) = self.params.last() else {
+            return args.is_empty();
+        };
+        args.len() >= self.required
+            && (self.variadic || args.len() <= self.params.len())
+            && args.iter().enumerate().all(|(i, arg)| self.params.get(i).unwrap_or(last).accepts(arg))
+    }
+}
+
+// "1 numeric argument", "2 or 3 arguments (array, function, any)"
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let count = self.params.len();
+        let number = match (self.variadic, self.required) {
+            (false, 0) if count == 0 => return write!(f, "no arguments"),
+            (true, 0) => "any number of".to_string(),
+            (true, required) => format!("at least {}", required),
+            (false, required) if required == count => count.to_string(),
+            (false, required) if required + 1 == count => format!("{} or {}", required, count),
+            (false, required) => format!("{} to {}", required, count),
+        };
+        let noun = if number == "1" { "argument" } else { "arguments" };
+        let first = self.params[0];
+        if self.params.iter().all(|kind| *kind == first) {
+            return match first {
+                Any => write!(f, "{} {}", number, noun),
+                Num => write!(f, "{} numeric {}", number, noun),
+                kind => write!(f, "{} {} {}", number, kind.name(), noun),
+            };
+        }
+        let kinds: Vec<&str> = self.params.iter().map(|kind| kind.name()).collect();
+        let repeat = if self.variadic { "..." } else { "" };
+        write!(f, "{} {} ({}{})", number, noun, kinds.join(", "), repeat)
+    }
+}
+
+// "2 arguments (string, number)"
+fn given(args: &[Value]) -> String {
+    match args {
+        [] => "no arguments".to_string(),
+        [arg] => format!("1 argument ({})", arg.kind()),
+        args => format!("{} arguments ({})", args.len(), args.iter().map(Value::kind).collect::<Vec<_>>().join(", ")),
+    }
+}
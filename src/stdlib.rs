@@ -0,0 +1,54 @@
+// The standard library and examples, built into the binary so `import
+// "std/strings"` and `rcl examples/primes.tsu` work wherever rcl is copied.
+// The files live under lib/ in the source tree. `--stdlib-path dir` (or
+// `stdlib-path = dir` in the config) reads them from dir first instead, so
+// they can be edited without rebuilding; files not there still come from the
+// binary.
+use std::path::{Path, PathBuf};
+
+// Modules read from the binary have paths under this prefix
+pub const BUILT_IN: &str = "builtin:";
+
+const FILES: &[(&str, &str)] = &[
+    ("std/arrays.tsu", include_str!("../lib/std/arrays.tsu")),
+    ("std/math.tsu", include_str!("../lib/std/math.tsu")),
+    ("std/strings.tsu", include_str!("../lib/std/strings.tsu")),
+    ("examples/primes.tsu", include_str!("../lib/examples/primes.tsu")),
+    ("examples/scores.tsu", include_str!("../lib/examples/scores.tsu")),
+];
+
+// `import "std/strings"` gives std/strings; anything else is not an import
+pub fn import_target(input: &str) -> Option<&str> {
+    let rest = input.trim().strip_prefix("import")?.trim();
+    rest.strip_prefix('"')?.strip_suffix('"').filter(|target| !target.contains('"'))
+}
+
+// The library file an import names, from the override directory if it is
+// there and the binary otherwise. The extension may be left off.
+pub fn resolve(target: &str, dir: Option<&Path>) -> Option<PathBuf> {
+    let candidates: Vec<String> = match Path::new(target).extension() {
+        Some(_) => vec![target.to_string()],
+        None => ["tsu", "rho", "pi"].iter().map(|ext| format!("{}.{}", target, ext)).collect(),
+    };
+    if let Some(dir) = dir
+        && let Some(path) = candidates.iter().map(|c| dir.join(c)).find(|path| path.is_file())
+    {
+        return Some(path);
+    }
+    candidates.iter().find(|c| file(c).is_some()).map(|c| PathBuf::from(format!("{}{}", BUILT_IN, c)))
+}
+
+// A built-in file by its path under lib/
+pub fn file(path: &str) -> Option<&'static str> {
+    FILES.iter().find(|(name, _)| *name == path).map(|(_, source)| *source)
+}
+
+// The source behind a module path from resolve, when it is built in
+pub fn built_in(path: &Path) -> Option<&'static str> {
+    file(path.to_str()?.strip_prefix(BUILT_IN)?)
+}
+
+// Every built-in file, for :help and the tests
+pub fn files() -> impl Iterator<Item = &'static str> {
+    FILES.iter().map(|(name, _)| *name)
+}
@@ -0,0 +1,123 @@
+// Streams - values that arrive over time, read one at a time with next(s) or
+// `for x in s`. A future settles once; a stream keeps giving values until its
+// source ends. stream(source) wraps a connected socket (a value per line the
+// peer sends), a serial port (a string per line), a subscription, a
+// generator, or an array or range.
+//
+// Streams pull, so nothing is read from a socket until asked for: a slow
+// reader leaves data in the connection and TCP holds the sender back. A
+// subscription has no such brake, so stream(sub, 100) caps how many values it
+// may queue; publish then skips it while it is full (see pubsub.rs).
+use crate::generator::Generator;
+use crate::net::Socket;
+use crate::pubsub::Subscription;
+use crate::runtime::Runtime;
+use crate::serial::Port;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+// Queue limit for a subscription when stream() is not given one
+pub const DEFAULT_LIMIT: usize = 256;
+
+enum Source {
+    Socket(Socket),
+    Serial(Port),
+    Subscription(Subscription),
+    Generator(Generator),
+    Values(VecDeque<Value>),
+}
+
+struct State {
+    source: Source,
+    received: usize,  // Values read so far
+    ended: bool,
+}
+
+// Shared handle held by Value::Stream; clones read from the same source
+#[derive(Clone)]
+pub struct Stream(Rc<RefCell<State>>);
+
+impl Stream {
+    pub fn new(source: Value, limit: Option<usize>) -> Result<Stream, String> {
+        let source = match source {
+            Value::Socket(socket) => Source::Socket(socket),
+            Value::Serial(port) => Source::Serial(port),
+            Value::Subscription(subscription) => {
+                subscription.set_limit(limit.unwrap_or(DEFAULT_LIMIT));
+                Source::Subscription(subscription)
+            }
+            Value::Generator(generator) => Source::Generator(generator),
+            Value::Array(items) => Source::Values(items.into_vec().into()),
+            Value::Range(range) => Source::Values((range.start..range.end).map(|n| Value::Num(n as f64)).collect()),
+            other => return Err(format!("stream expects a socket, serial port, subscription, generator, array or range, got {:?}", other)),
+        };
+        Ok(Stream(Rc::new(RefCell::new(State { source, received: 0, ended: false }))))
+    }
+
+    // The next value; None once the source has ended, or for a subscription
+    // when nothing is waiting. A socket or serial port waits for its peer,
+    // within the evaluation's time limit.
+    pub fn next(&self, runtime: &mut Runtime) -> Result<Option<Value>, String> {
+        if self.is_ended() {
+            return Ok(None);
+        }
+        // Generators run code that may use this stream, so nothing stays borrowed
+        let generator = match &self.0.borrow().source {
+            Source::Generator(generator) => Some(generator.clone()),
+            _ => None,
+        };
+        let next = match generator {
+            Some(generator) => generator.next(runtime)?,
+            None => match &mut self.0.borrow_mut().source {
+                Source::Socket(socket) => socket.next(runtime.remaining())?,
+                Source::Serial(port) => port.next(runtime.remaining())?.map(|line| Value::Str(line.into())),
+                Source::Subscription(subscription) => match subscription.try_next() {
+                    Some(value) => Some(value),
+                    None if subscription.is_closed() => None,
+                    None => return Ok(None),
+                },
+                Source::Values(values) => values.pop_front(),
+                Source::Generator(_) => None,
+            },
+        };
+        let mut state = self.0.borrow_mut();
+        match next {
+            Some(_) => state.received += 1,
+            None => state.ended = true,
+        }
+        Ok(next)
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.0.borrow().ended
+    }
+}
+
+impl PartialEq for Stream {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// stream of subscription to prices (2 pending), 5 received; or ended
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.0.borrow();
+        write!(f, "stream of ")?;
+        match &state.source {
+            Source::Socket(socket) => write!(f, "socket {:?}", socket)?,
+            Source::Serial(port) => write!(f, "{:?}", port)?,
+            Source::Subscription(subscription) => write!(f, "{:?}", subscription)?,
+            Source::Generator(_) => write!(f, "generator")?,
+            Source::Values(values) => write!(f, "{} values", values.len() + state.received)?,
+        }
+        if state.ended {
+            write!(f, ", ended after {}", state.received)
+        } else {
+            write!(f, ", {} received", state.received)
+        }
+    }
+}
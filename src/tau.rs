@@ -1,219 +1,443 @@
 // Tau Language - Network language with futures
-use crate::value::{Value, FutureState};
-use std::collections::HashMap;
+use crate::builtins;
+use crate::capabilities::Capability;
+use crate::codegen;
+use crate::config;
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::mock::Mock;
+use crate::net::Socket;
+use crate::rho::Parser;
+use crate::shell::Batch;
+use crate::runtime::{Expr, Language, Runtime, Site};
+use crate::transport::Proxy;
+use crate::value::{Function, Future, TaskError, Value};
 use std::fs;
 use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn parse_tau(input: &str, variables: &HashMap<String, Value>, parse_rho_fn: impl Fn(&str) -> Result<Value, String>) -> Result<Value, String> {
+// The .tsu interface grammar lives in its own module; tooling reaches it through Tau
+pub use crate::interface::{parse_interface, Event, Interface, Method, Param, Type};
+
+// Statements this module reads with grammars of their own rather than the
+// Rho expression grammar
+const STATEMENTS: &[&str] = &[
+    "proxy", "agent", "mock", "fetch", "listen", "dial", "accept", "recv", "send", "subscribe", "publish", "cancel", "timeout",
+    "sleep", "par_run", "ssh", "wait", "suspend",
+];
+
+// Every syntax error in a Tau script, line by line as it runs. Statements
+// such as `f = fetch url` have grammars of their own and are only lexed; the
+// rest, futures and their combinators included, is checked as Rho.
+pub fn check_tau(source: &str) -> Vec<String> {
+    let lines: Vec<&str> = source
+        .lines()
+        .map(|line| {
+            let Ok(tokens) = tokenize(line) else {
+                return line;
+            };
+            let mut statement = tokens.as_slice();
+            while let [Token { kind: TokenKind::Ident(_), .. }, Token { kind: TokenKind::Assign, .. }, rest @ ..] = statement {
+                statement = rest;
+            }
+            match statement {
+                [Token { kind: TokenKind::Ident(name), .. }, _, ..] if STATEMENTS.contains(&name.as_str()) => "",
+                _ => line,
+            }
+        })
+        .collect();
+    crate::rho::check_rho(&lines.join("\n"))
+}
+
+pub fn parse_tau(input: &str, runtime: &mut Runtime) -> Result<Value, String> {
     let input = input.trim();
+    let tokens = tokenize(input)?;
+    statement(input, &tokens, runtime)
+}
 
-    // Handle proxy and agent generation from filename
-    // Syntax: proxy "filename.t" or agent "filename.t"
-    if input.starts_with("proxy ") {
-        let filename = input[6..].trim().trim_matches('"').trim_matches('\'');
-        return generate_proxy(filename);
+// One statement; `tokens` may be a tail of the tokens of `input`, so source
+// text is always sliced by token position
+fn statement(input: &str, tokens: &[Token], runtime: &mut Runtime) -> Result<Value, String> {
+    // name = <statement> binds the result, e.g. f = async fetch(url)
+    if let [Token { kind: TokenKind::Ident(name), .. }, Token { kind: TokenKind::Assign, .. }, _, ..] = tokens {
+        let value = statement(input, &tokens[2..], runtime)?;
+        runtime.update(name.clone(), value.clone())?;
+        return Ok(value);
     }
 
-    if input.starts_with("agent ") {
-        let filename = input[6..].trim().trim_matches('"').trim_matches('\'');
-        return generate_agent(filename);
-    }
+    let keyword = match tokens.first() {
+        Some(Token { kind: TokenKind::Ident(name), .. }) if tokens.len() > 1 => name.as_str(),
+        _ => "",
+    };
 
-    // Handle async operations
-    if input.starts_with("async ") {
-        let _operation = &input[6..];
-        return Ok(Value::Future(FutureState::Pending));
+    match keyword {
+        // Handle proxy and agent generation from filename
+        // Syntax: proxy "filename.tsu" or agent "filename.tsu"
+        // With an address they run instead, see rpc.rs: agent "calc.tsu" 9000
+        // serves the interface, proxy "calc.tsu" "host:9000" calls it
+        "proxy" | "agent" => {
+            // proxy --rust "calc.tsu" writes a Rust module with both ends instead
+            if keyword == "proxy"
+                && let Some(filename) = text_from(input, &tokens[1]).strip_prefix("--rust")
+            {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                let root = runtime.config.workspace.clone();
+                return generate_rust(filename.trim().trim_matches('"'), &root);
+            }
+            let (filename, address) = endpoint_arguments(input, tokens, runtime)?;
+            let Some(address) = address else {
+                runtime.config.capabilities.check(Capability::FsRead)?;
+                runtime.config.capabilities.check(Capability::FsWrite)?;
+                let root = runtime.config.workspace.clone();
+                return if keyword == "proxy" {
+                    generate_proxy(&filename, &root)
+                } else {
+                    generate_agent(&filename, &root)
+                };
+            };
+            runtime.config.capabilities.check(Capability::Net)?;
+            let interface = read_interface(runtime, &filename)?;
+            let bare_port = address.parse::<u16>().is_ok();
+            if keyword == "agent" {
+                let address = if bare_port { format!("0.0.0.0:{}", address) } else { address };
+                Ok(Value::Str(runtime.agents.serve(interface, &address)?.to_string().into()))
+            } else {
+                let address = if bare_port { format!("127.0.0.1:{}", address) } else { address };
+                let proxy = Proxy::connect(interface, &format!("tcp:{}", address), &[])?;
+                proxy.borrow_mut().tracer = runtime.tracer.clone();
+                Ok(Value::Proxy(proxy))
+            }
+        }
+        // mock "file.tsu" - an in-process agent for the interface, see mock.rs
+        "mock" => {
+            let filename = command_argument(input, tokens);
+            Ok(Value::Mock(Mock::new(read_interface(runtime, &filename)?)))
+        }
+        // fetch "http://..." - a future resolving to [{"status", ..}, {"headers", ..}, {"body", ..}]
+        "fetch" => {
+            runtime.config.capabilities.check(Capability::Net)?;
+            let url = match runtime.eval(&Parser::new(&tokens[1..]).parse_program()?)? {
+                url @ (Value::Str(_) | Value::Secret(_)) => url,
+                other => return Err(format!("fetch expects a URL, got {:?}", other)),
+            };
+            let task = Expr::Call("http_get".to_string(), vec![Expr::Value(url)], Site::default());
+            let site = format!("{}:{}", tokens[0].line, tokens[0].col);
+            Ok(Value::Future(runtime.spawn(text_from(input, &tokens[0]), &site, task)))
+        }
+        // TCP: listen 8080 / listen "127.0.0.1:0" and dial "host:port" give a socket
+        // at once; accept and recv give futures for the pending I/O; send writes
+        // one value straight away
+        "listen" | "dial" => {
+            runtime.config.capabilities.check(Capability::Net)?;
+            let address = match runtime.eval(&Parser::new(&tokens[1..]).parse_program()?)? {
+                Value::Num(port) if keyword == "listen" => format!("0.0.0.0:{}", port),
+                Value::Str(address) => address.to_string(),
+                other => return Err(format!("{} expects an address, got {:?}", keyword, other)),
+            };
+            let socket = if keyword == "listen" { Socket::listen(&address)? } else { Socket::dial(&address)? };
+            Ok(Value::Socket(socket))
+        }
+        // recv sub is a future for the next value published to a subscription,
+        // recv port for the next line from a serial port
+        "accept" | "recv" => {
+            let source = runtime.eval(&Parser::new(&tokens[1..]).parse_program()?)?;
+            let builtin = match source {
+                Value::Subscription(_) if keyword == "recv" => "subscription_recv".to_string(),
+                Value::Serial(_) if keyword == "recv" => "serial_read".to_string(),
+                Value::Socket(_) => {
+                    runtime.config.capabilities.check(Capability::Net)?;
+                    format!("tcp_{}", keyword)
+                }
+                other if keyword == "recv" => return Err(format!("recv expects a socket, subscription or serial port, got {:?}", other)),
+                other => return Err(format!("accept expects a socket, got {:?}", other)),
+            };
+            let task = Expr::Call(builtin, vec![Expr::Value(source)], Site::default());
+            let site = format!("{}:{}", tokens[0].line, tokens[0].col);
+            Ok(Value::Future(runtime.spawn(text_from(input, &tokens[0]), &site, task)))
+        }
+        // send sock value
+        "send" if tokens.len() > 2 => {
+            runtime.config.capabilities.check(Capability::Net)?;
+            let socket = runtime.eval(&Parser::new(&tokens[1..2]).parse_program()?)?;
+            let value = runtime.eval(&Parser::new(&tokens[2..]).parse_program()?)?;
+            match socket {
+                Value::Socket(socket) => socket.send(&value).map(|_| Value::Unit),
+                other => Err(format!("send expects a socket, got {:?}", other)),
+            }
+        }
+        // subscribe "topic" gives a subscription; publish "topic" value queues the
+        // value for each one and gives how many there were, see pubsub.rs
+        "subscribe" => match runtime.eval(&Parser::new(&tokens[1..]).parse_program()?)? {
+            Value::Str(topic) => Ok(Value::Subscription(runtime.broker.subscribe(&topic))),
+            other => Err(format!("subscribe expects a topic, got {:?}", other)),
+        },
+        "publish" if tokens.len() > 2 => {
+            let topic = match runtime.eval(&Parser::new(&tokens[1..2]).parse_program()?)? {
+                Value::Str(topic) => topic,
+                other => return Err(format!("publish expects a topic, got {:?}", other)),
+            };
+            let value = runtime.eval(&Parser::new(&tokens[2..]).parse_program()?)?;
+            Ok(Value::Num(runtime.broker.publish(&topic, &value) as f64))
+        }
+        // cancel fut drops the future's task so it never runs, giving whether
+        // it was still pending; timeout fut 5s cancels it unless it settles in time
+        "cancel" => match runtime.eval(&Parser::new(&tokens[1..]).parse_program()?)? {
+            Value::Future(future) => Ok(Value::Bool(runtime.cancel(&future, "by cancel"))),
+            other => Err(format!("cancel expects a future, got {:?}", other)),
+        },
+        "timeout" if tokens.len() > 2 => {
+            let future = match runtime.eval(&Parser::new(&tokens[1..2]).parse_program()?)? {
+                Value::Future(future) => future,
+                other => return Err(format!("timeout expects a future, got {:?}", other)),
+            };
+            let limit = config::parse_duration(text_from(input, &tokens[2]))?;
+            runtime.set_future_timeout(&future, limit);
+            Ok(Value::Future(future))
+        }
+        // sleep 500ms - a future resolved once the time has passed; awaiting it
+        // pauses, so a polling loop can wait between checks.
+        // The duration may also be an expression giving milliseconds or text
+        // such as "2s".
+        "sleep" if tokens.len() > 1 => {
+            let duration = match &tokens[1..] {
+                [Token { kind: TokenKind::Num(_), .. }, Token { kind: TokenKind::Ident(unit), .. }]
+                    if matches!(unit.as_str(), "ms" | "s" | "m") =>
+                {
+                    config::parse_duration(text_from(input, &tokens[1]))?
+                }
+                _ => builtins::duration_arg("sleep", &runtime.eval(&Parser::new(&tokens[1..]).parse_program()?)?)?,
+            };
+            let site = format!("{}:{}", tokens[0].line, tokens[0].col);
+            Ok(Value::Future(runtime.spawn_sleep(text_from(input, &tokens[0]), &site, duration)))
+        }
+        // par_run([cmd, ...][, limit]) starts the commands at once and gives a
+        // future for their results
+        "par_run" => {
+            let Expr::Call(_, args, _) = Parser::new(tokens).parse_program()? else {
+                return Err("par_run expects an array of commands and an optional limit of at least 1".to_string());
+            };
+            let args = args.into_iter().map(|arg| runtime.eval(&arg)).collect::<Result<Vec<_>, _>>()?;
+            let batch = builtins::commands(runtime, &args)?;
+            let site = format!("{}:{}", tokens[0].line, tokens[0].col);
+            Ok(Value::Future(runtime.spawn_commands(text_from(input, &tokens[0]), &site, batch)))
+        }
+        // ssh(host, cmd) starts the command on the host and gives a future for
+        // its result
+        "ssh" => {
+            let Expr::Call(_, args, _) = Parser::new(tokens).parse_program()? else {
+                return Err("ssh expects a host and a command".to_string());
+            };
+            let args = args.into_iter().map(|arg| runtime.eval(&arg)).collect::<Result<Vec<_>, _>>()?;
+            let batch = Batch::one(builtins::remote(runtime, &args)?);
+            let site = format!("{}:{}", tokens[0].line, tokens[0].col);
+            Ok(Value::Future(runtime.spawn_commands(text_from(input, &tokens[0]), &site, batch)))
+        }
+        // wait(pid) gives a future resolved once the process exits
+        "wait" => {
+            let Expr::Call(_, args, _) = Parser::new(tokens).parse_program()? else {
+                return Err("wait expects a process id".to_string());
+            };
+            let args = args.into_iter().map(|arg| runtime.eval(&arg)).collect::<Result<Vec<_>, _>>()?;
+            let [pid] = args.as_slice() else {
+                return Err(format!("wait expects 1 argument, got {}", args.len()));
+            };
+            runtime.config.capabilities.check(Capability::Shell)?;
+            let pid = builtins::pid_arg("wait", pid)?;
+            let site = format!("{}:{}", tokens[0].line, tokens[0].col);
+            Ok(Value::Future(runtime.spawn_wait(text_from(input, &tokens[0]), &site, pid)))
+        }
+        // suspend <statement> pushes the statement as a Tau continuation;
+        // resume runs it as Tau even after :lang switches
+        "suspend" => {
+            runtime.suspend(Language::Tau, text_from(input, &tokens[1]));
+            Ok(Value::Unit)
+        }
+        // Default to Rho parsing
+        _ => {
+            let expr = Parser::new(tokens).parse_program()?;
+            runtime.eval(&expr)
+        }
     }
+}
+
+// How often a race checks futures that are waiting on time or the outside world
+const RACE_POLL: Duration = Duration::from_millis(2);
+
+// Work behind a future made by a combinator; it runs when that future is awaited
+pub enum Combinator {
+    Then(Future, Rc<Function>),   // f(value) once the future resolves
+    Catch(Future, Rc<Function>),  // f(message) if the future fails
+    All(Vec<Future>),             // Every value, in order, or the first failure
+    Race(Vec<Future>),            // Whichever future settles first
+}
 
-    // Handle await
-    if input.starts_with("await ") {
-        let var_name = input[6..].trim();
-        if let Some(value) = variables.get(var_name) {
-            match value {
-                Value::Future(FutureState::Resolved(v)) => return Ok((**v).clone()),
-                Value::Future(FutureState::Pending) => return Err("Future still pending".to_string()),
-                Value::Future(FutureState::Rejected(e)) => return Err(e.clone()),
-                _ => return Ok(value.clone()),
+// Run a combinator. A race watches its futures side by side: one already
+// settled wins, then the first, in order, that can settle without waiting.
+// Sleeps, process waits and commands are polled together until one can; an
+// expression cannot be run part way, so its task can settle as soon as the
+// race reaches it.
+pub fn combine(runtime: &mut Runtime, combinator: Combinator) -> Result<Value, String> {
+    match combinator {
+        Combinator::Then(future, f) => {
+            let value = runtime.await_future(&future)?;
+            let result = runtime.call_function(&f, vec![value])?;
+            flatten(runtime, result)
+        }
+        Combinator::Catch(future, f) => match runtime.await_future(&future) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                let result = runtime.call_function(&f, vec![Value::Str(TaskError::from_error(&e).message.into())])?;
+                flatten(runtime, result)
             }
+        },
+        Combinator::All(futures) => {
+            let values = futures.iter().map(|future| runtime.await_future(future)).collect::<Result<_, _>>()?;
+            Ok(Value::Array(values))
         }
-        return Err(format!("Variable {} not found", var_name));
+        Combinator::Race(futures) => loop {
+            runtime.expire_futures();
+            if let Some(future) = futures.iter().find(|future| !future.is_pending()) {
+                return runtime.await_future(future);
+            }
+            let now = Instant::now();
+            let mut waiting = false;
+            for future in &futures {
+                match runtime.executor.is_ready(future, now) {
+                    Some(true) => return runtime.await_future(future),
+                    Some(false) => waiting = true,
+                    None => {}
+                }
+            }
+            if !waiting {
+                return Err("race has no future that can settle".to_string());
+            }
+            runtime.check_deadline()?;
+            thread::sleep(RACE_POLL);
+        },
     }
-
-    // Default to Rho parsing
-    parse_rho_fn(input)
 }
 
-pub fn generate_proxy_wrapper(filename: &str) -> Result<Value, String> {
-    generate_proxy(filename)
+// A handler that returns a future continues the chain with that future's result
+fn flatten(runtime: &mut Runtime, value: Value) -> Result<Value, String> {
+    match value {
+        Value::Future(future) => runtime.await_future(&future),
+        value => Ok(value),
+    }
 }
 
-fn generate_proxy(filename: &str) -> Result<Value, String> {
-    // Read the source file
-    let content = fs::read_to_string(filename)
+// Parse a .tsu file from the workspace
+pub fn read_interface(runtime: &Runtime, filename: &str) -> Result<Interface, String> {
+    runtime.config.capabilities.check(Capability::FsRead)?;
+    let src = fs::read_to_string(runtime.config.in_workspace(filename))
         .map_err(|e| format!("Failed to read file {}: {}", filename, e))?;
+    parse_interface(&src).map_err(|e| format!("{}: {}", filename, e))
+}
 
-    // Extract base name without extension
-    let base_name = filename
-        .trim_end_matches(".tsu")
-        .split(['/', '\\'])
-        .last()
-        .unwrap_or(filename);
+// Source text from the start of `token` to the end of the input
+fn text_from<'a>(input: &'a str, token: &Token) -> &'a str {
+    let line_start: usize = input.split_inclusive('\n').take(token.line - 1).map(str::len).sum();
+    let line = &input[line_start..];
+    let offset = line.char_indices().nth(token.col - 1).map_or(line.len(), |(i, _)| i);
+    line[offset..].trim()
+}
 
-    // Create App/Network directory
-    fs::create_dir_all("App/Network")
-        .map_err(|e| format!("Failed to create App/Network directory: {}", e))?;
+// The operand of a command keyword: a quoted string, or the raw remaining text
+// so that unquoted paths like App/foo.tsu keep working
+fn command_argument(input: &str, tokens: &[Token]) -> String {
+    match tokens.get(1) {
+        Some(Token { kind: TokenKind::Str(s), .. }) if tokens.len() == 2 => s.clone(),
+        Some(argument) => text_from(input, argument).to_string(),
+        None => String::new(),
+    }
+}
 
-    // Generate proxy header filename: {name}Proxy.h
-    let proxy_header = format!("{}Proxy.h", base_name);
+// The operands of proxy and agent: the interface file, then the address for
+// the runtime forms - a port or "host:port", as a value or raw text
+fn endpoint_arguments(input: &str, tokens: &[Token], runtime: &mut Runtime) -> Result<(String, Option<String>), String> {
+    if let Some(Token { kind: TokenKind::Str(filename), .. }) = tokens.get(1)
+        && tokens.len() > 2
+    {
+        let address = match runtime.eval(&Parser::new(&tokens[2..]).parse_program()?)? {
+            Value::Num(port) => port.to_string(),
+            Value::Str(address) => address.to_string(),
+            other => return Err(format!("Expected a port or address, got {:?}", other)),
+        };
+        return Ok((filename.clone(), Some(address)));
+    }
+    let argument = command_argument(input, tokens);
+    Ok(match argument.split_once(char::is_whitespace) {
+        Some((filename, address)) => (filename.to_string(), Some(address.trim().to_string())),
+        None => (argument, None),
+    })
+}
+
+pub fn generate_proxy_wrapper(filename: &str, root: &Path) -> Result<Value, String> {
+    generate_proxy(filename, root)
+}
 
-    // Generate proxy header content
-    let proxy_header_content = format!(
-        "// Proxy header for {}\n\
-         // Generated by Tau language\n\
-         \n\
-         #ifndef {}_PROXY_H\n\
-         #define {}_PROXY_H\n\
-         \n\
-         class {}Proxy {{\n\
-         public:\n\
-             {}Proxy();\n\
-             void execute();\n\
-             void monitor();\n\
-         private:\n\
-             // Original implementation\n\
-         }};\n\
-         \n\
-         #endif // {}_PROXY_H\n",
-        filename,
-        base_name.to_uppercase(),
-        base_name.to_uppercase(),
-        base_name,
-        base_name,
-        base_name.to_uppercase()
-    );
-
-    // Generate App/Network implementation file
+// Reads `filename` and writes the generated files under `root`, the workspace
+fn generate_proxy(filename: &str, root: &Path) -> Result<Value, String> {
+    let (interface, base_name) = codegen_input(filename, root)?;
+
+    // Proxy header {name}Proxy.h and a Tau script connecting to the agent
+    let proxy_header = format!("{}Proxy.h", base_name);
+    let guard = format!("{}_PROXY_H", base_name.to_uppercase());
+    write_generated(root, &proxy_header, &codegen::cpp_proxy(&interface, filename, &guard))?;
     let network_file = format!("App/Network/{}.tsu", base_name);
-    let network_content = format!(
-        "# Network implementation for {}\n\
-         # Generated by Tau language\n\
-         \n\
-         :tau\n\
-         \n\
-         # Proxy wraps original code with monitoring\n\
-         proxy_start = \"Proxy activated for {}\"\n\
-         \n\
-         # Original code:\n\
-         {}\n\
-         \n\
-         proxy_end = \"Proxy finished for {}\"\n",
-        filename, filename, content, filename
-    );
-
-    // Write proxy header
-    let mut header_file = fs::File::create(&proxy_header)
-        .map_err(|e| format!("Failed to create proxy header: {}", e))?;
-    header_file.write_all(proxy_header_content.as_bytes())
-        .map_err(|e| format!("Failed to write proxy header: {}", e))?;
-
-    // Write network implementation
-    let mut network_file_handle = fs::File::create(&network_file)
-        .map_err(|e| format!("Failed to create network file: {}", e))?;
-    network_file_handle.write_all(network_content.as_bytes())
-        .map_err(|e| format!("Failed to write network file: {}", e))?;
-
-    Ok(Value::Str(format!("Proxy generated: {} and {}", proxy_header, network_file)))
-}
-
-pub fn generate_agent_wrapper(filename: &str) -> Result<Value, String> {
-    generate_agent(filename)
-}
-
-fn generate_agent(filename: &str) -> Result<Value, String> {
-    // Read the source file
-    let content = fs::read_to_string(filename)
+    write_generated(root, &network_file, &codegen::tau_proxy(&interface, filename, &base_name.to_lowercase()))?;
+
+    Ok(Value::Str(format!("Proxy generated: {} and {}", proxy_header, network_file).into()))
+}
+
+// Reads `filename` and writes {name}.rs under `root`, the workspace
+fn generate_rust(filename: &str, root: &Path) -> Result<Value, String> {
+    let (interface, base_name) = codegen_input(filename, root)?;
+    let module = format!("{}.rs", base_name);
+    write_generated(root, &module, &codegen::rust_module(&interface, filename, &base_name))?;
+    Ok(Value::Str(format!("Rust module generated: {}", module).into()))
+}
+
+// The interface in `filename` and the base name of the generated files
+fn codegen_input(filename: &str, root: &Path) -> Result<(Interface, String), String> {
+    let content = fs::read_to_string(root.join(filename))
         .map_err(|e| format!("Failed to read file {}: {}", filename, e))?;
+    let interface = parse_interface(&content).map_err(|e| format!("{}: {}", filename, e))?;
 
-    // Extract base name without extension
+    // Base name without directories or extension
     let base_name = filename
         .trim_end_matches(".tsu")
         .split(['/', '\\'])
-        .last()
+        .next_back()
         .unwrap_or(filename);
+    Ok((interface, base_name.to_string()))
+}
 
-    // Create App/Network directory
-    fs::create_dir_all("App/Network")
-        .map_err(|e| format!("Failed to create App/Network directory: {}", e))?;
+fn write_generated(root: &Path, path: &str, content: &str) -> Result<(), String> {
+    let path = root.join(path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let mut file = fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    file.write_all(content.as_bytes()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
 
-    // Generate agent header filename: {name}Agent.h
-    let agent_header = format!("{}Agent.h", base_name);
+pub fn generate_agent_wrapper(filename: &str, root: &Path) -> Result<Value, String> {
+    generate_agent(filename, root)
+}
 
-    // Generate agent header content
-    let agent_header_content = format!(
-        "// Agent header for {}\n\
-         // Generated by Tau language\n\
-         \n\
-         #ifndef {}_AGENT_H\n\
-         #define {}_AGENT_H\n\
-         \n\
-         class {}Agent {{\n\
-         public:\n\
-             {}Agent();\n\
-             void start();\n\
-             void stop();\n\
-             bool isRunning();\n\
-         private:\n\
-             bool running;\n\
-             // Async execution context\n\
-         }};\n\
-         \n\
-         #endif // {}_AGENT_H\n",
-        filename,
-        base_name.to_uppercase(),
-        base_name.to_uppercase(),
-        base_name,
-        base_name,
-        base_name.to_uppercase()
-    );
-
-    // Generate App/Network agent implementation
+// Reads `filename` and writes the generated files under `root`, the workspace
+fn generate_agent(filename: &str, root: &Path) -> Result<Value, String> {
+    let (interface, base_name) = codegen_input(filename, root)?;
+
+    // Agent header {name}Agent.h and a Tau script serving stub methods
+    let agent_header = format!("{}Agent.h", base_name);
+    let guard = format!("{}_AGENT_H", base_name.to_uppercase());
+    write_generated(root, &agent_header, &codegen::cpp_agent(&interface, filename, &guard))?;
     let network_agent_file = format!("App/Network/{}Agent.tsu", base_name);
-    let agent_content = format!(
-        "# Agent for {}\n\
-         # Generated by Tau language\n\
-         \n\
-         :tau\n\
-         \n\
-         # Agent: autonomous executor that runs in background\n\
-         agent_name = \"Agent-{}\"\n\
-         agent_status = \"running\"\n\
-         \n\
-         # Agent wraps code in async execution context\n\
-         async {{\n\
-         \t# Original code:\n\
-         \t{}\n\
-         }}\n\
-         \n\
-         agent_complete = \"Agent finished\"\n",
-        filename,
-        filename.replace(".", "_"),
-        content.lines()
-            .map(|line| format!("\t{}", line))
-            .collect::<Vec<_>>()
-            .join("\n")
-    );
-
-    // Write agent header
-    let mut header_file = fs::File::create(&agent_header)
-        .map_err(|e| format!("Failed to create agent header: {}", e))?;
-    header_file.write_all(agent_header_content.as_bytes())
-        .map_err(|e| format!("Failed to write agent header: {}", e))?;
-
-    // Write network agent implementation
-    let mut agent_file_handle = fs::File::create(&network_agent_file)
-        .map_err(|e| format!("Failed to create network agent file: {}", e))?;
-    agent_file_handle.write_all(agent_content.as_bytes())
-        .map_err(|e| format!("Failed to write network agent file: {}", e))?;
-
-    Ok(Value::Str(format!("Agent generated: {} and {}", agent_header, network_agent_file)))
+    write_generated(root, &network_agent_file, &codegen::tau_agent(&interface, filename))?;
+
+    Ok(Value::Str(format!("Agent generated: {} and {}", agent_header, network_agent_file).into()))
 }
@@ -0,0 +1,4702 @@
+// The oldest tests match numbers with guards, as they were first written
+#![allow(clippy::redundant_guards)]
+
+use crate::capabilities::{Capabilities, Capability};
+use crate::config::Config;
+use crate::lexer::{tokenize, TokenKind};
+use crate::repl::{Language, Repl};
+use crate::runtime::*;
+use crate::tau;
+use crate::value::*;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_resume_executes_continuation() {
+    let mut runtime = Runtime::new();
+    runtime.cont_stack.push(Continuation::Resume(Rc::new(|| Value::Num(42.0))));
+
+    match runtime.resume() {
+        Value::Num(n) if n == 42.0 => (),
+        _ => panic!("Expected Num(42.0)"),
+    }
+}
+
+#[test]
+fn test_resume_empty_stack() {
+    let mut runtime = Runtime::new();
+    match runtime.resume() {
+        Value::Unit => (),
+        _ => panic!("Expected Unit for empty stack"),
+    }
+}
+
+#[test]
+fn test_break_clears_stack() {
+    let mut runtime = Runtime::new();
+    runtime.cont_stack.push(Continuation::Resume(Rc::new(|| Value::Num(1.0))));
+    runtime.cont_stack.push(Continuation::Resume(Rc::new(|| Value::Num(2.0))));
+
+    runtime.break_flow();
+    assert!(runtime.cont_stack.is_empty());
+}
+
+// Arithmetic tests with f64
+#[test]
+fn test_add() {
+    let a = Value::Num(5.0);
+    let b = Value::Num(3.0);
+    match a.add(&b) {
+        Ok(Value::Num(n)) if n == 8.0 => (),
+        _ => panic!("Expected Num(8.0)"),
+    }
+}
+
+#[test]
+fn test_sub() {
+    let a = Value::Num(10.0);
+    let b = Value::Num(3.0);
+    match a.sub(&b) {
+        Ok(Value::Num(n)) if n == 7.0 => (),
+        _ => panic!("Expected Num(7.0)"),
+    }
+}
+
+#[test]
+fn test_mul() {
+    let a = Value::Num(6.0);
+    let b = Value::Num(7.0);
+    match a.mul(&b) {
+        Ok(Value::Num(n)) if n == 42.0 => (),
+        _ => panic!("Expected Num(42.0)"),
+    }
+}
+
+#[test]
+fn test_div() {
+    let a = Value::Num(20.0);
+    let b = Value::Num(4.0);
+    match a.div(&b) {
+        Ok(Value::Num(n)) if n == 5.0 => (),
+        _ => panic!("Expected Num(5.0)"),
+    }
+}
+
+#[test]
+fn test_div_by_zero() {
+    let a = Value::Num(10.0);
+    let b = Value::Num(0.0);
+    match a.div(&b) {
+        Err(_) => (),
+        _ => panic!("Expected error for division by zero"),
+    }
+}
+
+#[test]
+fn test_expr_arithmetic() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Add(
+        Box::new(Expr::Value(Value::Num(3.0))),
+        Box::new(Expr::Mul(
+            Box::new(Expr::Value(Value::Num(4.0))),
+            Box::new(Expr::Value(Value::Num(5.0))),
+        )),
+    ); // 3 + (4 * 5) = 23
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 23.0 => (),
+        _ => panic!("Expected Num(23.0)"),
+    }
+}
+
+// Array tests
+#[test]
+fn test_array_creation() {
+    let arr = Value::Array(vec![Value::Num(1.0), Value::Num(2.0), Value::Num(3.0)].into());
+    match arr {
+        Value::Array(v) if v.len() == 3 => (),
+        _ => panic!("Expected array with 3 elements"),
+    }
+}
+
+#[test]
+fn test_array_concat() {
+    let a1 = Value::Array(vec![Value::Num(1.0), Value::Num(2.0)].into());
+    let a2 = Value::Array(vec![Value::Num(3.0), Value::Num(4.0)].into());
+    match a1.add(&a2) {
+        Ok(Value::Array(v)) if v.len() == 4 => (),
+        _ => panic!("Expected concatenated array with 4 elements"),
+    }
+}
+
+// Map tests
+#[test]
+fn test_map_creation() {
+    let map = Value::Map(Map::from_pairs(vec![(Value::Num(1.0), Value::Num(10.0))]).unwrap());
+    match map {
+        Value::Map(m) if m.len() == 1 => (),
+        _ => panic!("Expected map with 1 element"),
+    }
+}
+
+// Array get tests
+#[test]
+fn test_array_get() {
+    let mut runtime = Runtime::new();
+    let arr = Value::Array(vec![Value::Num(10.0), Value::Num(20.0), Value::Num(30.0)].into());
+    let expr = Expr::Get(
+        Box::new(Expr::Value(arr)),
+        Box::new(Expr::Value(Value::Num(1.0)))
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 20.0 => (),
+        _ => panic!("Expected Num(20.0)"),
+    }
+}
+
+#[test]
+fn test_array_get_out_of_bounds() {
+    let mut runtime = Runtime::new();
+    let arr = Value::Array(vec![Value::Num(10.0)].into());
+    let expr = Expr::Get(
+        Box::new(Expr::Value(arr)),
+        Box::new(Expr::Value(Value::Num(5.0)))
+    );
+
+    match runtime.eval(&expr) {
+        Err(_) => (),
+        _ => panic!("Expected error for out of bounds"),
+    }
+}
+
+// Map get tests
+#[test]
+fn test_map_get_num_key() {
+    let mut runtime = Runtime::new();
+    let map = Value::Map(Map::from_pairs(vec![
+        (Value::Num(1.0), Value::Num(100.0)),
+        (Value::Num(2.0), Value::Num(200.0))
+    ]).unwrap());
+    let expr = Expr::Get(
+        Box::new(Expr::Value(map)),
+        Box::new(Expr::Value(Value::Num(2.0)))
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 200.0 => (),
+        _ => panic!("Expected Num(200.0)"),
+    }
+}
+
+#[test]
+fn test_map_get_str_key() {
+    let mut runtime = Runtime::new();
+    let map = Value::Map(Map::from_pairs(vec![
+        (Value::Str("x".into()), Value::Num(100.0)),
+        (Value::Str("y".into()), Value::Num(200.0))
+    ]).unwrap());
+    let expr = Expr::Get(
+        Box::new(Expr::Value(map)),
+        Box::new(Expr::Value(Value::Str("y".into())))
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 200.0 => (),
+        _ => panic!("Expected Num(200.0)"),
+    }
+}
+
+// Color tests
+#[test]
+fn test_color_new() {
+    let red = Color::new(255, 0, 0);
+    assert_eq!(red.r, 255);
+    assert_eq!(red.g, 0);
+    assert_eq!(red.b, 0);
+}
+
+#[test]
+fn test_color_blend() {
+    let red = Color::new(255, 0, 0);
+    let blue = Color::new(0, 0, 255);
+    let purple = red.blend(&blue);
+    assert_eq!(purple.r, 127);
+    assert_eq!(purple.g, 0);
+    assert_eq!(purple.b, 127);
+}
+
+#[test]
+fn test_color_add() {
+    let red = Color::new(100, 0, 0);
+    let green = Color::new(0, 150, 0);
+    let result = red.add(&green);
+    assert_eq!(result.r, 100);
+    assert_eq!(result.g, 150);
+    assert_eq!(result.b, 0);
+}
+
+#[test]
+fn test_color_add_saturation() {
+    let c1 = Color::new(200, 100, 50);
+    let c2 = Color::new(100, 200, 250);
+    let result = c1.add(&c2);
+    assert_eq!(result.r, 255); // saturated
+    assert_eq!(result.g, 255); // saturated
+    assert_eq!(result.b, 255); // saturated
+}
+
+#[test]
+fn test_color_sub() {
+    let c1 = Color::new(200, 100, 50);
+    let c2 = Color::new(50, 30, 10);
+    let result = c1.sub(&c2);
+    assert_eq!(result.r, 150);
+    assert_eq!(result.g, 70);
+    assert_eq!(result.b, 40);
+}
+
+#[test]
+fn test_color_scale() {
+    let c = Color::new(100, 50, 200);
+    let scaled = c.scale(2.0);
+    assert_eq!(scaled.r, 200);
+    assert_eq!(scaled.g, 100);
+    assert_eq!(scaled.b, 255); // clamped
+}
+
+#[test]
+fn test_color_mix() {
+    let red = Color::new(255, 0, 0);
+    let blue = Color::new(0, 0, 255);
+    let half = red.mix(&blue, 0.5);
+    assert_eq!(half.r, 127);
+    assert_eq!(half.g, 0);
+    assert_eq!(half.b, 127);
+}
+
+#[test]
+fn test_value_color_add() {
+    let c1 = Value::Color(Color::new(100, 50, 25));
+    let c2 = Value::Color(Color::new(50, 100, 75));
+    match c1.add(&c2) {
+        Ok(Value::Color(c)) => {
+            assert_eq!(c.r, 150);
+            assert_eq!(c.g, 150);
+            assert_eq!(c.b, 100);
+        }
+        _ => panic!("Expected Color"),
+    }
+}
+
+#[test]
+fn test_value_color_blend() {
+    let c1 = Value::Color(Color::new(255, 0, 0));
+    let c2 = Value::Color(Color::new(0, 0, 255));
+    match c1.blend(&c2) {
+        Ok(Value::Color(c)) => {
+            assert_eq!(c.r, 127);
+            assert_eq!(c.b, 127);
+        }
+        _ => panic!("Expected Color"),
+    }
+}
+
+#[test]
+fn test_expr_color_blend() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Blend(
+        Box::new(Expr::Value(Value::Color(Color::new(200, 100, 50)))),
+        Box::new(Expr::Value(Value::Color(Color::new(100, 200, 150)))),
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Color(c)) => {
+            assert_eq!(c.r, 150);
+            assert_eq!(c.g, 150);
+            assert_eq!(c.b, 100);
+        }
+        _ => panic!("Expected Color"),
+    }
+}
+
+#[test]
+fn test_expr_color_scale() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Scale(
+        Box::new(Expr::Value(Value::Color(Color::new(100, 50, 200)))),
+        0.5,
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Color(c)) => {
+            assert_eq!(c.r, 50);
+            assert_eq!(c.g, 25);
+            assert_eq!(c.b, 100);
+        }
+        _ => panic!("Expected Color"),
+    }
+}
+
+// Loop and continuation tests
+#[test]
+fn test_while_loop() {
+    let mut runtime = Runtime::new();
+    // while i < 5 { i = i + 1 }
+    // Simplified: while true { break } - should execute once
+    let expr = Expr::While(
+        Box::new(Expr::Value(Value::Bool(false))),
+        Box::new(Expr::Value(Value::Num(42.0))),
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Unit) => (), // Loop never executes body
+        _ => panic!("Expected Unit"),
+    }
+}
+
+#[test]
+fn test_while_loop_executes() {
+    let mut runtime = Runtime::new();
+    // Test that while loop with true condition executes at least once
+    // We'll use a simple counter simulation
+    let _expr = Expr::While(
+        Box::new(Expr::Value(Value::Bool(true))),
+        Box::new(Expr::Value(Value::Num(42.0))),
+    );
+
+    // This would loop forever, so we test with false condition instead
+    let expr_no_loop = Expr::While(
+        Box::new(Expr::Value(Value::Bool(false))),
+        Box::new(Expr::Value(Value::Num(99.0))),
+    );
+
+    match runtime.eval(&expr_no_loop) {
+        Ok(Value::Unit) => (),
+        _ => panic!("Expected Unit for non-executing loop"),
+    }
+}
+
+#[test]
+fn test_for_loop() {
+    let mut runtime = Runtime::new();
+    // for i in [1,2,3] { i }
+    let expr = Expr::For(
+        "i".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![
+            Value::Num(1.0),
+            Value::Num(2.0),
+            Value::Num(3.0),
+        ].into()))),
+        Box::new(Expr::Value(Value::Num(42.0))),
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 42.0 => (), // Returns last iteration value
+        _ => panic!("Expected Num(42.0)"),
+    }
+}
+
+#[test]
+fn test_for_loop_empty_array() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For(
+        "i".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![].into()))),
+        Box::new(Expr::Value(Value::Num(99.0))),
+    );
+
+    match runtime.eval(&expr) {
+        Ok(Value::Unit) => (), // Empty array returns Unit
+        _ => panic!("Expected Unit"),
+    }
+}
+
+#[test]
+fn test_block_expression() {
+    let mut runtime = Runtime::new();
+    // { 1; 2; 3 }
+    let expr = Expr::Block(vec![
+        Expr::Value(Value::Num(1.0)),
+        Expr::Value(Value::Num(2.0)),
+        Expr::Value(Value::Num(3.0)),
+    ]);
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 3.0 => (), // Returns last expression
+        _ => panic!("Expected Num(3.0)"),
+    }
+}
+
+#[test]
+fn test_nested_blocks() {
+    let mut runtime = Runtime::new();
+    // { { 1; 2 }; 3 }
+    let expr = Expr::Block(vec![
+        Expr::Block(vec![
+            Expr::Value(Value::Num(1.0)),
+            Expr::Value(Value::Num(2.0)),
+        ]),
+        Expr::Value(Value::Num(3.0)),
+    ]);
+
+    match runtime.eval(&expr) {
+        Ok(Value::Num(n)) if n == 3.0 => (),
+        _ => panic!("Expected Num(3.0)"),
+    }
+}
+
+#[test]
+fn test_value_comparison_less_than() {
+    let a = Value::Num(5.0);
+    let b = Value::Num(10.0);
+    match a.less_than(&b) {
+        Ok(Value::Bool(true)) => (),
+        _ => panic!("Expected Bool(true)"),
+    }
+}
+
+#[test]
+fn test_value_comparison_greater_than() {
+    let a = Value::Num(10.0);
+    let b = Value::Num(5.0);
+    match a.greater_than(&b) {
+        Ok(Value::Bool(true)) => (),
+        _ => panic!("Expected Bool(true)"),
+    }
+}
+
+#[test]
+fn test_value_comparison_equals() {
+    let a = Value::Num(5.0);
+    let b = Value::Num(5.0);
+    match a.equals(&b) {
+        Ok(Value::Bool(true)) => (),
+        _ => panic!("Expected Bool(true)"),
+    }
+}
+
+#[test]
+fn test_value_is_truthy() {
+    assert!(Value::Bool(true).is_truthy());
+    assert!(!Value::Bool(false).is_truthy());
+    assert!(Value::Num(1.0).is_truthy());
+    assert!(!Value::Num(0.0).is_truthy());
+    assert!(!Value::Unit.is_truthy());
+    assert!(Value::Str("hello".into()).is_truthy());
+}
+
+#[test]
+fn test_continuation_with_loop() {
+    let mut runtime = Runtime::new();
+    // Test that continuation stack works with loops
+    runtime.cont_stack.push(Continuation::Resume(Rc::new(|| Value::Num(100.0))));
+
+    let result = runtime.resume();
+    match result {
+        Value::Num(n) if n == 100.0 => (),
+        _ => panic!("Expected Num(100.0)"),
+    }
+}
+
+#[test]
+fn test_nested_for_loops() {
+    let mut runtime = Runtime::new();
+    // Outer loop: for i in [1,2]
+    // Inner loop: for j in [3,4]
+    let inner_loop = Expr::For(
+        "j".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![
+            Value::Num(3.0),
+            Value::Num(4.0),
+        ].into()))),
+        Box::new(Expr::Value(Value::Num(10.0))),
+    );
+
+    let outer_loop = Expr::For(
+        "i".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![
+            Value::Num(1.0),
+            Value::Num(2.0),
+        ].into()))),
+        Box::new(inner_loop),
+    );
+
+    match runtime.eval(&outer_loop) {
+        Ok(Value::Num(n)) if n == 10.0 => (), // Returns last inner iteration
+        _ => panic!("Expected Num(10.0) from nested loops"),
+    }
+}
+
+#[test]
+fn test_while_with_continuation() {
+    let mut runtime = Runtime::new();
+    // Push a continuation, then execute a while loop
+    runtime.cont_stack.push(Continuation::Resume(Rc::new(|| Value::Num(50.0))));
+
+    // While loop that doesn't execute
+    let expr = Expr::While(
+        Box::new(Expr::Value(Value::Bool(false))),
+        Box::new(Expr::Value(Value::Num(1.0))),
+    );
+
+    runtime.eval(&expr).unwrap();
+
+    // Resume the continuation
+    let result = runtime.resume();
+    match result {
+        Value::Num(n) if n == 50.0 => (),
+        _ => panic!("Expected continuation to work after loop"),
+    }
+}
+
+// ===== COMPREHENSIVE TEST SUITE (60 Additional Tests) =====
+
+// Pi Language Tests (20 tests)
+#[test] fn test_pi_simple_add() { assert_eq!(Value::Num(3.0).add(&Value::Num(4.0)).unwrap(), Value::Num(7.0)); }
+#[test] fn test_pi_simple_sub() { assert_eq!(Value::Num(10.0).sub(&Value::Num(3.0)).unwrap(), Value::Num(7.0)); }
+#[test] fn test_pi_simple_mul() { assert_eq!(Value::Num(6.0).mul(&Value::Num(7.0)).unwrap(), Value::Num(42.0)); }
+#[test] fn test_pi_simple_div() { assert_eq!(Value::Num(20.0).div(&Value::Num(4.0)).unwrap(), Value::Num(5.0)); }
+#[test] fn test_pi_chain_add() { assert_eq!(Value::Num(1.0).add(&Value::Num(2.0)).unwrap().add(&Value::Num(3.0)).unwrap(), Value::Num(6.0)); }
+
+#[test]
+fn test_pi_complex_expr() {
+    let mut runtime = Runtime::new();
+    // (3 + 4) * 2
+    let expr = Expr::Mul(
+        Box::new(Expr::Add(
+            Box::new(Expr::Value(Value::Num(3.0))),
+            Box::new(Expr::Value(Value::Num(4.0))),
+        )),
+        Box::new(Expr::Value(Value::Num(2.0))),
+    );
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(14.0));
+}
+
+#[test]
+fn test_pi_deeply_nested() {
+    let mut runtime = Runtime::new();
+    // ((1 + 2) + (3 + 4))
+    let expr = Expr::Add(
+        Box::new(Expr::Add(
+            Box::new(Expr::Value(Value::Num(1.0))),
+            Box::new(Expr::Value(Value::Num(2.0))),
+        )),
+        Box::new(Expr::Add(
+            Box::new(Expr::Value(Value::Num(3.0))),
+            Box::new(Expr::Value(Value::Num(4.0))),
+        )),
+    );
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(10.0));
+}
+
+#[test] fn test_pi_negative_numbers() { assert_eq!(Value::Num(-5.0).add(&Value::Num(3.0)).unwrap(), Value::Num(-2.0)); }
+#[test] fn test_pi_float_precision() { assert_eq!(Value::Num(0.1).add(&Value::Num(0.2)).unwrap(), Value::Num(0.30000000000000004)); }
+#[test] fn test_pi_zero_add() { assert_eq!(Value::Num(42.0).add(&Value::Num(0.0)).unwrap(), Value::Num(42.0)); }
+#[test] fn test_pi_zero_mul() { assert_eq!(Value::Num(42.0).mul(&Value::Num(0.0)).unwrap(), Value::Num(0.0)); }
+#[test] fn test_pi_one_mul() { assert_eq!(Value::Num(42.0).mul(&Value::Num(1.0)).unwrap(), Value::Num(42.0)); }
+#[test] fn test_pi_negative_mul() { assert_eq!(Value::Num(-3.0).mul(&Value::Num(4.0)).unwrap(), Value::Num(-12.0)); }
+#[test] fn test_pi_divide_negative() { assert_eq!(Value::Num(-20.0).div(&Value::Num(4.0)).unwrap(), Value::Num(-5.0)); }
+#[test] fn test_pi_sub_to_negative() { assert_eq!(Value::Num(3.0).sub(&Value::Num(10.0)).unwrap(), Value::Num(-7.0)); }
+#[test] fn test_pi_large_numbers() { assert_eq!(Value::Num(1000000.0).add(&Value::Num(2000000.0)).unwrap(), Value::Num(3000000.0)); }
+#[test] fn test_pi_small_numbers() { assert_eq!(Value::Num(0.001).add(&Value::Num(0.002)).unwrap(), Value::Num(0.003)); }
+#[test] fn test_pi_mixed_ops() { assert_eq!(Value::Num(10.0).add(&Value::Num(5.0)).unwrap().mul(&Value::Num(2.0)).unwrap(), Value::Num(30.0)); }
+#[test] fn test_pi_div_then_mul() { assert_eq!(Value::Num(100.0).div(&Value::Num(5.0)).unwrap().mul(&Value::Num(3.0)).unwrap(), Value::Num(60.0)); }
+
+// Rho Conditional Tests (20 tests)
+#[test] fn test_rho_less_than_true() { assert_eq!(Value::Num(5.0).less_than(&Value::Num(10.0)).unwrap(), Value::Bool(true)); }
+#[test] fn test_rho_less_than_false() { assert_eq!(Value::Num(10.0).less_than(&Value::Num(5.0)).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_less_than_equal() { assert_eq!(Value::Num(5.0).less_than(&Value::Num(5.0)).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_greater_than_true() { assert_eq!(Value::Num(10.0).greater_than(&Value::Num(5.0)).unwrap(), Value::Bool(true)); }
+#[test] fn test_rho_greater_than_false() { assert_eq!(Value::Num(5.0).greater_than(&Value::Num(10.0)).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_greater_than_equal() { assert_eq!(Value::Num(5.0).greater_than(&Value::Num(5.0)).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_equals_true() { assert_eq!(Value::Num(5.0).equals(&Value::Num(5.0)).unwrap(), Value::Bool(true)); }
+#[test] fn test_rho_equals_false() { assert_eq!(Value::Num(5.0).equals(&Value::Num(6.0)).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_equals_bool_true() { assert_eq!(Value::Bool(true).equals(&Value::Bool(true)).unwrap(), Value::Bool(true)); }
+#[test] fn test_rho_equals_bool_false() { assert_eq!(Value::Bool(true).equals(&Value::Bool(false)).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_equals_str_true() { assert_eq!(Value::Str("hello".into()).equals(&Value::Str("hello".into())).unwrap(), Value::Bool(true)); }
+#[test] fn test_rho_equals_str_false() { assert_eq!(Value::Str("hello".into()).equals(&Value::Str("world".into())).unwrap(), Value::Bool(false)); }
+#[test] fn test_rho_truthy_bool_true() { assert!(Value::Bool(true).is_truthy()); }
+#[test] fn test_rho_truthy_bool_false() { assert!(!Value::Bool(false).is_truthy()); }
+#[test] fn test_rho_truthy_num_nonzero() { assert!(Value::Num(42.0).is_truthy()); }
+#[test] fn test_rho_truthy_num_zero() { assert!(!Value::Num(0.0).is_truthy()); }
+#[test] fn test_rho_truthy_string() { assert!(Value::Str("test".into()).is_truthy()); }
+#[test] fn test_rho_truthy_unit() { assert!(!Value::Unit.is_truthy()); }
+#[test] fn test_rho_truthy_array() { assert!(Value::Array(vec![Value::Num(1.0)].into()).is_truthy()); }
+#[test] fn test_rho_negative_comparison() { assert_eq!(Value::Num(-5.0).less_than(&Value::Num(0.0)).unwrap(), Value::Bool(true)); }
+
+// Loop Tests (20 tests)
+#[test]
+fn test_loop_for_single_item() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For(
+        "x".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![Value::Num(99.0)].into()))),
+        Box::new(Expr::Value(Value::Num(42.0))),
+    );
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(42.0));
+}
+
+#[test]
+fn test_loop_for_multiple_items() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For(
+        "x".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![Value::Num(1.0), Value::Num(2.0), Value::Num(3.0)].into()))),
+        Box::new(Expr::Value(Value::Num(100.0))),
+    );
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(100.0));
+}
+
+#[test]
+fn test_loop_while_never_executes() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::While(
+        Box::new(Expr::Value(Value::Bool(false))),
+        Box::new(Expr::Value(Value::Num(999.0))),
+    );
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Unit);
+}
+
+#[test]
+fn test_loop_block_single() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![Expr::Value(Value::Num(42.0))]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(42.0));
+}
+
+#[test]
+fn test_loop_block_empty() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Unit);
+}
+
+#[test]
+fn test_loop_triple_nested_blocks() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![
+        Expr::Block(vec![
+            Expr::Block(vec![Expr::Value(Value::Num(1.0))]),
+            Expr::Value(Value::Num(2.0)),
+        ]),
+        Expr::Value(Value::Num(3.0)),
+    ]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(3.0));
+}
+
+#[test]
+fn test_loop_nested_for_different_arrays() {
+    let mut runtime = Runtime::new();
+    let inner = Expr::For("j".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(5.0)].into()))), Box::new(Expr::Value(Value::Num(20.0))));
+    let outer = Expr::For("i".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(1.0)].into()))), Box::new(inner));
+    assert_eq!(runtime.eval(&outer).unwrap(), Value::Num(20.0));
+}
+
+#[test]
+fn test_loop_for_with_strings() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For(
+        "s".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![Value::Str("a".into()), Value::Str("b".into())].into()))),
+        Box::new(Expr::Value(Value::Num(77.0))),
+    );
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(77.0));
+}
+
+#[test] fn test_loop_for_large_array() {
+    let mut runtime = Runtime::new();
+    let arr = (0..100).map(|i| Value::Num(i as f64)).collect();
+    let expr = Expr::For("x".to_string(), Box::new(Expr::Value(Value::Array(arr))), Box::new(Expr::Value(Value::Num(999.0))));
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(999.0));
+}
+
+#[test] fn test_loop_block_with_arithmetic() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![
+        Expr::Add(Box::new(Expr::Value(Value::Num(1.0))), Box::new(Expr::Value(Value::Num(2.0)))),
+        Expr::Mul(Box::new(Expr::Value(Value::Num(3.0))), Box::new(Expr::Value(Value::Num(4.0)))),
+    ]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(12.0));
+}
+
+#[test] fn test_loop_nested_blocks_with_ops() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![
+        Expr::Block(vec![Expr::Add(Box::new(Expr::Value(Value::Num(5.0))), Box::new(Expr::Value(Value::Num(5.0))))]),
+        Expr::Mul(Box::new(Expr::Value(Value::Num(2.0))), Box::new(Expr::Value(Value::Num(3.0)))),
+    ]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(6.0));
+}
+
+#[test] fn test_loop_four_level_nesting() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For("a".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(1.0)].into()))),
+        Box::new(Expr::For("b".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(2.0)].into()))),
+            Box::new(Expr::For("c".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(3.0)].into()))),
+                Box::new(Expr::For("d".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(4.0)].into()))),
+                    Box::new(Expr::Value(Value::Num(42.0))))))))));
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(42.0));
+}
+
+#[test] fn test_loop_mixed_nested() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For("i".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(1.0)].into()))),
+        Box::new(Expr::Block(vec![
+            Expr::Value(Value::Num(10.0)),
+            Expr::Value(Value::Num(20.0)),
+        ])));
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(20.0));
+}
+
+#[test] fn test_loop_block_returns_last() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![
+        Expr::Value(Value::Num(1.0)),
+        Expr::Value(Value::Num(2.0)),
+        Expr::Value(Value::Num(3.0)),
+        Expr::Value(Value::Num(4.0)),
+        Expr::Value(Value::Num(5.0)),
+    ]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(5.0));
+}
+
+#[test] fn test_loop_for_with_colors() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For("c".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![Value::Color(Color::new(255,0,0))].into()))),
+        Box::new(Expr::Value(Value::Num(33.0))));
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(33.0));
+}
+
+#[test] fn test_loop_for_nested_empty_inner() {
+    let mut runtime = Runtime::new();
+    let inner = Expr::For("j".to_string(), Box::new(Expr::Value(Value::Array(vec![].into()))), Box::new(Expr::Value(Value::Num(50.0))));
+    let outer = Expr::For("i".to_string(), Box::new(Expr::Value(Value::Array(vec![Value::Num(1.0)].into()))), Box::new(inner));
+    assert_eq!(runtime.eval(&outer).unwrap(), Value::Unit);
+}
+
+#[test] fn test_loop_block_with_unit() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![Expr::Value(Value::Unit), Expr::Value(Value::Num(42.0))]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(42.0));
+}
+
+#[test] fn test_loop_for_boolean_array() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::For("b".to_string(),
+        Box::new(Expr::Value(Value::Array(vec![Value::Bool(true), Value::Bool(false)].into()))),
+        Box::new(Expr::Value(Value::Num(88.0))));
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(88.0));
+}
+
+#[test] fn test_loop_deeply_nested_blocks_five_levels() {
+    let mut runtime = Runtime::new();
+    let expr = Expr::Block(vec![
+        Expr::Block(vec![
+            Expr::Block(vec![
+                Expr::Block(vec![
+                    Expr::Block(vec![Expr::Value(Value::Num(5.0))]),
+                ]),
+            ]),
+        ]),
+    ]);
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Num(5.0));
+}
+
+// Tau Generation Tests
+#[test]
+fn test_tau_proxy_generation() {
+    use std::fs;
+    let root = Config::with_temp_workspace("proxy").unwrap().workspace;
+    // Create a test file
+    fs::write(root.join("test_tau_proxy.tsu"), "# test proxy generation\ninterface Calculator {\n    add(a: num, b: num) -> num\n    history() -> [num]\n    reset()\n    event overflow(value: num)\n}\n").unwrap();
+
+    // Generate proxy
+    let result = tau::generate_proxy_wrapper("test_tau_proxy.tsu", &root);
+    assert!(result.is_ok());
+
+    // Verify files were created in the workspace, declaring the interface's methods
+    let header = fs::read_to_string(root.join("test_tau_proxyProxy.h")).unwrap();
+    assert!(header.contains("class CalculatorProxy {"));
+    assert!(header.contains("    double add(double a, double b);\n"));
+    assert!(header.contains("    std::vector<double> history();\n"));
+    assert!(header.contains("    void reset();\n"));
+    assert!(header.contains("    void on_overflow(std::function<void(double value)> handler);\n"));
+    assert!(!header.contains("TauValue.h"));
+    let script = fs::read_to_string(root.join("App/Network/test_tau_proxy.tsu")).unwrap();
+    assert!(script.contains("test_tau_proxy = proxy \"test_tau_proxy.tsu\" \"127.0.0.1:9000\""));
+    assert!(script.contains("#   add(a: num, b: num) -> num  =>  await test_tau_proxy.add(a, b)"));
+
+    // Cleanup
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_tau_agent_generation() {
+    use std::fs;
+    let root = Config::with_temp_workspace("agent").unwrap().workspace;
+    // Create a test file
+    fs::write(root.join("test_tau_agent.tsu"), "# test agent generation\ninterface Palette {\n    pick(name: str) -> color\n    names() -> [str]\n    event changed(name: str)\n}\n").unwrap();
+
+    // Generate agent
+    let result = tau::generate_agent_wrapper("test_tau_agent.tsu", &root);
+    assert!(result.is_ok());
+
+    // Verify files were created in the workspace, declaring the interface's methods
+    let header = fs::read_to_string(root.join("test_tau_agentAgent.h")).unwrap();
+    assert!(header.contains("class PaletteAgent {"));
+    assert!(header.contains("    virtual tau::Color pick(std::string name) = 0;\n"));
+    assert!(header.contains("    void changed(std::string name);"));
+    assert!(header.contains("#include \"TauValue.h\""));
+    let script = fs::read_to_string(root.join("App/Network/test_tau_agentAgent.tsu")).unwrap();
+    assert!(script.contains("pick = fn(name) color(0, 0, 0)\n"));
+    assert!(script.contains("names = fn() []\n"));
+    assert!(script.contains("agent \"test_tau_agent.tsu\" 9000"));
+
+    // Cleanup
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_tau_codegen_respects_workspace() {
+    use std::fs;
+    let config = Config::with_temp_workspace("codegen").unwrap();
+    let root = config.workspace.clone();
+    fs::write(root.join("ws.tsu"), "interface Ws {\n    ping()\n}\n").unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("proxy \"ws.tsu\"").unwrap();
+    assert!(root.join("wsProxy.h").exists());
+    assert!(!std::path::Path::new("wsProxy.h").exists());
+    // Files that are not interfaces are rejected with the parse error
+    fs::write(root.join("script.tsu"), "1 + 1").unwrap();
+    assert!(repl.parse_tau("agent \"script.tsu\"").unwrap_err().starts_with("script.tsu: "));
+    assert!(!root.join("scriptAgent.h").exists());
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_rust_module_generation() {
+    use crate::codegen::Typed;
+    use std::fs;
+    let config = Config::with_temp_workspace("rust").unwrap();
+    let root = config.workspace.clone();
+    fs::write(root.join("calc-v2.tsu"), "interface Calculator {\n    add(a: num, b: num) -> num\n    tags(c: color) -> [map]\n    reset()\n}\n").unwrap();
+    let mut repl = Repl::with_config(config);
+    assert_eq!(repl.parse_tau("proxy --rust \"calc-v2.tsu\"").unwrap(), Value::Str("Rust module generated: calc-v2.rs".into()));
+    let module = fs::read_to_string(root.join("calc-v2.rs")).unwrap();
+    assert!(module.contains("pub mod calc_v2 {\n"));
+    assert!(module.contains("    pub const INTERFACE: &str = \"interface Calculator {\\n    add(a: num, b: num) -> num\\n"));
+    // The agent trait and its dispatch
+    assert!(module.contains("    pub trait CalculatorAgent {\n        async fn add(&mut self, a: f64, b: f64) -> Result<f64, String>;\n"));
+    assert!(module.contains("        async fn tags(&mut self, c: Color) -> Result<Vec<Map>, String>;\n"));
+    assert!(module.contains("            \"reset\" => agent.reset().await?.into_value(),\n"));
+    // The client struct
+    assert!(module.contains("    pub struct CalculatorClient {\n"));
+    assert!(module.contains("            Typed::from_value(self.client.call(\"add\", vec![a.into_value(), b.into_value()])?)\n"));
+    let _ = fs::remove_dir_all(&root);
+
+    // Conversions the generated code relies on
+    let values = vec![1.5, 2.0].into_value();
+    assert_eq!(values, Value::Array(vec![Value::Num(1.5), Value::Num(2.0)].into()));
+    assert_eq!(Vec::<f64>::from_value(values).unwrap(), vec![1.5, 2.0]);
+    assert_eq!(<()>::from_value(Value::Unit), Ok(()));
+    assert_eq!(String::from_value(Value::Num(1.0)).unwrap_err(), "Expected str, got Num(1.0)");
+}
+
+#[test]
+fn test_compose_produces_continuation_value() {
+    use std::cell::RefCell;
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let step = |n: f64| {
+        let log = Rc::clone(&log);
+        Expr::Value(Value::Continuation(Box::new(Continuation::Resume(Rc::new(move || {
+            log.borrow_mut().push(n);
+            Value::Num(n)
+        })))))
+    };
+    let mut runtime = Runtime::new();
+    let composed = runtime.eval(&Expr::Compose(Box::new(step(1.0)), Box::new(step(2.0)))).unwrap();
+    assert_eq!(format!("{:?}", composed), "Continuation((Continuation::Resume ; Continuation::Resume))");
+    assert!(runtime.cont_stack.is_empty());
+    // Stored, copied and run twice, in order each time
+    runtime.set_variable("k".to_string(), composed);
+    let copy = runtime.eval(&Expr::Var("k".to_string())).unwrap();
+    assert_eq!(runtime.continue_with(copy), Value::Num(2.0));
+    assert_eq!(runtime.eval(&Expr::Call("continue".to_string(), vec![Expr::Var("k".to_string())], Site::default())).unwrap(), Value::Num(2.0));
+    assert_eq!(*log.borrow(), vec![1.0, 2.0, 1.0, 2.0]);
+    // Closed under composition
+    let nested = Expr::Compose(Box::new(Expr::Var("k".to_string())), Box::new(step(3.0)));
+    let nested = runtime.eval(&nested).unwrap();
+    assert_eq!(runtime.continue_with(nested), Value::Num(3.0));
+    assert!(runtime.eval(&Expr::Compose(Box::new(step(1.0)), Box::new(Expr::Value(Value::Num(1.0))))).is_err());
+}
+
+// Capability tests
+#[test]
+fn test_capabilities_default_all() {
+    let caps = Capabilities::default();
+    for cap in Capability::ALL {
+        assert!(caps.has(cap));
+    }
+}
+
+#[test]
+fn test_capabilities_grant_revoke_list() {
+    let mut caps = Capabilities::none();
+    caps.grant_list("shell, fs-read").unwrap();
+    assert!(caps.has(Capability::Shell));
+    assert!(caps.has(Capability::FsRead));
+    assert!(!caps.has(Capability::Net));
+    caps.revoke_list("shell").unwrap();
+    assert!(!caps.has(Capability::Shell));
+    assert!(caps.grant_list("teleport").is_err());
+}
+
+#[test]
+fn test_capabilities_denied_shell() {
+    let mut repl = Repl::with_capabilities(Capabilities::none());
+    let err = repl.process_bash("`echo hi`").unwrap_err();
+    assert!(err.contains("shell"));
+}
+
+#[test]
+fn test_capabilities_denied_codegen() {
+    let mut caps = Capabilities::all();
+    caps.revoke(Capability::FsWrite);
+    let mut repl = Repl::with_capabilities(caps);
+    let err = repl.parse_tau("proxy \"missing.tsu\"").unwrap_err();
+    assert!(err.contains("fs-write"));
+}
+
+#[test]
+fn test_config_file_and_flags() {
+    let mut config = Config::new();
+    config.apply_str("# comment\ndeny = all\nallow = env\n").unwrap();
+    assert!(config.capabilities.has(Capability::Env));
+    assert!(!config.capabilities.has(Capability::Shell));
+    assert!(config.apply_str("colour = red").is_err());
+
+    let args: Vec<String> = ["--no-config", "--deny", "net,clipboard"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert!(!config.capabilities.has(Capability::Net));
+    assert!(config.capabilities.has(Capability::Shell));
+
+    let args: Vec<String> = ["--no-config", "--sandbox", "--allow", "shell"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.capabilities.to_string(), "shell");
+}
+
+#[test]
+fn test_input_lines_are_lossy_and_limited() {
+    use crate::input::{Line, Lines, parse_size};
+    let input: &[u8] = b"ok\r\nbad \xff byte\n0123456789abcdef\nlast";
+    // A small buffer splits lines across reads
+    let reader = std::io::BufReader::with_capacity(3, input);
+    let lines: Vec<Line> = Lines::new(reader, 10).map(Result::unwrap).collect();
+    let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+    assert_eq!(texts, ["ok", "bad \u{FFFD} byte", "", "last"]);
+    assert_eq!(lines[0].warning, None);
+    assert_eq!(lines[1].warning.as_deref(), Some("line 2 is not valid UTF-8; its invalid bytes were replaced with U+FFFD"));
+    assert_eq!(lines[2].warning.as_deref(), Some("line 3 is 16 bytes, over the max-line limit of 10; skipped"));
+    assert_eq!(Lines::new(&b"a\n\n"[..], 10).count(), 2);
+    assert_eq!(Lines::new(&b""[..], 10).count(), 0);
+
+    assert_eq!(parse_size("64k"), Ok(64 * 1024));
+    assert_eq!(parse_size("1M"), Ok(1024 * 1024));
+    assert!(parse_size("0").is_err() && parse_size("big").is_err());
+    let args: Vec<String> = ["--no-config", "--max-line", "4k"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(Config::from_args(&args).unwrap().max_line(), 4096);
+    assert_eq!(Config::new().max_line(), crate::input::MAX_LINE);
+
+    // A script keeps running past a line it cannot use
+    let mut config = Config::with_temp_workspace("input").unwrap();
+    config.apply_str("max-line = 32").unwrap();
+    let script = config.in_workspace("mixed.rho");
+    let long = format!("x = {}", "9".repeat(100));
+    std::fs::write(&script, [b"x = 1\n".as_slice(), long.as_bytes(), b"\ny = \"a\xffb\"\r\nz = x\n"].concat()).unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.run_file(&script).unwrap();
+    assert_eq!(repl.parse_rho("[z, len(y)]").unwrap().to_string(), "[1, 3]");
+    assert_eq!(repl.check_file(&script).unwrap(), Vec::<String>::new());
+}
+
+// Shell command tests
+#[test]
+fn test_command_results_expose_exit_codes() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    assert_eq!(repl.eval("`echo hi`[\"stdout\"]").unwrap(), Value::Str("hi".into()));
+    assert_eq!(repl.eval("`exit 3`[\"status\"]").unwrap(), Value::Num(3.0));
+    assert_eq!(repl.eval("`echo oops >&2; false`[\"ok\"]").unwrap(), Value::Bool(false));
+    assert_eq!(repl.eval("`echo oops >&2`[\"stderr\"]").unwrap(), Value::Str("oops".into()));
+    repl.set_language(Language::Pi);
+    let Value::Map(result) = repl.eval("`true`").unwrap() else { panic!("expected a command result") };
+    assert_eq!(result.get(&Value::Str("ok".into())), Some(&Value::Bool(true)));
+    assert!(tokenize("`echo").unwrap_err().starts_with("Unterminated command"));
+
+    let mut repl = Repl::with_capabilities(Capabilities::none());
+    repl.set_language(Language::Rho);
+    assert!(repl.eval("`echo hi`").unwrap_err().contains("shell"));
+}
+
+#[test]
+fn test_sh_if_branches_on_success() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    let branch = |repl: &mut Repl, cmd: &str| repl.eval(&format!("sh_if({}, fn() \"then\", fn() \"else\")", cmd)).unwrap();
+    assert_eq!(branch(&mut repl, "`test -d /`"), Value::Str("then".into()));
+    assert_eq!(branch(&mut repl, "`test -f /no/such/file`"), Value::Str("else".into()));
+    // A command string runs first; a handler with a parameter gets the result
+    assert_eq!(repl.eval("sh_if(\"exit 4\", fn() 0, fn(r) r[\"status\"])").unwrap(), Value::Num(4.0));
+    assert_eq!(repl.eval("sh_if(`false`, fn() 1)").unwrap(), Value::Unit);
+    assert!(repl.eval("sh_if(1, fn() 1)").unwrap_err().contains("expects a command result"));
+}
+
+#[test]
+fn test_par_run_runs_commands_side_by_side() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    let started = Instant::now();
+    let results = repl.eval("par_run([\"sleep 0.2; echo a\", \"sleep 0.2; echo b\", \"sleep 0.2; exit 2\"])").unwrap();
+    assert!(started.elapsed() < Duration::from_millis(500));
+    let Value::Array(results) = results else { panic!("expected an array of results") };
+    let field = |result: &Value, key: &str| match result {
+        Value::Map(map) => map.get(&Value::Str(key.into())).cloned(),
+        _ => None,
+    };
+    assert_eq!(field(&results[0], "stdout"), Some(Value::Str("a".into())));
+    assert_eq!(field(&results[1], "stdout"), Some(Value::Str("b".into())));
+    assert_eq!(field(&results[2], "status"), Some(Value::Num(2.0)));
+    // A limit of one runs them in turn
+    let started = Instant::now();
+    repl.eval("par_run([\"sleep 0.1\", \"sleep 0.1\"], 1)").unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(repl.eval("par_run([\"true\"], 0)").unwrap_err().contains("limit of at least 1"));
+
+    // Tau starts them at once and gives a future
+    let mut runtime = Runtime::new();
+    tau::parse_tau("jobs = par_run([\"sleep 0.1; echo done\"])", &mut runtime).unwrap();
+    assert_eq!(runtime.futures()[0].state().name(), "pending");
+    let Value::Array(results) = tau::parse_tau("await jobs", &mut runtime).unwrap() else { panic!("expected an array of results") };
+    assert_eq!(field(&results[0], "stdout"), Some(Value::Str("done".into())));
+}
+
+#[cfg(feature = "ssh")]
+#[test]
+fn test_ssh_runs_commands_through_the_client() {
+    // echo stands in for the ssh client, showing the arguments it would get
+    let mut config = Config::new();
+    config.set("ssh", "echo").unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.set_language(Language::Rho);
+    let Value::Map(result) = repl.eval("ssh(\"web1\", \"uptime\")").unwrap() else { panic!("expected a command result") };
+    assert_eq!(result.get(&Value::Str("stdout".into())), Some(&Value::Str("-o BatchMode=yes web1 -- uptime".into())));
+    assert!(repl.eval("ssh(\"-oProxyCommand=x\", \"uptime\")").unwrap_err().starts_with("Invalid ssh host"));
+
+    // Tau starts it at once and gives a future
+    repl.set_language(Language::Tau);
+    repl.eval("up = ssh(\"db\", \"df -h\")").unwrap();
+    let Value::Map(result) = repl.eval("await up").unwrap() else { panic!("expected a command result") };
+    assert_eq!(result.get(&Value::Str("ok".into())), Some(&Value::Bool(true)));
+
+    let mut caps = Capabilities::all();
+    caps.revoke(Capability::Net);
+    let mut repl = Repl::with_capabilities(caps);
+    repl.set_language(Language::Rho);
+    assert!(repl.eval("ssh(\"web1\", \"uptime\")").unwrap_err().contains("net"));
+}
+
+// Secret tests
+#[test]
+fn test_secrets_show_only_as_redacted() {
+    use crate::output::Output;
+    use crate::secret::Secret;
+    let mut runtime = Runtime::new();
+    runtime.set_variable("key".to_string(), Value::Secret(Secret::new("hunter2")));
+    let header = tau::parse_tau("\"token=\" + key", &mut runtime).unwrap();
+    assert_eq!(header.to_string(), "<secret>");
+    assert_eq!(format!("{:?}", header), "Secret(<secret>)");
+    assert_eq!(Value::Array(vec![header.clone()].into()).to_string(), "[<secret>]");
+    let Value::Secret(joined) = &header else { panic!("expected a secret, got {:?}", header) };
+    assert_eq!(joined.expose(), "token=hunter2");
+    assert_eq!(Output::Json.format(&Ok(header.clone())), "\"<secret>\"");
+    let error = tau::parse_tau("to_json([key])", &mut runtime).unwrap_err();
+    assert!(error.contains("<secret>") && !error.contains("hunter2"));
+    assert!(!crate::transport::encode_value(&header).unwrap_err().contains("hunter2"));
+
+    // Commands and URLs use the text itself, while errors name only <secret>
+    runtime.set_variable("cmd".to_string(), Value::Secret(Secret::new("echo hunter2")));
+    assert_eq!(tau::parse_tau("sh(cmd)[\"stdout\"]", &mut runtime).unwrap(), Value::Str("hunter2".into()));
+    runtime.set_variable("url".to_string(), Value::Secret(Secret::new("gopher://example.com/?token=hunter2")));
+    let error = tau::parse_tau("http_get(url)", &mut runtime).unwrap_err();
+    assert!(error.starts_with("Unsupported URL <secret>"), "{}", error);
+    assert!(rho_eval("secret(1)").unwrap_err().starts_with("secret expects 1 string argument"));
+}
+
+#[cfg(all(feature = "keyring", unix, not(target_os = "macos")))]
+#[test]
+fn test_credentials_through_the_keyring() {
+    use std::os::unix::fs::PermissionsExt;
+    // A stand-in for secret-tool, keeping each service's secret in a file
+    let mut config = Config::with_temp_workspace("keyring").unwrap();
+    let tool = config.in_workspace("secret-tool");
+    let store = config.in_workspace("store");
+    let script = format!(
+        "#!/bin/sh\ncase $1 in\n  lookup) cat \"{0}/$3\" 2>/dev/null || exit 1 ;;\n  store) cat > \"{0}/$4\" ;;\nesac\n",
+        store.display()
+    );
+    std::fs::create_dir_all(&store).unwrap();
+    std::fs::write(&tool, script).unwrap();
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+    config.set("keyring", tool.to_str().unwrap()).unwrap();
+    let mut runtime = Runtime::with_config(config);
+
+    assert_eq!(tau::parse_tau("cred_set(\"github\", \"ghp_123\")", &mut runtime).unwrap(), Value::Unit);
+    assert_eq!(std::fs::read_to_string(store.join("github")).unwrap(), "ghp_123");
+    let token = tau::parse_tau("cred_get(\"github\")", &mut runtime).unwrap();
+    assert_eq!(token.to_string(), "<secret>");
+    let Value::Secret(token) = token else { panic!("expected a secret") };
+    assert_eq!(token.expose(), "ghp_123");
+    assert_eq!(tau::parse_tau("cred_get(\"gitlab\")", &mut runtime).unwrap_err(), "No credential stored for gitlab");
+    assert!(tau::parse_tau("cred_set(\"\", \"x\")", &mut runtime).is_err());
+
+    runtime.config.capabilities.revoke(Capability::Keyring);
+    assert!(tau::parse_tau("cred_get(\"github\")", &mut runtime).unwrap_err().contains("keyring"));
+}
+
+// Process tests
+#[test]
+fn test_processes_kill_and_wait() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    let mut sleeper = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+    let pid = sleeper.id();
+    let Value::Array(processes) = repl.eval("processes()").unwrap() else { panic!("expected an array of processes") };
+    let found = processes.iter().find_map(|process| match process {
+        Value::Map(map) if map.get(&Value::Str("pid".into())) == Some(&Value::Num(pid as f64)) => Some(map.clone()),
+        _ => None,
+    });
+    let found = found.expect("the sleeper should be listed");
+    assert_eq!(found.get(&Value::Str("name".into())), Some(&Value::Str("sleep".into())));
+    assert!(matches!(found.get(&Value::Str("mem".into())), Some(Value::Num(mem)) if *mem >= 0.0));
+
+    // wait gives up at the evaluation's deadline while the process runs on
+    repl.set_timeout(Language::Rho, Some(Duration::from_millis(50)));
+    assert!(repl.eval(&format!("wait({})", pid)).unwrap_err().starts_with("Evaluation timed out"));
+    repl.set_timeout(Language::Rho, None);
+    assert_eq!(repl.eval(&format!("kill({}, \"SIGKILL\")", pid)).unwrap(), Value::Unit);
+    // Our own child lingers as a zombie until reaped, which wait counts as exited
+    assert_eq!(repl.eval(&format!("wait({})", pid)).unwrap(), Value::Unit);
+    sleeper.wait().unwrap();
+    assert!(repl.eval("kill(0)").unwrap_err().contains("process id"));
+    assert!(repl.eval(&format!("kill({}, \"BOGUS\")", pid)).unwrap_err().starts_with("Unknown signal"));
+
+    // Tau gives a future, settled between inputs once the process exits
+    let mut sleeper = std::process::Command::new("sleep").arg("0.1").spawn().unwrap();
+    let mut runtime = Runtime::new();
+    tau::parse_tau(&format!("done = wait({})", sleeper.id()), &mut runtime).unwrap();
+    assert_eq!(runtime.futures()[0].state().name(), "pending");
+    sleeper.wait().unwrap();
+    assert_eq!(runtime.expire_futures(), 1);
+    assert_eq!(tau::parse_tau("await done", &mut runtime).unwrap(), Value::Unit);
+
+    let mut repl = Repl::with_capabilities(Capabilities::none());
+    repl.set_language(Language::Rho);
+    assert!(repl.eval("processes()").unwrap_err().contains("shell"));
+}
+
+// Lexer tests
+#[test]
+fn test_lexer_tokens() {
+    let kinds: Vec<TokenKind> = tokenize("x = [1.5, \"a+b\"] --> == <").unwrap().into_iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec![
+        TokenKind::Ident("x".to_string()),
+        TokenKind::Assign,
+        TokenKind::LBracket,
+        TokenKind::Num(1.5),
+        TokenKind::Comma,
+        TokenKind::Str("a+b".to_string()),
+        TokenKind::RBracket,
+        TokenKind::Arrow,
+        TokenKind::EqEq,
+        TokenKind::Less,
+    ]);
+}
+
+#[test]
+fn test_lexer_positions_and_indent() {
+    let tokens = tokenize("for i in a {\n\t\tprint(i) # comment\n}").unwrap();
+    let indent = tokens.iter().find(|t| matches!(t.kind, TokenKind::Indent(_))).unwrap();
+    assert_eq!(indent.kind, TokenKind::Indent(2));
+    assert_eq!(indent.line, 2);
+    let print = tokens.iter().find(|t| t.kind == TokenKind::Ident("print".to_string())).unwrap();
+    assert_eq!((print.line, print.col), (2, 3));
+    assert!(!tokens.iter().any(|t| t.kind == TokenKind::Ident("comment".to_string())));
+}
+
+#[test]
+fn test_lexer_errors() {
+    assert!(tokenize("\"open").unwrap_err().contains("Unterminated"));
+    assert!(tokenize("3 @ 4").unwrap_err().contains("1:3"));
+}
+
+// Rho parser tests
+fn rho_eval(input: &str) -> Result<Value, String> {
+    let mut repl = Repl::new();
+    repl.parse_rho(input)
+}
+
+#[test]
+fn test_rho_assignment() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    assert_eq!(repl.eval("x = 2 + 3").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.eval("x * 2").unwrap(), Value::Num(10.0));
+    // Compound forms, on variables and into maps and arrays
+    assert_eq!(repl.parse_rho("x += 1; x *= 4; x -= 2; x /= 2").unwrap(), Value::Num(11.0));
+    repl.parse_rho("m = [{\"n\", 1}]; xs = [1, 2]").unwrap();
+    repl.parse_rho("m[\"n\"] += 10; xs[-1] *= 5").unwrap();
+    assert_eq!(repl.parse_rho("m[\"n\"] + xs[1]").unwrap(), Value::Num(21.0));
+    // The environment is shared with the other languages, and with loop bodies
+    assert_eq!(repl.parse_pi("x 1 +").unwrap(), Value::Num(12.0));
+    repl.parse_rho("total = 0\nfor i in 1..=4 { total += i }").unwrap();
+    assert_eq!(repl.parse_tau("total").unwrap(), Value::Num(10.0));
+    assert!(repl.parse_rho("1 = 2").is_err());
+    assert!(repl.parse_rho("y += 1").unwrap_err().contains("Undefined variable"));
+}
+
+#[test]
+fn test_destructuring() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.parse_rho("[a, b, c] = [1, 2, 3]; a + b * c").unwrap(), Value::Num(7.0));
+    repl.parse_rho("{x, y} = [{\"y\", 2}, {\"x\", 1}, {\"z\", 0}]").unwrap();
+    assert_eq!(repl.parse_rho("[x, y]").unwrap(), Value::Array(nums(&[1.0, 2.0]).into()));
+    // Swapping needs no temporary
+    assert_eq!(repl.parse_rho("[x, y] = [y, x]; x").unwrap(), Value::Num(2.0));
+    assert_eq!(repl.parse_rho("[a, b] = [1]").unwrap_err(), "Expected 2 items to unpack, got 1");
+    assert_eq!(repl.parse_rho("{w} = [{\"x\", 1}]").unwrap_err(), "Map has no key w");
+    assert!(repl.parse_rho("[a, b] = 5").is_err());
+    // In loop headers, and in a generator's
+    assert_eq!(repl.parse_rho("t = 0; for [k, v] in [[1, 2], [3, 4]] { t += k * v }; t").unwrap(), Value::Num(14.0));
+    assert_eq!(repl.parse_rho("n = 0; for {x} in [[{\"x\", 5}]] { n = x }; n").unwrap(), Value::Num(5.0));
+    repl.parse_tau("gen firsts(ps) { for [k, v] in ps { yield k } }").unwrap();
+    repl.parse_tau("seen = []").unwrap();
+    repl.parse_tau("for k in firsts([[\"a\", 1], [\"b\", 2]]) { push(seen, k) }").unwrap();
+    assert_eq!(repl.parse_tau("seen").unwrap(), Value::Array(vec![Value::Str("a".into()), Value::Str("b".into())].into()));
+    // A bracketed expression that is not assigned to is still an array or block
+    assert_eq!(repl.parse_rho("[a, b]").unwrap(), Value::Array(nums(&[1.0, 2.0]).into()));
+    assert_eq!(repl.parse_rho("{a}").unwrap(), Value::Num(1.0));
+}
+
+#[test]
+fn test_structs() {
+    let mut repl = Repl::new();
+    repl.parse_rho("struct Point(x, y)").unwrap();
+    assert_eq!(repl.parse_rho("p = Point(1, 2); p.x + p.y * 10").unwrap(), Value::Num(21.0));
+    assert_eq!(repl.parse_rho("p").unwrap().to_string(), "Point(x: 1, y: 2)");
+    assert_eq!(format!("{:?}", repl.parse_rho("Point(1, \"a\")").unwrap()), "Struct(Point(x: Num(1.0), y: Str(\"a\")))");
+    // Equal by type and fields
+    assert_eq!(repl.parse_rho("Point(1, 2) == p").unwrap(), Value::Bool(true));
+    assert_eq!(repl.parse_rho("Point(2, 1) == p").unwrap(), Value::Bool(false));
+    repl.parse_rho("struct Pair(x, y)").unwrap();
+    assert_eq!(repl.parse_rho("Pair(1, 2) == p").unwrap(), Value::Bool(false));
+    // Fields hold any value and chain
+    repl.parse_rho("struct Line(from, to)").unwrap();
+    assert_eq!(repl.parse_rho("Line(p, Point(3, [4, 5])).to.y[1]").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.parse_rho("to_json(Line(p, p))").unwrap(), Value::Str("{\"from\":{\"x\":1,\"y\":2},\"to\":{\"x\":1,\"y\":2}}".into()));
+    // The constructor is a function like any other
+    assert_eq!(repl.parse_rho("map([1, 2], fn(n) Point(n, n)).len()").unwrap(), Value::Num(2.0));
+    assert_eq!(repl.parse_rho("Point").unwrap().to_string(), "struct Point(x, y)");
+    assert_eq!(repl.parse_rho("Point(1)").unwrap_err(), "struct Point(x, y) expects 2 arguments, got 1");
+    assert_eq!(repl.parse_rho("p.z").unwrap_err(), "Point has no field z");
+    assert_eq!(repl.parse_rho("[1].x").unwrap_err(), "Cannot read field x of Array([Num(1.0)])");
+    assert_eq!(repl.parse_rho("struct Bad(a, a)").unwrap_err(), "struct Bad has two fields named a");
+    assert!(repl.parse_rho("struct while(a)").is_err());
+    // Tau reads the same declarations
+    repl.parse_tau("struct Span(start, end)").unwrap();
+    assert_eq!(repl.parse_tau("Span(1, 4).end").unwrap(), Value::Num(4.0));
+    assert_eq!(crate::translate::translate(Language::Rho, "struct S(a); S(1).a").unwrap()[1].1, Ok("struct S(a); S(1).a".to_string()));
+}
+
+#[test]
+fn test_enums_and_match() {
+    let mut repl = Repl::new();
+    repl.parse_rho("enum Shape = Circle(r) | Rect(w, h) | Empty").unwrap();
+    repl.parse_rho("area = fn(s) match s {\n\tCircle(r) => 3 * r * r\n\tRect(w, h) => w * h\n\tEmpty => 0\n}").unwrap();
+    assert_eq!(repl.parse_rho("map([Circle(2), Rect(2, 5), Empty], area)").unwrap(), Value::Array(nums(&[12.0, 10.0, 0.0]).into()));
+    assert_eq!(repl.parse_rho("Rect(2, 5)").unwrap().to_string(), "Rect(w: 2, h: 5)");
+    assert_eq!(repl.parse_rho("Empty").unwrap().to_string(), "Empty");
+    assert_eq!(repl.parse_rho("Circle").unwrap().to_string(), "enum Shape = Circle(r)");
+    assert_eq!(repl.parse_rho("Circle(1).r").unwrap(), Value::Num(1.0));
+    // Variants compare like structs, their enum included
+    assert_eq!(repl.parse_rho("Circle(1) == Circle(1)").unwrap(), Value::Bool(true));
+    repl.parse_rho("struct Dot(r)").unwrap();
+    assert_eq!(repl.parse_rho("Circle(1) == Dot(1)").unwrap(), Value::Bool(false));
+
+    // Arms try in order: literals, _ and bare variant names, whose bindings last for the arm
+    let kind = "fn(v) match v { 0 => \"zero\", \"a\" => \"letter\", true => \"yes\", Rect(_, h) => h, Circle => \"round\", _ => \"other\" }";
+    repl.parse_rho(&format!("kind = {}", kind)).unwrap();
+    assert_eq!(repl.parse_rho("kind(0)").unwrap(), Value::Str("zero".into()));
+    assert_eq!(repl.parse_rho("kind(\"a\")").unwrap(), Value::Str("letter".into()));
+    assert_eq!(repl.parse_rho("kind(true)").unwrap(), Value::Str("yes".into()));
+    assert_eq!(repl.parse_rho("kind(Rect(1, 7))").unwrap(), Value::Num(7.0));
+    assert_eq!(repl.parse_rho("kind(Circle(5))").unwrap(), Value::Str("round".into()));
+    assert_eq!(repl.parse_rho("kind([1])").unwrap(), Value::Str("other".into()));
+    assert_eq!(repl.parse_rho("match Rect(1, 2) { Rect(w, h) => w }; w").unwrap_err(), "Undefined variable: w");
+    assert_eq!(repl.parse_rho("match 5 { 0 => 1 }").unwrap_err(), "No match arm for 5");
+    assert_eq!(repl.parse_rho("match Circle(1) { Circle(a, b) => a }").unwrap_err(), "Pattern Circle(a, b) does not fit Circle(r: 1)");
+    assert_eq!(repl.parse_rho("enum E = A | A").unwrap_err(), "enum E has two variants named A");
+    assert_eq!(repl.parse_rho("enum E = A(x, x)").unwrap_err(), "A has two fields named x");
+
+    // Variants travel through the binary wire format
+    let value = repl.parse_rho("[Rect(1, Circle(2)), Empty]").unwrap();
+    assert_eq!(crate::wire::decode(&crate::wire::encode(&value).unwrap()).unwrap(), value);
+    // And translate between Rho and Tau
+    let source = "enum T = A(x) | B; match A(1) { A(x) => x, B => 0, _ => -1 }";
+    assert_eq!(crate::translate::translate(Language::Rho, source).unwrap()[1].1, Ok(source.to_string()));
+}
+
+#[test]
+fn test_none_and_safe_access() {
+    let mut repl = Repl::new();
+    repl.parse_rho("m = [{\"a\", 1}]; xs = [10, 20]; struct P(x)").unwrap();
+    // get is indexing that answers none when nothing is there
+    assert_eq!(repl.parse_rho("get(m, \"a\")").unwrap(), Value::Num(1.0));
+    assert_eq!(repl.parse_rho("m.get(\"b\")").unwrap(), Value::None);
+    assert_eq!(repl.parse_rho("[get(xs, -1), get(xs, 2), get(\"ab\", 5), get(0..3, 3)]").unwrap().to_string(), "[20, none, none, none]");
+    assert_eq!(repl.parse_rho("m[\"b\"]").unwrap_err(), "Key Str(\"b\") not found in map");
+    assert!(repl.parse_rho("get(5, 0)").is_err());
+    // ?. reads a field or map entry, or none; ?? replaces none
+    assert_eq!(repl.parse_rho("m?.a").unwrap(), Value::Num(1.0));
+    assert_eq!(repl.parse_rho("m?.b").unwrap(), Value::None);
+    assert_eq!(repl.parse_rho("P(1)?.y ?? P(2)?.x").unwrap(), Value::Num(2.0));
+    assert_eq!(repl.parse_rho("none?.a?.b ?? \"default\"").unwrap(), Value::Str("default".into()));
+    assert!(repl.parse_rho("5?.a").is_err());
+    // Only none is replaced, and the default is not run otherwise
+    assert_eq!(repl.parse_rho("[0 ?? 1, false ?? 1, none ?? none ?? 3]").unwrap().to_string(), "[0, false, 3]");
+    assert_eq!(repl.parse_rho("1 ?? missing").unwrap(), Value::Num(1.0));
+    // ?? binds looser than or
+    assert_eq!(repl.parse_rho("none ?? false or true").unwrap(), Value::Bool(true));
+    // none is false, equal only to itself, and null in JSON
+    assert_eq!(repl.parse_rho("if_none = 0; while none { if_none = 1 }; if_none").unwrap(), Value::Num(0.0));
+    assert_eq!(repl.parse_rho("[none == none, none == 0]").unwrap().to_string(), "[true, false]");
+    assert_eq!(repl.parse_rho("to_json([none])").unwrap(), Value::Str("[null]".into()));
+    assert_eq!(repl.parse_pi("none").unwrap(), Value::None);
+    let source = "x = m?.a ?? get(xs, 5) ?? none";
+    assert_eq!(crate::translate::translate(Language::Rho, source).unwrap()[1].1, Ok(source.to_string()));
+}
+
+#[test]
+fn test_let_and_const() {
+    let mut repl = Repl::new();
+    // At the top level they outlive the input; a const cannot change
+    repl.parse_rho("let x = 1; const limit = 3").unwrap();
+    assert_eq!(repl.parse_rho("x = x + limit; x").unwrap(), Value::Num(4.0));
+    assert_eq!(repl.parse_rho("limit = 4").unwrap_err(), "Cannot assign to constant limit");
+    assert_eq!(repl.parse_tau("limit = 4").unwrap_err(), "Cannot assign to constant limit");
+    assert_eq!(repl.parse_pi("4 \"limit\" =").unwrap_err(), "Cannot assign to constant limit");
+    assert!(repl.parse_rho("limit += 1").is_err());
+    assert_eq!(repl.parse_rho("const limit = 5").unwrap_err(), "limit is already a constant");
+    assert!(repl.parse_rho("for limit in 1..3 { 0 }").is_err());
+    // Constant contents do not change in place either
+    repl.parse_rho("const xs = [1]; const m = [{\"a\", 1}]").unwrap();
+    assert_eq!(repl.parse_rho("push(xs, 2)").unwrap_err(), "Cannot assign to constant xs");
+    assert_eq!(repl.parse_rho("m[\"a\"] = 2").unwrap_err(), "Cannot assign to constant m");
+    assert_eq!(repl.parse_rho("len(xs)").unwrap(), Value::Num(1.0));
+
+    // In a block they end with it, shadowing what is outside
+    assert_eq!(repl.parse_rho("{ let y = 10; const z = 2; y * z }").unwrap(), Value::Num(20.0));
+    assert_eq!(repl.parse_rho("y").unwrap_err(), "Undefined variable: y");
+    assert_eq!(repl.parse_rho("{ let x = 100; x = 200 }; x").unwrap(), Value::Num(4.0));
+    assert_eq!(repl.parse_rho("{ let n = 0; for i in 1..=3 { n += i }; n }").unwrap(), Value::Num(6.0));
+    assert_eq!(repl.parse_rho("{ const limit = 9; limit }").unwrap(), Value::Num(9.0));
+    assert!(repl.parse_rho("{ const k = 1; { k = 2 } }").is_err());
+
+    // Functions see the bindings where they were made, not where they are called
+    repl.parse_rho("f = fn() y").unwrap();
+    assert_eq!(repl.parse_rho("{ let y = 5; f() }").unwrap_err(), "Undefined variable: y");
+    repl.parse_rho("add = { let base = 10; fn(x) x + base }").unwrap();
+    assert_eq!(repl.parse_rho("add(1)").unwrap(), Value::Num(11.0));
+    repl.parse_rho("double = { let x = 1; fn(x) x * 2 }").unwrap();
+    assert_eq!(repl.parse_rho("double(5)").unwrap(), Value::Num(10.0));
+    // A generator's lets last between resumes and stay inside it
+    repl.parse_tau("gen upto(n) { let step = 0; while step < n { step += 1; yield step } }").unwrap();
+    repl.parse_tau("seen = []").unwrap();
+    repl.parse_tau("for v in upto(3) { push(seen, v) }").unwrap();
+    assert_eq!(repl.parse_tau("seen").unwrap(), Value::Array(nums(&[1.0, 2.0, 3.0]).into()));
+    assert!(repl.parse_tau("step").is_err());
+
+    // A module's consts are declared again when it reloads, and kept if that fails
+    let config = Config::with_temp_workspace("modules-const").unwrap();
+    let root = config.workspace.clone();
+    let path = config.in_workspace("limits.rho");
+    std::fs::write(&path, "const cap = 3\n").unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.load_module("limits.rho").unwrap();
+    repl.reload_module("limits").unwrap();
+    std::fs::write(&path, "const cap = 4\ncap = 5\n").unwrap();
+    assert!(repl.reload_module("limits").is_err());
+    assert_eq!(repl.parse_rho("cap").unwrap(), Value::Num(3.0));
+    assert!(repl.parse_rho("cap = 1").is_err());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_strict_mode() {
+    // Loose by default: these all quietly work
+    let mut repl = Repl::new();
+    assert_eq!(repl.parse_rho("1 == \"1\"").unwrap(), Value::Bool(false));
+    assert_eq!(repl.parse_rho("[] and true").unwrap(), Value::Bool(true));
+    repl.parse_rho("total = 1; len = 2").unwrap();
+
+    let mut config = Config::new();
+    config.set("strict", "on").unwrap();
+    let mut repl = Repl::with_config(config);
+    assert_eq!(repl.parse_rho("1 == \"1\"").unwrap_err(), "Strict mode: cannot compare number with string");
+    assert_eq!(repl.parse_pi("1 \"1\" ==").unwrap_err(), "Strict mode: cannot compare number with string");
+    assert_eq!(repl.parse_rho("[1, 2] == [1, 2]").unwrap(), Value::Bool(true));
+    assert_eq!(repl.parse_rho("[] and true").unwrap_err(), "Strict mode: array used as a condition");
+    assert!(repl.parse_rho("while \"go\" { 0 }").is_err());
+    assert!(repl.parse_pi("[1] not").is_err());
+    assert_eq!(repl.parse_rho("len([1]) > 0 and not false").unwrap(), Value::Bool(true));
+    // A new name needs let; after that plain assignment is fine
+    assert_eq!(repl.parse_rho("total = 1").unwrap_err(), "Strict mode: total is not declared; use let total = ...");
+    assert!(repl.parse_tau("total = 1").is_err());
+    assert_eq!(repl.parse_rho("let total = 1; total += 1; total").unwrap(), Value::Num(2.0));
+    assert_eq!(repl.parse_rho("let len = 2").unwrap_err(), "Strict mode: len is a builtin");
+    assert!(repl.parse_rho("for map in 1..3 { 0 }").is_err());
+    assert_eq!(repl.parse_rho("len([1, 2])").unwrap(), Value::Num(2.0));
+}
+
+#[test]
+fn test_builtin_signatures() {
+    // Every builtin answers a wrong call from its signature, at the call's site
+    assert_eq!(rho_eval("len([1], 2)").unwrap_err(), "len expects 1 argument, got 2 arguments (array, number) at 1:1");
+    assert_eq!(rho_eval("x = 1\n  promise(x)").unwrap_err(), "promise expects no arguments, got 1 argument (number) at 2:3");
+    assert_eq!(
+        rho_eval("mix(color(1, 2, 3), 4, 0.5)").unwrap_err(),
+        "mix expects 3 or 4 arguments (color, color, number, string), got 3 arguments (color, number, number) at 1:1"
+    );
+    assert_eq!(rho_eval("color(1, 2)").unwrap_err(), "color expects 3 or 4 numeric arguments, got 2 arguments (number, number) at 1:1");
+    assert_eq!(rho_eval("map([1], 2)").unwrap_err(), "map expects 2 arguments (array, function), got 2 arguments (array, number) at 1:1");
+    assert_eq!(rho_eval("push([1])").unwrap_err(), "push expects at least 2 arguments, got 1 argument (array) at 1:1");
+    assert_eq!(rho_eval("path_join(\"a\", 1)").unwrap_err(), "path_join expects any number of string arguments, got 2 arguments (string, number) at 1:1");
+    // Checks a kind cannot express stay with the builtin
+    assert!(rho_eval("with_alpha(color(1, 2, 3), 300)").unwrap_err().contains("alpha from 0 to 255"));
+    // Pi's array words go through the same signatures
+    let mut runtime = Runtime::new();
+    let mut stack = Vec::new();
+    assert_eq!(crate::pi::parse_pi("[1] 1 contains reverse", &mut runtime, &mut stack).unwrap_err(), "reverse expects 1 array argument, got 1 argument (bool)");
+    // A name is a builtin exactly when it has a signature
+    assert!(crate::signatures::is_builtin("len"));
+    assert!(!crate::signatures::is_builtin("length"));
+}
+
+#[test]
+fn test_builtin_names() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    // Keywords are refused however the binding is made
+    assert_eq!(repl.parse_rho("let while = 3").unwrap_err(), "while is a keyword; :force-assign while = ... binds it anyway");
+    assert!(repl.parse_rho("let match = 1").is_err());
+    assert!(repl.parse_rho("[x, in] = [1, 2]").is_err());
+    assert_eq!(repl.parse_pi("1 \"for\" =").unwrap_err(), "for is a keyword; :force-assign for = ... binds it anyway");
+    // Builtin names stay free for values, which leave the builtin callable
+    assert_eq!(repl.parse_rho("values = [1]; values([{\"a\", 2}])").unwrap().to_string(), "[2]");
+    assert_eq!(repl.parse_rho("for next in [1, 2] { next }").unwrap(), Value::Num(2.0));
+    assert_eq!(repl.parse_rho("[count, x] = [1, 2]; let keys = 3; count + keys").unwrap(), Value::Num(4.0));
+    assert_eq!(repl.parse_pi("3 \"sort\" =").unwrap(), Value::Num(3.0));
+    // A function taking a builtin's name takes over its calls, with a warning
+    let mut runtime = Runtime::new();
+    tau::parse_tau("total = 1; gen stepper() { yield 1 }", &mut runtime).unwrap();
+    assert!(runtime.warnings.is_empty());
+    tau::parse_tau("gen next() { yield 1 }", &mut runtime).unwrap();
+    assert_eq!(runtime.warnings, ["next is a builtin; calls to next now run this function"]);
+    // Parameters may use the names, inside their function
+    assert_eq!(repl.parse_rho("f = fn(map) map + 1; f(1)").unwrap(), Value::Num(2.0));
+
+    // Resolution order for calls: a function variable, then the builtin; a
+    // variable that is not a function leaves the builtin callable
+    repl.parse_rho("sort = 5").unwrap();
+    assert_eq!(repl.parse_rho("sort([2, 1])").unwrap(), Value::Array(nums(&[1.0, 2.0]).into()));
+    assert_eq!(repl.parse_rho("sort").unwrap(), Value::Num(5.0));
+    repl.parse_rho("len = fn(xs) 42").unwrap();
+    assert_eq!(repl.parse_rho("len([1])").unwrap(), Value::Num(42.0));
+    repl.force_assign("push = fn(xs, x) 0").unwrap();
+    assert_eq!(repl.parse_rho("xs = [1]; push(xs, 2); xs").unwrap(), Value::Array(nums(&[1.0]).into()));
+    // And for names: the innermost let, then outer ones, then variables
+    repl.parse_rho("v = 1").unwrap();
+    assert_eq!(repl.parse_rho("{ let v = 2; { let v = 3; v } + v }").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.parse_rho("v").unwrap(), Value::Num(1.0));
+    // Forcing lasts one input
+    repl.force_assign("let in = 1").unwrap();
+    assert!(repl.parse_rho("let in = 1").is_err());
+}
+
+#[test]
+fn test_method_calls() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    // value.name(args) calls the builtin for the value's kind with it first
+    assert_eq!(repl.parse_rho("[3, 1, 2].sort().reverse()").unwrap().to_string(), "[3, 2, 1]");
+    assert_eq!(repl.parse_rho("\"abc\".len() + [1].len() + [{1, 2}].len()").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.parse_rho("\" Hi There \".trim().lower().split(\" \").join(\"-\")").unwrap(), Value::Str("hi-there".into()));
+    assert_eq!(repl.parse_rho("\"straße\".upper()").unwrap(), Value::Str("STRASSE".into()));
+    assert_eq!(repl.parse_rho("\"ab\".split(\"\")").unwrap().to_string(), "[\"a\", \"b\"]");
+    assert_eq!(repl.parse_rho("\"a-b-c\".replace(\"-\", \"+\")").unwrap(), Value::Str("a+b+c".into()));
+    assert_eq!(repl.parse_rho("[1, \"a\", true].join(\", \")").unwrap(), Value::Str("1, a, true".into()));
+    assert_eq!(repl.parse_rho("color(100, 50, 200).scale(2)").unwrap(), Value::Color(Color::new(200, 100, 255)));
+    assert_eq!(repl.parse_rho("[1, 2].map(fn(x) x * 2).reduce(0, fn(a, b) a + b)").unwrap(), Value::Num(6.0));
+    // A receiver of the wrong kind is reported as any call with its arguments would be
+    assert_eq!(
+        repl.parse_rho("\"red\".scale(2)").unwrap_err(),
+        "scale expects 2 arguments (color, number), got 2 arguments (string, number) at 1:7"
+    );
+    assert_eq!(repl.parse_rho("5.upper()").unwrap_err(), "upper expects 1 string argument, got 1 argument (number) at 1:3");
+    assert!(repl.parse_rho("\"a\".replace(\"\", \"b\")").unwrap_err().contains("non-empty"));
+    assert!(repl.parse_rho("color(1, 2, 3).scale(-1)").unwrap_err().contains("factor of 0 or more"));
+    // A function variable of the name is called ahead of the builtin, as with name(value)
+    repl.parse_rho("double = fn(x) x * 2").unwrap();
+    assert_eq!(repl.parse_rho("4.double().double()").unwrap(), Value::Num(16.0));
+}
+
+#[test]
+fn test_pipeline() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    repl.parse_rho("double = fn(x) x * 2; xs = [3, 1, 2]").unwrap();
+    // x |> f is f(x), and x |> g(a) is g(x, a)
+    assert_eq!(repl.parse_rho("xs |> sort |> reverse").unwrap().to_string(), "[3, 2, 1]");
+    assert_eq!(repl.parse_rho("\"a,b\" |> split(\",\") |> join(\"-\") |> upper").unwrap(), Value::Str("A-B".into()));
+    assert_eq!(repl.parse_rho("xs |> map(fn(x) x + 1) |> reduce(0, fn(a, b) a + b)").unwrap(), Value::Num(9.0));
+    assert_eq!(repl.parse_rho("4 |> double |> double").unwrap(), Value::Num(16.0));
+    // Looser than arithmetic and ranges, tighter than comparisons
+    assert_eq!(repl.parse_rho("1 + 2 |> double").unwrap(), Value::Num(6.0));
+    assert_eq!(repl.parse_rho("wrap = fn(x) [x]; len(0..3 |> wrap)").unwrap(), Value::Num(1.0));
+    assert_eq!(repl.parse_rho("xs |> len == 3 and 1 |> double < 3").unwrap(), Value::Bool(true));
+    // It is the nested call, so it translates as one
+    let (_, rho) = &crate::translate::translate(Language::Rho, "xs |> map(double) |> len").unwrap()[1];
+    assert_eq!(rho.as_deref(), Ok("len(map(xs, double))"));
+    assert_eq!(repl.parse_rho("xs |> 2").unwrap_err(), "Expected a function name or call after |> at 1:7");
+    assert_eq!(repl.parse_rho("xs |> fn(x) x").unwrap_err(), "Expected a function name or call after |> at 1:7");
+    assert_eq!(repl.parse_rho("xs |> len + 1").unwrap_err(), "Expected a function name or call after |> at 1:7");
+    assert_eq!(repl.parse_rho("xs |> nope").unwrap_err(), "Unknown function: nope");
+    assert_eq!(repl.parse_tau("[1, 2] |> reverse").unwrap().to_string(), "[2, 1]");
+}
+
+#[test]
+fn test_rho_string_with_operator() {
+    assert_eq!(rho_eval("\"a+b\"").unwrap(), Value::Str("a+b".into()));
+    assert_eq!(rho_eval("\"x-y\" == \"x-y\"").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_rho_precedence() {
+    assert_eq!(rho_eval("2 + 3 * 4").unwrap(), Value::Num(14.0));
+    assert_eq!(rho_eval("(2 + 3) * 4").unwrap(), Value::Num(20.0));
+    assert_eq!(rho_eval("10 - 2 - 3").unwrap(), Value::Num(5.0));
+    assert_eq!(rho_eval("-3 + 1").unwrap(), Value::Num(-2.0));
+}
+
+#[test]
+fn test_rho_literals_and_indexing() {
+    assert_eq!(rho_eval("[10,20,30][1]").unwrap(), Value::Num(20.0));
+    assert_eq!(rho_eval("[{\"x\",100},{\"y\",200}][\"y\"]").unwrap(), Value::Num(200.0));
+    assert_eq!(rho_eval("color(255,0,0) + color(0,255,0)").unwrap(), Value::Color(Color::new(255, 255, 0)));
+    assert!(rho_eval("color(300,0,0)").is_err());
+}
+
+#[test]
+fn test_rho_loops_parse() {
+    assert_eq!(rho_eval("for i in [1,2,3] { i * 10 }").unwrap(), Value::Num(30.0));
+    assert_eq!(rho_eval("while false { 1 }").unwrap(), Value::Unit);
+    assert_eq!(rho_eval("{ 1; 2\n3 }").unwrap(), Value::Num(3.0));
+}
+
+#[test]
+fn test_rho_parse_errors() {
+    assert!(rho_eval("3 +").is_err());
+    assert!(rho_eval("[1, 2").is_err());
+    assert!(rho_eval("3 4").unwrap_err().contains("1:3"));
+}
+
+// Pi parser tests
+fn pi_eval(input: &str) -> Result<Value, String> {
+    Repl::new().parse_pi(input)
+}
+
+#[test]
+fn test_pi_tokens_and_variables() {
+    assert_eq!(pi_eval("3 4 +").unwrap(), Value::Num(7.0));
+    assert_eq!(pi_eval("10 -5 +").unwrap(), Value::Num(5.0));
+    assert_eq!(pi_eval("\"a+b\"").unwrap(), Value::Str("a+b".into()));
+    assert!(pi_eval("3 +").is_err());
+
+    let mut repl = Repl::new();
+    assert_eq!(repl.parse_pi("[1,2,3] \"arr\" =").unwrap(), Value::Array(vec![Value::Num(1.0), Value::Num(2.0), Value::Num(3.0)].into()));
+    assert_eq!(repl.parse_pi("arr [4] +").unwrap(), Value::Array(vec![Value::Num(1.0), Value::Num(2.0), Value::Num(3.0), Value::Num(4.0)].into()));
+    // Variables are shared with Rho
+    assert_eq!(repl.parse_rho("arr[0] + 1").unwrap(), Value::Num(2.0));
+}
+
+// Color conversion tests
+#[test]
+fn test_color_to_ansi256() {
+    assert_eq!(Color::new(0, 0, 0).to_ansi256(), 16);
+    assert_eq!(Color::new(255, 0, 0).to_ansi256(), 196);
+    assert_eq!(Color::new(255, 255, 255).to_ansi256(), 231);
+    assert_eq!(Color::new(128, 128, 128).to_ansi256(), 244);
+}
+
+#[test]
+fn test_color_nearest_named() {
+    assert_eq!(Color::new(250, 5, 5).nearest_named(), "red");
+    assert_eq!(Color::new(250, 160, 10).nearest_named(), "orange");
+    assert_eq!(Color::named("Navy"), Some(Color::new(0, 0, 128)));
+    assert_eq!(Color::named("nope"), None);
+}
+
+#[test]
+fn test_color_contrast() {
+    let black = Color::new(0, 0, 0);
+    let white = Color::new(255, 255, 255);
+    assert!((black.contrast(&white) - 21.0).abs() < 0.01);
+    assert!((white.contrast(&black) - 21.0).abs() < 0.01);
+    assert!((white.contrast(&white) - 1.0).abs() < 0.01);
+    assert_eq!(Color::new(255, 128, 0).to_hex(), "#ff8000");
+}
+
+#[test]
+fn test_color_conversion_builtins() {
+    assert_eq!(rho_eval("to_ansi256(color(255,0,0))").unwrap(), Value::Num(196.0));
+    assert_eq!(rho_eval("nearest_named(color(0,0,120))").unwrap(), Value::Str("navy".into()));
+    assert_eq!(rho_eval("contrast(color(0,0,0), color(0,0,0))").unwrap(), Value::Num(1.0));
+    assert_eq!(rho_eval("show([color(1,2,3)])").unwrap(), Value::Unit);
+    assert!(rho_eval("contrast(color(0,0,0))").is_err());
+    assert!(rho_eval("to_ansi256(3)").is_err());
+}
+
+// Palette tests
+#[test]
+fn test_color_hsl_round_trip() {
+    for c in [Color::new(255, 0, 0), Color::new(12, 200, 99), Color::new(128, 128, 128), Color::new(250, 160, 10)] {
+        let (h, s, l) = c.to_hsl();
+        assert_eq!(Color::from_hsl(h, s, l), c);
+    }
+    let (h, s, l) = Color::new(0, 0, 255).to_hsl();
+    assert_eq!((h, s, l), (240.0, 1.0, 0.5));
+}
+
+#[test]
+fn test_color_gradient() {
+    let g = Color::new(0, 0, 0).gradient(&Color::new(100, 200, 50), 3);
+    assert_eq!(g, vec![Color::new(0, 0, 0), Color::new(50, 100, 25), Color::new(100, 200, 50)]);
+    assert_eq!(Color::new(1, 2, 3).gradient(&Color::new(0, 0, 0), 1), vec![Color::new(1, 2, 3)]);
+    assert!(Color::new(1, 2, 3).gradient(&Color::new(0, 0, 0), 0).is_empty());
+}
+
+#[test]
+fn test_color_harmonies() {
+    let red = Color::new(255, 0, 0);
+    assert_eq!(red.complementary(), vec![red, Color::new(0, 255, 255)]);
+    assert_eq!(red.triadic(), vec![red, Color::new(0, 255, 0), Color::new(0, 0, 255)]);
+    let analogous = red.analogous(3);
+    assert_eq!(analogous.len(), 3);
+    assert_eq!(analogous[1], red);
+    assert_eq!(analogous[0], Color::new(255, 0, 128));
+}
+
+#[test]
+fn test_palette_builtins() {
+    match rho_eval("gradient(color(0,0,0), color(255,255,255), 5)").unwrap() {
+        Value::Array(items) => assert_eq!(items.len(), 5),
+        other => panic!("Expected array, got {:?}", other),
+    }
+    assert_eq!(rho_eval("triadic(color(255,0,0))[1]").unwrap(), Value::Color(Color::new(0, 255, 0)));
+    assert_eq!(rho_eval("complementary(color(255,0,0))[1]").unwrap(), Value::Color(Color::new(0, 255, 255)));
+    assert!(rho_eval("analogous(color(255,0,0), -1)").is_err());
+    assert!(rho_eval("gradient(color(0,0,0), 3, 2)").is_err());
+}
+
+// Modulo and exponentiation tests
+#[test] fn test_value_rem() { assert_eq!(Value::Num(7.0).rem(&Value::Num(3.0)).unwrap(), Value::Num(1.0)); }
+#[test] fn test_value_rem_negative() { assert_eq!(Value::Num(-7.0).rem(&Value::Num(3.0)).unwrap(), Value::Num(-1.0)); }
+#[test] fn test_value_rem_by_zero() { assert!(Value::Num(7.0).rem(&Value::Num(0.0)).is_err()); }
+#[test] fn test_value_pow() { assert_eq!(Value::Num(2.0).pow(&Value::Num(10.0)).unwrap(), Value::Num(1024.0)); }
+#[test] fn test_value_pow_type_error() { assert!(Value::Str("a".into()).pow(&Value::Num(2.0)).is_err()); }
+
+#[test]
+fn test_rho_mod_pow_precedence() {
+    assert_eq!(rho_eval("2 + 7 % 4").unwrap(), Value::Num(5.0));
+    assert_eq!(rho_eval("2 * 3 ** 2").unwrap(), Value::Num(18.0));
+    assert_eq!(rho_eval("2 ** 3 ** 2").unwrap(), Value::Num(512.0));
+    assert_eq!(rho_eval("-2 ** 2").unwrap(), Value::Num(-4.0));
+    assert_eq!(rho_eval("2 ^ -1").unwrap(), Value::Num(0.5));
+}
+
+#[test]
+fn test_pi_mod_pow() {
+    assert_eq!(pi_eval("10 4 %").unwrap(), Value::Num(2.0));
+    assert_eq!(pi_eval("2 8 **").unwrap(), Value::Num(256.0));
+    assert_eq!(pi_eval("3 2 ^").unwrap(), Value::Num(9.0));
+}
+
+// Gamma-aware blending tests
+#[test]
+fn test_blend_mode_srgb_vs_linear() {
+    let black = Color::new(0, 0, 0);
+    let white = Color::new(255, 255, 255);
+    assert_eq!(black.blend_with(&white, BlendMode::Srgb), Color::new(127, 127, 127));
+    assert_eq!(black.blend_with(&white, BlendMode::Linear), Color::new(188, 188, 188));
+
+    // Red + green: linear light keeps the midpoint bright instead of muddy
+    let red = Color::new(255, 0, 0);
+    let green = Color::new(0, 255, 0);
+    let srgb = red.blend_with(&green, BlendMode::Srgb);
+    let linear = red.blend_with(&green, BlendMode::Linear);
+    assert_eq!(srgb, Color::new(127, 127, 0));
+    assert_eq!(linear, Color::new(188, 188, 0));
+    assert!(linear.luminance() > srgb.luminance());
+}
+
+#[test]
+fn test_mix_linear_endpoints() {
+    let a = Color::new(10, 200, 30);
+    let b = Color::new(240, 20, 90);
+    assert_eq!(a.mix_linear(&b, 0.0), a);
+    assert_eq!(a.mix_linear(&b, 1.0), b);
+}
+
+#[test]
+fn test_oklch_round_trip() {
+    for c in [Color::new(255, 0, 0), Color::new(12, 200, 99), Color::new(128, 128, 128), Color::new(0, 0, 0)] {
+        let (l, ch, h) = c.to_oklch();
+        assert_eq!(Color::from_oklch(l, ch, h), c);
+    }
+    let (l, _, _) = Color::new(255, 255, 255).to_oklch();
+    assert!((l - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_mix_perceptual() {
+    let red = Color::new(255, 0, 0);
+    let blue = Color::new(0, 0, 255);
+    assert_eq!(red.mix_perceptual(&blue, 0.0), red);
+    assert_eq!(red.mix_perceptual(&blue, 1.0), blue);
+    // Perceptual midpoint keeps its chroma rather than passing through dull purple
+    let perceptual = red.mix_perceptual(&blue, 0.5);
+    let srgb = red.mix(&blue, 0.5);
+    assert_ne!(perceptual, srgb);
+    assert!(perceptual.to_oklch().1 > srgb.to_oklch().1);
+    // Gray endpoints keep the other color's hue
+    let gray_mix = Color::new(128, 128, 128).mix_perceptual(&red, 0.5);
+    assert!(gray_mix.r > gray_mix.g && gray_mix.r > gray_mix.b);
+}
+
+#[test]
+fn test_blend_mode_per_call_and_config() {
+    assert_eq!(rho_eval("blend(color(0,0,0), color(255,255,255))").unwrap(), Value::Color(Color::new(127, 127, 127)));
+    assert_eq!(rho_eval("blend(color(0,0,0), color(255,255,255), \"linear\")").unwrap(), Value::Color(Color::new(188, 188, 188)));
+    assert_eq!(rho_eval("mix(color(0,0,0), color(255,255,255), 0.5, \"linear\")").unwrap(), Value::Color(Color::new(188, 188, 188)));
+    assert!(rho_eval("blend(color(0,0,0), color(255,255,255), \"muddy\")").is_err());
+
+    let mut config = Config::new();
+    config.apply_str("blend = linear").unwrap();
+    let mut repl = Repl::with_config(config);
+    assert_eq!(repl.parse_rho("blend(color(0,0,0), color(255,255,255))").unwrap(), Value::Color(Color::new(188, 188, 188)));
+    assert_eq!(repl.parse_rho("gradient(color(0,0,0), color(255,255,255), 3)[1]").unwrap(), Value::Color(Color::new(188, 188, 188)));
+}
+
+#[test]
+fn test_hsl_gradient() {
+    let red = Color::new(255, 0, 0);
+    let blue = Color::new(0, 0, 255);
+    assert_eq!(red.mix_hsl(&blue, 0.5), Color::new(255, 0, 255));
+    // Red to green goes through yellow, not the muddy sRGB midpoint
+    let hues = red.gradient_with(&Color::new(0, 255, 0), 3, BlendMode::Hsl);
+    assert_eq!(hues, vec![red, Color::new(255, 255, 0), Color::new(0, 255, 0)]);
+    // The short way round: 350 to 10 degrees passes 0, not 180
+    let (h, _, _) = Color::from_hsl(350.0, 1.0, 0.5).mix_hsl(&Color::from_hsl(10.0, 1.0, 0.5), 0.5).to_hsl();
+    assert!(!(1.0..359.0).contains(&h));
+    // A gray end keeps the other end's hue while it desaturates
+    let toward_gray = red.mix_hsl(&Color::new(128, 128, 128), 0.5);
+    assert!(toward_gray.r > toward_gray.g && toward_gray.g == toward_gray.b);
+
+    match rho_eval("gradient(color(255,0,0), color(0,0,255), 5, \"hsl\")").unwrap() {
+        Value::Array(colors) => {
+            assert_eq!(colors.len(), 5);
+            assert_eq!(colors[2], Value::Color(Color::new(255, 0, 255)));
+        }
+        other => panic!("expected an array, got {:?}", other),
+    }
+    assert!(rho_eval("gradient(color(0,0,0), color(1,1,1), 3, 4)").is_err());
+}
+
+// Alpha tests
+#[test]
+fn test_rgba_colors() {
+    assert_eq!(rho_eval("color(1, 2, 3)").unwrap(), Value::Color(Color::rgba(1, 2, 3, 255)));
+    assert_eq!(rho_eval("color(1, 2, 3, 128)").unwrap(), Value::Color(Color::rgba(1, 2, 3, 128)));
+    assert_eq!(rho_eval("with_alpha(color(1, 2, 3), 0)").unwrap().to_string(), "color(1, 2, 3, 0)");
+    assert_eq!(Color::rgba(255, 128, 0, 64).to_hex(), "#ff800040");
+    assert!(rho_eval("color(1, 2, 3, 256)").is_err());
+
+    // Mixing toward transparent: straight sRGB darkens, premultiplied keeps the hue
+    let red = Color::new(255, 0, 0);
+    let clear = Color::rgba(0, 0, 0, 0);
+    assert_eq!(red.mix(&clear, 0.5), Color::rgba(127, 0, 0, 127));
+    assert_eq!(red.mix_with(&clear, 0.5, BlendMode::Premultiplied), Color::rgba(255, 0, 0, 128));
+    assert_eq!(red.mix_linear(&clear, 1.0).a, 0);
+    assert_eq!(rho_eval("blend(color(0, 0, 255, 0), color(255, 0, 0), \"premultiplied\")").unwrap(), Value::Color(Color::rgba(255, 0, 0, 128)));
+
+    // add and sub weigh the other color by its alpha; scale keeps alpha
+    let half_red = Color::rgba(255, 0, 0, 128);
+    assert_eq!(half_red.add(&clear), half_red);
+    assert_eq!(Color::new(200, 200, 200).sub(&Color::rgba(100, 100, 100, 0)), Color::new(200, 200, 200));
+    assert_eq!(half_red.scale(0.5), Color::rgba(127, 0, 0, 128));
+    // Opaque colors behave as before
+    assert_eq!(Color::new(100, 0, 0).add(&Color::new(100, 50, 0)), Color::new(200, 50, 0));
+
+    // Alpha survives the wire, literals and JSON
+    let faded = Value::Color(Color::rgba(1, 2, 3, 4));
+    assert_eq!(crate::wire::decode(&crate::wire::encode(&faded).unwrap()).unwrap(), faded);
+    assert_eq!(crate::transport::decode_value(&crate::transport::encode_value(&faded).unwrap()).unwrap(), faded);
+    assert_eq!(crate::json::to_json(&faded).unwrap(), "{\"r\":1,\"g\":2,\"b\":3,\"a\":4}");
+}
+
+// Logical operator tests
+#[test]
+fn test_logical_and_or_not() {
+    assert_eq!(rho_eval("true and false").unwrap(), Value::Bool(false));
+    assert_eq!(rho_eval("true && 1").unwrap(), Value::Bool(true));
+    assert_eq!(rho_eval("false or 0").unwrap(), Value::Bool(false));
+    assert_eq!(rho_eval("0 || \"x\"").unwrap(), Value::Bool(true));
+    assert_eq!(rho_eval("not true").unwrap(), Value::Bool(false));
+    assert_eq!(rho_eval("!0").unwrap(), Value::Bool(true));
+    assert_eq!(rho_eval("1 != 2").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_logical_precedence() {
+    // and binds tighter than or; not tighter than and; comparisons tighter still
+    assert_eq!(rho_eval("true or false and false").unwrap(), Value::Bool(true));
+    assert_eq!(rho_eval("not 1 < 2 and true").unwrap(), Value::Bool(false));
+    assert_eq!(rho_eval("1 < 2 && 3 > 2").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_logical_short_circuit() {
+    // The right side would fail with an undefined variable if evaluated
+    assert_eq!(rho_eval("false and missing").unwrap(), Value::Bool(false));
+    assert_eq!(rho_eval("true or missing").unwrap(), Value::Bool(true));
+    assert!(rho_eval("true and missing").is_err());
+
+    let mut runtime = Runtime::new();
+    let expr = Expr::And(Box::new(Expr::Value(Value::Bool(false))), Box::new(Expr::Div(
+        Box::new(Expr::Value(Value::Num(1.0))),
+        Box::new(Expr::Value(Value::Num(0.0))),
+    )));
+    assert_eq!(runtime.eval(&expr).unwrap(), Value::Bool(false));
+}
+
+#[test]
+fn test_pi_logical_words() {
+    assert_eq!(pi_eval("true false and").unwrap(), Value::Bool(false));
+    assert_eq!(pi_eval("true false ||").unwrap(), Value::Bool(true));
+    assert_eq!(pi_eval("0 not").unwrap(), Value::Bool(true));
+    assert_eq!(pi_eval("1 2 < 3 2 > &&").unwrap(), Value::Bool(true));
+    assert!(pi_eval("not").is_err());
+}
+
+// Pi stack word tests
+fn nums(values: &[f64]) -> Vec<Value> {
+    values.iter().map(|n| Value::Num(*n)).collect()
+}
+
+#[test]
+fn test_pi_stack_words() {
+    let mut repl = Repl::new();
+    repl.parse_pi("1 2 3").unwrap();
+    assert_eq!(repl.pi_stack(), nums(&[1.0, 2.0, 3.0]).as_slice());
+    repl.parse_pi("rot").unwrap();
+    assert_eq!(repl.pi_stack(), nums(&[2.0, 3.0, 1.0]).as_slice());
+    repl.parse_pi("swap").unwrap();
+    assert_eq!(repl.pi_stack(), nums(&[2.0, 1.0, 3.0]).as_slice());
+    repl.parse_pi("over").unwrap();
+    assert_eq!(repl.pi_stack(), nums(&[2.0, 1.0, 3.0, 1.0]).as_slice());
+    repl.parse_pi("drop dup").unwrap();
+    assert_eq!(repl.pi_stack(), nums(&[2.0, 1.0, 3.0, 3.0]).as_slice());
+    assert_eq!(repl.parse_pi("* + +").unwrap(), Value::Num(12.0));
+}
+
+#[test]
+fn test_pi_stack_word_underflow() {
+    assert!(pi_eval("dup").is_err());
+    assert!(pi_eval("1 swap").is_err());
+    assert!(pi_eval("1 2 rot").is_err());
+}
+
+#[test]
+fn test_pi_print_arrow() {
+    // An array prints its items and leaves Unit; anything else stays put
+    assert_eq!(pi_eval("[1, 2] -->").unwrap(), Value::Unit);
+    assert_eq!(pi_eval("5 -->").unwrap(), Value::Num(5.0));
+    assert!(pi_eval("-->").is_err());
+    let mut repl = Repl::new();
+    repl.parse_pi("4 [1, 2] -->").unwrap();
+    assert_eq!(repl.pi_stack(), &[Value::Num(4.0), Value::Unit]);
+}
+
+#[test]
+fn test_pi_stack_persists_across_inputs() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.parse_pi("3").unwrap(), Value::Num(3.0));
+    assert_eq!(repl.parse_pi("4").unwrap(), Value::Num(4.0));
+    assert_eq!(repl.parse_pi("+").unwrap(), Value::Num(7.0));
+    assert_eq!(repl.parse_pi(".s").unwrap(), Value::Num(7.0));
+    assert_eq!(repl.pi_stack(), nums(&[7.0]).as_slice());
+    // A failing line leaves the stack as it was
+    assert!(repl.parse_pi("1 \"a\" *").is_err());
+    assert_eq!(repl.pi_stack(), nums(&[7.0]).as_slice());
+    assert_eq!(crate::pi::format_stack(repl.pi_stack()), "<1> 7");
+    // Even after eating below where it started
+    repl.parse_pi("8 9").unwrap();
+    assert!(repl.parse_pi("rot swap drop drop drop 1 \"a\" *").is_err());
+    assert!(repl.parse_pi("+ + 2 swap rot").is_err());
+    assert_eq!(repl.pi_stack(), nums(&[7.0, 8.0, 9.0]).as_slice());
+}
+
+#[test]
+fn test_clear_pi_stack() {
+    let mut repl = Repl::new();
+    repl.parse_pi("1 2 3").unwrap();
+    repl.clear_pi_stack();
+    assert!(repl.pi_stack().is_empty());
+    assert_eq!(repl.parse_pi("4").unwrap(), Value::Num(4.0));
+    assert!(repl.parse_pi("+").is_err());
+}
+
+// Future metadata tests
+#[test]
+fn test_async_creates_numbered_futures() {
+    let mut runtime = Runtime::new();
+    let first = tau::parse_tau("async fetch(data)", &mut runtime).unwrap();
+    let second = tau::parse_tau("async 1 + 2", &mut runtime).unwrap();
+    match (first, second) {
+        (Value::Future(a), Value::Future(b)) => {
+            assert_eq!((a.id, a.source.as_str()), (1, "fetch(data)"));
+            assert_eq!((b.id, b.source.as_str()), (2, "1 + 2"));
+            assert!(a.is_pending());
+        }
+        other => panic!("Expected futures, got {:?}", other),
+    }
+    assert_eq!(runtime.futures().len(), 2);
+}
+
+#[test]
+fn test_future_display_shows_state_and_progress() {
+    let future = Future::new(7, "fetch");
+    future.set_progress(0.4);
+    assert!(future.to_string().starts_with("<future #7 pending 40% \"fetch\""));
+    assert_eq!(format!("{:?}", Value::Future(future.clone())), "Future(#7 pending \"fetch\")");
+    future.set_state(FutureState::Rejected(TaskError::new("timeout")));
+    assert!(future.to_string().starts_with("<future #7 failed (timeout) \"fetch\""));
+}
+
+#[test]
+fn test_future_clones_share_state() {
+    let mut runtime = Runtime::new();
+    let future = runtime.new_future("job");
+    runtime.set_variable("f".to_string(), Value::Future(future.clone()));
+    assert!(tau::parse_tau("await f", &mut runtime).unwrap_err().contains("#1 pending"));
+    future.set_state(FutureState::Resolved(Box::new(Value::Num(3.0))));
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap(), Value::Num(3.0));
+    assert_eq!(runtime.futures()[0].progress(), Some(1.0));
+}
+
+// Executor tests
+#[test]
+fn test_await_drives_async_task() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("x = 20", &mut runtime).unwrap();
+    tau::parse_tau("f = async x * 2 + 2", &mut runtime).unwrap();
+    assert!(runtime.futures()[0].is_pending());
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap(), Value::Num(42.0));
+    assert!(runtime.futures()[0].to_string().starts_with("<future #1 resolved"));
+}
+
+#[test]
+fn test_repeated_await_uses_cached_result() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("x = 1", &mut runtime).unwrap();
+    tau::parse_tau("f = async x", &mut runtime).unwrap();
+    tau::parse_tau("g = f", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap(), Value::Num(1.0));
+    // The task does not run again, and clones share the settled state
+    tau::parse_tau("x = 2", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("await g", &mut runtime).unwrap(), Value::Num(1.0));
+}
+
+#[test]
+fn test_failed_task_rejects_future() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("f = async missing + 1", &mut runtime).unwrap();
+    let expected = "Undefined variable: missing\n  at task #1 `missing + 1` spawned at 1:5";
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap_err(), expected);
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap_err(), expected);
+    assert!(tau::parse_tau("async 1 +", &mut runtime).is_err());
+}
+
+// Promise tests
+#[test]
+fn test_promise_resolve() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("p = promise()", &mut runtime).unwrap();
+    assert!(tau::parse_tau("await p", &mut runtime).unwrap_err().contains("pending"));
+    tau::parse_tau("resolve(p, 42)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await p", &mut runtime).unwrap(), Value::Num(42.0));
+    assert_eq!(tau::parse_tau("resolve(p, 1)", &mut runtime).unwrap_err(), "Future #1 is already resolved");
+}
+
+#[test]
+fn test_promise_reject() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("p = promise()", &mut runtime).unwrap();
+    tau::parse_tau("reject(p, \"boom\")", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await p", &mut runtime).unwrap_err(), "boom");
+    assert!(tau::parse_tau("resolve(1, 2)", &mut runtime).is_err());
+    assert!(tau::parse_tau("promise(1)", &mut runtime).is_err());
+}
+
+// Future combinator tests
+#[test]
+fn test_future_then_and_catch() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("f = async 20 + 1", &mut runtime).unwrap();
+    tau::parse_tau("g = f.then(fn(x) x * 2).then(fn(x) x + 1)", &mut runtime).unwrap();
+    // Nothing runs until the end of the chain is awaited
+    assert!(runtime.futures()[0].is_pending());
+    assert_eq!(tau::parse_tau("await g", &mut runtime).unwrap(), Value::Num(43.0));
+    tau::parse_tau("bad = async 1 / nothing", &mut runtime).unwrap();
+    tau::parse_tau("safe = bad.then(fn(x) x + 1).catch(fn(e) \"caught\")", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await safe", &mut runtime).unwrap(), Value::Str("caught".into()));
+    // catch passes a resolved value straight through; a handler may return another future
+    tau::parse_tau("p = promise()", &mut runtime).unwrap();
+    tau::parse_tau("h = f.catch(fn(e) 0).then(fn(x) p)", &mut runtime).unwrap();
+    tau::parse_tau("resolve(p, \"later\")", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await h", &mut runtime).unwrap(), Value::Str("later".into()));
+    assert_eq!(tau::parse_tau("f.then(1)", &mut runtime).unwrap_err(), "then expects 2 arguments (future, function), got 2 arguments (future, number) at 1:3");
+    assert_eq!(tau::parse_tau("f.finally(fn(x) x)", &mut runtime).unwrap_err(), "Unknown function: finally");
+}
+
+#[test]
+fn test_future_all_and_race() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("a = async 1 + 1", &mut runtime).unwrap();
+    tau::parse_tau("b = async 3", &mut runtime).unwrap();
+    tau::parse_tau("c = b.then(fn(x) x * 10)", &mut runtime).unwrap();
+    tau::parse_tau("both = all([a, c])", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await both", &mut runtime).unwrap(), Value::Array(nums(&[2.0, 30.0]).into()));
+    tau::parse_tau("bad = async 1 / nothing", &mut runtime).unwrap();
+    tau::parse_tau("broken = all([a, bad])", &mut runtime).unwrap();
+    assert!(tau::parse_tau("await broken", &mut runtime).is_err());
+    // A settled future wins a race; otherwise the first runnable one is driven
+    tau::parse_tau("p = promise()", &mut runtime).unwrap();
+    tau::parse_tau("d = async 4", &mut runtime).unwrap();
+    tau::parse_tau("first = race([p, d, a])", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await first", &mut runtime).unwrap(), Value::Num(2.0));
+    tau::parse_tau("second = race([p, d])", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await second", &mut runtime).unwrap(), Value::Num(4.0));
+    tau::parse_tau("stuck = race([promise()])", &mut runtime).unwrap();
+    let error = tau::parse_tau("await stuck", &mut runtime).unwrap_err();
+    assert!(error.starts_with("race has no future that can settle\n  at task #"), "{}", error);
+    assert!(tau::parse_tau("all([1])", &mut runtime).is_err());
+}
+
+#[test]
+fn test_future_combinators_compose() {
+    assert_eq!(rho_eval("await all([async 1, async 2])").unwrap(), Value::Array(nums(&[1.0, 2.0]).into()));
+    assert_eq!(rho_eval("await (async 1 / 0).catch(fn(e) e)").unwrap(), Value::Str("Division by zero".into()));
+    assert_eq!(rho_eval("await all([async 1, async 2]).then(fn(xs) len(xs)) + 1").unwrap(), Value::Num(3.0));
+    assert_eq!(rho_eval("await race([async 5]).then(fn(x) x * 2)").unwrap(), Value::Num(10.0));
+    let mut runtime = Runtime::new();
+    assert_eq!(tau::parse_tau("total = await async 20 + 1", &mut runtime).unwrap(), Value::Num(21.0));
+    tau::parse_tau("f = async 3", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await all([f, f.then(fn(x) x + 1)])", &mut runtime).unwrap(), Value::Array(nums(&[3.0, 4.0]).into()));
+    // A race is won by whichever settles first, not by the first in the list
+    tau::parse_tau("slow = sleep 2s", &mut runtime).unwrap();
+    tau::parse_tau("fast = sleep 10ms", &mut runtime).unwrap();
+    let started = Instant::now();
+    assert_eq!(tau::parse_tau("await race([slow, fast.then(fn(x) \"fast\")])", &mut runtime).unwrap(), Value::Str("fast".into()));
+    assert_eq!(tau::parse_tau("await race([slow, async 7])", &mut runtime).unwrap(), Value::Num(7.0));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(runtime.get_variable("slow").is_some_and(|slow| matches!(slow, Value::Future(f) if f.is_pending())));
+    // Chains are checked as Rho
+    assert!(tau::check_tau("g = f.then(fn(x) x * 2).catch(fn(e) 0)").is_empty());
+    assert_eq!(tau::check_tau("g = all([async 1,").len(), 1);
+}
+
+// Scope tests
+#[test]
+fn test_scope_joins_spawned_tasks() {
+    let mut runtime = Runtime::new();
+    let result = tau::parse_tau("scope { spawn 1 + 1; spawn color(1, 2, 3) }", &mut runtime).unwrap();
+    assert_eq!(result, Value::Array(vec![Value::Num(2.0), Value::Color(Color::new(1, 2, 3))].into()));
+    assert_eq!(runtime.futures()[0].source, "1 + 1");
+    assert_eq!(runtime.futures()[1].source, "color(1, 2, 3)");
+    assert!(runtime.futures().iter().all(|f| !f.is_pending()));
+}
+
+#[test]
+fn test_scope_cancels_remaining_tasks_on_error() {
+    let mut runtime = Runtime::new();
+    let err = tau::parse_tau("scope { spawn 1; spawn missing; spawn 3 }", &mut runtime).unwrap_err();
+    assert!(err.starts_with("Undefined variable: missing\n  at task #2 `missing` spawned at 1:18"));
+    match runtime.futures()[2].state() {
+        FutureState::Cancelled(e) => assert_eq!(e.message, "Cancelled: scope exited with an error"),
+        other => panic!("Expected cancelled future, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_scope_body_error_cancels_all_tasks() {
+    let mut runtime = Runtime::new();
+    assert!(tau::parse_tau("scope { spawn 1\nspawn 2\n1 / \"x\" }", &mut runtime).is_err());
+    assert!(runtime.futures().iter().all(|f| matches!(f.state(), FutureState::Cancelled(_))));
+    // Nested scopes join independently
+    let nested = rho_eval("scope { spawn 1; spawn scope { spawn 2 } }").unwrap();
+    assert_eq!(nested, Value::Array(vec![Value::Num(1.0), Value::Array(nums(&[2.0]).into())].into()));
+}
+
+// Cancellation and timeout tests
+#[test]
+fn test_cancel_future() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("f = async print(\"never\")", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("cancel f", &mut runtime).unwrap(), Value::Bool(true));
+    assert!(!runtime.has_task(&runtime.futures()[0]));
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap_err(), "Cancelled: by cancel\n  at task #1 `print(\"never\")` spawned at 1:5");
+    assert!(runtime.futures()[0].to_string().starts_with("<future #1 cancelled (Cancelled: by cancel)"));
+    // Settled futures keep their value
+    tau::parse_tau("g = async 2", &mut runtime).unwrap();
+    tau::parse_tau("await g", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("cancel g", &mut runtime).unwrap(), Value::Bool(false));
+    assert_eq!(tau::parse_tau("await g", &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("cancel 1", &mut runtime).unwrap_err(), "cancel expects a future, got Num(1.0)");
+}
+
+#[test]
+fn test_future_timeout() {
+    let mut runtime = Runtime::new();
+    // A runaway task is stopped once its time is up
+    tau::parse_tau("spin = async for i in 0..1000000000 { i }", &mut runtime).unwrap();
+    tau::parse_tau("timeout spin 50ms", &mut runtime).unwrap();
+    let err = tau::parse_tau("await spin", &mut runtime).unwrap_err();
+    assert!(err.starts_with("Cancelled: timed out after 50ms\n  at task #1"));
+    assert_eq!(runtime.futures()[0].state().name(), "cancelled");
+    // One not awaited in time is cancelled before it runs
+    tau::parse_tau("late = async 1", &mut runtime).unwrap();
+    tau::parse_tau("timeout late 0s", &mut runtime).unwrap();
+    assert_eq!(runtime.expire_futures(), 1);
+    assert!(!runtime.has_task(&runtime.futures()[1]));
+    // Quick tasks are unaffected
+    tau::parse_tau("quick = async 1 + 1", &mut runtime).unwrap();
+    tau::parse_tau("timeout quick 5s", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await quick", &mut runtime).unwrap(), Value::Num(2.0));
+    assert!(tau::parse_tau("timeout quick soon", &mut runtime).unwrap_err().starts_with("Invalid duration: soon"));
+}
+
+#[test]
+fn test_timeout_stops_waiting_for_a_socket() {
+    let mut server = Runtime::new();
+    tau::parse_tau("server = listen \"127.0.0.1:0\"", &mut server).unwrap();
+    let address = tau::parse_tau("socket_address(server)", &mut server).unwrap();
+    let mut client = Runtime::new();
+    client.set_variable("address".to_string(), address);
+    tau::parse_tau("sock = dial address", &mut client).unwrap();
+    tau::parse_tau("reply = recv sock", &mut client).unwrap();
+    tau::parse_tau("timeout reply 50ms", &mut client).unwrap();
+    assert!(tau::parse_tau("await reply", &mut client).unwrap_err().starts_with("Cancelled: timed out after 50ms"));
+}
+
+// Sleep tests
+#[test]
+fn test_sleep_future_resolves_after_its_duration() {
+    let mut runtime = Runtime::new();
+    let started = Instant::now();
+    tau::parse_tau("nap = sleep 60ms", &mut runtime).unwrap();
+    assert!(started.elapsed() < Duration::from_millis(50));
+    assert_eq!(runtime.futures()[0].state().name(), "pending");
+    assert_eq!(tau::parse_tau("await nap", &mut runtime).unwrap(), Value::Unit);
+    assert!(started.elapsed() >= Duration::from_millis(60));
+    // Sleeps settle on their own once due, without being awaited
+    tau::parse_tau("short = sleep 1", &mut runtime).unwrap();
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(runtime.expire_futures(), 1);
+    assert_eq!(runtime.futures()[1].state().name(), "resolved");
+    assert!(tau::parse_tau("sleep \"soon\"", &mut runtime).unwrap_err().starts_with("Invalid duration: soon"));
+}
+
+#[test]
+fn test_sleep_blocks_in_rho_within_the_timeout() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    let started = Instant::now();
+    assert_eq!(repl.eval("sleep(30)").unwrap(), Value::Unit);
+    assert!(started.elapsed() >= Duration::from_millis(30));
+    repl.set_timeout(Language::Rho, Some(Duration::from_millis(50)));
+    let started = Instant::now();
+    assert!(repl.eval("sleep(\"5s\")").unwrap_err().starts_with("Evaluation timed out after 50ms"));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(repl.eval("sleep(true)").unwrap_err().contains("sleep expects milliseconds"));
+}
+
+// Task error trace tests
+#[test]
+fn test_nested_task_failure_keeps_trace() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("f = async scope { spawn 1 / \"x\" }", &mut runtime).unwrap();
+    let err = tau::parse_tau("await f", &mut runtime).unwrap_err();
+    let error = TaskError::from_error(&err);
+    assert!(error.message.starts_with("Cannot divide"));
+    assert_eq!(error.trace, vec![
+        "task #2 `1 / \"x\"` spawned at 1:19".to_string(),
+        "task #1 `scope { spawn 1 / \"x\" }` spawned at 1:5".to_string(),
+    ]);
+    match runtime.futures()[1].state() {
+        FutureState::Rejected(e) => assert_eq!(e.trace.len(), 1),
+        other => panic!("Expected failed future, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_failed_future_display() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("f = async nope", &mut runtime).unwrap();
+    assert!(tau::parse_tau("await f", &mut runtime).is_err());
+    let listing = runtime.futures()[0].to_string();
+    assert!(listing.starts_with("<future #1 failed (Undefined variable: nope) \"nope\" at 1:5"));
+    assert_eq!(TaskError::new("plain").to_string(), "plain");
+}
+
+// Lambda and higher-order function tests
+#[test]
+fn test_map_filter_reduce() {
+    assert_eq!(rho_eval("map([1, 2, 3], fn(x) x * 2)").unwrap(), Value::Array(nums(&[2.0, 4.0, 6.0]).into()));
+    assert_eq!(rho_eval("filter([1, 2, 3, 4], fn(x) x % 2 == 0)").unwrap(), Value::Array(nums(&[2.0, 4.0]).into()));
+    assert_eq!(rho_eval("reduce([1, 2, 3, 4], 0, fn(acc, x) acc + x)").unwrap(), Value::Num(10.0));
+    assert_eq!(rho_eval("reduce([], 5, fn(acc, x) acc + x)").unwrap(), Value::Num(5.0));
+    assert!(rho_eval("map(1, fn(x) x)").is_err());
+    assert!(rho_eval("map([1], 2)").is_err());
+    assert!(rho_eval("map([1], fn(a, b) a)").is_err());
+}
+
+#[test]
+fn test_sort_reverse_unique_contains() {
+    let show = |code: &str| rho_eval(code).unwrap().to_string();
+    assert_eq!(show("sort([3, 1, 2])"), "[1, 2, 3]");
+    assert_eq!(show("sort([\"pear\", \"apple\", \"fig\"])"), r#"["apple", "fig", "pear"]"#);
+    assert_eq!(show("sort([[2], [1, 5], [1]])"), "[[1], [1, 5], [2]]");
+    assert_eq!(show("sort([3, 1, 2], fn(a, b) b - a)"), "[3, 2, 1]");
+    // Stable: equal keys keep their order
+    assert_eq!(show("sort([[1, \"b\"], [0, \"z\"], [1, \"a\"]], fn(a, b) a[0] < b[0])"), r#"[[0, "z"], [1, "b"], [1, "a"]]"#);
+    assert_eq!(show("sort([])"), "[]");
+    assert_eq!(rho_eval("sort([1, \"a\"])").unwrap_err(), "Cannot compare Str(\"a\") and Num(1.0)");
+    assert!(rho_eval("sort([1, 2], fn(a, b) \"x\")").unwrap_err().starts_with("sort comparator must give"));
+    assert_eq!(show("reverse([1, 2, 3])"), "[3, 2, 1]");
+    assert_eq!(show("unique([3, 1, 3, [1], 2, 1, [1]])"), "[3, 1, [1], 2]");
+    assert_eq!(show("contains([1, [2]], [2])"), "true");
+    assert_eq!(show("contains([1, 2], 3)"), "false");
+    assert_eq!(show("contains(\"haystack\", \"st\")"), "true");
+    assert!(rho_eval("reverse(1)").is_err());
+
+    // The same as Pi words
+    let mut runtime = Runtime::new();
+    let mut stack = Vec::new();
+    let mut pi = |code: &str| crate::pi::parse_pi(code, &mut runtime, &mut stack).unwrap().to_string();
+    assert_eq!(pi("[3, 1, 2] sort"), "[1, 2, 3]");
+    assert_eq!(pi("reverse"), "[3, 2, 1]");
+    assert_eq!(pi("[1, 1, 2] unique"), "[1, 2]");
+    assert_eq!(pi("2 contains"), "true");
+    assert_eq!(pi("[1, 3, 2] fn(a, b) a > b sort"), "[3, 2, 1]");
+}
+
+#[test]
+fn test_len_across_containers() {
+    assert_eq!(rho_eval("len([1, [2, 3], 4])").unwrap(), Value::Num(3.0));
+    assert_eq!(rho_eval("len([])").unwrap(), Value::Num(0.0));
+    assert_eq!(rho_eval("len([{\"a\", 1}, {\"b\", 2}])").unwrap(), Value::Num(2.0));
+    // Characters, not bytes
+    assert_eq!(rho_eval("len(\"héllo\")").unwrap(), Value::Num(5.0));
+    assert_eq!(rho_eval("len(5)").unwrap_err(), "len expects an array, map or string, got Num(5.0)");
+    assert!(rho_eval("len()").is_err());
+    let mut stack = Vec::new();
+    assert_eq!(crate::pi::parse_pi("[1, 2, 3] len", &mut Runtime::new(), &mut stack).unwrap(), Value::Num(3.0));
+}
+
+#[test]
+fn test_named_lambda_call_restores_parameters() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("x = 100", &mut runtime).unwrap();
+    tau::parse_tau("double = fn(x) { x * 2 }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("double(21)", &mut runtime).unwrap(), Value::Num(42.0));
+    assert_eq!(tau::parse_tau("map([1, 2], double)", &mut runtime).unwrap(), Value::Array(nums(&[2.0, 4.0]).into()));
+    assert_eq!(runtime.get_variable("x"), Some(&Value::Num(100.0)));
+    assert_eq!(format!("{:?}", runtime.get_variable("double").unwrap()), "Function(fn(x))");
+}
+
+#[test]
+fn test_parameters_are_local_to_the_call() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("id = fn(y) y", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("id(3)", &mut runtime).unwrap(), Value::Num(3.0));
+    assert_eq!(runtime.get_variable("y"), None);
+    // A function called from the body does not see the caller's parameters
+    tau::parse_tau("inner = fn() a", &mut runtime).unwrap();
+    tau::parse_tau("outer = fn(a) inner()", &mut runtime).unwrap();
+    assert!(tau::parse_tau("outer(1)", &mut runtime).is_err());
+    // A function made in the body keeps the parameters it uses
+    tau::parse_tau("adder = fn(n) fn(x) x + n", &mut runtime).unwrap();
+    tau::parse_tau("add2 = adder(2)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("add2(3)", &mut runtime).unwrap(), Value::Num(5.0));
+    assert_eq!(runtime.get_variable("n"), None);
+}
+
+#[test]
+fn test_return_exits_function_early() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("first_big = fn(xs) { for x in xs { x > 2 and return x }; -1 }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("first_big([1, 5, 9])", &mut runtime).unwrap(), Value::Num(5.0));
+    assert_eq!(tau::parse_tau("first_big([1, 2])", &mut runtime).unwrap(), Value::Num(-1.0));
+    assert_eq!(tau::parse_tau("map([[3], [0]], first_big)", &mut runtime).unwrap(), Value::Array(nums(&[3.0, -1.0]).into()));
+    tau::parse_tau("nothing = fn() { return; 1 }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("nothing()", &mut runtime).unwrap(), Value::Unit);
+    assert_eq!(rho_eval("return 1").unwrap_err(), "return outside a function");
+}
+
+// callcc tests
+#[test]
+fn test_callcc_escapes_loops() {
+    assert_eq!(rho_eval("callcc(fn(k) { for x in 1..100 { x == 5 and continue(k, x * 10) }; 0 })").unwrap(), Value::Num(50.0));
+    assert_eq!(rho_eval("callcc(fn(k) { while true { continue(k) } })").unwrap(), Value::Unit);
+    assert_eq!(rho_eval("callcc(fn(k) 7)").unwrap(), Value::Num(7.0));
+}
+
+#[test]
+fn test_callcc_escapes_through_builtins_and_nesting() {
+    let found = rho_eval("callcc(fn(k) map([1, 2, 3], fn(x) { x == 2 and continue(k, \"found\"); x }))");
+    assert_eq!(found.unwrap(), Value::Str("found".into()));
+    // The inner escape only leaves the inner callcc
+    assert_eq!(rho_eval("callcc(fn(outer) 1 + callcc(fn(inner) continue(inner, 10)))").unwrap(), Value::Num(11.0));
+    assert_eq!(rho_eval("callcc(fn(outer) 1 + callcc(fn(inner) continue(outer, 10)))").unwrap(), Value::Num(10.0));
+}
+
+#[test]
+fn test_callcc_continuation_after_exit() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("saved = callcc(fn(k) k)", &mut runtime).unwrap();
+    assert_eq!(format!("{:?}", runtime.get_variable("saved").unwrap()), "Continuation(Continuation::Escape(#1))");
+    assert_eq!(tau::parse_tau("continue(saved, 1)", &mut runtime).unwrap_err(), "Continuation #1 is no longer active");
+    assert!(tau::parse_tau("callcc(1)", &mut runtime).is_err());
+    assert!(tau::parse_tau("continue(1)", &mut runtime).is_err());
+}
+
+#[test]
+fn test_continuations_resume_in_their_own_language() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.set_language(Language::Pi), None);
+    repl.eval("\"1 2 +\" suspend").unwrap();
+    assert_eq!(repl.pi_stack(), &[]);
+    let warning = repl.set_language(Language::Rho).unwrap();
+    assert!(warning.starts_with("1 pending continuation (Pi, top last)"), "{}", warning);
+    repl.parse_tau("x = 4").unwrap();
+    repl.eval("suspend x * 10").unwrap();
+    repl.set_language(Language::Tau);
+    repl.eval("suspend y = await f").unwrap();
+    repl.eval("f = async 5").unwrap();
+    let warning = repl.set_language(Language::Rho).unwrap();
+    assert!(warning.starts_with("3 pending continuations (Pi, Rho, Tau, top last)"), "{}", warning);
+    // Each resumes through its own frontend, whatever the current language
+    assert_eq!(repl.eval("resume").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.eval("resume").unwrap(), Value::Num(40.0));
+    assert_eq!(repl.eval("resume").unwrap(), Value::Num(3.0));
+    assert_eq!(repl.eval("resume").unwrap(), Value::Unit);
+    assert_eq!(repl.set_language(Language::Pi), None);
+    // Pi's resume pushes the result onto the stack
+    repl.parse_rho("suspend [1, 2]").unwrap();
+    repl.eval("resume").unwrap();
+    assert_eq!(repl.pi_stack(), &[Value::Array(vec![Value::Num(1.0), Value::Num(2.0)].into())]);
+    assert!(repl.eval("5 suspend").is_err());
+}
+
+// Generator tests
+#[test]
+fn test_generator_yields_lazily() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("gen evens(n) { for x in 0..n { x % 2 == 0 and yield x } }", &mut runtime).unwrap();
+    tau::parse_tau("g = evens(5)", &mut runtime).unwrap();
+    assert_eq!(format!("{:?}", runtime.get_variable("g").unwrap()), "Generator(suspended)");
+    assert_eq!(tau::parse_tau("resume(g)", &mut runtime).unwrap(), Value::Num(0.0));
+    assert_eq!(tau::parse_tau("resume(g)", &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("resume(g)", &mut runtime).unwrap(), Value::Num(4.0));
+    assert_eq!(tau::parse_tau("resume(g)", &mut runtime).unwrap(), Value::Unit);
+    assert_eq!(format!("{:?}", runtime.get_variable("g").unwrap()), "Generator(done)");
+    // Parameters are only bound while the body runs
+    assert!(runtime.get_variable("n").is_none());
+}
+
+#[test]
+fn test_for_iterates_generator() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("gen squares(xs) { for x in xs { yield x * x }; yield -1 }", &mut runtime).unwrap();
+    tau::parse_tau("acc = [{\"sum\", 0}]", &mut runtime).unwrap();
+    tau::parse_tau("for s in squares([1, 2, 3]) { acc[\"sum\"] = acc[\"sum\"] + s }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("acc[\"sum\"]", &mut runtime).unwrap(), Value::Num(13.0));
+    // An endless generator is fine as long as the consumer stops
+    tau::parse_tau("gen naturals() for n in 0..1000000000 { yield n }", &mut runtime).unwrap();
+    let first = tau::parse_tau("callcc(fn(k) for n in naturals() { n > 3 and continue(k, n) })", &mut runtime);
+    assert_eq!(first.unwrap(), Value::Num(4.0));
+    // Generators nest, and return ends one early
+    tau::parse_tau("gen upto(g, limit) for x in g { x > limit and return; yield x }", &mut runtime).unwrap();
+    tau::parse_tau("h = upto(naturals(), 1)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("resume(h)", &mut runtime).unwrap(), Value::Num(0.0));
+    assert_eq!(tau::parse_tau("resume(h)", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("resume(h)", &mut runtime).unwrap(), Value::Unit);
+}
+
+#[test]
+fn test_generator_errors() {
+    assert_eq!(rho_eval("yield 1").unwrap_err(), "yield outside a generator");
+    assert_eq!(rho_eval("resume(1)").unwrap_err(), "resume expects 1 generator argument, got 1 argument (number) at 1:1");
+    assert_eq!(format!("{:?}", rho_eval("gen(a, b) yield a").unwrap()), "Function(gen(a, b))");
+    let mut runtime = Runtime::new();
+    tau::parse_tau("gen pair(a) yield a", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("pair()", &mut runtime).unwrap_err(), "gen(a) expects 1 arguments, got 0");
+}
+
+// Slicing and negative index tests
+#[test]
+fn test_negative_index() {
+    assert_eq!(rho_eval("[10, 20, 30][-1]").unwrap(), Value::Num(30.0));
+    assert_eq!(rho_eval("[10, 20, 30][-3]").unwrap(), Value::Num(10.0));
+    assert!(rho_eval("[10, 20, 30][-4]").is_err());
+    assert_eq!(rho_eval("\"hello\"[1]").unwrap(), Value::Str("e".into()));
+    assert_eq!(rho_eval("\"hello\"[-1]").unwrap(), Value::Str("o".into()));
+}
+
+#[test]
+fn test_array_slices() {
+    assert_eq!(rho_eval("[1, 2, 3, 4][1:3]").unwrap(), Value::Array(nums(&[2.0, 3.0]).into()));
+    assert_eq!(rho_eval("[1, 2, 3, 4][:2]").unwrap(), Value::Array(nums(&[1.0, 2.0]).into()));
+    assert_eq!(rho_eval("[1, 2, 3, 4][2:]").unwrap(), Value::Array(nums(&[3.0, 4.0]).into()));
+    assert_eq!(rho_eval("[1, 2, 3, 4][-2:]").unwrap(), Value::Array(nums(&[3.0, 4.0]).into()));
+    assert_eq!(rho_eval("[1, 2, 3, 4][:]").unwrap(), Value::Array(nums(&[1.0, 2.0, 3.0, 4.0]).into()));
+    // Out of range bounds clamp instead of failing
+    assert_eq!(rho_eval("[1, 2][1:10]").unwrap(), Value::Array(nums(&[2.0]).into()));
+    assert_eq!(rho_eval("[1, 2][2:1]").unwrap(), Value::Array(vec![].into()));
+}
+
+#[test]
+fn test_string_slices() {
+    assert_eq!(rho_eval("\"hello\"[1:3]").unwrap(), Value::Str("el".into()));
+    assert_eq!(rho_eval("\"héllo\"[:2]").unwrap(), Value::Str("hé".into()));
+    assert_eq!(rho_eval("\"hello\"[-3:]").unwrap(), Value::Str("llo".into()));
+    assert!(rho_eval("5[1:2]").is_err());
+}
+
+// Timeout tests
+#[test]
+fn test_parse_duration() {
+    use crate::config::{parse_duration, parse_timeout};
+    assert_eq!(parse_duration("2s").unwrap(), Duration::from_secs(2));
+    assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+    assert_eq!(parse_duration("1m").unwrap(), Duration::from_secs(60));
+    assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+    assert!(parse_duration("soon").is_err());
+    assert_eq!(parse_timeout("off").unwrap(), None);
+}
+
+#[test]
+fn test_timeout_aborts_runaway_loop() {
+    let mut repl = Repl::new();
+    repl.set_timeout(Language::Rho, Some(Duration::from_millis(50)));
+    let started = Instant::now();
+    let err = repl.parse_rho("while true { 1 }").unwrap_err();
+    assert_eq!(err, "Evaluation timed out after 50ms");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    // The limit is per language and starts afresh with each evaluation
+    assert_eq!(repl.parse_rho("1 + 1").unwrap(), Value::Num(2.0));
+    assert_eq!(repl.timeout(Language::Tau), None);
+}
+
+#[test]
+fn test_timeout_from_config_and_shell() {
+    let mut config = Config::new();
+    config.apply_str("timeout = 100ms").unwrap();
+    let mut repl = Repl::with_config(config);
+    assert_eq!(repl.timeout(Language::Pi), Some(Duration::from_millis(100)));
+    repl.set_timeout(Language::Pi, Some(Duration::from_millis(80)));
+    let started = Instant::now();
+    let err = repl.process_bash("`sleep 5`").unwrap_err();
+    assert!(err.starts_with("Command timed out after 80ms"));
+    assert!(started.elapsed() < Duration::from_secs(4));
+    repl.set_timeout(Language::Pi, None);
+    assert_eq!(repl.timeout(Language::Pi), Some(Duration::from_millis(100)));
+}
+
+// Hash map tests
+#[test]
+fn test_map_insert_remove_keep_order() {
+    let mut map = Map::new();
+    map.insert(Value::Str("b".into()), Value::Num(1.0)).unwrap();
+    map.insert(Value::Str("a".into()), Value::Num(2.0)).unwrap();
+    map.insert(Value::Num(3.0), Value::Num(3.0)).unwrap();
+    assert_eq!(map.insert(Value::Str("b".into()), Value::Num(9.0)).unwrap(), Some(Value::Num(1.0)));
+    assert_eq!(map.remove(&Value::Str("a".into())), Some(Value::Num(2.0)));
+    assert_eq!(map.keys().cloned().collect::<Vec<_>>(), vec![Value::Str("b".into()), Value::Num(3.0)]);
+    assert_eq!(map.get(&Value::Num(3.0)), Some(&Value::Num(3.0)));
+    // Ranges cannot be keys, even inside an array
+    let range = Value::Array(vec![Value::Range(Range { start: 0, end: 2 })].into());
+    assert!(map.insert(range.clone(), Value::Unit).is_err());
+    assert!(!map.contains_key(&range));
+}
+
+#[test]
+fn test_deep_equality() {
+    let checks = [
+        ("[1, [2, 3]] == [1, [2, 3]]", true),
+        ("[1, [2, 3]] == [1, [2, 4]]", false),
+        ("[1, 2] == [1, 2, 3]", false),
+        ("[] == []", true),
+        ("[{\"a\", 1}, {\"b\", [2]}] == [{\"b\", [2]}, {\"a\", 1}]", true),
+        ("[{\"a\", 1}] == [{\"a\", 2}]", false),
+        ("[{\"a\", 1}] == [{\"a\", 1}, {\"b\", 1}]", false),
+        ("color(1, 2, 3) == color(1, 2, 3)", true),
+        ("color(1, 2, 3) == color(1, 2, 3, 128)", false),
+        ("[color(1, 2, 3)] == [color(1, 2, 3)]", true),
+        ("1 == \"1\"", false),
+        ("[1, 2] != [1, 2]", false),
+    ];
+    for (code, expected) in checks {
+        assert_eq!(rho_eval(code).unwrap(), Value::Bool(expected), "{}", code);
+    }
+    assert_eq!(Value::Unit.equals(&Value::Unit).unwrap(), Value::Bool(true));
+
+    // Map keys match by the same equality, in any entry order
+    let mut runtime = Runtime::new();
+    tau::parse_tau("grid = [{[0, 1], \"a\"}, {[{\"x\", 1}, {\"y\", 2}], \"b\"}]", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("grid[[0, 1]]", &mut runtime).unwrap(), Value::Str("a".into()));
+    assert_eq!(tau::parse_tau("grid[[{\"y\", 2}, {\"x\", 1}]]", &mut runtime).unwrap(), Value::Str("b".into()));
+    tau::parse_tau("grid[[0, 1]] = \"c\"", &mut runtime).unwrap();
+    let Value::Array(keys) = tau::parse_tau("keys(grid)", &mut runtime).unwrap() else { panic!("expected the keys") };
+    assert_eq!(keys.len(), 2);
+    assert_eq!(tau::parse_tau("grid[[0, 1]]", &mut runtime).unwrap(), Value::Str("c".into()));
+}
+
+#[test]
+fn test_string_and_array_ordering() {
+    let checks = [
+        ("\"apple\" < \"banana\"", true),
+        ("\"apple\" > \"apples\"", false),
+        ("\"Z\" < \"a\"", true),
+        ("\"\" < \"a\"", true),
+        ("[1, 2] < [1, 3]", true),
+        ("[1] < [1, 0]", true),
+        ("[2] > [1, 9]", true),
+        ("[\"b\", [1]] > [\"b\", []]", true),
+        ("[1, 2] < [1, 2]", false),
+    ];
+    for (code, expected) in checks {
+        assert_eq!(rho_eval(code).unwrap(), Value::Bool(expected), "{}", code);
+    }
+    assert_eq!(rho_eval("\"1\" < 2").unwrap_err(), "Cannot compare Str(\"1\") and Num(2.0)");
+    assert!(rho_eval("[1] < [\"a\"]").is_err());
+    let (a, b) = (Value::Num(0.1 + 0.2), Value::Num(0.3));
+    assert_eq!(a.compare(&b).unwrap(), std::cmp::Ordering::Equal);
+    assert_eq!(Value::Num(f64::NAN).compare(&Value::Num(f64::INFINITY)).unwrap(), std::cmp::Ordering::Greater);
+    let mut stack = Vec::new();
+    assert_eq!(crate::pi::parse_pi("\"a\" \"b\" <", &mut Runtime::new(), &mut stack).unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn test_map_index_assignment_and_remove() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("m = [{\"x\", 1}]", &mut runtime).unwrap();
+    tau::parse_tau("m[\"y\"] = 2; m[\"x\"] = 10", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("keys(m)", &mut runtime).unwrap(), Value::Array(vec![Value::Str("x".into()), Value::Str("y".into())].into()));
+    assert_eq!(tau::parse_tau("values(m)", &mut runtime).unwrap(), Value::Array(nums(&[10.0, 2.0]).into()));
+    assert_eq!(tau::parse_tau("remove(m, \"x\")", &mut runtime).unwrap(), Value::Num(10.0));
+    assert_eq!(tau::parse_tau("remove(m, \"x\")", &mut runtime).unwrap(), Value::Unit);
+    assert_eq!(tau::parse_tau("values(m)", &mut runtime).unwrap(), Value::Array(nums(&[2.0]).into()));
+    assert!(tau::parse_tau("n[1] = 2", &mut runtime).is_err());
+    assert!(rho_eval("1 = 2").is_err());
+}
+
+#[test]
+fn test_map_order_and_sort_keys() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("m = [{\"b\", 1}, {\"c\", 2}, {\"a\", 3}]", &mut runtime).unwrap();
+    tau::parse_tau("m[\"b\"] = 10; remove(m, \"c\"); m[\"c\"] = 4; m[\"d\"] = 5", &mut runtime).unwrap();
+    // Insertion order everywhere: iteration, display, keys and JSON
+    let strs = |items: &[&str]| Value::Array(items.iter().map(|s| Value::Str((*s).into())).collect());
+    assert_eq!(tau::parse_tau("keys(m)", &mut runtime).unwrap(), strs(&["b", "a", "c", "d"]));
+    tau::parse_tau("order = []", &mut runtime).unwrap();
+    tau::parse_tau("for k, v in m { push(order, k) }", &mut runtime).unwrap();
+    assert_eq!(runtime.get_variable("order"), Some(&strs(&["b", "a", "c", "d"])));
+    assert_eq!(format!("{}", runtime.get_variable("m").unwrap()), "{\"b\": 10, \"a\": 3, \"c\": 4, \"d\": 5}");
+    assert_eq!(tau::parse_tau("to_json(m)", &mut runtime).unwrap(), Value::Str("{\"b\":10,\"a\":3,\"c\":4,\"d\":5}".into()));
+    // sort_keys gives a sorted copy and leaves the map as it was
+    assert_eq!(tau::parse_tau("keys(sort_keys(m))", &mut runtime).unwrap(), strs(&["a", "b", "c", "d"]));
+    assert_eq!(tau::parse_tau("values(sort_keys(m))", &mut runtime).unwrap(), Value::Array(nums(&[3.0, 10.0, 4.0, 5.0]).into()));
+    assert_eq!(tau::parse_tau("keys(m)", &mut runtime).unwrap(), strs(&["b", "a", "c", "d"]));
+    assert_eq!(rho_eval("keys(sort_keys([{10, 0}, {-1, 0}, {2, 0}]))").unwrap(), Value::Array(nums(&[-1.0, 2.0, 10.0]).into()));
+    assert_eq!(rho_eval("sort_keys([{1, 0}, {\"a\", 0}])").unwrap_err(), "Cannot compare Str(\"a\") and Num(1.0)");
+    assert!(rho_eval("sort_keys([1])").is_err());
+}
+
+#[test]
+fn test_array_mutation() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("xs = [1, 2, 3]", &mut runtime).unwrap();
+    tau::parse_tau("xs[0] = 10; xs[-1] = 30", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("push(xs, 4, 5)", &mut runtime).unwrap(), Value::Num(5.0));
+    assert_eq!(tau::parse_tau("pop(xs)", &mut runtime).unwrap(), Value::Num(5.0));
+    tau::parse_tau("insert(xs, 0, 0); insert(xs, 5, 9)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("remove(xs, -2)", &mut runtime).unwrap(), Value::Num(4.0));
+    assert_eq!(runtime.get_variable("xs"), Some(&Value::Array(nums(&[0.0, 10.0, 2.0, 30.0, 9.0]).into())));
+    assert_eq!(tau::parse_tau("xs[5] = 1", &mut runtime).unwrap_err(), "Index 5 out of bounds");
+    assert!(tau::parse_tau("insert(xs, 7, 1)", &mut runtime).is_err());
+    assert!(tau::parse_tau("remove(xs, \"a\")", &mut runtime).is_err());
+
+    // Arrays are values: assignment copies, and only the named variable changes
+    tau::parse_tau("ys = xs", &mut runtime).unwrap();
+    tau::parse_tau("push(ys, 1)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("len(xs)", &mut runtime).unwrap(), Value::Num(5.0));
+    assert_eq!(tau::parse_tau("pop([1, 2])", &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("pop([])", &mut runtime).unwrap(), Value::Unit);
+    // A function gets a copy; it can push onto its parameter and return it
+    tau::parse_tau("grow = fn(a) { push(a, 0); a }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("len(grow(xs))", &mut runtime).unwrap(), Value::Num(6.0));
+    assert_eq!(tau::parse_tau("len(xs)", &mut runtime).unwrap(), Value::Num(5.0));
+}
+
+#[test]
+fn test_copies_share_until_changed() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("m = [{\"a\", 1}]", &mut runtime).unwrap();
+    tau::parse_tau("n = m", &mut runtime).unwrap();
+    tau::parse_tau("n[\"a\"] = 2", &mut runtime).unwrap();
+    tau::parse_tau("remove(n, \"a\")", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("m[\"a\"]", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("len(n)", &mut runtime).unwrap(), Value::Num(0.0));
+
+    // A changed clone leaves the original alone, and an unchanged one is equal
+    let original = Value::Array(nums(&[1.0, 2.0]).into());
+    let Value::Array(mut copy) = original.clone() else { unreachable!() };
+    assert_eq!(Value::Array(copy.clone()), original);
+    copy.make_mut().push(Value::Num(3.0));
+    assert_eq!(original, Value::Array(nums(&[1.0, 2.0]).into()));
+    assert_eq!(copy.into_vec(), nums(&[1.0, 2.0, 3.0]));
+    let text = Value::Str("shared".into());
+    assert_eq!(text.clone(), text);
+}
+
+#[test]
+fn test_for_over_map() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("m = [{\"a\", 1}, {\"b\", 2}]", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("for k in m { k }", &mut runtime).unwrap(), Value::Str("b".into()));
+    assert_eq!(tau::parse_tau("for k, v in m { v * 10 }", &mut runtime).unwrap(), Value::Num(20.0));
+    assert_eq!(runtime.get_variable("k"), Some(&Value::Str("b".into())));
+    assert!(tau::parse_tau("for k, v in [1] { k }", &mut runtime).is_err());
+}
+
+// Range tests
+#[test]
+fn test_range_literals() {
+    assert_eq!(rho_eval("1..4").unwrap(), Value::Range(Range { start: 1, end: 4 }));
+    assert_eq!(rho_eval("1..=4").unwrap(), Value::Range(Range { start: 1, end: 5 }));
+    assert_eq!(rho_eval("0..2 + 1").unwrap(), Value::Range(Range { start: 0, end: 3 }));
+    assert_eq!(rho_eval("(10..20)[-1]").unwrap(), Value::Num(19.0));
+    assert!(rho_eval("1..2.5").is_err());
+    assert!(rho_eval("0..=1e19").unwrap_err().contains("out of bounds"));
+    assert!(rho_eval("-1e19..0").unwrap_err().contains("out of bounds"));
+    assert_eq!(rho_eval("(-9e18..9e18)[0]").unwrap(), Value::Num(-9e18));
+    assert_eq!(format!("{:?}", rho_eval("1..=3").unwrap()), "Range(1..4)");
+    let kinds: Vec<TokenKind> = tokenize("1..=2").unwrap().into_iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec![TokenKind::Num(1.0), TokenKind::DotDotEq, TokenKind::Num(2.0)]);
+}
+
+#[test]
+fn test_for_over_range() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("m = [{\"sum\", 0}]", &mut runtime).unwrap();
+    tau::parse_tau("for i in 1..=100 { m[\"sum\"] = m[\"sum\"] + i }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("m[\"sum\"]", &mut runtime).unwrap(), Value::Num(5050.0));
+    assert_eq!(rho_eval("for i in 1..4 { i * 10 }").unwrap(), Value::Num(30.0));
+    assert_eq!(rho_eval("for i in 5..5 { i }").unwrap(), Value::Unit);
+}
+
+// Tau interface parser tests
+#[test]
+fn test_parse_interface() {
+    let src = "# calculator\ninterface Calculator {\n    add(a: num, b: num) -> num\n    history() -> [num]\n    reset()\n    event overflow(value: num)\n}\n";
+    let iface = tau::parse_interface(src).unwrap();
+    assert_eq!(iface.name, "Calculator");
+    assert_eq!(iface.methods.len(), 3);
+    assert_eq!(iface.method("add").unwrap().returns, tau::Type::Num);
+    assert_eq!(iface.method("history").unwrap().returns, tau::Type::Array(Box::new(tau::Type::Num)));
+    assert_eq!(iface.method("reset").unwrap().returns, tau::Type::Unit);
+    assert_eq!(iface.event("overflow").unwrap().params[0].name, "value");
+    // Display is the canonical source and parses back to the same interface
+    assert_eq!(iface.to_string(), src.trim_start_matches("# calculator\n"));
+    assert_eq!(tau::parse_interface(&iface.to_string()).unwrap(), iface);
+}
+
+#[test]
+fn test_parse_interface_errors() {
+    let err = |src: &str| tau::parse_interface(src).unwrap_err();
+    assert_eq!(err("interface A {\n  f(x: nmu)\n}"), "Unknown type 'nmu' (expected num, str, bool, color, map, any, unit or [T]) at 2:8");
+    assert_eq!(err("interface A {\n  f()\n  f()\n}"), "Duplicate member 'f' at 3:3");
+    assert_eq!(err("interface A {\n  f(x: num, x: str)\n}"), "Duplicate parameter 'x' at 2:13");
+    assert_eq!(err("interface A {\n  f(x num)\n}"), "Expected : at 2:7, found num");
+    assert_eq!(err("class A {}"), "Expected interface at 1:1, found class");
+    assert_eq!(err("interface A {\n  f()"), "Expected method, event or }, found end of input");
+    assert!(err("interface A {}\ninterface B {}").starts_with("Only one interface"));
+    assert_eq!(err("interface A {\n  f(x: unit)\n}"), "Parameter 'x' cannot be unit at 2:8");
+}
+
+#[test]
+fn test_interface_checks_return_values() {
+    let iface = tau::parse_interface("interface Calculator {\n  add(a: num, b: num) -> num\n  reset()\n}").unwrap();
+    assert_eq!(iface.check_return("add", Value::Num(3.0)), Ok(Value::Num(3.0)));
+    assert_eq!(iface.check_return("add", Value::Str("3".into())).unwrap_err(), "Calculator.add must return num, got Str(\"3\")");
+    // Whatever a unit method's implementation returns, callers get unit
+    assert_eq!(iface.check_return("reset", Value::Num(0.0)), Ok(Value::Unit));
+    assert_eq!(iface.check_return("mul", Value::Unit).unwrap_err(), "Calculator has no method mul");
+}
+
+// Mock agent tests
+fn mock_workspace(name: &str) -> Config {
+    let config = Config::with_temp_workspace(name).unwrap();
+    let src = "interface Calculator {\n    add(a: num, b: num) -> num\n    name() -> str\n    reset()\n}\n";
+    std::fs::write(config.in_workspace("calc.tsu"), src).unwrap();
+    config
+}
+
+#[test]
+fn test_mock_returns_canned_values() {
+    let config = mock_workspace("mock");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("calc = mock \"calc.tsu\"").unwrap();
+    assert_eq!(repl.parse_tau("call(calc, \"add\", 1, 2)").unwrap(), Value::Num(0.0));
+    assert_eq!(repl.parse_tau("call(calc, \"name\")").unwrap(), Value::Str("".into()));
+    repl.parse_tau("mock_return(calc, \"add\", 42)").unwrap();
+    assert_eq!(repl.parse_tau("call(calc, \"add\", 3, 4)").unwrap(), Value::Num(42.0));
+    assert_eq!(
+        repl.parse_tau("mock_calls(calc, \"add\")").unwrap(),
+        Value::Array(vec![Value::Array(nums(&[1.0, 2.0]).into()), Value::Array(nums(&[3.0, 4.0]).into())].into())
+    );
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_mock_validates_against_interface() {
+    let config = mock_workspace("mock-check");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("calc = mock calc.tsu").unwrap();
+    assert_eq!(repl.parse_tau("call(calc, \"mul\", 1, 2)").unwrap_err(), "Calculator has no method mul");
+    assert_eq!(repl.parse_tau("call(calc, \"add\", 1)").unwrap_err(), "Calculator.add expects 2 arguments, got 1");
+    assert!(repl.parse_tau("call(calc, \"add\", 1, \"2\")").unwrap_err().contains("b must be num"));
+    assert!(repl.parse_tau("mock_return(calc, \"add\", \"x\")").is_err());
+    assert!(repl.parse_tau("mock \"missing.tsu\"").is_err());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_mock_latency() {
+    let config = mock_workspace("mock-latency");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("calc = mock \"calc.tsu\"").unwrap();
+    repl.parse_tau("mock_latency(calc, \"reset\", 30)").unwrap();
+    let started = Instant::now();
+    assert_eq!(repl.parse_tau("call(calc, \"reset\")").unwrap(), Value::Unit);
+    assert!(started.elapsed() >= Duration::from_millis(30));
+    assert_eq!(format!("{:?}", repl.parse_tau("calc").unwrap()), "Mock(Calculator)");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Transport tests
+#[test]
+fn test_proxy_calls_agent_over_loopback() {
+    let config = mock_workspace("loopback");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("calc = mock \"calc.tsu\"").unwrap();
+    repl.parse_tau("mock_return(calc, \"add\", 7)").unwrap();
+    repl.parse_tau("link = connect(calc)").unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"add\", 3, 4)").unwrap(), Value::Num(7.0));
+    assert_eq!(repl.parse_tau("mock_calls(calc, \"add\")").unwrap(), Value::Array(vec![Value::Array(nums(&[3.0, 4.0]).into())].into()));
+    assert_eq!(format!("{:?}", repl.parse_tau("link").unwrap()), "Proxy(Calculator via loopback)");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_proxy_rejects_bad_calls_before_sending() {
+    let config = mock_workspace("loopback-check");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("calc = mock \"calc.tsu\"").unwrap();
+    repl.parse_tau("link = connect(calc)").unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"add\", 1)").unwrap_err(), "Calculator.add expects 2 arguments, got 1");
+    assert_eq!(repl.parse_tau("call(link, \"mul\", 1, 2)").unwrap_err(), "Calculator has no method mul");
+    assert_eq!(repl.parse_tau("mock_calls(calc, \"add\")").unwrap(), Value::Array(vec![].into()));
+    assert!(repl.parse_tau("connect(1)").is_err());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_loopback_transport_replies_in_order() {
+    use crate::transport::{Loopback, Message, Transport};
+    let interface = tau::parse_interface("interface Echo {\n    ping() -> str\n}").unwrap();
+    let agent = crate::mock::Mock::new(interface);
+    agent.borrow_mut().set_return("ping", Value::Str("pong".into())).unwrap();
+    let mut transport = Loopback::new(Box::new(agent));
+    for id in 1..=2 {
+        transport.send(Message::Call { id, method: "ping".to_string(), args: vec![] }).unwrap();
+    }
+    assert_eq!(transport.recv().unwrap(), Message::Reply { id: 1, result: Ok(Value::Str("pong".into())) });
+    assert_eq!(transport.recv().unwrap(), Message::Reply { id: 2, result: Ok(Value::Str("pong".into())) });
+    assert!(transport.recv().is_err());
+}
+
+#[test]
+fn test_wire_values_round_trip() {
+    use crate::transport::{decode_message, decode_value, encode_message, encode_value, Message};
+    let map = Map::from_pairs(vec![(Value::Str("k".into()), Value::Array(nums(&[1.0, -2.5]).into()))]).unwrap();
+    let values = vec![
+        Value::Num(-3.0),
+        Value::Str("say \"hi\"\n\\".into()),
+        Value::Bool(true),
+        Value::Unit,
+        Value::Color(Color::new(1, 2, 3)),
+        Value::Range(Range { start: 1, end: 4 }),
+        Value::Map(map),
+        Value::Map(Map::new()),
+    ];
+    for value in values {
+        assert_eq!(decode_value(&encode_value(&value).unwrap()).unwrap(), value);
+    }
+    let call = Message::Call { id: 3, method: "add".to_string(), args: nums(&[1.0, 2.0]) };
+    assert_eq!(encode_message(&call).unwrap(), "call 3 add [1, 2]");
+    assert_eq!(decode_message("call 3 add [1, 2]").unwrap(), call);
+    assert_eq!(decode_message("reply 3 err \"no\"").unwrap(), Message::Reply { id: 3, result: Err("no".to_string()) });
+    assert!(encode_value(&Value::Num(f64::NAN)).is_err());
+    // Peers only ever send literals; nothing they send is evaluated
+    assert!(decode_value("1 + 2").is_err());
+    assert!(decode_value("`touch /tmp/x`").is_err());
+    assert!(decode_message("reply x ok 1").is_err());
+}
+
+#[test]
+fn test_proxy_over_child_stdio() {
+    let config = mock_workspace("stdio");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    // The child answers one call and exits; later calls respawn it
+    repl.parse_tau("link = connect(\"calc.tsu\", \"stdio:read kind id rest; echo reply $id ok 42\")").unwrap();
+    repl.parse_tau("reconnect(link, 1, 0)").unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"add\", 1, 2)").unwrap(), Value::Num(42.0));
+    assert_eq!(repl.parse_tau("call(link, \"add\", 3, 4)").unwrap(), Value::Num(42.0));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_proxy_over_unix_socket() {
+    use crate::transport::{serve, unix_transport};
+    use std::os::unix::net::UnixListener;
+    let config = mock_workspace("unix");
+    let root = config.workspace.clone();
+    let socket = root.join("calc.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let src = std::fs::read_to_string(root.join("calc.tsu")).unwrap();
+    let server = std::thread::spawn(move || {
+        let mut agent = crate::mock::MockAgent::new(tau::parse_interface(&src).unwrap());
+        agent.set_return("add", Value::Num(5.0)).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut transport = unix_transport("agent", stream).unwrap();
+        serve(&mut transport, &mut agent).unwrap_err()
+    });
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau(&format!("link = connect(\"calc.tsu\", \"unix:{}\")", socket.display())).unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"add\", 2, 3)").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.parse_tau("call(link, \"name\")").unwrap(), Value::Str("".into()));
+    repl.parse_tau("link = 0").unwrap();
+    assert_eq!(server.join().unwrap(), "agent closed the connection");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_connect_checks_capabilities() {
+    let mut config = mock_workspace("connect-caps");
+    let root = config.workspace.clone();
+    config.capabilities.revoke(Capability::Shell);
+    let mut repl = Repl::with_config(config);
+    let err = repl.parse_tau("connect(\"calc.tsu\", \"stdio:cat\")").unwrap_err();
+    assert!(err.starts_with("Capability denied: shell"));
+    assert!(repl.parse_tau("connect(\"calc.tsu\", \"udp:1\")").unwrap_err().starts_with("Unknown transport address"));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Connection health tests
+#[test]
+fn test_ping_and_backoff() {
+    use crate::transport::Reconnect;
+    let config = mock_workspace("ping");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau("calc = mock \"calc.tsu\"").unwrap();
+    repl.parse_tau("link = connect(calc)").unwrap();
+    assert!(matches!(repl.parse_tau("ping(link)").unwrap(), Value::Num(ms) if ms >= 0.0));
+    let policy = Reconnect { attempts: 5, base: Duration::from_millis(100), max: Duration::from_millis(500) };
+    let delays: Vec<u128> = (0..5).map(|n| policy.delay(n).as_millis()).collect();
+    assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_proxy_reconnects_and_reports_disconnect() {
+    use crate::transport::{dispatch, unix_transport, Transport};
+    use std::os::unix::net::UnixListener;
+    let config = mock_workspace("reconnect");
+    let root = config.workspace.clone();
+    let socket = root.join("calc.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let src = std::fs::read_to_string(root.join("calc.tsu")).unwrap();
+    // Each connection answers a single message, then drops
+    let server = std::thread::spawn(move || {
+        let mut agent = crate::mock::MockAgent::new(tau::parse_interface(&src).unwrap());
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut transport = unix_transport("agent", stream).unwrap();
+            let reply = dispatch(&mut agent, transport.recv().unwrap()).unwrap();
+            transport.send(reply).unwrap();
+        }
+        agent.calls("add").len()
+    });
+    let mut repl = Repl::with_config(config);
+    repl.parse_tau(&format!("link = connect(\"calc.tsu\", \"unix:{}\")", socket.display())).unwrap();
+    repl.parse_tau("reconnect(link, 3, 10)").unwrap();
+    repl.parse_tau("log = [{\"drops\", 0}]").unwrap();
+    repl.parse_tau("on_disconnect(link, fn(reason) { log[\"drops\"] = log[\"drops\"] + 1; log[\"why\"] = reason })").unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"add\", 1, 2)").unwrap(), Value::Num(0.0));
+    assert_eq!(repl.parse_tau("log[\"drops\"]").unwrap(), Value::Num(0.0));
+    // The first connection is gone: the call reconnects and is sent again
+    assert_eq!(repl.parse_tau("call(link, \"add\", 3, 4)").unwrap(), Value::Num(0.0));
+    assert_eq!(repl.parse_tau("log[\"drops\"]").unwrap(), Value::Num(1.0));
+    assert!(matches!(repl.parse_tau("log[\"why\"]").unwrap(), Value::Str(why) if why.contains("calc.sock")));
+    assert_eq!(server.join().unwrap(), 2);
+    // The listener is gone, so the next call gives up after its attempts
+    let err = repl.parse_tau("call(link, \"add\", 5, 6)").unwrap_err();
+    assert!(err.ends_with("(gave up after 3 reconnect attempts)"), "{}", err);
+    assert!(format!("{:?}", repl.parse_tau("link").unwrap()).ends_with(", disconnected)"));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Compression tests
+#[test]
+fn test_compress_round_trip() {
+    use crate::compress::{compress, decompress, from_base64, to_base64};
+    let repetitive = "[1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3, 1, 2, 3]".repeat(20);
+    let mixed: Vec<u8> = (0..5000u32).map(|i| (i * 7919 % 251) as u8).collect();
+    let inputs: Vec<&[u8]> = vec![b"", b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaa", repetitive.as_bytes(), &mixed];
+    for input in inputs {
+        let packed = compress(input);
+        assert_eq!(decompress(&packed).unwrap(), input);
+        assert_eq!(from_base64(&to_base64(&packed)).unwrap(), packed);
+    }
+    assert!(compress(repetitive.as_bytes()).len() < repetitive.len() / 10);
+    assert_eq!(to_base64(b"hi!?"), "aGkhPw==");
+    assert!(decompress(&[0x80, 5, 0]).is_err());
+    assert!(decompress(&[3, b'a']).is_err());
+    assert!(from_base64("a*b").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_compression_negotiated_at_connect() {
+    use crate::transport::{serve, unix_transport};
+    use std::os::unix::net::UnixListener;
+    let config = mock_workspace("compress");
+    let root = config.workspace.clone();
+    let socket = root.join("calc.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let src = std::fs::read_to_string(root.join("calc.tsu")).unwrap().replace("name() -> str", "name() -> [num]");
+    std::fs::write(root.join("calc.tsu"), &src).unwrap();
+    let server = std::thread::spawn(move || {
+        let mut agent = crate::mock::MockAgent::new(tau::parse_interface(&src).unwrap());
+        agent.set_return("name", Value::Array(vec![Value::Num(7.0); 500].into())).unwrap();
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut transport = unix_transport("agent", stream).unwrap();
+            let _ = serve(&mut transport, &mut agent);
+        }
+    });
+    let mut repl = Repl::with_config(config);
+    let address = format!("unix:{}", socket.display());
+    let big = Value::Array(vec![Value::Num(7.0); 500].into());
+    // The agent serves one connection at a time, so each is closed before the next
+    repl.parse_tau(&format!("link = connect(\"calc.tsu\", \"{}\")", address)).unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"name\")").unwrap(), big);
+    assert_eq!(repl.parse_tau("wire_stats(link)[\"saved\"]").unwrap(), Value::Num(0.0));
+    repl.parse_tau("link = 0").unwrap();
+    repl.parse_tau(&format!("link = connect(\"calc.tsu\", \"{}\", \"compress\")", address)).unwrap();
+    assert_eq!(repl.parse_tau("call(link, \"name\")").unwrap(), big);
+    assert!(matches!(repl.parse_tau("wire_stats(link)[\"saved\"]").unwrap(), Value::Num(n) if n > 1000.0));
+    repl.parse_tau("link = 0").unwrap();
+    server.join().unwrap();
+    assert!(repl.parse_tau(&format!("connect(\"calc.tsu\", \"{}\", \"zip\")", address)).unwrap_err().starts_with("Unknown connect option"));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Archive tests
+#[test]
+fn test_deflate_round_trip() {
+    use crate::deflate::{crc32, deflate, inflate};
+    let repetitive = "packaging scripts, ".repeat(500);
+    let mixed: Vec<u8> = (0..70000u32).map(|i| (i * 7919 % 251) as u8).collect();
+    let inputs: Vec<&[u8]> = vec![b"", b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaa", repetitive.as_bytes(), &mixed];
+    for input in inputs {
+        assert_eq!(inflate(&deflate(input), input.len()).unwrap(), input);
+    }
+    assert!(deflate(repetitive.as_bytes()).len() < repetitive.len() / 20);
+    // A stored block, as other tools write for incompressible data
+    assert_eq!(inflate(&[1, 3, 0, 0xfc, 0xff, b'a', b'b', b'c'], 3).unwrap(), b"abc");
+    assert!(inflate(&deflate(repetitive.as_bytes()), 100).unwrap_err().contains("expands past 100 bytes"));
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+}
+
+#[test]
+fn test_zip_and_tar_archives() {
+    let config = Config::with_temp_workspace("archive").unwrap();
+    let root = config.workspace.clone();
+    std::fs::create_dir_all(root.join("site/css")).unwrap();
+    std::fs::write(root.join("site/index.html"), "<h1>hi</h1>\n".repeat(100)).unwrap();
+    std::fs::write(root.join("site/css/main.css"), "body {}").unwrap();
+    std::fs::write(root.join("notes.txt"), "").unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.set_language(Language::Rho);
+    for archive in ["out.zip", "out.tar.gz", "out.tar"] {
+        let kind = &archive[4..7];
+        let create = format!("{}_create(\"{}\", [\"site\", \"notes.txt\"])", kind, archive);
+        assert_eq!(repl.eval(&create).unwrap(), Value::Num(3.0));
+        let listed = repl.eval(&format!("{}_list(\"{}\")[0][\"name\"]", kind, archive)).unwrap();
+        assert_eq!(listed, Value::Str("site/css/main.css".into()));
+        let dest = format!("unpacked-{}", archive);
+        let written = repl.eval(&format!("{}_extract(\"{}\", \"{}\")", kind, archive, dest)).unwrap();
+        assert_eq!(written.to_string(), r#"["site/css/main.css", "site/index.html", "notes.txt"]"#);
+        let html = std::fs::read_to_string(root.join(&dest).join("site/index.html")).unwrap();
+        assert_eq!(html, "<h1>hi</h1>\n".repeat(100));
+    }
+    let gzipped = std::fs::read(root.join("out.tar.gz")).unwrap();
+    assert_eq!(&gzipped[..2], &[0x1f, 0x8b]);
+
+    // Names outside the destination are refused, both ways
+    assert!(repl.eval("zip_create(\"up.zip\", [\"../etc\"])").unwrap_err().contains("inside the workspace"));
+    let mut tar = std::fs::read(root.join("out.tar")).unwrap();
+    tar[..100].fill(0);
+    tar[..10].copy_from_slice(b"../evil.cs");
+    tar[148..156].fill(b' ');
+    let checksum: u32 = tar[..512].iter().map(|&b| b as u32).sum();
+    tar[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    std::fs::write(root.join("evil.tar"), tar).unwrap();
+    assert!(repl.eval("tar_extract(\"evil.tar\", \"x\")").unwrap_err().starts_with("Refusing archive entry outside the destination"));
+    assert!(!root.join("evil.cs").exists() && !root.join("x").exists());
+
+    let mut repl = Repl::with_capabilities(Capabilities::none());
+    repl.set_language(Language::Rho);
+    assert!(repl.eval("zip_list(\"out.zip\")").unwrap_err().contains("fs-read"));
+}
+
+// Glob and path tests
+#[test]
+fn test_glob_matches_paths_in_the_workspace() {
+    let config = Config::with_temp_workspace("glob").unwrap();
+    let root = config.workspace.clone();
+    for file in ["src/lib.rs", "src/net/tcp.rs", "src/net/.hidden.rs", "src/notes.md", "Cargo.toml", "a1.txt", "b2.txt"] {
+        std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+        std::fs::write(root.join(file), "").unwrap();
+    }
+    let mut repl = Repl::with_config(config);
+    repl.set_language(Language::Rho);
+    let glob = |repl: &mut Repl, pattern: &str| repl.eval(&format!("glob(\"{}\")", pattern)).unwrap().to_string();
+    assert_eq!(glob(&mut repl, "src/**/*.rs"), r#"["src/lib.rs", "src/net/tcp.rs"]"#);
+    assert_eq!(glob(&mut repl, "src/*"), r#"["src/lib.rs", "src/net", "src/notes.md"]"#);
+    assert_eq!(glob(&mut repl, "**/.*.rs"), r#"["src/net/.hidden.rs"]"#);
+    assert_eq!(glob(&mut repl, "[!b]?.txt"), r#"["a1.txt"]"#);
+    assert_eq!(glob(&mut repl, "*.{toml,md}"), r#"["Cargo.toml"]"#);
+    assert_eq!(glob(&mut repl, "src/{lib.rs,net/tcp.rs,missing.rs}"), r#"["src/lib.rs", "src/net/tcp.rs"]"#);
+    assert_eq!(glob(&mut repl, "*.nothing"), "[]");
+    assert!(repl.eval("glob(\"src/{lib\")").unwrap_err().contains("Unclosed"));
+
+    let canonical = repl.eval("canonicalize(\"src/net/../lib.rs\")").unwrap();
+    assert_eq!(canonical, Value::Str(root.canonicalize().unwrap().join("src/lib.rs").to_string_lossy().into()));
+    assert!(repl.eval("canonicalize(\"missing\")").is_err());
+
+    let mut repl = Repl::with_capabilities(Capabilities::none());
+    repl.set_language(Language::Rho);
+    assert!(repl.eval("glob(\"*\")").unwrap_err().contains("fs-read"));
+}
+
+#[test]
+fn test_path_builtins() {
+    let checks = [
+        ("path_join(\"src\", \"net\", \"tcp.rs\")", "src/net/tcp.rs"),
+        ("path_join(\"src\", \"/etc\")", "/etc"),
+        ("basename(\"src/net/tcp.rs\")", "tcp.rs"),
+        ("basename(\"src/net/\")", "net"),
+        ("dirname(\"src/net/tcp.rs\")", "src/net"),
+        ("dirname(\"tcp.rs\")", ""),
+        ("ext(\"archive.tar.gz\")", "gz"),
+        ("ext(\".bashrc\")", ""),
+    ];
+    for (code, expected) in checks {
+        assert_eq!(rho_eval(code).unwrap(), Value::Str(expected.into()), "{}", code);
+    }
+    assert!(rho_eval("basename(1)").is_err());
+}
+
+// Binary wire format tests
+#[test]
+fn test_binary_wire_round_trip() {
+    use crate::wire::{decode, encode};
+    let map = Map::from_pairs(vec![
+        (Value::Str("b".into()), Value::Array(vec![Value::Unit, Value::Bool(true)].into())),
+        (Value::Num(1.0), Value::Color(Color::new(255, 128, 0))),
+    ])
+    .unwrap();
+    let values = vec![
+        Value::Unit,
+        Value::None,
+        Value::Bool(false),
+        Value::Num(-2.5),
+        Value::Num(1e300),
+        Value::Str("héllo\n\"x\"".into()),
+        Value::Str("".into()),
+        Value::Color(Color::new(1, 2, 3)),
+        Value::Range(Range { start: -3, end: 10 }),
+        Value::Array(vec![].into()),
+        Value::Array(vec![Value::Array(vec![Value::Num(1.0)].into()), Value::Map(map.clone())].into()),
+        Value::Map(Map::new()),
+        Value::Map(map),
+        Value::Struct(Struct { name: "P".into(), fields: Rc::new(vec![("x".to_string(), Value::Num(1.0))]), enumeration: None }),
+        Value::Struct(Struct { name: "None".into(), fields: Rc::new(Vec::new()), enumeration: Some("Option".into()) }),
+    ];
+    for value in values {
+        let bytes = encode(&value).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), value, "{:?}", bytes);
+    }
+    // Version, tag and little-endian length lead every value
+    assert_eq!(encode(&Value::Bool(true)).unwrap(), vec![1, 1, 1, 0, 0, 0, 1]);
+    assert_eq!(encode(&Value::Array(vec![Value::Unit].into())).unwrap(), vec![1, 6, 5, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(encode(&Value::Future(Future::new(1, "x"))).unwrap_err().starts_with("Cannot send"));
+}
+
+#[test]
+fn test_binary_wire_rejects_malformed_input() {
+    use crate::wire::decode;
+    assert_eq!(decode(&[]).unwrap_err(), "Empty wire data");
+    assert_eq!(decode(&[2, 0, 0, 0, 0, 0]).unwrap_err(), "Unsupported wire version 2 (expected 1)");
+    assert_eq!(decode(&[1, 2, 8, 0, 0, 0, 0]).unwrap_err(), "Truncated wire value at byte 6");
+    assert_eq!(decode(&[1, 10, 0, 0, 0, 0]).unwrap_err(), "Unknown wire tag 10 at byte 1");
+    assert_eq!(decode(&[1, 1, 1, 0, 0, 0, 2]).unwrap_err(), "Malformed wire value with tag 1 at byte 1");
+    assert_eq!(decode(&[1, 0, 0, 0, 0, 0, 0]).unwrap_err(), "Trailing bytes after wire value at byte 6");
+    assert_eq!(decode(&[1, 3, 1, 0, 0, 0, 0xff]).unwrap_err(), "Invalid UTF-8 in wire string at byte 1");
+    assert_eq!(decode(&[1, 7, 5, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap_err(), "Map entry without a value at byte 1");
+    // A struct needs a string name
+    assert_eq!(decode(&[1, 8, 5, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap_err(), "Malformed wire struct at byte 1");
+    // A nested length may not run past its parent
+    assert!(decode(&[1, 6, 5, 0, 0, 0, 3, 9, 0, 0, 0]).is_err());
+    // Nesting is bounded
+    let mut deep = vec![1];
+    for _ in 0..300 {
+        deep.extend_from_slice(&[6, 0, 0, 0, 0]);
+    }
+    let mut bytes = deep.clone();
+    let mut length = 0u32;
+    for level in (0..300).rev() {
+        let at = 1 + level * 5;
+        bytes[at + 1..at + 5].copy_from_slice(&length.to_le_bytes());
+        length += 5;
+    }
+    assert!(decode(&bytes).unwrap_err().starts_with("Wire value nested deeper than 256"));
+}
+
+// Tracing tests
+#[test]
+fn test_tracing_records_task_and_rpc_spans() {
+    let config = mock_workspace("trace");
+    let root = config.workspace.clone();
+    let mut runtime = Runtime::with_config(config);
+    tau::parse_tau("f = async 1 + 1", &mut runtime).unwrap();
+    assert!(runtime.tracer.spans().is_empty());
+    tau::parse_tau("trace(true)", &mut runtime).unwrap();
+    tau::parse_tau("calc = mock \"calc.tsu\"", &mut runtime).unwrap();
+    tau::parse_tau("link = connect(calc)", &mut runtime).unwrap();
+    tau::parse_tau("g = async call(link, \"add\", 1, 2)", &mut runtime).unwrap();
+    tau::parse_tau("await g", &mut runtime).unwrap();
+    let spans = runtime.tracer.spans();
+    let names: Vec<&str> = spans.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["task.spawn", "task.resolve", "rpc.call", "rpc.send", "rpc.receive"]);
+    // The RPC spans nest under the task that made the call, in one trace
+    assert_eq!(spans[2].parent, Some(spans[1].span_id));
+    assert_eq!(spans[3].parent, Some(spans[2].span_id));
+    assert!(spans[1..].iter().all(|s| s.trace_id == spans[1].trace_id && s.end.is_some()));
+    assert!(spans[2].attributes.contains(&("rpc.method".to_string(), "add".to_string())));
+    tau::parse_tau("bad = async 1 / nothing", &mut runtime).unwrap();
+    tau::parse_tau("await bad", &mut runtime).unwrap_err();
+    assert!(runtime.tracer.spans().last().unwrap().error.is_some());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_trace_exports_otlp_json() {
+    let config = Config { trace: true, ..Config::with_temp_workspace("otlp").unwrap() };
+    let root = config.workspace.clone();
+    let mut runtime = Runtime::with_config(config);
+    tau::parse_tau("f = async \"a\\\"b\"", &mut runtime).unwrap();
+    tau::parse_tau("await f", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("trace_export(\"run.json\")", &mut runtime).unwrap(), Value::Num(2.0));
+    let json = std::fs::read_to_string(root.join("run.json")).unwrap();
+    assert!(json.starts_with("{\"resourceSpans\":[{\"resource\":{\"attributes\":[{\"key\":\"service.name\""));
+    assert!(json.contains("\"name\":\"task.resolve\",\"kind\":1"));
+    assert!(json.contains("{\"key\":\"task.source\",\"value\":{\"stringValue\":\"\\\"a\\\\\\\"b\\\"\"}}"));
+    assert!(!json.contains("\"parentSpanId\"") && json.contains("\"status\":{\"code\":1}"));
+    runtime.config.capabilities.revoke(Capability::FsWrite);
+    assert!(tau::parse_tau("trace_export(\"run.json\")", &mut runtime).is_err());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Metrics tests
+#[test]
+fn test_counters_and_gauges() {
+    let mut runtime = Runtime::new();
+    assert_eq!(tau::parse_tau("counter(\"jobs\").inc()", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("counter(\"jobs\").inc(4)", &mut runtime).unwrap(), Value::Num(5.0));
+    tau::parse_tau("queue = gauge(\"queue\")", &mut runtime).unwrap();
+    tau::parse_tau("queue.set(7)", &mut runtime).unwrap();
+    tau::parse_tau("set(queue, 3)", &mut runtime).unwrap();
+    assert_eq!(format!("{:?}", runtime.get_variable("queue").unwrap()), "Metric(gauge queue = 3)");
+    let exported = tau::parse_tau("metrics_export()", &mut runtime).unwrap();
+    assert_eq!(exported, Value::Map(Map::from_pairs(vec![(Value::Str("jobs".into()), Value::Num(5.0)), (Value::Str("queue".into()), Value::Num(3.0))]).unwrap()));
+    assert_eq!(tau::parse_tau("counter(\"jobs\").inc(-1)", &mut runtime).unwrap_err(), "Counter jobs cannot go down");
+    assert_eq!(tau::parse_tau("gauge(\"jobs\")", &mut runtime).unwrap_err(), "jobs is a counter, not a gauge");
+    assert_eq!(tau::parse_tau("queue.inc()", &mut runtime).unwrap_err(), "inc needs a counter, queue is a gauge");
+}
+
+#[test]
+fn test_timer_records_samples() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("t = timer(\"build\")", &mut runtime).unwrap();
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(matches!(tau::parse_tau("t.stop()", &mut runtime).unwrap(), Value::Num(ms) if ms >= 5.0));
+    assert_eq!(tau::parse_tau("t.stop()", &mut runtime).unwrap_err(), "Timer build is not running");
+    tau::parse_tau("timer(\"build\").stop()", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("metrics_export()[\"build\"][\"count\"]", &mut runtime).unwrap(), Value::Num(2.0));
+    assert!(matches!(tau::parse_tau("metrics_export()[\"build\"][\"max_ms\"]", &mut runtime).unwrap(), Value::Num(ms) if ms >= 5.0));
+}
+
+#[test]
+fn test_method_call_sugar() {
+    assert_eq!(rho_eval("[{1, 2}].keys()").unwrap(), Value::Array(nums(&[1.0]).into()));
+    assert_eq!(rho_eval("[1, 2, 3].map(fn(x) x * 2)[1]").unwrap(), Value::Num(4.0));
+    assert_eq!(rho_eval("1.5 + 1").unwrap(), Value::Num(2.5));
+}
+
+// HTTP tests
+// Serves one canned response per connection and hands back each request line,
+// followed by the request's body when it has one
+fn http_server(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            requests.push(line.trim().to_string());
+            let mut length = 0;
+            while line.trim() != "" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            if length > 0 {
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                requests.push(String::from_utf8(body).unwrap());
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+        requests
+    });
+    (address, server)
+}
+
+#[test]
+fn test_fetch_resolves_to_response_map() {
+    let (address, server) = http_server(vec![
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhello",
+        "HTTP/1.1 404 Not Found\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nmiss\r\n3\r\ning\r\n0\r\n\r\n",
+    ]);
+    let mut runtime = Runtime::new();
+    tau::parse_tau(&format!("page = fetch \"http://{}/index.txt\"", address), &mut runtime).unwrap();
+    assert!(matches!(runtime.get_variable("page"), Some(Value::Future(f)) if f.is_pending()));
+    tau::parse_tau("response = await page", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("response[\"status\"]", &mut runtime).unwrap(), Value::Num(200.0));
+    assert_eq!(tau::parse_tau("response[\"body\"]", &mut runtime).unwrap(), Value::Str("hello".into()));
+    assert_eq!(tau::parse_tau("response[\"headers\"][\"content-type\"]", &mut runtime).unwrap(), Value::Str("text/plain".into()));
+    tau::parse_tau(&format!("url = \"http://{}\"", address), &mut runtime).unwrap();
+    tau::parse_tau("missing = fetch url", &mut runtime).unwrap();
+    tau::parse_tau("response = await missing", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("response[\"status\"]", &mut runtime).unwrap(), Value::Num(404.0));
+    assert_eq!(tau::parse_tau("response[\"body\"]", &mut runtime).unwrap(), Value::Str("missing".into()));
+    assert_eq!(server.join().unwrap(), ["GET /index.txt HTTP/1.1", "GET / HTTP/1.1"]);
+}
+
+#[test]
+fn test_fetch_checks_url_and_capability() {
+    let mut runtime = Runtime::new();
+    assert_eq!(tau::parse_tau("fetch 1", &mut runtime).unwrap_err(), "fetch expects a URL, got Num(1.0)");
+    tau::parse_tau("f = fetch \"ftp://example.com\"", &mut runtime).unwrap();
+    assert!(tau::parse_tau("await f", &mut runtime).unwrap_err().starts_with("Unsupported URL ftp://example.com"));
+    runtime.config.capabilities.revoke(Capability::Net);
+    assert!(tau::parse_tau("fetch \"http://example.com\"", &mut runtime).is_err());
+}
+
+#[test]
+fn test_http_get_cached_uses_disk_and_rate_limit() {
+    let (address, server) = http_server(vec![
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nagain",
+    ]);
+    let mut config = Config::with_temp_workspace("http-cache").unwrap();
+    config.set("http-rate", "4/s").unwrap();
+    let mut runtime = Runtime::with_config(config);
+    runtime.set_variable("url".to_string(), Value::Str(format!("http://{}/data", address).into()));
+    let body = |runtime: &mut Runtime, ttl: &str| tau::parse_tau(&format!("http_get_cached(url, {})[\"body\"]", ttl), runtime).unwrap();
+    let start = std::time::Instant::now();
+    assert_eq!(body(&mut runtime, "\"1m\""), Value::Str("hello".into()));
+    // Fresh enough, so the server is not asked again
+    assert_eq!(body(&mut runtime, "\"1m\""), Value::Str("hello".into()));
+    // A zero ttl refetches, waiting out the host's 250ms slot
+    assert_eq!(body(&mut runtime, "0"), Value::Str("again".into()));
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+    assert_eq!(server.join().unwrap(), ["GET /data HTTP/1.1", "GET /data HTTP/1.1"]);
+    // With the server gone the stale copy is given instead of the error
+    assert_eq!(body(&mut runtime, "0"), Value::Str("again".into()));
+    assert_eq!(runtime.config.in_workspace(".http-cache").read_dir().unwrap().count(), 1);
+
+    runtime.config.capabilities.revoke(Capability::FsWrite);
+    assert!(tau::parse_tau("http_get_cached(url, \"1m\")", &mut runtime).is_err());
+    let mut config = Config::new();
+    assert_eq!(config.set("http-rate", "fast").unwrap_err(), "Invalid rate: fast (expected e.g. 5/s, 30/m or off)");
+    config.set("http-rate", "30/m").unwrap();
+    assert_eq!(config.http_rate, Some(std::time::Duration::from_secs(2)));
+}
+
+#[test]
+fn test_http_json_and_graphql() {
+    let (address, server) = http_server(vec![
+        "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\n\r\n{\"id\": 7, \"ok\": true}",
+        "HTTP/1.1 404 Not Found\r\n\r\n{\"error\": {\"message\": \"gone\"}}",
+        "HTTP/1.1 200 OK\r\n\r\n{\"data\": {\"user\": {\"name\": \"ada\"}}}",
+        "HTTP/1.1 200 OK\r\n\r\n{\"data\": null, \"errors\": [{\"message\": \"no\"}]}",
+    ]);
+    let mut runtime = Runtime::new();
+    for (name, path) in [("base", ""), ("items", "/items"), ("item", "/items/9"), ("api", "/graphql")] {
+        runtime.set_variable(name.to_string(), Value::Str(format!("http://{}{}", address, path).into()));
+    }
+    let reply = tau::parse_tau(r#"http_json("post", items, [{"name", "x"}], [{"X-Token", "t"}])"#, &mut runtime).unwrap();
+    assert_eq!(reply.to_string(), r#"{"id": 7, "ok": true}"#);
+    let error = tau::parse_tau(r#"http_json("GET", item)"#, &mut runtime).unwrap_err();
+    assert_eq!(error, format!("GET http://{}/items/9 failed with status 404: gone", address));
+    let data = tau::parse_tau(r#"graphql(api, "query($id: ID) { user(id: $id) { name } }", [{"id", 1}])"#, &mut runtime).unwrap();
+    assert_eq!(data.to_string(), r#"{"user": {"name": "ada"}}"#);
+    assert_eq!(tau::parse_tau(r#"graphql(base, "{ nope }")"#, &mut runtime).unwrap_err(), "GraphQL error: no");
+    assert_eq!(server.join().unwrap(), [
+        "POST /items HTTP/1.1",
+        r#"{"name":"x"}"#,
+        "GET /items/9 HTTP/1.1",
+        "POST /graphql HTTP/1.1",
+        r#"{"query":"query($id: ID) { user(id: $id) { name } }","variables":{"id":1}}"#,
+        "POST / HTTP/1.1",
+        r#"{"query":"{ nope }","variables":null}"#,
+    ]);
+    assert!(tau::parse_tau(r#"http_json("GET", base, 1, "headers")"#, &mut runtime).unwrap_err().contains("headers as a map"));
+}
+
+// Scheduler tests
+fn utc(secs: u64) -> std::time::SystemTime {
+    std::time::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn test_cron_next_times() {
+    use crate::schedule::{format_utc, Cron};
+    let friday_noon = utc(1792152210);  // 2026-10-16 12:03:30 UTC
+    let next = |spec: &str| format_utc(Cron::parse(spec).unwrap().next_after(friday_noon).unwrap());
+    assert_eq!(next("*/5 * * * *"), "2026-10-16 12:05 UTC");
+    assert_eq!(next("* * * * *"), "2026-10-16 12:04 UTC");
+    assert_eq!(next("0 9 * * 1-5"), "2026-10-19 09:00 UTC");
+    assert_eq!(next("15,45 */6 * * *"), "2026-10-16 12:15 UTC");
+    assert_eq!(next("30 8 1 1 *"), "2027-01-01 08:30 UTC");
+    assert_eq!(next("0 0 29 2 *"), "2028-02-29 00:00 UTC");
+    assert_eq!(next("0 0 * * 7"), "2026-10-18 00:00 UTC");
+    // With both day fields restricted either one matches
+    assert_eq!(next("0 0 13 * 5"), "2026-10-23 00:00 UTC");
+    assert!(Cron::parse("* * *").unwrap_err().contains("needs 5 fields"));
+    assert_eq!(Cron::parse("61 * * * *").unwrap_err(), "Invalid cron minute field \"61\"");
+    assert!(Cron::parse("*/0 * * * *").is_err());
+    assert_eq!(Cron::parse("0 0 31 2 *").unwrap().next_after(friday_noon).unwrap_err(), "Cron spec never matches");
+}
+
+#[test]
+fn test_schedule_runs_due_handlers() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("jobs = schedule(\"*/5 * * * *\", fn() counter(\"ticks\").inc())", &mut runtime).unwrap();
+    tau::parse_tau("broken = schedule(\"0 * * * *\", fn() 1 / nothing)", &mut runtime).unwrap();
+    let now = std::time::SystemTime::now();
+    assert!(runtime.run_due_schedules(now).is_empty());
+    // Falling behind by a few slots still runs each handler once
+    let outcomes = runtime.run_due_schedules(now + Duration::from_secs(2 * 3600));
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0], (1, Ok(Value::Num(1.0))));
+    assert!(outcomes[1].1.is_err());
+    let listing: Vec<String> = runtime.scheduler.schedules().iter().map(|s| s.to_string()).collect();
+    assert!(listing[0].starts_with("#1 */5 * * * * next ") && listing[0].ends_with(", 1 runs"));
+    assert!(listing[1].contains("(last failed: "));
+    assert_eq!(tau::parse_tau("cancel_schedule(jobs)", &mut runtime).unwrap(), Value::Bool(true));
+    assert_eq!(tau::parse_tau("cancel_schedule(jobs)", &mut runtime).unwrap(), Value::Bool(false));
+    assert_eq!(runtime.scheduler.schedules().len(), 1);
+    assert!(tau::parse_tau("schedule(\"* * * * *\", fn(x) x)", &mut runtime).is_err());
+    assert!(tau::parse_tau("schedule(\"every minute\", fn() 1)", &mut runtime).is_err());
+    runtime.config.capabilities.revoke(Capability::Schedule);
+    assert!(tau::parse_tau("schedule(\"* * * * *\", fn() 1)", &mut runtime).unwrap_err().contains("schedule"));
+    assert_eq!(runtime.scheduler.schedules().len(), 1);
+}
+
+#[test]
+fn test_env_and_clipboard_builtins() {
+    let mut runtime = Runtime::new();
+    assert_eq!(tau::parse_tau("env(\"PATH\")", &mut runtime).unwrap(), Value::Str(std::env::var("PATH").unwrap().into()));
+    assert_eq!(tau::parse_tau("env(\"RCL_SURELY_UNSET_VARIABLE\")", &mut runtime).unwrap(), Value::None);
+    assert!(tau::parse_tau("env(1)", &mut runtime).is_err());
+    assert!(tau::parse_tau("clipboard(1)", &mut runtime).is_err());
+    runtime.config.capabilities.revoke_list("env, clipboard").unwrap();
+    assert!(tau::parse_tau("env(\"PATH\")", &mut runtime).unwrap_err().contains("env"));
+    assert!(tau::parse_tau("clipboard()", &mut runtime).unwrap_err().starts_with("Capability denied: clipboard"));
+    assert!(tau::parse_tau("clipboard_set(\"x\")", &mut runtime).unwrap_err().starts_with("Capability denied: clipboard"));
+}
+
+// TCP socket tests
+#[test]
+fn test_tcp_sessions_exchange_values() {
+    let mut server = Runtime::new();
+    let mut client = Runtime::new();
+    tau::parse_tau("server = listen \"127.0.0.1:0\"", &mut server).unwrap();
+    let address = tau::parse_tau("socket_address(server)", &mut server).unwrap();
+    tau::parse_tau("incoming = accept server", &mut server).unwrap();
+    assert!(matches!(server.get_variable("incoming"), Some(Value::Future(f)) if f.is_pending()));
+    client.set_variable("address".to_string(), address);
+    tau::parse_tau("sock = dial address", &mut client).unwrap();
+    tau::parse_tau("conn = await incoming", &mut server).unwrap();
+    assert!(format!("{:?}", server.get_variable("conn").unwrap()).starts_with("Socket(connected to 127.0.0.1:"));
+    tau::parse_tau("send sock [{\"job\", \"build\"}, {\"args\", [1, 2]}]", &mut client).unwrap();
+    tau::parse_tau("message = recv conn", &mut server).unwrap();
+    tau::parse_tau("request = await message", &mut server).unwrap();
+    assert_eq!(tau::parse_tau("request[\"args\"][1]", &mut server).unwrap(), Value::Num(2.0));
+    tau::parse_tau("send conn request[\"job\"]", &mut server).unwrap();
+    tau::parse_tau("reply = recv sock", &mut client).unwrap();
+    assert_eq!(tau::parse_tau("await reply", &mut client).unwrap(), Value::Str("build".into()));
+    // Closing one end fails the other's pending receive
+    tau::parse_tau("close(conn)", &mut server).unwrap();
+    tau::parse_tau("reply = recv sock", &mut client).unwrap();
+    assert!(tau::parse_tau("await reply", &mut client).unwrap_err().contains("closed the connection"));
+    assert_eq!(tau::parse_tau("send conn 1", &mut server).unwrap_err(), "send needs a connected socket");
+}
+
+#[test]
+fn test_tcp_errors() {
+    let mut runtime = Runtime::new();
+    assert_eq!(tau::parse_tau("recv 1", &mut runtime).unwrap_err(), "recv expects a socket, subscription or serial port, got Num(1.0)");
+    assert!(tau::parse_tau("send 1 2", &mut runtime).is_err());
+    assert!(tau::parse_tau("dial \"127.0.0.1:1\"", &mut runtime).unwrap_err().starts_with("Failed to connect to 127.0.0.1:1"));
+    tau::parse_tau("server = listen \"127.0.0.1:0\"", &mut runtime).unwrap();
+    assert!(tau::parse_tau("send server 1", &mut runtime).is_err());
+    runtime.config.capabilities.revoke(Capability::Net);
+    assert!(tau::parse_tau("listen 0", &mut runtime).is_err());
+}
+
+// Publish/subscribe tests
+#[test]
+fn test_publish_subscribe() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("prices = subscribe \"prices\"", &mut runtime).unwrap();
+    tau::parse_tau("audit = subscribe \"prices\"", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("publish \"prices\" 10", &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("publish \"prices\" [{\"sym\", \"X\"}]", &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("publish \"news\" 1", &mut runtime).unwrap(), Value::Num(0.0));
+    // recv takes the oldest value when the future is awaited
+    tau::parse_tau("next = recv prices", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await next", &mut runtime).unwrap(), Value::Num(10.0));
+    assert_eq!(format!("{:?}", runtime.get_variable("prices").unwrap()), "Subscription(subscription to prices (1 pending))");
+    // for drains what has arrived, oldest first
+    let last = tau::parse_tau("for p in audit { p }", &mut runtime).unwrap();
+    assert_eq!(last, Value::Map(Map::from_pairs(vec![(Value::Str("sym".into()), Value::Str("X".into()))]).unwrap()));
+    assert_eq!(format!("{:?}", runtime.get_variable("audit").unwrap()), "Subscription(subscription to prices (0 pending))");
+    tau::parse_tau("next = recv audit", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await next", &mut runtime).unwrap_err(), "Nothing published on prices yet\n  at task #2 `recv audit` spawned at 1:8");
+    // Closed and dropped subscriptions stop receiving
+    tau::parse_tau("close(audit)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("publish \"prices\" 11", &mut runtime).unwrap(), Value::Num(1.0));
+    tau::parse_tau("prices = 0", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("publish \"prices\" 12", &mut runtime).unwrap(), Value::Num(0.0));
+    assert_eq!(tau::parse_tau("subscribe 1", &mut runtime).unwrap_err(), "subscribe expects a topic, got Num(1.0)");
+}
+
+// MQTT tests
+// One packet from a client: the fixed header byte and the body
+#[cfg(feature = "mqtt")]
+fn mqtt_packet(stream: &mut std::net::TcpStream) -> (u8, Vec<u8>) {
+    use std::io::Read;
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte).unwrap();
+    let kind = byte[0];
+    let (mut length, mut shift) = (0usize, 0);
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).unwrap();
+    (kind, body)
+}
+
+#[cfg(feature = "mqtt")]
+#[test]
+fn test_mqtt_publish_and_subscribe() {
+    use std::io::Write;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    // A broker that accepts one client, sends two messages once it has
+    // subscribed, and hands back what the client published
+    let broker = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let (kind, connect) = mqtt_packet(&mut stream);
+        assert_eq!((kind, &connect[..7]), (0x10, &b"\0\x04MQTT\x04"[..]));
+        stream.write_all(&[0x20, 2, 0, 0]).unwrap();
+        let (kind, subscribe) = mqtt_packet(&mut stream);
+        assert_eq!(kind, 0x82);
+        assert_eq!(&subscribe[2..], b"\0\x0esensors/+/temp\0");
+        stream.write_all(&[0x90, 3, subscribe[0], subscribe[1], 0]).unwrap();
+        for (topic, payload) in [("sensors/kitchen/temp", "21.5"), ("sensors/kitchen/door", "open")] {
+            let mut packet = vec![0x30, (2 + topic.len() + payload.len()) as u8, 0, topic.len() as u8];
+            packet.extend_from_slice(topic.as_bytes());
+            packet.extend_from_slice(payload.as_bytes());
+            stream.write_all(&packet).unwrap();
+        }
+        let mut published = Vec::new();
+        loop {
+            match mqtt_packet(&mut stream) {
+                // Topic and payload, without the topic's length
+                (0x30, body) => published.push(String::from_utf8(body[2..].to_vec()).unwrap()),
+                (0xe0, _) => return published,
+                (kind, _) => panic!("unexpected packet {:x}", kind),
+            }
+        }
+    });
+
+    let mut runtime = Runtime::new();
+    runtime.set_variable("url".to_string(), Value::Str(format!("mqtt://{}", address).into()));
+    tau::parse_tau("c = mqtt_connect(url)", &mut runtime).unwrap();
+    tau::parse_tau("c.subscribe(\"sensors/+/temp\", fn(topic, payload) [topic, payload])", &mut runtime).unwrap();
+    assert_eq!(runtime.get_variable("c").unwrap().to_string(), format!("mqtt client for {} (1 subscription)", address));
+    // Only the message matching the filter reaches the handler
+    let mut outcomes = Vec::new();
+    for _ in 0..200 {
+        outcomes.extend(runtime.serve_mqtt());
+        if !outcomes.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].1.as_ref().unwrap().to_string(), r#"["sensors/kitchen/temp", "21.5"]"#);
+    tau::parse_tau("c.publish(\"lights/kitchen\", \"on\")", &mut runtime).unwrap();
+    tau::parse_tau("c.publish(\"lights/hall\", [{\"level\", 40}])", &mut runtime).unwrap();
+    assert!(tau::parse_tau("c.publish(\"lights/#\", 1)", &mut runtime).unwrap_err().contains("no wildcards"));
+    tau::parse_tau("close(c)", &mut runtime).unwrap();
+    assert_eq!(broker.join().unwrap(), ["lights/kitchenon", "lights/hall{\"level\":40}"]);
+    assert!(tau::parse_tau("c.publish(\"lights/hall\", 1)", &mut runtime).unwrap_err().ends_with("is closed"));
+    assert!(runtime.serve_mqtt().is_empty());
+
+    assert!(crate::mqtt::matches("sensors/#", "sensors/a/b"));
+    assert!(crate::mqtt::matches("sensors/+", "sensors/a"));
+    assert!(!crate::mqtt::matches("sensors/+", "sensors/a/b"));
+    runtime.config.capabilities.revoke(Capability::Net);
+    assert!(tau::parse_tau("mqtt_connect(url)", &mut runtime).is_err());
+}
+
+// Serial port tests
+// script(1) runs a shell on a fresh pseudo-terminal and relays it: what the
+// port writes comes out of script's stdout, and what is written to script's
+// stdin arrives at the port
+#[cfg(target_os = "linux")]
+#[test]
+fn test_serial_port_over_a_pty() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::process::{Command, Stdio};
+    let mut device = Command::new("script")
+        .args(["-qfc", "stty raw -echo; tty; sleep 10", "/dev/null"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = device.stdin.take().unwrap();
+    let mut output = BufReader::new(device.stdout.take().unwrap());
+    let mut path = String::new();
+    output.read_line(&mut path).unwrap();
+
+    let mut runtime = Runtime::new();
+    runtime.set_variable("path".to_string(), Value::Str(path.trim().into()));
+    tau::parse_tau("p = serial_open(path, 115200)", &mut runtime).unwrap();
+    assert_eq!(runtime.get_variable("p").unwrap().to_string(), format!("serial port {} at 115200 baud", path.trim()));
+    tau::parse_tau("serial_write(p, \"ping\\n\")", &mut runtime).unwrap();
+    tau::parse_tau("serial_write(p, [1, 2, 255])", &mut runtime).unwrap();
+    let mut written = [0u8; 8];
+    output.read_exact(&mut written).unwrap();
+    assert_eq!(&written, b"ping\n\x01\x02\xff");
+
+    input.write_all(b"pong\r\nt=2").unwrap();
+    assert_eq!(tau::parse_tau("serial_read(p)", &mut runtime).unwrap(), Value::Str("pong".into()));
+    // Part of a line is kept until the rest arrives
+    assert_eq!(tau::parse_tau("serial_read(p, 200)", &mut runtime).unwrap_err(), format!("Timed out reading from {}", path.trim()));
+    tau::parse_tau("f = recv p", &mut runtime).unwrap();
+    input.write_all(b"1.5\nt=22\n").unwrap();
+    assert_eq!(tau::parse_tau("await f", &mut runtime).unwrap(), Value::Str("t=21.5".into()));
+    tau::parse_tau("s = stream(p)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("next(s)", &mut runtime).unwrap(), Value::Str("t=22".into()));
+    tau::parse_tau("close(p)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("next(s)", &mut runtime).unwrap(), Value::Unit);
+    assert!(tau::parse_tau("serial_write(p, \"x\")", &mut runtime).unwrap_err().ends_with("is closed"));
+    assert!(tau::parse_tau("serial_write(p, [256])", &mut runtime).is_err());
+    runtime.config.capabilities.revoke(Capability::FsRead);
+    assert!(tau::parse_tau("serial_read(p)", &mut runtime).unwrap_err().contains("fs-read"));
+    runtime.config.capabilities.revoke(Capability::FsWrite);
+    assert!(tau::parse_tau("serial_write(p, \"x\")", &mut runtime).unwrap_err().contains("fs-write"));
+    runtime.config.capabilities.grant_list("fs-read, fs-write").unwrap();
+    device.kill().unwrap();
+    device.wait().unwrap();
+
+    assert!(tau::parse_tau("serial_open(\"/nonexistent/tty\", 9600)", &mut runtime).unwrap_err().starts_with("Failed to open serial port"));
+    runtime.config.capabilities.revoke(Capability::FsWrite);
+    assert!(tau::parse_tau("serial_open(path, 9600)", &mut runtime).is_err());
+}
+
+// Stream tests
+#[test]
+fn test_stream_of_subscription_applies_backpressure() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("ticks = subscribe \"ticks\"", &mut runtime).unwrap();
+    tau::parse_tau("s = stream(ticks, 2)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("publish \"ticks\" 1", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("publish \"ticks\" 2", &mut runtime).unwrap(), Value::Num(1.0));
+    // Full: the publisher sees nobody took the value
+    assert_eq!(tau::parse_tau("publish \"ticks\" 3", &mut runtime).unwrap(), Value::Num(0.0));
+    assert_eq!(
+        format!("{:?}", runtime.get_variable("s").unwrap()),
+        "Stream(stream of subscription to ticks (2 pending, 1 dropped), 0 received)"
+    );
+    assert_eq!(tau::parse_tau("next(s)", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("for t in s { t * 10 }", &mut runtime).unwrap(), Value::Num(20.0));
+    // Nothing waiting is not the end of a subscription
+    assert_eq!(tau::parse_tau("next(s)", &mut runtime).unwrap(), Value::Unit);
+    assert_eq!(tau::parse_tau("publish \"ticks\" 4", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("next(s)", &mut runtime).unwrap(), Value::Num(4.0));
+}
+
+#[test]
+fn test_stream_sources() {
+    let mut runtime = Runtime::new();
+    tau::parse_tau("r = stream(0..3)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("next(r)", &mut runtime).unwrap(), Value::Num(0.0));
+    assert_eq!(tau::parse_tau("for x in r { x }", &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("next(r)", &mut runtime).unwrap(), Value::Unit);
+    assert_eq!(format!("{:?}", runtime.get_variable("r").unwrap()), "Stream(stream of 3 values, ended after 3)");
+    tau::parse_tau("counter = gen(n) for i in 0..n { yield i * i }", &mut runtime).unwrap();
+    tau::parse_tau("g = stream(counter(3))", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("for x in g { x }", &mut runtime).unwrap(), Value::Num(4.0));
+    assert!(tau::parse_tau("stream(5)", &mut runtime).unwrap_err().starts_with("stream expects a socket"));
+    assert!(tau::parse_tau("stream([1], 2)", &mut runtime).is_err());
+    assert_eq!(tau::parse_tau("next([1])", &mut runtime).unwrap_err(), "next expects 1 stream argument, got 1 argument (array) at 1:1");
+}
+
+#[test]
+fn test_stream_of_socket_ends_when_peer_closes() {
+    let mut server = Runtime::new();
+    tau::parse_tau("server = listen \"127.0.0.1:0\"", &mut server).unwrap();
+    let address = tau::parse_tau("socket_address(server)", &mut server).unwrap();
+    tau::parse_tau("incoming = accept server", &mut server).unwrap();
+    let mut client = Runtime::new();
+    client.set_variable("address".to_string(), address);
+    tau::parse_tau("sock = dial address", &mut client).unwrap();
+    tau::parse_tau("conn = await incoming", &mut server).unwrap();
+    tau::parse_tau("send sock 1", &mut client).unwrap();
+    tau::parse_tau("send sock 2", &mut client).unwrap();
+    tau::parse_tau("close(sock)", &mut client).unwrap();
+    drop(client);
+    tau::parse_tau("lines = stream(conn)", &mut server).unwrap();
+    assert_eq!(tau::parse_tau("for v in lines { v }", &mut server).unwrap(), Value::Num(2.0));
+    assert!(format!("{:?}", server.get_variable("lines").unwrap()).ends_with("ended after 2)"));
+}
+
+// RPC agent tests
+#[test]
+fn test_agent_serves_session_functions_to_proxy() {
+    let (sender, address) = std::sync::mpsc::channel();
+    let agent = std::thread::spawn(move || {
+        let config = mock_workspace("rpc_agent");
+        let root = config.workspace.clone();
+        let mut runtime = Runtime::with_config(config);
+        tau::parse_tau("add = fn(a, b) a + b", &mut runtime).unwrap();
+        let Value::Str(address) = tau::parse_tau("agent \"calc.tsu\" \"127.0.0.1:0\"", &mut runtime).unwrap() else {
+            panic!("agent gives its address");
+        };
+        sender.send(address.to_string()).unwrap();
+        // Two calls and the reset that has no function behind it
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut answered = 0;
+        while answered < 3 && Instant::now() < deadline {
+            answered += runtime.serve_agents();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let _ = std::fs::remove_dir_all(&root);
+        answered
+    });
+    let config = mock_workspace("rpc_proxy");
+    let root = config.workspace.clone();
+    let mut runtime = Runtime::with_config(config);
+    let address = address.recv().unwrap();
+    tau::parse_tau(&format!("calc = proxy \"calc.tsu\" \"{}\"", address), &mut runtime).unwrap();
+    tau::parse_tau("sum = calc.add(1, 2)", &mut runtime).unwrap();
+    assert!(matches!(runtime.get_variable("sum"), Some(Value::Future(f)) if f.is_pending()));
+    assert_eq!(tau::parse_tau("await sum", &mut runtime).unwrap(), Value::Num(3.0));
+    tau::parse_tau("later = calc.add(40, 2)", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("await later", &mut runtime).unwrap(), Value::Num(42.0));
+    tau::parse_tau("cleared = calc.reset()", &mut runtime).unwrap();
+    let err = tau::parse_tau("await cleared", &mut runtime).unwrap_err();
+    assert!(err.contains("Calculator.reset is not implemented by this agent"), "{}", err);
+    assert_eq!(agent.join().unwrap(), 3);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_agent_and_proxy_arguments() {
+    let config = mock_workspace("rpc_args");
+    let root = config.workspace.clone();
+    let mut runtime = Runtime::with_config(config);
+    // Without an address both still generate headers
+    assert!(tau::parse_tau("agent \"calc.tsu\"", &mut runtime).is_ok());
+    let address = tau::parse_tau("agent calc.tsu 127.0.0.1:0", &mut runtime).unwrap();
+    assert!(matches!(&address, Value::Str(a) if a.starts_with("127.0.0.1:")));
+    let (interface, bound) = runtime.agents().next().unwrap();
+    assert_eq!(interface.name, "Calculator");
+    assert_eq!(address, Value::Str(bound.to_string().into()));
+    assert_eq!(tau::parse_tau("agent \"calc.tsu\" [1]", &mut runtime).unwrap_err(), "Expected a port or address, got Array([Num(1.0)])");
+    assert!(tau::parse_tau("proxy \"calc.tsu\" \"127.0.0.1:1\"", &mut runtime).unwrap_err().starts_with("Failed to connect to 127.0.0.1:1"));
+    // A proxy's methods are checked before anything is sent
+    runtime.set_variable("address".to_string(), address);
+    tau::parse_tau("calc = proxy \"calc.tsu\" address", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("calc.mul(1, 2)", &mut runtime).unwrap_err(), "Unknown function: mul");
+    tau::parse_tau("bad = calc.add(\"1\", 2)", &mut runtime).unwrap();
+    assert!(tau::parse_tau("await bad", &mut runtime).unwrap_err().contains("a must be num"));
+    runtime.config.capabilities.revoke(Capability::Net);
+    assert!(tau::parse_tau("agent \"calc.tsu\" 0", &mut runtime).is_err());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Output mode tests
+#[test]
+fn test_output_modes() {
+    use crate::output::Output;
+    let map = Map::from_pairs(vec![
+        (Value::Str("a".into()), Value::Array(vec![Value::Num(1.0), Value::Num(2.5)].into())),
+        (Value::Num(1.0), Value::Color(Color::new(255, 0, 0))),
+        (Value::Str("q\"".into()), Value::Unit),
+    ])
+    .unwrap();
+    let result = Ok(Value::Map(map));
+    assert_eq!(Output::Debug.format(&Ok(Value::Num(7.0))), "Num(7.0)");
+    assert_eq!(Output::Display.format(&Ok(Value::Num(7.0))), "7");
+    assert_eq!(Output::Display.format(&result), "{\"a\": [1, 2.5], 1: color(255, 0, 0), \"q\\\"\": ()}");
+    assert_eq!(Output::Json.format(&result), "{\"a\":[1,2.5],\"1\":{\"r\":255,\"g\":0,\"b\":0},\"q\\\"\":null}");
+    assert_eq!(Output::Json.format(&Ok(Value::Str("line\n".into()))), "\"line\\n\"");
+    // What JSON cannot hold goes as a string; errors as an object
+    assert_eq!(Output::Json.format(&Ok(Value::Num(f64::NAN))), "\"NaN\"");
+    assert_eq!(Output::Json.format(&Err("Division by zero".to_string())), "{\"error\":\"Division by zero\"}");
+    assert_eq!(Output::Display.format(&Err("oops".to_string())), "Error: oops");
+    // print shows a string's text, and everything else as displayed
+    assert_eq!(Value::Str("hi".into()).print_text(), "hi");
+    assert_eq!(Value::Array(vec![Value::Str("hi".into()), Value::Num(7.0)].into()).print_text(), "[\"hi\", 7]");
+
+    let mut config = Config::new();
+    assert_eq!(config.output, Output::Display);
+    config.set("output", "json").unwrap();
+    assert_eq!(config.output, Output::Json);
+    assert!(config.set("output", "xml").unwrap_err().starts_with("Unknown output mode: xml"));
+    let args: Vec<String> = ["--no-config", "--output", "display"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(Config::from_args(&args).unwrap().output, Output::Display);
+}
+
+#[test]
+fn test_map_layout() {
+    use crate::output::{MapLayout, Output};
+    let long: Vec<Value> = (1..=12).map(|n| Value::Num(n as f64)).collect();
+    let map = Map::from_pairs(vec![
+        (Value::Str("zeta".into()), Value::Num(1.0)),
+        (Value::Str("alpha".into()), Value::Array(long.into())),
+        (Value::Num(2.0), Value::Str("x".into())),
+    ])
+    .unwrap();
+    let result = Ok(Value::Map(map));
+    let mut layout = MapLayout::default();
+    assert_eq!(Output::Display.echo(&result, &layout), Output::Display.format(&result));
+    layout.set("sort", "on").unwrap();
+    assert_eq!(
+        Output::Display.echo(&result, &layout),
+        "{2: \"x\", \"alpha\": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], \"zeta\": 1}"
+    );
+    layout.set("align", "on").unwrap();
+    layout.set("summarize", "10").unwrap();
+    assert_eq!(
+        Output::Display.echo(&result, &layout),
+        "{\n  2:       \"x\"\n  \"alpha\": [1, 2, 3, ... (12 items)\n  \"zeta\":  1\n}"
+    );
+    assert!(Output::Debug.echo(&result, &layout).contains("  Str(\"zeta\"):  Num(1.0)"));
+    // JSON and values other than maps are echoed as before
+    assert_eq!(Output::Json.echo(&result, &layout), Output::Json.format(&result));
+    assert_eq!(Output::Display.echo(&Ok(Value::Num(7.0)), &layout), "7");
+    assert_eq!(layout.to_string(), "sort on, align on, summarize 10");
+
+    assert!(layout.set("align", "maybe").unwrap_err().starts_with("align must be on or off"));
+    assert!(layout.set("summarize", "0").is_err());
+    assert!(layout.set("width", "3").unwrap_err().starts_with("Unknown output option: width"));
+    let mut config = Config::new();
+    config.set("output.sort", "on").unwrap();
+    assert!(config.map_layout.sort);
+}
+
+#[test]
+fn test_color_swatches() {
+    use crate::output::swatches;
+    assert_eq!(swatches(&Value::Color(Color::rgba(255, 128, 0, 10))), Some("\x1b[48;2;255;128;0m  \x1b[0m".to_string()));
+    let row = swatches(&rho_eval("gradient(color(0,0,0), color(255,255,255), 3)").unwrap()).unwrap();
+    assert_eq!(row.matches("\x1b[48;2;").count(), 3);
+    assert!(row.contains("127;127;127"));
+    // Only colors, and arrays holding nothing else, get swatches
+    assert_eq!(swatches(&Value::Array(vec![Value::Color(Color::new(1, 2, 3)), Value::Num(1.0)].into())), None);
+    assert_eq!(swatches(&Value::Array(Vec::new().into())), None);
+    assert_eq!(swatches(&Value::Num(1.0)), None);
+
+    let mut config = Config::new();
+    assert!(!config.color);
+    config.set("color", "on").unwrap();
+    assert!(config.color);
+    config.set("no-color", "").unwrap();
+    assert!(!config.color);
+    config.set("no-color", "off").unwrap();
+    assert!(config.color);
+    assert!(config.set("color", "rainbow").is_err());
+    let args: Vec<String> = ["--no-config", "--no-color"].iter().map(|s| s.to_string()).collect();
+    assert!(!Config::from_args(&args).unwrap().color);
+}
+
+#[test]
+fn test_silenced_input() {
+    use crate::output::silenced;
+    assert_eq!(silenced("inc(hits);"), Some("inc(hits)"));
+    assert_eq!(silenced("x = 1; y = 2 ;"), Some("x = 1; y = 2"));
+    assert_eq!(silenced(":silent print(1)"), Some("print(1)"));
+    assert_eq!(silenced(":silent"), Some(""));
+    assert_eq!(silenced("x = 1; y = 2"), None);
+    // Only :silent itself, and no other command, is silenced
+    assert_eq!(silenced(":silently"), None);
+    assert_eq!(silenced(":set timeout 2s;"), None);
+}
+
+// JSON tests
+#[test]
+fn test_json_builtins() {
+    assert_eq!(
+        rho_eval("to_json([{\"name\", \"Ann\"}, {\"tags\", [1, 2.5, true]}, {\"c\", color(1, 2, 3)}])").unwrap(),
+        Value::Str("{\"name\":\"Ann\",\"tags\":[1,2.5,true],\"c\":{\"r\":1,\"g\":2,\"b\":3}}".into())
+    );
+    let parsed = rho_eval("parse_json(\"{\\\"b\\\": [1, null, {}], \\\"a\\\": \\\"x\\\\u00e9\\\\ud83d\\\\ude00\\\"}\")").unwrap();
+    let expected = Map::from_pairs(vec![
+        (Value::Str("b".into()), Value::Array(vec![Value::Num(1.0), Value::Unit, Value::Map(Map::new())].into())),
+        (Value::Str("a".into()), Value::Str("xé😀".into())),
+    ])
+    .unwrap();
+    assert_eq!(parsed, Value::Map(expected));
+    // Round trip, keeping object order
+    let text = "{\"z\":[-1.5,\"q\\\"\\n\"],\"a\":{\"k\":false}}";
+    assert_eq!(crate::json::to_json(&crate::json::parse_json(text).unwrap()).unwrap(), text);
+    assert!(rho_eval("to_json(fn(x) x)").unwrap_err().starts_with("Cannot convert"));
+    assert!(rho_eval("parse_json(1)").is_err());
+}
+
+#[test]
+fn test_parse_json_errors() {
+    use crate::json::parse_json;
+    assert_eq!(parse_json("[1, 2").unwrap_err(), "Invalid JSON at 1:6: expected ',' or ']'");
+    assert_eq!(parse_json("{\n  1: 2}").unwrap_err(), "Invalid JSON at 2:3: expected a string key");
+    assert_eq!(parse_json("tru").unwrap_err(), "Invalid JSON at 1:1: expected a value");
+    assert_eq!(parse_json("1 2").unwrap_err(), "Invalid JSON at 1:3: expected end of input");
+    assert_eq!(parse_json("\"a\\x\"").unwrap_err(), "Invalid JSON at 1:4: expected an escape");
+    assert!(parse_json("\"\\ud83d\"").is_err());
+    assert!(parse_json(&"[".repeat(300)).unwrap_err().starts_with("JSON nested deeper than 256"));
+}
+
+// Control socket tests
+#[test]
+fn test_daemon_flags() {
+    let args: Vec<String> = ["--no-config", "--daemon", "--socket", "/tmp/rcl.sock", "jobs.tsu"].iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert!(config.daemon);
+    assert_eq!(config.socket, Some(std::path::PathBuf::from("/tmp/rcl.sock")));
+    assert_eq!(config.script, Some(std::path::PathBuf::from("jobs.tsu")));
+    let args: Vec<String> = ["--no-config", "a.tsu", "b.tsu"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(Config::from_args(&args).unwrap_err(), "Unknown argument: b.tsu");
+    let args: Vec<String> = ["--no-config", "eval", "1"].iter().map(|s| s.to_string()).collect();
+    assert!(crate::control::ctl(&args).unwrap_err().starts_with("No control socket"));
+    assert!(crate::control::ctl(&[]).unwrap_err().starts_with("Usage: rcl ctl"));
+}
+
+#[test]
+fn test_daemon_without_script_runs_input_headless() {
+    let config = Config::with_temp_workspace("headless").unwrap();
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.run_input(std::io::Cursor::new(":tau\nx = 40\ny = x + 2\n:quit\nz = 1\n")).unwrap();
+    // With nothing scheduled or served the daemon ends at once
+    repl.run_daemon();
+    assert_eq!(repl.eval("y"), Ok(Value::Num(42.0)));
+    assert!(repl.eval("z").is_err());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctl_evaluates_in_running_daemon() {
+    let mut config = Config::with_temp_workspace("control").unwrap();
+    let root = config.workspace.clone();
+    let socket = config.in_workspace("rcl.sock");
+    let script = config.in_workspace("startup.tsu");
+    std::fs::write(&script, "x = 40\ncounter(\"requests\")\n").unwrap();
+    config.socket = Some(socket.clone());
+    let mut repl = Repl::with_config(config);
+    repl.run_file(&script).unwrap();
+    let client = std::thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !socket.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let ctl = |args: &[&str]| {
+            let mut full = vec!["--no-config".to_string(), "--socket".to_string(), socket.display().to_string()];
+            full.extend(args.iter().map(|a| a.to_string()));
+            crate::control::ctl(&full)
+        };
+        let outcomes = vec![ctl(&["eval", "x + 2"]), ctl(&["eval", "counter(\"requests\").inc()"]), ctl(&["eval", "nope(1)"])];
+        (outcomes, ctl(&["stop"]))
+    });
+    repl.run_daemon();
+    let (outcomes, stopped) = client.join().unwrap();
+    assert_eq!(outcomes[0], Ok("42".to_string()));
+    assert_eq!(outcomes[1], Ok("1".to_string()));
+    assert_eq!(outcomes[2], Err("Unknown function: nope".to_string()));
+    assert_eq!(stopped, Ok("Stopped".to_string()));
+    // The daemon takes its socket file with it
+    assert!(!root.join("rcl.sock").exists());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Module reload tests
+#[test]
+fn test_reload_swaps_functions_and_keeps_state() {
+    let config = Config::with_temp_workspace("modules").unwrap();
+    let root = config.workspace.clone();
+    let path = config.in_workspace("calc.tsu");
+    std::fs::write(&path, "total = 0\nadd = fn(a, b) a + b\nold = 1\n").unwrap();
+    let mut repl = Repl::with_config(config);
+    assert_eq!(repl.load_module("calc.tsu").unwrap(), "calc");
+    repl.parse_tau("total = add(total, 5)").unwrap();
+    // add changes, total keeps its live value, old goes and extra arrives
+    std::fs::write(&path, "total = 0\nadd = fn(a, b) a * b\nextra = 7\n").unwrap();
+    repl.reload_module("calc").unwrap();
+    assert_eq!(repl.parse_tau("total").unwrap(), Value::Num(5.0));
+    assert_eq!(repl.parse_tau("add(2, 3)").unwrap(), Value::Num(6.0));
+    assert_eq!(repl.parse_tau("extra").unwrap(), Value::Num(7.0));
+    assert!(repl.parse_tau("old").is_err());
+    // A version that fails part way changes nothing
+    std::fs::write(&path, "add = fn(a, b) a - b\nmissing(1)\n").unwrap();
+    assert_eq!(repl.reload_module("calc").unwrap_err(), "Unknown function: missing");
+    assert_eq!(repl.parse_tau("add(2, 3)").unwrap(), Value::Num(6.0));
+    assert_eq!(repl.parse_tau("extra").unwrap(), Value::Num(7.0));
+    assert_eq!(repl.reload_module("nope").unwrap_err(), "No module named nope");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_changed_modules_reload_between_inputs() {
+    let config = Config::with_temp_workspace("modules-watch").unwrap();
+    let root = config.workspace.clone();
+    let path = config.in_workspace("greet.tsu");
+    std::fs::write(&path, "greeting = fn() \"hello\"\n").unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.load_module("greet.tsu").unwrap();
+    std::fs::write(&path, "greeting = fn() \"hi\"\n").unwrap();
+    // Some file systems keep whole seconds, so move the time on explicitly
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(std::time::SystemTime::now() + Duration::from_secs(10)).unwrap();
+    repl.reload_changed();
+    assert_eq!(repl.parse_tau("greeting()").unwrap(), Value::Str("hi".into()));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_import_standard_library() {
+    // Every built-in library loads
+    for file in crate::stdlib::files().filter(|file| file.starts_with("std/")) {
+        Repl::new().import(file).unwrap();
+    }
+    let mut repl = Repl::new();
+    repl.eval("import \"std/strings\"").unwrap();
+    repl.set_language(Language::Rho);
+    assert_eq!(repl.eval("starts_with(\"hello\", \"he\") and ends_with(\"hello\", \"lo\")").unwrap(), Value::Bool(true));
+    assert_eq!(repl.eval("ends_with(\"hello\", \"\") and not ends_with(\"lo\", \"hello\")").unwrap(), Value::Bool(true));
+    // Imports work in every language, and only the first one evaluates
+    repl.eval("import \"std/arrays.tsu\"").unwrap();
+    repl.eval("import \"std/arrays\"").unwrap();
+    assert_eq!(repl.eval("mean([2, 4, 9])").unwrap(), Value::Num(5.0));
+    assert!(repl.modules().iter().any(|m| m.to_string() == "std/arrays (first, last, mean, product, skip, sum, take) from builtin:std/arrays.tsu, loaded 1 time"));
+    assert_eq!(repl.eval("import \"std/nope\"").unwrap_err(), "No library or file named std/nope");
+    // Examples run from the binary when they are not on disk
+    assert!(!std::path::Path::new("examples/primes.tsu").exists());
+    repl.run_file(std::path::Path::new("examples/primes.tsu")).unwrap();
+    assert_eq!(repl.eval("len(numbers)").unwrap(), Value::Num(48.0));
+
+    // --stdlib-path files come first; the rest still come from the binary
+    let mut config = Config::with_temp_workspace("stdlib").unwrap();
+    let root = config.workspace.clone();
+    std::fs::create_dir_all(root.join("std")).unwrap();
+    std::fs::write(root.join("std/math.tsu"), "max = fn(a, b) 42\n").unwrap();
+    config.stdlib_path = Some(root.clone());
+    let mut repl = Repl::with_config(config);
+    repl.eval("import \"std/math\"").unwrap();
+    repl.eval("import \"std/arrays\"").unwrap();
+    assert_eq!(repl.eval("max(1, 2)").unwrap(), Value::Num(42.0));
+    assert_eq!(repl.eval("sum([1, 2])").unwrap(), Value::Num(3.0));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+// Provenance tests
+#[test]
+fn test_whence_reports_where_variables_came_from() {
+    let mut config = Config::with_temp_workspace("whence").unwrap();
+    let root = config.workspace.clone();
+    std::fs::write(config.in_workspace("calc.tsu"), "rate = 3\n").unwrap();
+    let mut repl = Repl::with_config(config.clone());
+    repl.set_language(Language::Tau);
+    repl.eval("before = 1").unwrap();
+    assert_eq!(repl.whence("before"), "Provenance is off; :set provenance on to record it");
+    config.set("provenance", "on").unwrap();
+    let mut repl = Repl::with_config(config);
+    repl.set_language(Language::Tau);
+    repl.eval("f = async 1 + 1").unwrap();
+    repl.eval("g = 2").unwrap();
+    assert!(repl.whence("f").starts_with("f: \"f = async 1 + 1\" in Tau, input 1, 0.0s ago"));
+    assert!(repl.whence("g").starts_with("g: \"g = 2\" in Tau, input 2, "));
+    repl.load_module("calc.tsu").unwrap();
+    assert!(repl.whence("rate").starts_with("rate: \"rate = 3\" in Tau, module calc, "));
+    repl.set_language(Language::Rho);
+    repl.eval("for i in [1, 2] { i }").unwrap();
+    assert!(repl.whence("i").starts_with("i: \"for i in [1, 2] { i }\" in Rho, input 3, "));
+    assert_eq!(repl.whence("nope"), "Variable nope not found");
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_session_events() {
+    use crate::events::Event;
+    let mut repl = Repl::new();
+    repl.set_language(Language::Tau);
+    let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let log = Rc::clone(&seen);
+    let id = repl.events().subscribe(move |event| log.borrow_mut().push(event.clone()));
+    repl.eval("x = 1").unwrap();
+    repl.eval("f = async 2").unwrap();
+    repl.eval("y = await f").unwrap();
+    repl.eval("`true`").unwrap();
+    repl.eval("g = async z").unwrap();
+    assert!(repl.eval("await g").is_err());
+    let events = seen.borrow().clone();
+    let Event::EvalStarted { language, source, origin } = &events[0] else { panic!("{:?}", events[0]) };
+    assert_eq!((language, source.as_str(), origin.as_str()), (&Language::Tau, "x = 1", "input 1"));
+    assert_eq!(events[1], Event::VarAssigned { name: "x".to_string() });
+    assert!(matches!(&events[2], Event::EvalFinished { language: Language::Tau, error: None, .. }));
+    // An awaited future settles before the binding it gives
+    assert!(matches!(&events[7], Event::FutureResolved { error: None, .. }));
+    assert_eq!(events[8], Event::VarAssigned { name: "y".to_string() });
+    assert!(events.contains(&Event::ShellCommandRun { command: "true".to_string(), status: Some(0) }));
+    let rejected = events.iter().find_map(|event| match event {
+        Event::FutureResolved { error: Some(error), .. } => Some(error.clone()),
+        _ => None,
+    });
+    assert_eq!(rejected.as_deref(), Some("Undefined variable: z"));
+    assert!(matches!(events.last(), Some(Event::EvalFinished { error: Some(_), .. })));
+    // Unsubscribed, nothing more arrives
+    assert!(repl.events().unsubscribe(id));
+    assert!(!repl.events().unsubscribe(id));
+    repl.eval("w = 3").unwrap();
+    assert_eq!(seen.borrow().len(), events.len());
+}
+
+// Watch tests
+#[test]
+fn test_watch_expressions() {
+    let mut repl = Repl::new();
+    repl.set_language(Language::Tau);
+    repl.eval("total = 1").unwrap();
+    assert_eq!(repl.watch("total * 2"), 1);
+    assert_eq!(repl.watch("missing"), 2);
+    repl.set_language(Language::Pi);
+    repl.eval("5").unwrap();
+    assert_eq!(repl.watch("dup +"), 3);
+    repl.set_language(Language::Tau);
+    repl.eval("total = 4").unwrap();
+    assert_eq!(
+        repl.watch_lines(),
+        vec!["[1] total * 2 = 8", "[2] missing = Error: Undefined variable: missing", "[3] dup + = 10"]
+    );
+    // Pi watches leave the stack alone
+    assert_eq!(repl.pi_stack(), nums(&[5.0]).as_slice());
+    repl.unwatch("2").unwrap();
+    assert_eq!(repl.watch_lines()[1], "[2] dup + = 10");
+    assert_eq!(repl.unwatch("7").unwrap_err(), "No watch 7 (:watch lists them)");
+    repl.unwatch("").unwrap();
+    assert!(repl.watch_lines().is_empty());
+}
+
+// Exercise tests
+#[test]
+fn test_pi_exercises() {
+    // Every exercise accepts its own solution
+    for koan in crate::learn::PI {
+        let outcome = crate::learn::check(koan, &mut Runtime::new(), koan.solution);
+        assert!(matches!(outcome, crate::learn::Outcome::Correct), "{}", koan.title);
+    }
+
+    let mut repl = Repl::new();
+    repl.eval("9").unwrap();
+    assert!(repl.learn("").starts_with("No exercises running"));
+    assert!(repl.learn("pi").ends_with("Exercise 1/12: The stack\nPi keeps values on a stack. Each number you type is pushed on top.\nPush 1, 2 and 3."));
+    assert!(repl.answer("1 2 3").starts_with("Correct!\n\nExercise 2/12: Operators"));
+    assert_eq!(repl.answer("7"), "Right stack, but use + this time\nHint: Push both numbers, then the operator: 3 4 +");
+    assert_eq!(repl.answer("3 4 *"), "That leaves <1> 12 but the goal is <1> 7\nHint: Push both numbers, then the operator: 3 4 +");
+    assert!(repl.answer("+").starts_with("That failed: Not enough operands for +"));
+    assert_eq!(repl.learn("hint"), "Hint: Push both numbers, then the operator: 3 4 +");
+    assert!(repl.learn("skip").starts_with("Exercise 3/12"));
+    // Answers leave the session's stack alone
+    assert_eq!(repl.pi_stack(), nums(&[9.0]).as_slice());
+    assert_eq!(repl.learn("stop"), "Stopped the exercises");
+    assert!(repl.learn("rho").starts_with("Error: No exercises for rho"));
+    repl.learn("pi");
+    for _ in 0..11 {
+        repl.learn("skip");
+    }
+    assert_eq!(repl.answer("42 \"answer\" = answer answer"), "Correct!\n\nThat was the last of the Pi exercises");
+    assert!(repl.learn("").starts_with("No exercises running"));
+}
+
+// Translation tests
+#[test]
+fn test_translate() {
+    use crate::translate::translate;
+    let show = |lang, source: &str| -> Vec<String> {
+        translate(lang, source).unwrap().into_iter().map(|(lang, text)| format!("{}: {}", lang, text.unwrap_or_else(|e| e))).collect()
+    };
+    assert_eq!(show(Language::Pi, "3 4 + 2 *"), ["Rho: (3 + 4) * 2", "Tau: (3 + 4) * 2"]);
+    assert_eq!(show(Language::Rho, "1 - (2 - 3) * -4 ** 2"), ["Pi: 1 2 3 - 0 4 2 ** - * -", "Tau: 1 - (2 - 3) * -4 ** 2"]);
+    assert_eq!(show(Language::Pi, "9 dup * -2 2 ** 1 2 swap -"), ["Rho: 9 * 9; (-2) ** 2; 2 - 1", "Tau: 9 * 9; (-2) ** 2; 2 - 1"]);
+    assert_eq!(show(Language::Pi, "[3, 1, 2] sort len \"n\" ="), ["Rho: n = len(sort([3, 1, 2]))", "Tau: n = len(sort([3, 1, 2]))"]);
+    assert_eq!(show(Language::Tau, "total = a + b"), ["Pi: a b + \"total\" =", "Rho: total = a + b"]);
+    assert_eq!(show(Language::Rho, "x != 1 and not y"), ["Pi: x 1 == not y not and", "Tau: x != 1 and not y"]);
+    assert_eq!(show(Language::Rho, "sort(xs, fn(a, b) a > b)"), ["Pi: xs fn(a, b) a > b sort", "Tau: sort(xs, fn(a, b) a > b)"]);
+    assert_eq!(show(Language::Rho, "xs[1..3]"), ["Pi: Pi has no form for indexing", "Tau: xs[1..3]"]);
+
+    // Whatever the printers write reads back as the same tree
+    for source in ["1 - (2 - 3) * -4 ** 2", "(1 + 2) ** 3 ** 2 < 10 == true", "[{\"a\\n\", [1, 2]}]", "for x in 1..=3 { x * 2 }"] {
+        let tree = crate::rho::parse_rho(source).unwrap();
+        let text = crate::translate::print(Language::Rho, &crate::translate::parse(Language::Rho, source).unwrap()).unwrap();
+        assert_eq!(format!("{:?}", crate::rho::parse_rho(&text).unwrap()), format!("{:?}", tree), "{}", text);
+    }
+
+    let mut repl = Repl::new();
+    assert_eq!(repl.translate("2 3 +"), "Rho: 2 + 3\nTau: 2 + 3");
+    assert_eq!(repl.translate("2 +"), "Error: Not enough operands for +");
+    repl.set_language(Language::Rho);
+    assert_eq!(repl.translate("len(\"ab\") + 1"), "Pi:  \"ab\" len 1 +\nTau: len(\"ab\") + 1");
+    // The operands are only read, never run
+    assert_eq!(repl.pi_stack(), &[]);
+}
+
+// Exception handling tests
+#[test]
+fn test_try_catch_finally() {
+    assert_eq!(rho_eval("try { 1 / 0 } catch e { e[\"message\"] }").unwrap(), Value::Str("Division by zero".into()));
+    assert_eq!(rho_eval("try { 6 / 2 } catch e { 0 }").unwrap(), Value::Num(3.0));
+    // Missing keys, and the catch and finally clauses on lines of their own
+    let mut runtime = Runtime::new();
+    tau::parse_tau("m = [{\"a\", 1}]", &mut runtime).unwrap();
+    tau::parse_tau("log = [{\"done\", false}]", &mut runtime).unwrap();
+    let caught = tau::parse_tau("try { m[\"b\"] }\ncatch e { e }\nfinally { log[\"done\"] = true }", &mut runtime).unwrap();
+    assert_eq!(format!("{}", caught), "{\"message\": \"Key Str(\\\"b\\\") not found in map\"}");
+    assert_eq!(tau::parse_tau("log[\"done\"]", &mut runtime).unwrap(), Value::Bool(true));
+    // Rejected futures
+    tau::parse_tau("p = promise()", &mut runtime).unwrap();
+    tau::parse_tau("reject(p, \"boom\")", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("try { await(p) } catch e { e[\"message\"] }", &mut runtime).unwrap(), Value::Str("boom".into()));
+    // The error is bound only inside the handler
+    assert_eq!(runtime.get_variable("e"), None);
+    // finally alone lets the error through once it has run, and its own error wins
+    tau::parse_tau("log[\"done\"] = false", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("try { 1 / 0 } finally { log[\"done\"] = true }", &mut runtime).unwrap_err(), "Division by zero");
+    assert_eq!(tau::parse_tau("log[\"done\"]", &mut runtime).unwrap(), Value::Bool(true));
+    assert_eq!(tau::parse_tau("try { 1 } finally { m[\"c\"] }", &mut runtime).unwrap_err(), "Key Str(\"c\") not found in map");
+    // An error in the handler is not caught by the same try
+    assert_eq!(rho_eval("try { 1 / 0 } catch e { e[\"code\"] }").unwrap_err(), "Key Str(\"code\") not found in map");
+    // return passes through, running finally on the way
+    tau::parse_tau("f = fn() { try { return 1 } finally { log[\"done\"] = 2 }; 3 }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("f()", &mut runtime).unwrap(), Value::Num(1.0));
+    assert_eq!(tau::parse_tau("log[\"done\"]", &mut runtime).unwrap(), Value::Num(2.0));
+    assert!(crate::rho::parse_rho("try { 1 }").unwrap_err().contains("catch or finally"));
+    // A timeout is not caught, so a loop of tries still stops
+    runtime.set_timeout(Some(Duration::from_millis(50)));
+    let err = tau::parse_tau("while true { try { 1 / 0 } catch e { 0 } }", &mut runtime).unwrap_err();
+    assert!(err.contains("timed out"), "{}", err);
+}
+
+#[test]
+fn test_throw() {
+    // Caught as thrown, whatever the value
+    assert_eq!(rho_eval("try { throw 42 } catch e { e + 1 }").unwrap(), Value::Num(43.0));
+    let mut runtime = Runtime::new();
+    tau::parse_tau("check = fn(n) { n > 0 or throw [{\"code\", \"negative\"}, {\"message\", \"n must be positive\"}]; n }", &mut runtime).unwrap();
+    assert_eq!(tau::parse_tau("try { check(-1) } catch e { e[\"code\"] }", &mut runtime).unwrap(), Value::Str("negative".into()));
+    assert_eq!(tau::parse_tau("check(2)", &mut runtime).unwrap(), Value::Num(2.0));
+    // Through a builtin calling back into the script
+    assert_eq!(tau::parse_tau("try { map([1, -1], check) } catch e { e[\"code\"] }", &mut runtime).unwrap(), Value::Str("negative".into()));
+    // Uncaught, a map gives its message and anything else itself
+    assert_eq!(tau::parse_tau("check(-1)", &mut runtime).unwrap_err(), "n must be positive");
+    assert_eq!(rho_eval("throw \"plain\"").unwrap_err(), "plain");
+    assert_eq!(rho_eval("throw [1, 2]").unwrap_err(), "[1, 2]");
+    // finally still runs, and a rethrow reaches the next try out
+    tau::parse_tau("log = [{\"done\", false}]", &mut runtime).unwrap();
+    let rethrown = "try { try { throw 1 } catch e { throw e + 1 } finally { log[\"done\"] = true } } catch e { e }";
+    assert_eq!(tau::parse_tau(rethrown, &mut runtime).unwrap(), Value::Num(2.0));
+    assert_eq!(tau::parse_tau("log[\"done\"]", &mut runtime).unwrap(), Value::Bool(true));
+}
+
+// Differential tests
+// Random expression trees, built from a fixed seed so a failure reproduces
+struct Programs(u64);
+
+impl Programs {
+    fn below(&mut self, n: u64) -> u64 {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    fn expr(&mut self, depth: u32) -> Expr {
+        if depth == 0 || self.below(4) == 0 {
+            return match self.below(6) {
+                0 => Expr::Value(Value::Bool(self.below(2) == 0)),
+                1 => Expr::Value(Value::Str(["", "a", "ab", "b"][self.below(4) as usize].into())),
+                2 => Expr::Array((0..self.below(3)).map(|_| Expr::Value(Value::Num(self.below(4) as f64))).collect()),
+                _ => Expr::Value(Value::Num(self.below(9) as f64 - 3.0)),
+            };
+        }
+        let binary: [crate::rho::Binary; 11] = [
+            Expr::Add, Expr::Sub, Expr::Mul, Expr::Div, Expr::Mod, Expr::Pow, Expr::Less, Expr::Greater, Expr::Equal, Expr::And, Expr::Or,
+        ];
+        match self.below(14) as usize {
+            11 => Expr::Not(Box::new(self.expr(depth - 1))),
+            12 => Expr::Call("len".to_string(), vec![self.expr(depth - 1)], Site::default()),
+            13 => Expr::Call("sort".to_string(), vec![self.expr(depth - 1)], Site::default()),
+            op => binary[op](Box::new(self.expr(depth - 1)), Box::new(self.expr(depth - 1))),
+        }
+    }
+}
+
+// Whether `and` or `or` appears anywhere, which Pi runs without short-circuit
+fn short_circuits(expr: &Expr) -> bool {
+    match expr {
+        Expr::And(..) | Expr::Or(..) => true,
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) | Expr::Mod(a, b) | Expr::Pow(a, b) => {
+            short_circuits(a) || short_circuits(b)
+        }
+        Expr::Less(a, b) | Expr::Greater(a, b) | Expr::Equal(a, b) => short_circuits(a) || short_circuits(b),
+        Expr::Not(a) => short_circuits(a),
+        Expr::Call(_, args, _) => args.iter().any(short_circuits),
+        _ => false,
+    }
+}
+
+// Each generated tree is run by the runtime directly, then printed in every
+// language and run again from that source; all must agree on the value or
+// the error, apart from where parsed calls say they are. A second backend
+// joins by adding its result to the list.
+#[test]
+fn test_languages_agree_on_generated_programs() {
+    use crate::translate::{print, Statement};
+    let mut programs = Programs(0x5eed);
+    for _ in 0..2000 {
+        let expr = programs.expr(4);
+        let expected = format!("{:?}", Runtime::new().eval(&expr));
+        let languages: &[Language] = if short_circuits(&expr) { &[Language::Rho, Language::Tau] } else { &[Language::Pi, Language::Rho, Language::Tau] };
+        let statement = [Statement { name: None, expr }];
+        for &language in languages {
+            let source = print(language, &statement).unwrap();
+            let mut repl = Repl::new();
+            let result = match language {
+                Language::Pi => repl.parse_pi(&source),
+                Language::Rho => repl.parse_rho(&source),
+                Language::Tau => repl.parse_tau(&source),
+            };
+            assert_eq!(unlocated(&format!("{:?}", result)), expected, "{}: {}", language, source);
+        }
+    }
+}
+
+// The text without the " at 1:9" a parsed call adds to its errors
+fn unlocated(text: &str) -> String {
+    let mut out = text.to_string();
+    let mut from = 0;
+    while let Some(found) = out[from..].find(" at ") {
+        let start = from + found;
+        let rest = &out[start + 4..];
+        let site = rest.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(rest.len());
+        if rest[..site].contains(':') {
+            out.replace_range(start..start + 4 + site, "");
+        } else {
+            from = start + 4;
+        }
+    }
+    out
+}
+
+// Diagnostics tests
+#[test]
+fn test_check_reports_every_error() {
+    let source = "x = (1 + 2\ny = 3 @ 4\nok = 5\nfor i in 1..=3 {\n\ttotal += * i\n}\nw = 1 2";
+    assert_eq!(
+        crate::rho::check_rho(source),
+        [
+            "Expected ) at 2:1, found y",
+            "Unexpected character '@' at 2:7",
+            "Expected an expression at 5:11, found *",
+            "Expected end of statement at 7:7, found 2",
+        ]
+    );
+    assert!(crate::rho::check_rho("x = 1\nfor i in xs { x += i }").is_empty());
+    // The first error is still the one an input stops at
+    assert_eq!(crate::rho::parse_rho(source).unwrap_err(), "Unexpected character '@' at 2:7");
+
+    // Commands, imports and Tau's own statements are left alone
+    let repl = Repl::new();
+    let tau = "import \"std/arrays\"\n:silent f = async fetch(url)\nn = await f\nf.then(g)\n:caps\ntotal = sum([1,)\nm[\"k\"] = `ls";
+    assert_eq!(repl.check(Language::Tau, tau), ["Expected an expression at 6:16, found )", "Unterminated command at 7:10"]);
+    assert_eq!(repl.check(Language::Pi, "1 2 +\n3 $ 4 ~"), ["Unexpected character '$' at 2:3", "Unexpected character '~' at 2:7"]);
+    for file in crate::stdlib::files() {
+        assert_eq!(repl.check_file(std::path::Path::new(file)).unwrap(), Vec::<String>::new(), "{}", file);
+    }
+}
+
+// Snapshot tests. What a test renders is kept in src/snapshots by insta, so a
+// change to how values, maps or errors print shows up in review rather than in
+// front of users. A mismatch fails and leaves the new text beside the old as a
+// .snap.new file; `cargo insta review` (or INSTA_UPDATE=always) accepts it.
+// A future's display ends with its age, which no two runs share
+fn ageless(text: String) -> String {
+    let Some(end) = text.rfind("s>").filter(|_| text.contains("<future")) else {
+        return text;
+    };
+    match text[..end].rfind(' ') {
+        Some(start) => format!("{} _{}", &text[..start], &text[end..]),
+        None => text,
+    }
+}
+
+#[test]
+fn test_snapshot_values() {
+    use crate::output::Output;
+    let config = mock_workspace("snapshot");
+    let root = config.workspace.clone();
+    let mut repl = Repl::with_config(config);
+    repl.set_language(Language::Rho);
+    let mut eval = |source: &str| repl.eval(source).unwrap();
+    let map = Map::from_pairs(vec![
+        (Value::Str("name".into()), Value::Str("ada".into())),
+        (Value::Num(2.0), Value::Array(nums(&[1.0, 2.5]).into())),
+        (Value::Bool(true), Value::Unit),
+    ])
+    .unwrap();
+    let values = vec![
+        ("integer", Value::Num(7.0)),
+        ("fraction", Value::Num(-0.125)),
+        ("large number", Value::Num(1e21)),
+        ("not a number", Value::Num(f64::NAN)),
+        ("string", Value::Str("say \"hi\"\n\tand \\ go".into())),
+        ("empty string", Value::Str("".into())),
+        ("bool", Value::Bool(false)),
+        ("unit", Value::Unit),
+        ("none", Value::None),
+        ("color", Value::Color(Color::new(255, 128, 0))),
+        ("translucent color", Value::Color(Color::rgba(1, 2, 3, 4))),
+        ("array", Value::Array(vec![Value::Num(1.0), Value::Str("two".into()), Value::Array(vec![].into())].into())),
+        ("empty array", Value::Array(vec![].into())),
+        ("map", Value::Map(map.clone())),
+        ("empty map", Value::Map(Map::new())),
+        ("nested", Value::Array(vec![Value::Map(map), Value::Color(Color::new(0, 0, 0))].into())),
+        ("range", Value::Range(Range { start: -2, end: 5 })),
+        ("future", Value::Future(Future::new(1, "fetch"))),
+        ("escape continuation", Value::Continuation(Box::new(Continuation::Escape(3)))),
+        ("suspended continuation", Value::Continuation(Box::new(Continuation::Source(Language::Rho, "x + 1".to_string())))),
+        ("function", eval("fn(a, b) a + b")),
+        ("generator function", eval("gen(n) yield n")),
+        ("struct", eval("struct Point(x, y); Point(1, [2, \"three\"])")),
+        ("struct constructor", eval("Point")),
+        ("variant", eval("enum Shape = Circle(r) | Empty; Circle(Point(0, 0))")),
+        ("variant without fields", eval("Empty")),
+        ("generator", eval("g = gen(n) yield n; g(1)")),
+        ("counter", eval("c = counter(\"requests\"); inc(c, 3); c")),
+        ("timer", eval("timer(\"latency\")")),
+        ("secret", Value::Secret(crate::secret::Secret::new("hunter2"))),
+        ("mock", { repl.set_language(Language::Tau); repl.eval("mock \"calc.tsu\"").unwrap() }),
+        ("subscription", repl.eval("subscribe \"news\"").unwrap()),
+        ("stream", repl.eval("stream(0..3)").unwrap()),
+    ];
+    let mut out = String::new();
+    for (label, value) in values {
+        out.push_str(&format!("{}\n", label));
+        out.push_str(&format!("  display: {}\n", ageless(Output::Display.format(&Ok(value.clone())))));
+        out.push_str(&format!("  debug:   {}\n", Output::Debug.format(&Ok(value.clone()))));
+        out.push_str(&format!("  json:    {}\n", ageless(Output::Json.format(&Ok(value)))));
+    }
+    insta::assert_snapshot!("values", out);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn test_snapshot_map_layouts() {
+    use crate::output::{MapLayout, Output};
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    let map = repl.eval("[{\"zeta\", 1}, {10, \"ten\"}, {\"a\", [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]}, {2, color(1, 2, 3)}]");
+    let mut out = String::new();
+    for options in ["", "sort on", "align on", "summarize 12", "sort on,align on,summarize 12"] {
+        let mut layout = MapLayout::default();
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            let (option, value) = option.split_once(' ').unwrap();
+            layout.set(option, value).unwrap();
+        }
+        for output in [Output::Display, Output::Debug] {
+            out.push_str(&format!("{} ({})\n{}\n", output, layout, output.echo(&map, &layout)));
+        }
+    }
+    insta::assert_snapshot!("map_layouts", out);
+}
+
+#[test]
+fn test_snapshot_errors() {
+    use crate::output::Output;
+    let mut repl = Repl::new();
+    repl.set_language(Language::Rho);
+    let inputs = [
+        "1 +",
+        "x = (1 + 2",
+        "y @ 2",
+        "missing + 1",
+        "len([1], 2)",
+        "1 / 0",
+        "[1, 2][5]",
+        "\"a\" * 2",
+        "throw \"boom\"",
+        "throw [{\"code\", 7}]",
+        "f = fn(a) a; f(1, 2)",
+        "match 3 { 1 => 1 }",
+        "struct P(x); P(1).y",
+        "[a, b] = [1]",
+        "let match = 1",
+        "const k = 1; k = 2",
+    ];
+    let errors: Vec<(&str, String, String)> = inputs
+        .into_iter()
+        .map(|input| {
+            let result = repl.eval(input);
+            (input, Output::Display.format(&result), Output::Json.format(&result))
+        })
+        .collect();
+    insta::assert_debug_snapshot!("errors", errors);
+}
+
+// The public items of a source file, one per line: each pub item's signature,
+// then indented under it the variants of a pub enum, the pub fields of a pub
+// struct and the pub methods of an impl. Bodies and private items are left out.
+fn public_items(source: &str) -> String {
+    let mut out = String::new();
+    let mut lines = source.lines();
+    let mut attribute = "";
+    // The open item's header, and whether its members are listed
+    let mut open: Option<(String, bool)> = None;
+    let mut shown = false;
+    while let Some(line) = lines.next() {
+        let code = line.split(" //").next().unwrap().trim_end();
+        let trimmed = code.trim_start();
+        let indent = code.len() - trimmed.len();
+        if indent == 0 {
+            if code == "}" {
+                open = None;
+                continue;
+            }
+            if code == "#[non_exhaustive]" {
+                attribute = "#[non_exhaustive] ";
+                continue;
+            }
+            if code.starts_with("#[") || code.is_empty() {
+                continue;
+            }
+            let public = code.starts_with("pub ") || code.starts_with("impl");
+            let signature = signature(code, &mut lines);
+            if code.starts_with("pub ") {
+                out.push_str(&format!("{}{}\n", attribute, signature));
+            }
+            attribute = "";
+            if code.ends_with('{') || !signature.ends_with(';') && !code.ends_with(';') {
+                open = Some((signature, public));
+                shown = code.starts_with("pub ");
+            }
+            continue;
+        }
+        let Some((header, true)) = &open else { continue };
+        let member = if indent != 4 {
+            None
+        } else if header.starts_with("pub enum") && trimmed.starts_with(|c: char| c.is_ascii_uppercase()) {
+            Some(trimmed.trim_end_matches(',').to_string())
+        } else if trimmed.starts_with("pub ") && !header.starts_with("pub enum") {
+            Some(signature(trimmed, &mut lines).trim_end_matches(',').to_string())
+        } else {
+            None
+        };
+        if let Some(member) = member {
+            if !shown {
+                out.push_str(&format!("{}\n", header));
+                shown = true;
+            }
+            out.push_str(&format!("    {}\n", member));
+        }
+    }
+    out
+}
+
+// An item's declaration up to its body, joining the lines of a long one
+fn signature<'a>(first: &str, lines: &mut impl Iterator<Item = &'a str>) -> String {
+    let mut text = first.trim().to_string();
+    while text.matches('(').count() > text.matches(')').count() {
+        let Some(line) = lines.next() else { break };
+        text.push(' ');
+        text.push_str(line.trim());
+    }
+    let text = text.replace("( ", "(").replace(", )", ")");
+    let text = text.trim_end_matches('{').trim_end();
+    match text.split_once(" = ") {
+        Some((declaration, _)) if text.starts_with("pub const") || text.starts_with("pub static") => format!("{};", declaration),
+        _ => text.to_string(),
+    }
+}
+
+#[test]
+fn test_public_api() {
+    // Everything embedders may rely on: the crate root and every pub module
+    // it declares. A change here is a change to the public API; accept it with
+    // `cargo insta review` and version it accordingly.
+    use crate::prelude::*;
+    let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let lib = std::fs::read_to_string(src.join("lib.rs")).unwrap();
+    let modules = lib.lines().filter_map(|line| line.strip_prefix("pub mod ")?.strip_suffix(';'));
+    let mut out = String::new();
+    for file in std::iter::once("lib".to_string()).chain(modules.map(str::to_string)) {
+        let source = std::fs::read_to_string(src.join(format!("{}.rs", file))).unwrap();
+        out.push_str(&format!("// src/{}.rs\n{}\n", file, public_items(&source)));
+    }
+    insta::assert_snapshot!("public_api", out);
+
+    // The prelude is enough to embed a session
+    let mut repl = Repl::with_config(Config { capabilities: Capabilities::none(), ..Config::default() });
+    let seen = Rc::new(std::cell::Cell::new(0));
+    let count = Rc::clone(&seen);
+    repl.events().subscribe(move |event| count.set(count.get() + matches!(event, Event::VarAssigned { .. }) as usize));
+    repl.set_language(Language::Rho);
+    assert_eq!(repl.eval("x = color(1, 2, 3)").unwrap(), Value::Color(Color::new(1, 2, 3)));
+    assert!(repl.eval("`true`").unwrap_err().contains(&Capability::Shell.to_string()));
+    assert_eq!(seen.get(), 1);
+    let _: fn(&Runtime) -> bool = |runtime| runtime.variables().next().is_none();
+    let _: Option<Map> = None;
+    let _: Bus = Bus::default();
+
+    // Errors reach embedders as LangErrors through `?`
+    fn run(interpreter: &mut Interpreter, input: &str) -> Result<Value, LangError> {
+        Ok(interpreter.eval(input)?)
+    }
+    let mut interpreter = Interpreter::new();
+    interpreter.set_language(Language::Rho);
+    assert_eq!(run(&mut interpreter, "1 + 2").unwrap(), Value::Num(3.0));
+    let err = run(&mut interpreter, "1 / 0").unwrap_err();
+    assert_eq!(err.to_string(), err.message());
+    assert!(err.message().contains("zero"));
+    let boxed: Box<dyn std::error::Error> = Box::new(err.clone());
+    assert_eq!(boxed.to_string(), err.to_string());
+    assert_eq!(LangError::from("boom"), LangError::new("boom".to_string()));
+}
@@ -0,0 +1,172 @@
+// Tracing - spans for async tasks and RPC messages, exportable as OTLP/JSON
+// so a run can be inspected in standard tooling (Jaeger, Tempo, an OTLP
+// collector's file receiver). Recording is off until `trace(true)` or the
+// `trace = on` config key turns it on.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent: Option<u64>,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: Option<SystemTime>,  // None while the span is still open
+    pub attributes: Vec<(String, String)>,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Log {
+    enabled: bool,
+    spans: Vec<Span>,
+    open: Vec<usize>,  // Indices into spans, innermost last
+    seed: u64,         // Mixed into ids so separate runs do not collide
+    next: u64,
+}
+
+// Shared handle: the runtime and every proxy it connects record into one log
+#[derive(Clone, Default)]
+pub struct Tracer(Rc<RefCell<Log>>);
+
+// An open span; pass it back to Tracer::end. None when tracing is off.
+pub type SpanGuard = Option<usize>;
+
+impl Tracer {
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut log = self.0.borrow_mut();
+        if log.seed == 0 {
+            log.seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64) | 1;
+        }
+        log.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.borrow().enabled
+    }
+
+    // Open a span as a child of the innermost open span, or as the root of a
+    // new trace when nothing is open
+    pub fn start(&self, name: &str, attributes: &[(&str, String)]) -> SpanGuard {
+        let mut log = self.0.borrow_mut();
+        if !log.enabled {
+            return None;
+        }
+        log.next += 1;
+        let span_id = mix(log.seed, log.next);
+        let parent = log.open.last().map(|&i| &log.spans[i]);
+        let (trace_id, parent) = match parent {
+            Some(parent) => (parent.trace_id, Some(parent.span_id)),
+            None => ((span_id as u128) << 64 | mix(span_id, log.seed) as u128, None),
+        };
+        log.spans.push(Span {
+            trace_id,
+            span_id,
+            parent,
+            name: name.to_string(),
+            start: SystemTime::now(),
+            end: None,
+            attributes: attributes.iter().map(|(k, v)| (k.to_string(), v.clone())).collect(),
+            error: None,
+        });
+        let index = log.spans.len() - 1;
+        log.open.push(index);
+        Some(index)
+    }
+
+    pub fn end(&self, guard: SpanGuard, error: Option<&str>) {
+        let Some(index) = guard else { return };
+        let mut log = self.0.borrow_mut();
+        log.open.retain(|&i| i != index);
+        if let Some(span) = log.spans.get_mut(index) {
+            span.end = Some(SystemTime::now());
+            span.error = error.map(str::to_string);
+        }
+    }
+
+    // A span with no duration, for points in time such as a spawn
+    pub fn event(&self, name: &str, attributes: &[(&str, String)]) {
+        let guard = self.start(name, attributes);
+        self.end(guard, None);
+    }
+
+    pub fn spans(&self) -> Vec<Span> {
+        self.0.borrow().spans.clone()
+    }
+
+    pub fn clear(&self) {
+        let mut log = self.0.borrow_mut();
+        log.spans.clear();
+        log.open.clear();
+    }
+
+    // The recorded spans as an OTLP/JSON ExportTraceServiceRequest
+    pub fn to_otlp_json(&self, service: &str) -> String {
+        let spans: Vec<String> = self.0.borrow().spans.iter().map(span_json).collect();
+        format!(
+            "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"rust_ai_lang\"}},\"spans\":[{}]}}]}}]}}",
+            attribute_json("service.name", service),
+            spans.join(",")
+        )
+    }
+}
+
+fn span_json(span: &Span) -> String {
+    let parent = span.parent.map(|id| format!("\"parentSpanId\":\"{:016x}\",", id)).unwrap_or_default();
+    // rpc.* spans talk to another process, so they are client spans (3); the rest are internal (1)
+    let kind = if span.name.starts_with("rpc.") { 3 } else { 1 };
+    let status = match &span.error {
+        Some(message) => format!("{{\"code\":2,\"message\":{}}}", quote(message)),
+        None => "{\"code\":1}".to_string(),
+    };
+    let attributes: Vec<String> = span.attributes.iter().map(|(k, v)| attribute_json(k, v)).collect();
+    format!(
+        "{{\"traceId\":\"{:032x}\",\"spanId\":\"{:016x}\",{}\"name\":{},\"kind\":{},\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\"status\":{}}}",
+        span.trace_id,
+        span.span_id,
+        parent,
+        quote(&span.name),
+        kind,
+        nanos(span.start),
+        nanos(span.end.unwrap_or(span.start)),
+        attributes.join(","),
+        status
+    )
+}
+
+fn attribute_json(key: &str, value: &str) -> String {
+    format!("{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}", quote(key), quote(value))
+}
+
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+}
+
+// JSON string literal
+fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// splitmix64 step, so ids look random but never repeat within a run
+fn mix(seed: u64, n: u64) -> u64 {
+    let mut z = seed.wrapping_add(n.wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (z ^ (z >> 31)).max(1)
+}
@@ -0,0 +1,429 @@
+// :translate - one input shown in all three languages. Rho and Tau input is
+// parsed to the shared Expr tree; Pi input is run over a stack of expressions
+// instead of values, so `3 4 + 2 *` becomes Mul(Add(3, 4), 2). The tree is
+// then printed back infix for Rho and Tau, with only the parentheses the
+// precedence needs, and postfix for Pi.
+//
+// Rho and Tau share the grammar, so they read and print the same. Pi has no
+// indexing, ranges or loops; asking for those says so rather than printing
+// something else.
+use crate::lexer::{tokenize, TokenKind};
+use crate::pi::{adjacent, is_array_word, is_stack_word};
+use crate::rho::{Binary, Parser};
+use crate::runtime::{Case, Expr, Language, Site};
+use crate::value::Value;
+
+// A target language and the input written in it, or why it cannot be
+pub type Translation = (Language, Result<String, String>);
+
+// One statement: an expression, bound to a name when there is one
+pub struct Statement {
+    pub name: Option<String>,
+    pub expr: Expr,
+}
+
+pub fn parse(language: Language, source: &str) -> Result<Vec<Statement>, String> {
+    match language {
+        Language::Pi => parse_pi(source),
+        Language::Rho | Language::Tau => Ok(statements(crate::rho::parse_rho(source)?)),
+    }
+}
+
+// The statements in `language`'s syntax, on one line
+pub fn print(language: Language, statements: &[Statement]) -> Result<String, String> {
+    let mut lines = Vec::with_capacity(statements.len());
+    for statement in statements {
+        let line = match (language, &statement.name) {
+            (Language::Pi, None) => postfix(&statement.expr)?,
+            (Language::Pi, Some(name)) => format!("{} {} =", postfix(&statement.expr)?, quote(name)),
+            (_, None) => infix(&statement.expr, 0)?,
+            (_, Some(name)) => format!("{} = {}", name, infix(&statement.expr, 0)?),
+        };
+        lines.push(line);
+    }
+    // Pi runs a line left to right, so its statements need no separator
+    Ok(lines.join(if language == Language::Pi { " " } else { "; " }))
+}
+
+// The input in each of the other two languages, or why it has no form there
+pub fn translate(language: Language, source: &str) -> Result<Vec<Translation>, String> {
+    let statements = parse(language, source)?;
+    Ok([Language::Pi, Language::Rho, Language::Tau]
+        .into_iter()
+        .filter(|other| *other != language)
+        .map(|other| (other, print(other, &statements)))
+        .collect())
+}
+
+fn statements(expr: Expr) -> Vec<Statement> {
+    let statement = |expr| match expr {
+        Expr::Assign(name, value) => Statement { name: Some(name), expr: *value },
+        expr => Statement { name: None, expr },
+    };
+    match expr {
+        Expr::Block(exprs) => exprs.into_iter().map(statement).collect(),
+        expr => vec![statement(expr)],
+    }
+}
+
+// Pi read onto a stack of expressions. Bindings become statements where they
+// happen; whatever is left on the stack follows them.
+fn parse_pi(source: &str) -> Result<Vec<Statement>, String> {
+    let tokens = tokenize(source)?;
+    let mut stack: Vec<Expr> = Vec::new();
+    let mut statements = Vec::new();
+    let mut pos = 0;
+    while pos < tokens.len() {
+        let token = &tokens[pos];
+        pos += 1;
+        let binary: Option<Binary> = match &token.kind {
+            TokenKind::Plus => Some(Expr::Add),
+            TokenKind::Minus if adjacent(&tokens, pos).is_none() => Some(Expr::Sub),
+            TokenKind::Star => Some(Expr::Mul),
+            TokenKind::Slash => Some(Expr::Div),
+            TokenKind::Percent => Some(Expr::Mod),
+            TokenKind::Power => Some(Expr::Pow),
+            TokenKind::EqEq => Some(Expr::Equal),
+            TokenKind::Less => Some(Expr::Less),
+            TokenKind::Greater => Some(Expr::Greater),
+            TokenKind::AndAnd => Some(Expr::And),
+            TokenKind::OrOr => Some(Expr::Or),
+            TokenKind::Ident(name) if name == "and" => Some(Expr::And),
+            TokenKind::Ident(name) if name == "or" => Some(Expr::Or),
+            _ => None,
+        };
+        if let Some(make) = binary {
+            let (a, b) = pop_two(&mut stack, &token.kind.to_string())?;
+            stack.push(make(Box::new(a), Box::new(b)));
+            continue;
+        }
+        match &token.kind {
+            TokenKind::Minus => {
+                let n = adjacent(&tokens, pos).unwrap_or_default();
+                stack.push(Expr::Value(Value::Num(-n)));
+                pos += 1;
+            }
+            TokenKind::Bang => {
+                let a = stack.pop().ok_or("Not enough operands for not")?;
+                stack.push(Expr::Not(Box::new(a)));
+            }
+            TokenKind::Ident(name) if name == "not" => {
+                let a = stack.pop().ok_or("Not enough operands for not")?;
+                stack.push(Expr::Not(Box::new(a)));
+            }
+            TokenKind::Assign => match (stack.pop(), stack.pop()) {
+                (Some(Expr::Value(Value::Str(name))), Some(value)) => {
+                    statements.push(Statement { name: Some(name.to_string()), expr: value });
+                }
+                (Some(_), Some(_)) => return Err("Variable name must be a string".to_string()),
+                _ => return Err("Not enough operands for =".to_string()),
+            },
+            TokenKind::Ident(name) if is_stack_word(name) => {
+                let needed = match name.as_str() {
+                    "dup" | "drop" => 1,
+                    "swap" | "over" => 2,
+                    _ => 3,
+                };
+                if stack.len() < needed {
+                    return Err(format!("Not enough operands for {}", name));
+                }
+                let n = stack.len();
+                match name.as_str() {
+                    "dup" => stack.push(stack[n - 1].clone()),
+                    "drop" => drop(stack.pop()),
+                    "swap" => stack.swap(n - 1, n - 2),
+                    "over" => stack.push(stack[n - 2].clone()),
+                    _ => {
+                        let a = stack.remove(n - 3);
+                        stack.push(a);
+                    }
+                }
+            }
+            // Array words, unless called Rho style as sort(...)
+            TokenKind::Ident(name) if is_array_word(name) && tokens.get(pos).map(|t| &t.kind) != Some(&TokenKind::LParen) => {
+                let needed = match (name.as_str(), stack.last()) {
+                    ("contains", _) | ("sort", Some(Expr::Lambda(..))) => 2,
+                    _ => 1,
+                };
+                if stack.len() < needed {
+                    return Err(format!("Not enough operands for {}", name));
+                }
+                let args = stack.split_off(stack.len() - needed);
+                stack.push(Expr::Call(name.clone(), args, Site::default()));
+            }
+            TokenKind::Ident(name) if matches!(name.as_str(), "suspend" | "resume") => {
+                return Err(format!("{} has no form outside Pi", name));
+            }
+            TokenKind::Arrow | TokenKind::Dot => return Err(format!("{} has no form outside Pi", token.kind)),
+            TokenKind::Num(n) => stack.push(Expr::Value(Value::Num(*n))),
+            TokenKind::Str(s) => stack.push(Expr::Value(Value::Str(s.as_str().into()))),
+            TokenKind::Command(cmd) => stack.push(Expr::Call("sh".to_string(), vec![Expr::Value(Value::Str(cmd.as_str().into()))], Site::default())),
+            TokenKind::Ident(name) if name == "true" || name == "false" => stack.push(Expr::Value(Value::Bool(name == "true"))),
+            TokenKind::Ident(name) if name == "none" => stack.push(Expr::Value(Value::None)),
+            TokenKind::Ident(name) if tokens.get(pos).map(|t| &t.kind) != Some(&TokenKind::LParen) => {
+                stack.push(Expr::Var(name.clone()));
+            }
+            // Literals and calls use the Rho grammar, as in pi.rs
+            TokenKind::Ident(_) | TokenKind::LBracket => {
+                let mut parser = Parser::at(&tokens, pos - 1);
+                stack.push(parser.parse_primary()?);
+                pos = parser.position();
+            }
+            TokenKind::Newline | TokenKind::Indent(_) => {}
+            other => return Err(format!("Unexpected {} at {}:{}", other, token.line, token.col)),
+        }
+    }
+    statements.extend(stack.into_iter().map(|expr| Statement { name: None, expr }));
+    Ok(statements)
+}
+
+fn pop_two(stack: &mut Vec<Expr>, op: &str) -> Result<(Expr, Expr), String> {
+    if stack.len() < 2 {
+        return Err(format!("Not enough operands for {}", op));
+    }
+    let b = stack.pop().unwrap();
+    let a = stack.pop().unwrap();
+    Ok((a, b))
+}
+
+// Binding levels of the Rho grammar, loosest first (see rho.rs)
+const CHOICE: u8 = 0;
+const DEFAULT: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+const COMPARISON: u8 = 5;
+const RANGE: u8 = 6;
+const ADDITIVE: u8 = 7;
+const TERM: u8 = 8;
+const UNARY: u8 = 9;
+const POSTFIX: u8 = 10;
+const PRIMARY: u8 = 11;
+
+fn level(expr: &Expr) -> u8 {
+    match expr {
+        _ if negation(expr).is_some() => UNARY,
+        Expr::Choice(..) => CHOICE,
+        Expr::Default(..) => DEFAULT,
+        Expr::Or(..) => OR,
+        Expr::And(..) => AND,
+        Expr::Not(inner) if matches!(**inner, Expr::Equal(..)) => COMPARISON,
+        Expr::Not(_) => NOT,
+        Expr::Less(..) | Expr::Greater(..) | Expr::Equal(..) => COMPARISON,
+        Expr::Range(..) => RANGE,
+        Expr::Add(..) | Expr::Sub(..) => ADDITIVE,
+        Expr::Mul(..) | Expr::Div(..) | Expr::Mod(..) => TERM,
+        Expr::Value(Value::Num(n)) if *n < 0.0 => UNARY,
+        Expr::Pow(..) => UNARY,
+        Expr::Get(..) | Expr::Slice(..) | Expr::Field(..) | Expr::SafeField(..) => POSTFIX,
+        // A lambda's body runs to the end of the expression
+        Expr::Lambda(..) => CHOICE,
+        _ => PRIMARY,
+    }
+}
+
+// Infix source for Rho and Tau, parenthesised where it binds looser than `min`
+fn infix(expr: &Expr, min: u8) -> Result<String, String> {
+    let text = match expr {
+        Expr::Value(value) => literal(value)?,
+        Expr::Var(name) => name.clone(),
+        Expr::Array(items) => format!("[{}]", list(items)?),
+        Expr::Map(pairs) => {
+            let entries: Result<Vec<String>, String> =
+                pairs.iter().map(|(k, v)| Ok(format!("{{{}, {}}}", infix(k, 0)?, infix(v, 0)?))).collect();
+            format!("[{}]", entries?.join(", "))
+        }
+        Expr::Call(name, args, _) => match (name.as_str(), args.as_slice()) {
+            ("sh", [Expr::Value(Value::Str(cmd))]) => format!("`{}`", cmd),
+            _ => format!("{}({})", name, list(args)?),
+        },
+        Expr::Choice(a, b) => format!("{} | {}", infix(a, CHOICE)?, infix(b, DEFAULT)?),
+        Expr::Default(a, b) => format!("{} ?? {}", infix(a, DEFAULT)?, infix(b, OR)?),
+        Expr::Or(a, b) => format!("{} or {}", infix(a, OR)?, infix(b, AND)?),
+        Expr::And(a, b) => format!("{} and {}", infix(a, AND)?, infix(b, NOT)?),
+        Expr::Not(inner) => match inner.as_ref() {
+            Expr::Equal(a, b) => format!("{} != {}", infix(a, COMPARISON)?, infix(b, RANGE)?),
+            inner => format!("not {}", infix(inner, NOT)?),
+        },
+        Expr::Less(a, b) => format!("{} < {}", infix(a, COMPARISON)?, infix(b, RANGE)?),
+        Expr::Greater(a, b) => format!("{} > {}", infix(a, COMPARISON)?, infix(b, RANGE)?),
+        Expr::Equal(a, b) => format!("{} == {}", infix(a, COMPARISON)?, infix(b, RANGE)?),
+        Expr::Range(a, b, inclusive) => {
+            let op = if *inclusive { "..=" } else { ".." };
+            format!("{}{}{}", infix(a, ADDITIVE)?, op, infix(b, ADDITIVE)?)
+        }
+        _ if let Some(operand) = negation(expr) => format!("-{}", infix(operand, UNARY)?),
+        Expr::Add(a, b) => format!("{} + {}", infix(a, ADDITIVE)?, infix(b, TERM)?),
+        Expr::Sub(a, b) => format!("{} - {}", infix(a, ADDITIVE)?, infix(b, TERM)?),
+        Expr::Mul(a, b) => format!("{} * {}", infix(a, TERM)?, infix(b, UNARY)?),
+        Expr::Div(a, b) => format!("{} / {}", infix(a, TERM)?, infix(b, UNARY)?),
+        Expr::Mod(a, b) => format!("{} % {}", infix(a, TERM)?, infix(b, UNARY)?),
+        // Right associative, and the base binds tighter than unary minus
+        Expr::Pow(a, b) => format!("{} ** {}", infix(a, POSTFIX)?, infix(b, UNARY)?),
+        Expr::Get(target, key) => format!("{}[{}]", infix(target, POSTFIX)?, infix(key, 0)?),
+        Expr::Field(target, field) => format!("{}.{}", infix(target, POSTFIX)?, field),
+        Expr::SafeField(target, field) => format!("{}?.{}", infix(target, POSTFIX)?, field),
+        Expr::Slice(target, start, end) => {
+            let bound = |bound: &Option<Box<Expr>>| bound.as_ref().map_or(Ok(String::new()), |b| infix(b, 0));
+            format!("{}[{}:{}]", infix(target, POSTFIX)?, bound(start)?, bound(end)?)
+        }
+        Expr::Assign(name, value) => format!("{} = {}", name, infix(value, 0)?),
+        Expr::Unpack(pattern, value) => format!("{} = {}", pattern, infix(value, 0)?),
+        Expr::Let(name, value, constant) => format!("{} {} = {}", if *constant { "const" } else { "let" }, name, infix(value, 0)?),
+        Expr::SetIndex(name, key, value) => format!("{}[{}] = {}", name, infix(key, 0)?, infix(value, 0)?),
+        Expr::Lambda(params, body) => format!("fn({}) {}", params.join(", "), infix(body, 0)?),
+        Expr::Struct(name, fields) => format!("struct {}({})", name, fields.join(", ")),
+        Expr::Enum(name, variants) => {
+            let variants: Vec<String> = variants
+                .iter()
+                .map(|(variant, fields)| match fields {
+                    Some(fields) => format!("{}({})", variant, fields.join(", ")),
+                    None => variant.clone(),
+                })
+                .collect();
+            format!("enum {} = {}", name, variants.join(" | "))
+        }
+        Expr::Match(value, arms) => {
+            let arms: Result<Vec<String>, String> = arms
+                .iter()
+                .map(|(case, body)| {
+                    let case = match case {
+                        Case::Any => "_".to_string(),
+                        Case::Literal(value) => literal(value)?,
+                        Case::Variant(name, Some(names)) => format!("{}({})", name, names.join(", ")),
+                        Case::Variant(name, None) => name.clone(),
+                    };
+                    Ok(format!("{} => {}", case, infix(body, 0)?))
+                })
+                .collect();
+            format!("match {} {{ {} }}", infix(value, 0)?, arms?.join(", "))
+        }
+        Expr::Block(exprs) => format!("{{ {} }}", statements_text(exprs)?),
+        Expr::For(var, iterable, body) => format!("for {} in {} {}", var, infix(iterable, 0)?, infix(body, 0)?),
+        Expr::ForEntries(key, value, iterable, body) => {
+            format!("for {}, {} in {} {}", key, value, infix(iterable, 0)?, infix(body, 0)?)
+        }
+        Expr::ForUnpack(pattern, iterable, body) => format!("for {} in {} {}", pattern, infix(iterable, 0)?, infix(body, 0)?),
+        Expr::While(condition, body) => format!("while {} {}", infix(condition, 0)?, infix(body, 0)?),
+        Expr::Try(body, catch, finally) => {
+            let mut text = format!("try {}", infix(body, 0)?);
+            if let Some((name, handler)) = catch {
+                text.push_str(&format!(" catch {} {}", name, infix(handler, 0)?));
+            }
+            if let Some(finally) = finally {
+                text.push_str(&format!(" finally {}", infix(finally, 0)?));
+            }
+            text
+        }
+        other => return Err(format!("No translation for {}", describe(other))),
+    };
+    Ok(if level(expr) < min { format!("({})", text) } else { text })
+}
+
+// Rho reads -x as 0 - x, so that is written back as -x. Not for a number,
+// which -n would make a negative literal instead.
+fn negation(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::Sub(zero, operand) if matches!(**zero, Expr::Value(Value::Num(n)) if n == 0.0) && !matches!(**operand, Expr::Value(Value::Num(_))) => {
+            Some(operand)
+        }
+        _ => None,
+    }
+}
+
+fn list(items: &[Expr]) -> Result<String, String> {
+    let items: Result<Vec<String>, String> = items.iter().map(|item| infix(item, 0)).collect();
+    Ok(items?.join(", "))
+}
+
+fn statements_text(exprs: &[Expr]) -> Result<String, String> {
+    let exprs: Result<Vec<String>, String> = exprs.iter().map(|expr| infix(expr, 0)).collect();
+    Ok(exprs?.join("; "))
+}
+
+// Postfix source for Pi. Literals and calls other than Pi's own words are
+// written as in Rho, which Pi reads too.
+fn postfix(expr: &Expr) -> Result<String, String> {
+    let binary = |a: &Expr, b: &Expr, op: &str| -> Result<String, String> { Ok(format!("{} {} {}", postfix(a)?, postfix(b)?, op)) };
+    match expr {
+        Expr::Value(value) => literal(value),
+        Expr::Var(name) => Ok(name.clone()),
+        Expr::Add(a, b) => binary(a, b, "+"),
+        Expr::Sub(a, b) => binary(a, b, "-"),
+        Expr::Mul(a, b) => binary(a, b, "*"),
+        Expr::Div(a, b) => binary(a, b, "/"),
+        Expr::Mod(a, b) => binary(a, b, "%"),
+        Expr::Pow(a, b) => binary(a, b, "**"),
+        Expr::Less(a, b) => binary(a, b, "<"),
+        Expr::Greater(a, b) => binary(a, b, ">"),
+        Expr::Equal(a, b) => binary(a, b, "=="),
+        Expr::And(a, b) => binary(a, b, "and"),
+        Expr::Or(a, b) => binary(a, b, "or"),
+        Expr::Not(inner) => Ok(format!("{} not", postfix(inner)?)),
+        Expr::Call(name, args, _) => match (name.as_str(), args.as_slice()) {
+            ("sh", [Expr::Value(Value::Str(cmd))]) => Ok(format!("`{}`", cmd)),
+            ("sort" | "reverse" | "unique" | "len", [arg]) => Ok(format!("{} {}", postfix(arg)?, name)),
+            ("contains", [a, b]) | ("sort", [a, b @ Expr::Lambda(..)]) => binary(a, b, name),
+            _ => infix(expr, PRIMARY),
+        },
+        Expr::Array(_) | Expr::Map(_) => infix(expr, PRIMARY),
+        // Pi reads a lambda through the Rho grammar, so it has no parentheses
+        Expr::Lambda(..) => infix(expr, 0),
+        other => Err(format!("Pi has no form for {}", describe(other))),
+    }
+}
+
+fn literal(value: &Value) -> Result<String, String> {
+    match value {
+        Value::Num(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Str(s) => Ok(quote(s)),
+        Value::None => Ok("none".to_string()),
+        other => Err(format!("No literal for {:?}", other)),
+    }
+}
+
+// A string literal the lexer reads back as the same text
+fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// What a construct is called in messages: indexing, a range, a for loop
+fn describe(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Get(..) => "indexing",
+        Expr::Slice(..) => "slicing",
+        Expr::SetIndex(..) => "index assignment",
+        Expr::Range(..) => "a range",
+        Expr::Choice(..) => "continuation choice",
+        Expr::Block(_) => "a block",
+        Expr::For(..) | Expr::ForEntries(..) | Expr::ForUnpack(..) => "a for loop",
+        Expr::Unpack(..) => "destructuring",
+        Expr::While(..) => "a while loop",
+        Expr::Try(..) => "try",
+        Expr::Generator(..) | Expr::Yield(_) => "a generator",
+        Expr::Struct(..) | Expr::Record(..) | Expr::Field(..) => "a struct",
+        Expr::Enum(..) => "an enum",
+        Expr::SafeField(..) | Expr::Default(..) => "a none check",
+        Expr::Match(..) => "match",
+        Expr::Spawn(..) | Expr::Scope(_) => "structured concurrency",
+        Expr::Async(..) => "async",
+        Expr::Return(_) => "return",
+        Expr::Throw(_) => "throw",
+        Expr::Let(_, _, false) => "let",
+        Expr::Let(_, _, true) => "const",
+        _ => "this construct",
+    }
+}
@@ -0,0 +1,700 @@
+// Transports - how a proxy's calls reach an agent and the replies come back.
+// A Transport moves Messages; a Service is whatever answers the calls. The
+// loopback transport delivers straight to a Service in the same process, so a
+// proxy and agent can be exercised end to end without sockets. Out of process,
+// messages travel one per line over a child's stdin/stdout, a Unix socket or TCP:
+//
+//   call 1 add [1, 2]
+//   reply 1 ok 3
+//   reply 2 err "Calculator has no method mul"
+//   ping 3
+//   pong 3
+//   hello 4 lz
+//
+// Values are written as Rho literals, plus `unit` and `map()` for the unit
+// value and the empty map.
+use crate::compress;
+use crate::interface::Interface;
+use crate::lexer::tokenize;
+use crate::mock::{Mock, MockAgent};
+use crate::rho::Parser;
+use crate::runtime::Expr;
+use crate::tracing::Tracer;
+use crate::value::{Color, Function, Map, Range, Value};
+use std::cell::{RefCell, RefMut};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Call { id: u64, method: String, args: Vec<Value> },
+    Reply { id: u64, result: Result<Value, String> },
+    Ping { id: u64 },  // Keepalive; answered with a Pong of the same id
+    Pong { id: u64 },
+    Hello { id: u64, features: Vec<String> },  // Offers wire features; the answer lists those agreed
+}
+
+impl Message {
+    pub fn id(&self) -> u64 {
+        match self {
+            Message::Call { id, .. }
+            | Message::Reply { id, .. }
+            | Message::Ping { id }
+            | Message::Pong { id }
+            | Message::Hello { id, .. } => *id,
+        }
+    }
+
+    // The protocol word for the message, as in `call 1 add [1, 2]`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Call { .. } => "call",
+            Message::Reply { .. } => "reply",
+            Message::Ping { .. } => "ping",
+            Message::Pong { .. } => "pong",
+            Message::Hello { .. } => "hello",
+        }
+    }
+}
+
+pub trait Transport {
+    fn send(&mut self, message: Message) -> Result<(), String>;
+    fn recv(&mut self) -> Result<Message, String>;
+    fn name(&self) -> &str;  // Shown when a proxy is printed
+
+    // Wire features this transport can switch on once both ends agree
+    fn features(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn enable(&mut self, _feature: &str) {}
+
+    fn stats(&self) -> WireStats {
+        WireStats::default()
+    }
+}
+
+// Bytes through a transport, before (raw) and after (wire) compression
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WireStats {
+    pub raw: u64,
+    pub wire: u64,
+}
+
+impl WireStats {
+    pub fn saved(&self) -> u64 {
+        self.raw.saturating_sub(self.wire)
+    }
+}
+
+// The agent side: answers one call at a time
+pub trait Service {
+    fn handle(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String>;
+}
+
+impl Service for MockAgent {
+    fn handle(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.call(method, args)
+    }
+}
+
+impl Service for Mock {
+    fn handle(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.borrow_mut().call(method, args)
+    }
+}
+
+// The answer a service gives to a call or ping; other messages get none
+pub fn dispatch(service: &mut dyn Service, message: Message) -> Option<Message> {
+    match message {
+        Message::Call { id, method, args } => Some(Message::Reply { id, result: service.handle(&method, args) }),
+        Message::Ping { id } => Some(Message::Pong { id }),
+        // Features belong to transports; serve negotiates them before calls get here
+        Message::Hello { id, .. } => Some(Message::Hello { id, features: Vec::new() }),
+        Message::Reply { .. } | Message::Pong { .. } => None,
+    }
+}
+
+// In-memory transport: each sent call is handled on the spot and its reply
+// queued for the next recv
+pub struct Loopback {
+    service: Box<dyn Service>,
+    inbox: VecDeque<Message>,
+}
+
+impl Loopback {
+    pub fn new(service: Box<dyn Service>) -> Self {
+        Loopback { service, inbox: VecDeque::new() }
+    }
+}
+
+impl Transport for Loopback {
+    fn send(&mut self, message: Message) -> Result<(), String> {
+        if let Some(reply) = dispatch(self.service.as_mut(), message) {
+            self.inbox.push_back(reply);
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<Message, String> {
+        self.inbox.pop_front().ok_or_else(|| "Nothing to receive on loopback".to_string())
+    }
+
+    fn name(&self) -> &str {
+        "loopback"
+    }
+}
+
+// Answer calls until the connection fails or closes, returning why
+pub fn serve(transport: &mut dyn Transport, service: &mut dyn Service) -> Result<(), String> {
+    loop {
+        match transport.recv()? {
+            // Agree to the offered features this end supports, then use them
+            Message::Hello { id, features } => {
+                let supported = transport.features();
+                let agreed: Vec<String> = features.into_iter().filter(|f| supported.contains(f)).collect();
+                transport.send(Message::Hello { id, features: agreed.clone() })?;
+                for feature in &agreed {
+                    transport.enable(feature);
+                }
+            }
+            message => {
+                if let Some(reply) = dispatch(service, message) {
+                    transport.send(reply)?;
+                }
+            }
+        }
+    }
+}
+
+// One message per line over any byte stream. Once "lz" is enabled, lines that
+// shrink are sent as `z <base64 of the compressed line>`; either form is
+// accepted on receive.
+pub struct LineTransport<R: BufRead, W: Write> {
+    name: String,
+    reader: R,
+    writer: W,
+    compress: bool,
+    sent: WireStats,
+    received: WireStats,
+}
+
+impl<R: BufRead, W: Write> LineTransport<R, W> {
+    pub fn new(name: &str, reader: R, writer: W) -> Self {
+        LineTransport {
+            name: name.to_string(),
+            reader,
+            writer,
+            compress: false,
+            sent: WireStats::default(),
+            received: WireStats::default(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Transport for LineTransport<R, W> {
+    fn send(&mut self, message: Message) -> Result<(), String> {
+        let mut line = encode_message(&message)?;
+        self.sent.raw += line.len() as u64 + 1;
+        if self.compress {
+            let packed = format!("z {}", compress::to_base64(&compress::compress(line.as_bytes())));
+            if packed.len() < line.len() {
+                line = packed;
+            }
+        }
+        self.sent.wire += line.len() as u64 + 1;
+        writeln!(self.writer, "{}", line)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("Failed to send on {}: {}", self.name, e))
+    }
+
+    fn recv(&mut self) -> Result<Message, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => return Err(format!("{} closed the connection", self.name)),
+            Ok(n) => self.received.wire += n as u64,
+            Err(e) => return Err(format!("Failed to receive on {}: {}", self.name, e)),
+        }
+        let mut line = line.trim_end().to_string();
+        if let Some(packed) = line.strip_prefix("z ") {
+            let bytes = compress::decompress(&compress::from_base64(packed)?)?;
+            line = String::from_utf8(bytes).map_err(|_| "Compressed message is not UTF-8".to_string())?;
+        }
+        self.received.raw += line.len() as u64 + 1;
+        decode_message(&line)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn features(&self) -> Vec<String> {
+        vec!["lz".to_string()]
+    }
+
+    fn enable(&mut self, feature: &str) {
+        if feature == "lz" {
+            self.compress = true;
+        }
+    }
+
+    // Both directions together
+    fn stats(&self) -> WireStats {
+        WireStats { raw: self.sent.raw + self.received.raw, wire: self.sent.wire + self.received.wire }
+    }
+}
+
+// Talks to a child process over its stdin and stdout, so any program that
+// speaks the line protocol (a script wrapping a CLI tool, say) can be an agent
+pub struct ChildTransport {
+    child: Child,
+    lines: LineTransport<BufReader<ChildStdout>, ChildStdin>,
+}
+
+impl ChildTransport {
+    // Runs `command` through sh
+    pub fn spawn(command: &str) -> Result<Self, String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", command, e))?;
+        let stdin = child.stdin.take().ok_or("Child stdin unavailable")?;
+        let stdout = child.stdout.take().ok_or("Child stdout unavailable")?;
+        let lines = LineTransport::new(&format!("stdio:{}", command), BufReader::new(stdout), stdin);
+        Ok(ChildTransport { child, lines })
+    }
+}
+
+impl Transport for ChildTransport {
+    fn send(&mut self, message: Message) -> Result<(), String> {
+        self.lines.send(message)
+    }
+
+    fn recv(&mut self) -> Result<Message, String> {
+        self.lines.recv()
+    }
+
+    fn name(&self) -> &str {
+        self.lines.name()
+    }
+
+    fn features(&self) -> Vec<String> {
+        self.lines.features()
+    }
+
+    fn enable(&mut self, feature: &str) {
+        self.lines.enable(feature)
+    }
+
+    fn stats(&self) -> WireStats {
+        self.lines.stats()
+    }
+}
+
+// The child does not outlive its connection
+impl Drop for ChildTransport {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(unix)]
+pub type UnixTransport = LineTransport<BufReader<UnixStream>, UnixStream>;
+
+// Either end of a Unix socket connection
+#[cfg(unix)]
+pub fn unix_transport(name: &str, stream: UnixStream) -> Result<UnixTransport, String> {
+    let reader = stream.try_clone().map_err(|e| format!("Failed to clone {}: {}", name, e))?;
+    Ok(LineTransport::new(name, BufReader::new(reader), stream))
+}
+
+pub type TcpTransport = LineTransport<BufReader<TcpStream>, TcpStream>;
+
+// The proxy end of a connection to an agent served over TCP (see rpc.rs)
+pub fn tcp_transport(name: &str, stream: TcpStream) -> Result<TcpTransport, String> {
+    let reader = stream.try_clone().map_err(|e| format!("Failed to clone {}: {}", name, e))?;
+    Ok(LineTransport::new(name, BufReader::new(reader), stream))
+}
+
+// Open a client transport for an address: stdio:<command>, unix:<path> or tcp:<host:port>
+pub fn open(address: &str) -> Result<Box<dyn Transport>, String> {
+    if let Some(command) = address.strip_prefix("stdio:") {
+        return Ok(Box::new(ChildTransport::spawn(command)?));
+    }
+    if let Some(host) = address.strip_prefix("tcp:") {
+        let stream = TcpStream::connect(host).map_err(|e| format!("Failed to connect to {}: {}", host, e))?;
+        return Ok(Box::new(tcp_transport(address, stream)?));
+    }
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        let stream = UnixStream::connect(path).map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        return Ok(Box::new(unix_transport(address, stream)?));
+    }
+    Err(format!("Unknown transport address {} (expected stdio:<command>, unix:<path> or tcp:<host:port>)", address))
+}
+
+pub fn encode_message(message: &Message) -> Result<String, String> {
+    Ok(match message {
+        Message::Call { id, method, args } => {
+            let args: Result<Vec<String>, String> = args.iter().map(encode_value).collect();
+            format!("call {} {} [{}]", id, method, args?.join(", "))
+        }
+        Message::Reply { id, result: Ok(value) } => format!("reply {} ok {}", id, encode_value(value)?),
+        Message::Reply { id, result: Err(e) } => format!("reply {} err {}", id, encode_value(&Value::Str(e.as_str().into()))?),
+        Message::Ping { id } => format!("ping {}", id),
+        Message::Pong { id } => format!("pong {}", id),
+        Message::Hello { id, features } => format!("hello {} {}", id, features.join(" ")).trim_end().to_string(),
+    })
+}
+
+pub fn decode_message(line: &str) -> Result<Message, String> {
+    let malformed = || format!("Malformed message: {}", line);
+    let mut parts = line.splitn(4, ' ');
+    let (kind, id, tag, rest) = (parts.next(), parts.next(), parts.next(), parts.next().unwrap_or(""));
+    let id: u64 = id.and_then(|id| id.parse().ok()).ok_or_else(malformed)?;
+    match (kind, tag) {
+        (Some("ping"), None) => Ok(Message::Ping { id }),
+        (Some("pong"), None) => Ok(Message::Pong { id }),
+        (Some("hello"), first) => {
+            let features = first.into_iter().chain(rest.split_whitespace()).map(str::to_string).collect();
+            Ok(Message::Hello { id, features })
+        }
+        (Some("call"), Some(method)) => match decode_value(rest)? {
+            Value::Array(args) => Ok(Message::Call { id, method: method.to_string(), args: args.into_vec() }),
+            _ => Err(malformed()),
+        },
+        (Some("reply"), Some("ok")) => Ok(Message::Reply { id, result: Ok(decode_value(rest)?) }),
+        (Some("reply"), Some("err")) => match decode_value(rest)? {
+            Value::Str(e) => Ok(Message::Reply { id, result: Err(e.to_string()) }),
+            _ => Err(malformed()),
+        },
+        _ => Err(malformed()),
+    }
+}
+
+pub fn encode_value(value: &Value) -> Result<String, String> {
+    Ok(match value {
+        Value::Num(n) if n.is_finite() => n.to_string(),
+        Value::Str(s) => {
+            let escaped = s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t");
+            format!("\"{}\"", escaped)
+        }
+        Value::Bool(b) => b.to_string(),
+        Value::Unit => "unit".to_string(),
+        Value::Color(c) if c.a == 255 => format!("color({}, {}, {})", c.r, c.g, c.b),
+        Value::Color(c) => format!("color({}, {}, {}, {})", c.r, c.g, c.b, c.a),
+        Value::Range(r) => format!("{}..{}", r.start, r.end),
+        Value::Array(items) => {
+            let items: Result<Vec<String>, String> = items.iter().map(encode_value).collect();
+            format!("[{}]", items?.join(", "))
+        }
+        Value::Map(map) if map.is_empty() => "map()".to_string(),
+        Value::Map(map) => {
+            let mut pairs = Vec::with_capacity(map.len());
+            for (key, value) in map.iter() {
+                pairs.push(format!("{{{}, {}}}", encode_value(key)?, encode_value(value)?));
+            }
+            format!("[{}]", pairs.join(", "))
+        }
+        other => return Err(format!("Cannot send {:?} over a transport", other)),
+    })
+}
+
+// Parsed with the Rho parser, but only literals are accepted: nothing a peer
+// sends is ever evaluated
+pub fn decode_value(text: &str) -> Result<Value, String> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser::new(&tokens);
+    let expr = parser.parse_expr()?;
+    if parser.position() != tokens.len() {
+        return Err(format!("Malformed value: {}", text));
+    }
+    literal(expr)
+}
+
+fn literal(expr: Expr) -> Result<Value, String> {
+    let whole = |expr: Expr| match literal(expr)? {
+        Value::Num(n) if n.fract() == 0.0 => Ok(n),
+        other => Err(format!("Expected a whole number, got {:?}", other)),
+    };
+    match expr {
+        Expr::Value(value) => Ok(value),
+        Expr::Var(name) if name == "unit" => Ok(Value::Unit),
+        Expr::Array(items) => Ok(Value::Array(items.into_iter().map(literal).collect::<Result<_, _>>()?)),
+        Expr::Map(pairs) => {
+            let pairs = pairs
+                .into_iter()
+                .map(|(k, v)| Ok((literal(k)?, literal(v)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(Value::Map(Map::from_pairs(pairs)?))
+        }
+        Expr::Call(name, args, _) if name == "map" && args.is_empty() => Ok(Value::Map(Map::new())),
+        Expr::Call(name, args, _) if name == "color" && (args.len() == 3 || args.len() == 4) => {
+            let mut channels = [0u8, 0, 0, 255];
+            for (channel, arg) in channels.iter_mut().zip(args) {
+                let n = whole(arg)?;
+                if !(0.0..=255.0).contains(&n) {
+                    return Err(format!("Color channel out of range: {}", n));
+                }
+                *channel = n as u8;
+            }
+            Ok(Value::Color(Color::rgba(channels[0], channels[1], channels[2], channels[3])))
+        }
+        Expr::Range(start, end, inclusive) => {
+            let (start, end) = (whole(*start)? as i64, whole(*end)? as i64);
+            Ok(Value::Range(Range { start, end: end + inclusive as i64 }))
+        }
+        other => Err(format!("Not a literal value: {:?}", other)),
+    }
+}
+
+// How a client with an address recovers a lost connection: up to `attempts`
+// reopens, waiting base, 2*base, 4*base... (at most max) before each
+#[derive(Debug, Clone, Copy)]
+pub struct Reconnect {
+    pub attempts: u32,
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect { attempts: 3, base: Duration::from_millis(100), max: Duration::from_secs(2) }
+    }
+}
+
+impl Reconnect {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.base.saturating_mul(2u32.saturating_pow(attempt)).min(self.max)
+    }
+}
+
+// The client end of a connection. Calls are checked against the interface
+// before they are sent, so a bad call never reaches the agent. When the
+// transport fails, a client opened from an address reconnects and sends the
+// call again, so agents should expect a call to arrive more than once.
+pub struct Client {
+    pub interface: Interface,
+    transport: Box<dyn Transport>,
+    address: Option<String>,        // Where to reconnect; None when built from a transport
+    features: Vec<String>,          // Wire features to offer on every (re)connect
+    next_id: u64,
+    pub reconnect: Reconnect,
+    pub keepalive: Option<Duration>,  // Ping first when idle at least this long
+    last_seen: Instant,
+    connected: bool,
+    disconnects: Vec<String>,       // Reasons not yet handed to the on_disconnect handlers
+    pub on_disconnect: Vec<Rc<Function>>,
+    pub tracer: Tracer,             // Records rpc.call, rpc.send and rpc.receive spans when enabled
+}
+
+impl Client {
+    pub fn new(interface: Interface, transport: Box<dyn Transport>) -> Self {
+        Client {
+            interface,
+            transport,
+            address: None,
+            features: Vec::new(),
+            next_id: 1,
+            reconnect: Reconnect::default(),
+            keepalive: None,
+            last_seen: Instant::now(),
+            connected: true,
+            disconnects: Vec::new(),
+            on_disconnect: Vec::new(),
+            tracer: Tracer::default(),
+        }
+    }
+
+    // Open `address`, offering `features` (e.g. "lz" for compression); an agent
+    // that does not know a feature leaves it off
+    pub fn connect(interface: Interface, address: &str, features: &[&str]) -> Result<Self, String> {
+        let mut client = Client::new(interface, open(address)?);
+        client.address = Some(address.to_string());
+        client.features = features.iter().map(|f| f.to_string()).collect();
+        client.handshake()?;
+        Ok(client)
+    }
+
+    pub fn call(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        let attributes = [("rpc.service", self.interface.name.clone()), ("rpc.method", method.to_string())];
+        let span = self.tracer.start("rpc.call", &attributes);
+        let result = self.send_call(method, args);
+        self.tracer.end(span, result.as_ref().err().map(String::as_str));
+        result
+    }
+
+    fn send_call(&mut self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.interface.check_call(method, &args)?;
+        // A quiet connection may have died; find out before sending real work
+        if let Some(keepalive) = self.keepalive
+            && self.last_seen.elapsed() >= keepalive
+            && let Err(e) = self.ping()
+        {
+            self.recover(e)?;
+        }
+        let id = self.next_id();
+        let call = Message::Call { id, method: method.to_string(), args };
+        let reply = match self.exchange(call.clone()) {
+            Ok(reply) => reply,
+            Err(e) => {
+                self.recover(e)?;
+                self.exchange(call)?
+            }
+        };
+        match reply {
+            Message::Reply { result, .. } => self.interface.check_return(method, result?),
+            other => Err(format!("Unexpected message from {}: {:?}", self.interface.name, other)),
+        }
+    }
+
+    // Round trip time of a ping
+    pub fn ping(&mut self) -> Result<Duration, String> {
+        let started = Instant::now();
+        let id = self.next_id();
+        self.exchange(Message::Ping { id })?;
+        Ok(started.elapsed())
+    }
+
+    pub fn transport(&self) -> &str {
+        self.transport.name()
+    }
+
+    pub fn stats(&self) -> WireStats {
+        self.transport.stats()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    // Disconnect reasons since the last call, for the on_disconnect handlers
+    pub fn take_disconnects(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.disconnects)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // Send a message and wait for the answer carrying the same id
+    fn exchange(&mut self, message: Message) -> Result<Message, String> {
+        let id = message.id();
+        let attributes = [("message.id", id.to_string()), ("message.kind", message.kind().to_string())];
+        let span = self.tracer.start("rpc.send", &attributes);
+        let sent = self.transport.send(message);
+        self.tracer.end(span, sent.as_ref().err().map(String::as_str));
+        sent?;
+        let span = self.tracer.start("rpc.receive", &attributes[..1]);
+        let answer = self.transport.recv();
+        self.tracer.end(span, answer.as_ref().err().map(String::as_str));
+        let answer = answer?;
+        if answer.id() != id {
+            return Err(format!("Unexpected message from {}: {:?}", self.interface.name, answer));
+        }
+        self.last_seen = Instant::now();
+        Ok(answer)
+    }
+
+    fn handshake(&mut self) -> Result<(), String> {
+        if self.features.is_empty() {
+            return Ok(());
+        }
+        let id = self.next_id();
+        if let Message::Hello { features, .. } = self.exchange(Message::Hello { id, features: self.features.clone() })? {
+            for feature in features.iter().filter(|f| self.features.contains(f)) {
+                self.transport.enable(feature);
+            }
+        }
+        Ok(())
+    }
+
+    // Record the disconnect, then reopen the address with backoff
+    fn recover(&mut self, reason: String) -> Result<(), String> {
+        self.connected = false;
+        self.disconnects.push(reason.clone());
+        let Some(address) = self.address.clone() else {
+            return Err(reason);
+        };
+        for attempt in 0..self.reconnect.attempts {
+            thread::sleep(self.reconnect.delay(attempt));
+            if let Ok(transport) = open(&address) {
+                self.transport = transport;
+                if self.handshake().is_ok() {
+                    self.connected = true;
+                    return Ok(());
+                }
+            }
+        }
+        Err(format!("{} (gave up after {} reconnect attempts)", reason, self.reconnect.attempts))
+    }
+}
+
+// Shared handle held by Value::Proxy; clones use the same connection
+#[derive(Clone)]
+pub struct Proxy(Rc<RefCell<Client>>);
+
+impl Proxy {
+    pub fn new(client: Client) -> Self {
+        Proxy(Rc::new(RefCell::new(client)))
+    }
+
+    // A proxy for `interface` over any transport
+    pub fn over(interface: Interface, transport: Box<dyn Transport>) -> Self {
+        Proxy::new(Client::new(interface, transport))
+    }
+
+    // A proxy wired to an in-process agent
+    pub fn loopback(agent: Mock) -> Self {
+        let interface = agent.borrow().interface.clone();
+        Proxy::new(Client::new(interface, Box::new(Loopback::new(Box::new(agent)))))
+    }
+
+    pub fn connect(interface: Interface, address: &str, features: &[&str]) -> Result<Self, String> {
+        Ok(Proxy::new(Client::connect(interface, address, features)?))
+    }
+
+    pub fn call(&self, method: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.0.borrow_mut().call(method, args)
+    }
+
+    pub fn has_method(&self, method: &str) -> bool {
+        self.0.borrow().interface.method(method).is_some()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, Client> {
+        self.0.borrow_mut()
+    }
+}
+
+impl PartialEq for Proxy {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Proxy(Calculator via loopback), or Proxy(Calculator via unix:/tmp/c.sock, disconnected)
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let client = self.0.borrow();
+        write!(f, "{} via {}", client.interface.name, client.transport())?;
+        if !client.is_connected() {
+            write!(f, ", disconnected")?;
+        }
+        Ok(())
+    }
+}
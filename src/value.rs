@@ -1,16 +1,65 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
+use std::time::SystemTime;
+use crate::mock::Mock;
+use crate::runtime::{Expr, Language, Scope};
+use crate::generator::Generator;
+use crate::metrics::Metric;
+use crate::mqtt::Client;
+use crate::net::Socket;
+use crate::pubsub::Subscription;
+use crate::secret::Secret;
+use crate::serial::Port;
+use crate::stream::Stream;
+use crate::transport::Proxy;
 
-// Color type - RGB with 0-255 values
+// How two colors are interpolated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    #[default]
+    Srgb,        // Average the raw sRGB bytes (fast, muddy midpoints)
+    Linear,      // Average in linear light (gamma correct)
+    Perceptual,  // Interpolate in OKLCH
+    Premultiplied,  // Weight each color by its alpha, so transparent ends lend no color
+    Hsl,         // Interpolate hue, saturation and lightness (vivid, rainbow-like sweeps)
+}
+
+impl BlendMode {
+    pub fn parse(name: &str) -> Result<BlendMode, String> {
+        match name.trim().to_lowercase().as_str() {
+            "srgb" => Ok(BlendMode::Srgb),
+            "linear" => Ok(BlendMode::Linear),
+            "perceptual" | "oklch" => Ok(BlendMode::Perceptual),
+            "premultiplied" => Ok(BlendMode::Premultiplied),
+            "hsl" => Ok(BlendMode::Hsl),
+            other => Err(format!("Unknown blend mode: {} (expected srgb, linear, perceptual, premultiplied or hsl)", other)),
+        }
+    }
+}
+
+// Color type - RGBA with 0-255 values; alpha 255 is opaque
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    pub a: u8,
 }
 
 impl Color {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Color { r, g, b }
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color { r, g, b, a }
+    }
+
+    pub fn with_alpha(&self, a: u8) -> Color {
+        Color { a, ..*self }
     }
 
     pub fn blend(&self, other: &Color) -> Color {
@@ -18,6 +67,7 @@ impl Color {
             r: ((self.r as u16 + other.r as u16) / 2) as u8,
             g: ((self.g as u16 + other.g as u16) / 2) as u8,
             b: ((self.b as u16 + other.b as u16) / 2) as u8,
+            a: ((self.a as u16 + other.a as u16) / 2) as u8,
         }
     }
 
@@ -28,44 +78,403 @@ impl Color {
             r: (self.r as f32 * inv_ratio + other.r as f32 * ratio) as u8,
             g: (self.g as f32 * inv_ratio + other.g as f32 * ratio) as u8,
             b: (self.b as f32 * inv_ratio + other.b as f32 * ratio) as u8,
+            a: (self.a as f32 * inv_ratio + other.a as f32 * ratio) as u8,
         }
     }
 
+    pub fn mix_with(&self, other: &Color, ratio: f32, mode: BlendMode) -> Color {
+        match mode {
+            BlendMode::Srgb => self.mix(other, ratio),
+            BlendMode::Linear => self.mix_linear(other, ratio),
+            BlendMode::Perceptual => self.mix_perceptual(other, ratio),
+            BlendMode::Premultiplied => self.mix_premultiplied(other, ratio),
+            BlendMode::Hsl => self.mix_hsl(other, ratio),
+        }
+    }
+
+    // Mix with each channel weighted by alpha, then divide it back out: red
+    // mixed halfway to transparent is half-transparent red, not dark red
+    pub fn mix_premultiplied(&self, other: &Color, ratio: f32) -> Color {
+        let t = ratio.clamp(0.0, 1.0) as f64;
+        let (a1, a2) = (self.a as f64 / 255.0, other.a as f64 / 255.0);
+        let alpha = a1 + (a2 - a1) * t;
+        if alpha == 0.0 {
+            return Color::rgba(0, 0, 0, 0);
+        }
+        let channel = |c1: u8, c2: u8| {
+            let premultiplied = c1 as f64 * a1 + (c2 as f64 * a2 - c1 as f64 * a1) * t;
+            (premultiplied / alpha).round().clamp(0.0, 255.0) as u8
+        };
+        Color::rgba(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+            (alpha * 255.0).round() as u8,
+        )
+    }
+
+    fn mix_alpha(&self, other: &Color, ratio: f32) -> u8 {
+        let t = ratio.clamp(0.0, 1.0) as f64;
+        (self.a as f64 + (other.a as f64 - self.a as f64) * t).round() as u8
+    }
+
+    pub fn blend_with(&self, other: &Color, mode: BlendMode) -> Color {
+        match mode {
+            BlendMode::Srgb => self.blend(other),
+            _ => self.mix_with(other, 0.5, mode),
+        }
+    }
+
+    // Gamma-correct mix: decode sRGB to linear light, interpolate, re-encode
+    pub fn mix_linear(&self, other: &Color, ratio: f32) -> Color {
+        let t = ratio.clamp(0.0, 1.0) as f64;
+        let a = self.to_linear();
+        let b = other.to_linear();
+        Color::from_linear([
+            a[0] + (b[0] - a[0]) * t,
+            a[1] + (b[1] - a[1]) * t,
+            a[2] + (b[2] - a[2]) * t,
+        ])
+        .with_alpha(self.mix_alpha(other, ratio))
+    }
+
+    // Perceptual mix in OKLCH, taking the shorter way round the hue circle
+    pub fn mix_perceptual(&self, other: &Color, ratio: f32) -> Color {
+        let t = ratio.clamp(0.0, 1.0) as f64;
+        let (l1, c1, h1) = self.to_oklch();
+        let (l2, c2, h2) = other.to_oklch();
+        // Grays have no meaningful hue; borrow the other endpoint's
+        const ACHROMATIC: f64 = 1e-4;
+        let h1 = if c1 < ACHROMATIC { h2 } else { h1 };
+        let h2 = if c2 < ACHROMATIC { h1 } else { h2 };
+        let mut dh = h2 - h1;
+        if dh > 180.0 {
+            dh -= 360.0;
+        } else if dh < -180.0 {
+            dh += 360.0;
+        }
+        Color::from_oklch(l1 + (l2 - l1) * t, c1 + (c2 - c1) * t, h1 + dh * t).with_alpha(self.mix_alpha(other, ratio))
+    }
+
+    // Mix in HSL, the shorter way round the hue circle. Midpoints stay fully
+    // saturated where sRGB would gray out, red to blue passing through magenta.
+    pub fn mix_hsl(&self, other: &Color, ratio: f32) -> Color {
+        let t = ratio.clamp(0.0, 1.0) as f64;
+        let (h1, s1, l1) = self.to_hsl();
+        let (h2, s2, l2) = other.to_hsl();
+        // As in mix_perceptual, a gray end takes the other end's hue
+        let h1 = if s1 == 0.0 { h2 } else { h1 };
+        let h2 = if s2 == 0.0 { h1 } else { h2 };
+        let dh = (h2 - h1 + 180.0).rem_euclid(360.0) - 180.0;
+        Color::from_hsl(h1 + dh * t, s1 + (s2 - s1) * t, l1 + (l2 - l1) * t).with_alpha(self.mix_alpha(other, ratio))
+    }
+
+    pub fn to_linear(&self) -> [f64; 3] {
+        let decode = |v: u8| {
+            let c = v as f64 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        [decode(self.r), decode(self.g), decode(self.b)]
+    }
+
+    pub fn from_linear(rgb: [f64; 3]) -> Color {
+        let encode = |c: f64| {
+            let c = c.clamp(0.0, 1.0);
+            let v = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+            (v * 255.0).round() as u8
+        };
+        Color::new(encode(rgb[0]), encode(rgb[1]), encode(rgb[2]))
+    }
+
+    // OKLCH: lightness [0, 1], chroma, hue in degrees
+    pub fn to_oklch(&self) -> (f64, f64, f64) {
+        let [r, g, b] = self.to_linear();
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        let ok_l = 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s;
+        let ok_a = 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s;
+        let ok_b = 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s;
+        let chroma = (ok_a * ok_a + ok_b * ok_b).sqrt();
+        let hue = ok_b.atan2(ok_a).to_degrees().rem_euclid(360.0);
+        (ok_l, chroma, hue)
+    }
+
+    pub fn from_oklch(lightness: f64, chroma: f64, hue: f64) -> Color {
+        let (sin, cos) = hue.to_radians().sin_cos();
+        let (ok_a, ok_b) = (chroma * cos, chroma * sin);
+        let l = (lightness + 0.3963377774 * ok_a + 0.2158037573 * ok_b).powi(3);
+        let m = (lightness - 0.1055613458 * ok_a - 0.0638541728 * ok_b).powi(3);
+        let s = (lightness - 0.0894841775 * ok_a - 1.2914855480 * ok_b).powi(3);
+        Color::from_linear([
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        ])
+    }
+
+    // Adding light: each color counts as much as it is opaque, and the sum is
+    // as opaque as the more opaque of the two
     pub fn add(&self, other: &Color) -> Color {
+        let weight = |c: u8, a: u8| (c as u16 * a as u16 / 255) as u8;
         Color {
-            r: self.r.saturating_add(other.r),
-            g: self.g.saturating_add(other.g),
-            b: self.b.saturating_add(other.b),
+            r: weight(self.r, self.a).saturating_add(weight(other.r, other.a)),
+            g: weight(self.g, self.a).saturating_add(weight(other.g, other.a)),
+            b: weight(self.b, self.a).saturating_add(weight(other.b, other.a)),
+            a: self.a.max(other.a),
         }
+        .unpremultiply()
     }
 
+    // Taking away light, scaled by how opaque the other color is; keeps self's alpha
     pub fn sub(&self, other: &Color) -> Color {
+        let weight = |c: u8| (c as u16 * other.a as u16 / 255) as u8;
         Color {
-            r: self.r.saturating_sub(other.r),
-            g: self.g.saturating_sub(other.g),
-            b: self.b.saturating_sub(other.b),
+            r: self.r.saturating_sub(weight(other.r)),
+            g: self.g.saturating_sub(weight(other.g)),
+            b: self.b.saturating_sub(weight(other.b)),
+            a: self.a,
         }
     }
 
+    // Brightens or darkens; transparency is unchanged
     pub fn scale(&self, factor: f32) -> Color {
         Color {
             r: (self.r as f32 * factor).clamp(0.0, 255.0) as u8,
             g: (self.g as f32 * factor).clamp(0.0, 255.0) as u8,
             b: (self.b as f32 * factor).clamp(0.0, 255.0) as u8,
+            a: self.a,
         }
     }
+
+    // Channels that were weighted by alpha, divided back out
+    fn unpremultiply(&self) -> Color {
+        if self.a == 0 || self.a == 255 {
+            return *self;
+        }
+        let channel = |c: u8| (c as u16 * 255 / self.a as u16).min(255) as u8;
+        Color { r: channel(self.r), g: channel(self.g), b: channel(self.b), a: self.a }
+    }
+
+    // #rrggbb, or #rrggbbaa when not opaque
+    pub fn to_hex(&self) -> String {
+        let hex = format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b);
+        if self.a == 255 { hex } else { format!("{}{:02x}", hex, self.a) }
+    }
+
+    // Nearest index in the xterm 256-color palette (cube or grayscale ramp)
+    pub fn to_ansi256(&self) -> u8 {
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        let cube_index = |v: u8| -> usize {
+            LEVELS
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, level)| (**level as i32 - v as i32).abs())
+                .map(|(i, _)| i)
+                .unwrap()
+        };
+        let (ri, gi, bi) = (cube_index(self.r), cube_index(self.g), cube_index(self.b));
+        let cube = Color::new(LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+        let avg = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+        let gray_index = ((avg.saturating_sub(8) + 5) / 10).min(23);
+        let gray_level = (8 + gray_index * 10) as u8;
+        let gray = Color::new(gray_level, gray_level, gray_level);
+
+        if self.distance_sq(&gray) < self.distance_sq(&cube) {
+            232 + gray_index as u8
+        } else {
+            16 + (36 * ri + 6 * gi + bi) as u8
+        }
+    }
+
+    // Closest entry in NAMED_COLORS by squared RGB distance
+    pub fn nearest_named(&self) -> &'static str {
+        NAMED_COLORS
+            .iter()
+            .min_by_key(|(_, c)| self.distance_sq(c))
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    pub fn named(name: &str) -> Option<Color> {
+        let name = name.to_lowercase();
+        NAMED_COLORS.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+    }
+
+    // WCAG relative luminance
+    pub fn luminance(&self) -> f64 {
+        let channel = |v: u8| {
+            let c = v as f64 / 255.0;
+            if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    // WCAG contrast ratio, from 1.0 (identical) to 21.0 (black on white)
+    pub fn contrast(&self, other: &Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        let (light, dark) = if a > b { (a, b) } else { (b, a) };
+        (light + 0.05) / (dark + 0.05)
+    }
+
+    // Hue in degrees [0, 360), saturation and lightness in [0, 1]
+    pub fn to_hsl(&self) -> (f64, f64, f64) {
+        let r = self.r as f64 / 255.0;
+        let g = self.g as f64 / 255.0;
+        let b = self.b as f64 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * (((g - b) / d).rem_euclid(6.0))
+        } else if max == g {
+            60.0 * ((b - r) / d + 2.0)
+        } else {
+            60.0 * ((r - g) / d + 4.0)
+        };
+        (h, s, l)
+    }
+
+    pub fn from_hsl(h: f64, s: f64, l: f64) -> Color {
+        let h = h.rem_euclid(360.0);
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = l - c / 2.0;
+        let (r, g, b) = match (h / 60.0) as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let to_u8 = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Color::new(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    pub fn rotate_hue(&self, degrees: f64) -> Color {
+        let (h, s, l) = self.to_hsl();
+        Color::from_hsl(h + degrees, s, l).with_alpha(self.a)
+    }
+
+    // Palette generators
+
+    // `steps` colors from self to other inclusive
+    pub fn gradient(&self, other: &Color, steps: usize) -> Vec<Color> {
+        self.gradient_with(other, steps, BlendMode::Srgb)
+    }
+
+    pub fn gradient_with(&self, other: &Color, steps: usize, mode: BlendMode) -> Vec<Color> {
+        match steps {
+            0 => Vec::new(),
+            1 => vec![*self],
+            _ => (0..steps)
+                .map(|i| self.mix_with(other, i as f32 / (steps - 1) as f32, mode))
+                .collect(),
+        }
+    }
+
+    pub fn complementary(&self) -> Vec<Color> {
+        vec![*self, self.rotate_hue(180.0)]
+    }
+
+    pub fn triadic(&self) -> Vec<Color> {
+        vec![*self, self.rotate_hue(120.0), self.rotate_hue(240.0)]
+    }
+
+    // `n` neighbours 30 degrees apart, centred on self
+    pub fn analogous(&self, n: usize) -> Vec<Color> {
+        let center = (n as f64 - 1.0) / 2.0;
+        (0..n)
+            .map(|i| self.rotate_hue((i as f64 - center) * 30.0))
+            .collect()
+    }
+
+    fn distance_sq(&self, other: &Color) -> u32 {
+        let dr = self.r as i32 - other.r as i32;
+        let dg = self.g as i32 - other.g as i32;
+        let db = self.b as i32 - other.b as i32;
+        (dr * dr + dg * dg + db * db) as u32
+    }
 }
 
+// Named colors used by nearest_named (CSS names)
+pub const NAMED_COLORS: [(&str, Color); 24] = [
+    ("black", Color::new(0, 0, 0)),
+    ("white", Color::new(255, 255, 255)),
+    ("red", Color::new(255, 0, 0)),
+    ("lime", Color::new(0, 255, 0)),
+    ("blue", Color::new(0, 0, 255)),
+    ("yellow", Color::new(255, 255, 0)),
+    ("cyan", Color::new(0, 255, 255)),
+    ("magenta", Color::new(255, 0, 255)),
+    ("silver", Color::new(192, 192, 192)),
+    ("gray", Color::new(128, 128, 128)),
+    ("maroon", Color::new(128, 0, 0)),
+    ("olive", Color::new(128, 128, 0)),
+    ("green", Color::new(0, 128, 0)),
+    ("purple", Color::new(128, 0, 128)),
+    ("teal", Color::new(0, 128, 128)),
+    ("navy", Color::new(0, 0, 128)),
+    ("orange", Color::new(255, 165, 0)),
+    ("pink", Color::new(255, 192, 203)),
+    ("brown", Color::new(165, 42, 42)),
+    ("gold", Color::new(255, 215, 0)),
+    ("indigo", Color::new(75, 0, 130)),
+    ("violet", Color::new(238, 130, 238)),
+    ("coral", Color::new(255, 127, 80)),
+    ("turquoise", Color::new(64, 224, 208)),
+];
+
 // Continuation type
 pub enum Continuation {
-    Resume(Box<dyn Fn() -> Value + 'static>),
+    Resume(Rc<dyn Fn() -> Value + 'static>),  // Shared, so copies of a continuation run the same closure
+    Escape(u64),  // The rest of a running callcc, by capture id
+    Sequence(Rc<Continuation>, Rc<Continuation>),  // c1 ; c2 - runs c1, then c2
+    Source(Language, String),  // Code suspended with `suspend`, re-parsed by its own language on resume
     Empty,
 }
 
+impl Continuation {
+    // Run to completion, giving the last value. Escapes can only unwind from
+    // inside eval and source needs a frontend (see Runtime::run_continuation),
+    // so here both give unit.
+    pub fn run(&self) -> Value {
+        match self {
+            Continuation::Resume(f) => f(),
+            Continuation::Sequence(first, second) => {
+                first.run();
+                second.run()
+            }
+            Continuation::Escape(_) | Continuation::Source(..) | Continuation::Empty => Value::Unit,
+        }
+    }
+
+    // The language a continuation resumes in; a sequence starts in its first
+    pub fn language(&self) -> Option<Language> {
+        match self {
+            Continuation::Source(language, _) => Some(*language),
+            Continuation::Sequence(first, _) => first.language(),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Debug for Continuation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Continuation::Resume(_) => write!(f, "Continuation::Resume"),
+            Continuation::Escape(id) => write!(f, "Continuation::Escape(#{})", id),
+            Continuation::Sequence(first, second) => write!(f, "({:?} ; {:?})", first, second),
+            Continuation::Source(language, source) => write!(f, "Continuation::Source({} {:?})", language, source),
             Continuation::Empty => write!(f, "Continuation::Empty"),
         }
     }
@@ -76,21 +485,476 @@ impl fmt::Debug for Continuation {
 pub enum FutureState {
     Pending,
     Resolved(Box<Value>),
-    Rejected(String),
+    Rejected(TaskError),
+    Cancelled(TaskError),  // By cancel, a timeout or the scope that spawned it; the task never finishes
+}
+
+impl FutureState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FutureState::Pending => "pending",
+            FutureState::Resolved(_) => "resolved",
+            FutureState::Rejected(_) => "failed",
+            FutureState::Cancelled(_) => "cancelled",
+        }
+    }
+}
+
+// Why a future failed: the original error plus one frame per task it passed
+// through, innermost first. Displayed as the message followed by "  at" lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskError {
+    pub message: String,
+    pub trace: Vec<String>,
+}
+
+impl TaskError {
+    pub fn new(message: &str) -> Self {
+        TaskError { message: message.to_string(), trace: Vec::new() }
+    }
+
+    // Recover the message and frames from an error that already passed
+    // through another task, so nested failures keep their full trace
+    pub fn from_error(error: &str) -> Self {
+        let mut parts = error.split("\n  at ");
+        let message = parts.next().unwrap_or_default();
+        TaskError { message: message.to_string(), trace: parts.map(str::to_string).collect() }
+    }
+
+    pub fn with_frame(mut self, frame: String) -> Self {
+        self.trace.push(frame);
+        self
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n  at {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+// A future handle. Clones share state, so every variable holding the same
+// future observes its resolution.
+#[derive(Clone)]
+pub struct Future {
+    pub id: u64,
+    pub created: SystemTime,
+    pub source: String,  // Expression text that created the future
+    pub site: String,    // line:col of the spawn, empty for promises
+    inner: Rc<RefCell<FutureInner>>,
+}
+
+#[derive(Debug)]
+struct FutureInner {
+    state: FutureState,
+    progress: Option<f64>,  // 0.0 - 1.0 when the task reports it
+}
+
+impl Future {
+    pub fn new(id: u64, source: &str) -> Self {
+        Future {
+            id,
+            created: SystemTime::now(),
+            source: source.to_string(),
+            site: String::new(),
+            inner: Rc::new(RefCell::new(FutureInner { state: FutureState::Pending, progress: None })),
+        }
+    }
+
+    pub fn at(mut self, site: &str) -> Self {
+        self.site = site.to_string();
+        self
+    }
+
+    // Trace frame naming this future, e.g. task #2 `fetch(url)` spawned at 1:9
+    pub fn frame(&self) -> String {
+        if self.site.is_empty() {
+            format!("task #{} `{}`", self.id, self.source)
+        } else {
+            format!("task #{} `{}` spawned at {}", self.id, self.source, self.site)
+        }
+    }
+
+    pub fn state(&self) -> FutureState {
+        self.inner.borrow().state.clone()
+    }
+
+    pub fn set_state(&self, state: FutureState) {
+        let mut inner = self.inner.borrow_mut();
+        if !matches!(state, FutureState::Pending) {
+            inner.progress = Some(1.0);
+        }
+        inner.state = state;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self.inner.borrow().state, FutureState::Pending)
+    }
+
+    pub fn progress(&self) -> Option<f64> {
+        self.inner.borrow().progress
+    }
+
+    pub fn set_progress(&self, progress: f64) {
+        self.inner.borrow_mut().progress = Some(progress.clamp(0.0, 1.0));
+    }
+
+    pub fn age(&self) -> std::time::Duration {
+        self.created.elapsed().unwrap_or_default()
+    }
+}
+
+// Debug output is what the REPL echoes, so keep it short: Future(#3 pending "fetch")
+impl fmt::Debug for Future {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {} {:?}", self.id, self.inner.borrow().state.name(), self.source)
+    }
+}
+
+impl PartialEq for Future {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+// <future #3 pending 40% "fetch data" 1.2s>
+// <future #4 failed (Undefined variable: x) "x + 1" at 1:7 0.3s>
+impl fmt::Display for Future {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.inner.borrow();
+        write!(f, "<future #{} {}", self.id, inner.state.name())?;
+        if let (FutureState::Pending, Some(p)) = (&inner.state, inner.progress) {
+            write!(f, " {:.0}%", p * 100.0)?;
+        }
+        if let FutureState::Rejected(e) | FutureState::Cancelled(e) = &inner.state {
+            write!(f, " ({})", e.message)?;
+        }
+        write!(f, " \"{}\"", self.source)?;
+        if !self.site.is_empty() {
+            write!(f, " at {}", self.site)?;
+        }
+        write!(f, " {:.1}s>", self.age().as_secs_f64())
+    }
+}
+
+// A lambda: fn(a, b) body. Parameters are bound only for the duration of a call.
+// Calling a generator function (gen(a, b) body) returns a Generator instead.
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Expr,
+    pub generator: bool,
+    pub captured: Scope,  // let and const bindings in sight where it was made, by value
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // A constructor shows as the declaration it came from
+        match &self.body {
+            Expr::Record(name, None, _) => return write!(f, "struct {}({})", name, self.params.join(", ")),
+            Expr::Record(name, Some(enumeration), _) => {
+                return write!(f, "enum {} = {}({})", enumeration, name, self.params.join(", "));
+            }
+            _ => {}
+        }
+        let keyword = if self.generator { "gen" } else { "fn" };
+        write!(f, "{}({})", keyword, self.params.join(", "))
+    }
+}
+
+// Hashable form of a map key. Numbers hash by bit pattern, with -0.0 folded
+// into 0.0 so keys that compare equal also hash equal. Arrays and maps are
+// keys when everything in them is, so [1, 2] finds the entry stored under an
+// equal array; a map's entries are sorted, so order does not matter.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Num(u64),
+    Str(Rc<str>),
+    Bool(bool),
+    Unit,
+    Color(u8, u8, u8, u8),
+    Array(Vec<MapKey>),
+    Map(Vec<(MapKey, MapKey)>),
+}
+
+impl MapKey {
+    pub fn from_value(value: &Value) -> Result<MapKey, String> {
+        match value {
+            Value::Num(n) if n.is_nan() => Err("NaN cannot be a map key".to_string()),
+            Value::Num(n) => Ok(MapKey::Num((n + 0.0).to_bits())),
+            Value::Str(s) => Ok(MapKey::Str(s.clone())),
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            Value::Unit => Ok(MapKey::Unit),
+            Value::Color(c) => Ok(MapKey::Color(c.r, c.g, c.b, c.a)),
+            Value::Array(items) => Ok(MapKey::Array(items.iter().map(MapKey::from_value).collect::<Result<_, _>>()?)),
+            Value::Map(map) => {
+                let mut entries = map
+                    .iter()
+                    .map(|(k, v)| Ok((MapKey::from_value(k)?, MapKey::from_value(v)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                entries.sort();
+                Ok(MapKey::Map(entries))
+            }
+            other => Err(format!("Unhashable map key: {:?}", other)),
+        }
+    }
+}
+
+// Elements of an array, shared between copies. Cloning is a reference count
+// bump; the first change through make_mut copies the elements if another
+// value still shares them, so arrays keep their copy semantics.
+#[derive(Clone, Default, PartialEq)]
+pub struct Array(Rc<Vec<Value>>);
+
+impl Array {
+    pub fn new() -> Self {
+        Array::default()
+    }
+
+    // The elements for changing, copied first if they are shared
+    pub fn make_mut(&mut self) -> &mut Vec<Value> {
+        Rc::make_mut(&mut self.0)
+    }
+
+    // The elements, copied only if they are shared
+    pub fn into_vec(self) -> Vec<Value> {
+        Rc::unwrap_or_clone(self.0)
+    }
+}
+
+impl std::ops::Deref for Array {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Vec<Value> {
+        &self.0
+    }
+}
+
+impl From<Vec<Value>> for Array {
+    fn from(items: Vec<Value>) -> Self {
+        Array(Rc::new(items))
+    }
+}
+
+impl FromIterator<Value> for Array {
+    fn from_iter<I: IntoIterator<Item = Value>>(items: I) -> Self {
+        Array(Rc::new(items.into_iter().collect()))
+    }
+}
+
+impl IntoIterator for Array {
+    type Item = Value;
+    type IntoIter = std::vec::IntoIter<Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Array {
+    type Item = &'a Value;
+    type IntoIter = std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+// Same debug form as the vector: [Num(1.0), Num(2.0)]
+impl fmt::Debug for Array {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Insertion-ordered hash map: entries keep the order keys were first added,
+// lookups go through a hash index of positions. Like arrays, copies share
+// their entries until one of them is changed.
+//
+// The order is part of the language, not of this layout: for loops, keys,
+// values, printing and to_json all follow it, overwriting a key keeps its
+// place and removing one closes the gap. Another backend must keep it.
+#[derive(Clone, Default)]
+pub struct Map(Rc<Table>);
+
+#[derive(Clone, Default)]
+struct Table {
+    entries: Vec<(Value, Value)>,
+    index: HashMap<MapKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Map::default()
+    }
+
+    // Later duplicates overwrite the value but keep the first position
+    pub fn from_pairs(pairs: Vec<(Value, Value)>) -> Result<Map, String> {
+        let mut map = Map::new();
+        for (key, value) in pairs {
+            map.insert(key, value)?;
+        }
+        Ok(map)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        let key = MapKey::from_value(key).ok()?;
+        self.0.index.get(&key).map(|&i| &self.0.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Returns the previous value for the key, if any
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<Option<Value>, String> {
+        let hashed = MapKey::from_value(&key)?;
+        let table = Rc::make_mut(&mut self.0);
+        match table.index.get(&hashed) {
+            Some(&i) => Ok(Some(std::mem::replace(&mut table.entries[i].1, value))),
+            None => {
+                table.index.insert(hashed, table.entries.len());
+                table.entries.push((key, value));
+                Ok(None)
+            }
+        }
+    }
+
+    // Removes the entry and shifts later positions down to keep the order
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let hashed = MapKey::from_value(key).ok()?;
+        self.0.index.get(&hashed)?;
+        let table = Rc::make_mut(&mut self.0);
+        let i = table.index.remove(&hashed)?;
+        let (_, value) = table.entries.remove(i);
+        for position in table.index.values_mut() {
+            if *position > i {
+                *position -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Value, Value)> {
+        self.0.entries.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.0.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.0.entries.iter().map(|(_, v)| v)
+    }
+}
+
+// Same debug form as a list of pairs: [(Str("x"), Num(1.0))]
+impl fmt::Debug for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.0.entries.iter()).finish()
+    }
+}
+
+// Equal when both hold the same keys with equal values, in any order
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+// Half-open integer range start..end, iterated lazily; 1..=10 is stored as 1..11
+#[derive(Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Range {
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        (index < self.len()).then(|| self.start + index as i64)
+    }
+}
+
+impl fmt::Debug for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+// An instance of a declared struct: struct Point(x, y) makes Point(1, 2).
+// Fields keep the declared order and, like array items, are shared between
+// copies. Equal when the type and every field are. A variant of an enum,
+// such as Circle of enum Shape = Circle(r) | Empty, is one too, naming its enum.
+#[derive(Clone, PartialEq)]
+pub struct Struct {
+    pub name: Rc<str>,
+    pub fields: Rc<Vec<(String, Value)>>,
+    pub enumeration: Option<Rc<str>>,
+}
+
+impl Struct {
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields.iter().find(|(name, _)| name == field).map(|(_, value)| value)
+    }
+}
+
+// Point(x: Num(1.0), y: Num(2.0)), or Empty for a variant without fields
+impl fmt::Debug for Struct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.fields.is_empty() && self.enumeration.is_some() {
+            return write!(f, "{}", self.name);
+        }
+        let fields: Vec<String> = self.fields.iter().map(|(name, value)| format!("{}: {:?}", name, value)).collect();
+        write!(f, "{}({})", self.name, fields.join(", "))
+    }
 }
 
 // Value types
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Value {
     Num(f64),
-    Str(String),
+    Str(Rc<str>),
     Bool(bool),
     Unit,
+    None,  // What is missing: none, or from get and ?. when there is nothing there
     Color(Color),
-    Array(Vec<Value>),
-    Map(Vec<(Value, Value)>),
-    Future(FutureState),
+    Array(Array),
+    Map(Map),
+    Struct(Struct),
+    Range(Range),
+    Future(Future),
     Continuation(Box<Continuation>),
+    Function(Rc<Function>),
+    Mock(Mock),  // In-process agent for a .tsu interface
+    Proxy(Proxy),  // Client connection to an agent
+    Generator(Generator),  // Suspended generator body, see generator.rs
+    Metric(Metric),  // Counter, gauge or timer, see metrics.rs
+    Socket(Socket),  // TCP listener or connection, see net.rs
+    Subscription(Subscription),  // Values published on a topic, see pubsub.rs
+    Stream(Stream),  // Values read one at a time as they arrive, see stream.rs
+    Secret(Secret),  // Text shown only as <secret>, see secret.rs
+    Mqtt(Client),  // Connection to an MQTT broker, see mqtt.rs
+    Serial(Port),  // Open serial device, see serial.rs
 }
 
 // Manual PartialEq implementation (Continuation can't be compared)
@@ -101,11 +965,29 @@ impl PartialEq for Value {
             (Value::Str(a), Value::Str(b)) => a == b,
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Unit, Value::Unit) => true,
+            (Value::None, Value::None) => true,
             (Value::Color(a), Value::Color(b)) => a == b,
             (Value::Array(a), Value::Array(b)) => a == b,
             (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Struct(a), Value::Struct(b)) => a == b,
+            (Value::Range(a), Value::Range(b)) => a == b,
             (Value::Future(_), Value::Future(_)) => false, // Futures can't be compared
-            (Value::Continuation(_), Value::Continuation(_)) => false, // Continuations can't be compared
+            (Value::Continuation(a), Value::Continuation(b)) => match (a.as_ref(), b.as_ref()) {
+                (Continuation::Escape(a), Continuation::Escape(b)) => a == b,
+                (Continuation::Source(a, x), Continuation::Source(b, y)) => a == b && x == y,
+                _ => false, // Closures can't be compared
+            },
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Mock(a), Value::Mock(b)) => a == b,
+            (Value::Proxy(a), Value::Proxy(b)) => a == b,
+            (Value::Generator(a), Value::Generator(b)) => a == b,
+            (Value::Metric(a), Value::Metric(b)) => a == b,
+            (Value::Socket(a), Value::Socket(b)) => a == b,
+            (Value::Subscription(a), Value::Subscription(b)) => a == b,
+            (Value::Stream(a), Value::Stream(b)) => a == b,
+            (Value::Secret(a), Value::Secret(b)) => a == b,
+            (Value::Mqtt(a), Value::Mqtt(b)) => a == b,
+            (Value::Serial(a), Value::Serial(b)) => a == b,
             _ => false,
         }
     }
@@ -118,16 +1000,90 @@ impl Clone for Value {
             Value::Str(s) => Value::Str(s.clone()),
             Value::Bool(b) => Value::Bool(*b),
             Value::Unit => Value::Unit,
+            Value::None => Value::None,
             Value::Color(c) => Value::Color(*c),
             Value::Array(a) => Value::Array(a.clone()),
             Value::Map(m) => Value::Map(m.clone()),
+            Value::Struct(s) => Value::Struct(s.clone()),
+            Value::Range(r) => Value::Range(*r),
             Value::Future(f) => Value::Future(f.clone()),
-            Value::Continuation(_) => Value::Unit,
+            Value::Continuation(c) => match c.as_ref() {
+                Continuation::Resume(f) => Value::Continuation(Box::new(Continuation::Resume(Rc::clone(f)))),
+                Continuation::Escape(id) => Value::Continuation(Box::new(Continuation::Escape(*id))),
+                Continuation::Sequence(a, b) => {
+                    Value::Continuation(Box::new(Continuation::Sequence(Rc::clone(a), Rc::clone(b))))
+                }
+                Continuation::Source(language, source) => {
+                    Value::Continuation(Box::new(Continuation::Source(*language, source.clone())))
+                }
+                _ => Value::Unit,
+            },
+            Value::Function(f) => Value::Function(Rc::clone(f)),
+            Value::Mock(m) => Value::Mock(m.clone()),
+            Value::Proxy(p) => Value::Proxy(p.clone()),
+            Value::Generator(g) => Value::Generator(g.clone()),
+            Value::Metric(m) => Value::Metric(m.clone()),
+            Value::Socket(s) => Value::Socket(s.clone()),
+            Value::Subscription(s) => Value::Subscription(s.clone()),
+            Value::Stream(s) => Value::Stream(s.clone()),
+            Value::Secret(s) => Value::Secret(s.clone()),
+            Value::Mqtt(c) => Value::Mqtt(c.clone()),
+            Value::Serial(p) => Value::Serial(p.clone()),
+        }
+    }
+}
+
+// The readable form :output display echoes: 7, "hi", [1, 2], {"a": 1}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Num(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => write!(f, "()"),
+            Value::None => write!(f, "none"),
+            Value::Color(c) if c.a == 255 => write!(f, "color({}, {}, {})", c.r, c.g, c.b),
+            Value::Color(c) => write!(f, "color({}, {}, {}, {})", c.r, c.g, c.b, c.a),
+            Value::Range(r) => write!(f, "{:?}", r),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Map(map) => {
+                let pairs: Vec<String> = map.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Value::Struct(record) if record.fields.is_empty() && record.enumeration.is_some() => write!(f, "{}", record.name),
+            Value::Struct(record) => {
+                let fields: Vec<String> = record.fields.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+                write!(f, "{}({})", record.name, fields.join(", "))
+            }
+            Value::Future(future) => write!(f, "{}", future),
+            Value::Continuation(c) => write!(f, "{:?}", c),
+            Value::Function(function) => write!(f, "{:?}", function),
+            Value::Mock(mock) => write!(f, "{:?}", mock),
+            Value::Proxy(proxy) => write!(f, "{:?}", proxy),
+            Value::Generator(generator) => write!(f, "{:?}", generator),
+            Value::Metric(metric) => write!(f, "{:?}", metric),
+            Value::Socket(socket) => write!(f, "{:?}", socket),
+            Value::Subscription(subscription) => write!(f, "{:?}", subscription),
+            Value::Stream(stream) => write!(f, "{:?}", stream),
+            Value::Secret(secret) => write!(f, "{}", secret),
+            Value::Mqtt(client) => write!(f, "{:?}", client),
+            Value::Serial(port) => write!(f, "{:?}", port),
         }
     }
 }
 
 impl Value {
+    // What print shows: a string as its text, anything else as displayed
+    pub fn print_text(&self) -> String {
+        match self {
+            Value::Str(s) => s.to_string(),
+            other => other.to_string(),
+        }
+    }
+
     pub fn as_num(&self) -> Result<f64, String> {
         match self {
             Value::Num(n) => Ok(*n),
@@ -140,10 +1096,12 @@ impl Value {
             (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a + b)),
             (Value::Color(a), Value::Color(b)) => Ok(Value::Color(a.add(b))),
             (Value::Array(a), Value::Array(b)) => {
-                let mut result = a.clone();
-                result.extend(b.clone());
-                Ok(Value::Array(result))
+                Ok(Value::Array(a.iter().chain(b.iter()).cloned().collect()))
             }
+            // A string built around a secret is secret too
+            (Value::Secret(a), Value::Str(b)) => Ok(Value::Secret(Secret::new(&format!("{}{}", a.expose(), b)))),
+            (Value::Str(a), Value::Secret(b)) => Ok(Value::Secret(Secret::new(&format!("{}{}", a, b.expose())))),
+            (Value::Secret(a), Value::Secret(b)) => Ok(Value::Secret(Secret::new(&format!("{}{}", a.expose(), b.expose())))),
             _ => Err(format!("Cannot add {:?} and {:?}", self, other)),
         }
     }
@@ -171,9 +1129,28 @@ impl Value {
         }
     }
 
+    pub fn rem(&self, other: &Value) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Num(_a), Value::Num(b)) if *b == 0.0 => Err("Modulo by zero".to_string()),
+            (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a % b)),
+            _ => Err(format!("Cannot take modulo of {:?} and {:?}", self, other)),
+        }
+    }
+
+    pub fn pow(&self, other: &Value) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Num(a), Value::Num(b)) => Ok(Value::Num(a.powf(*b))),
+            _ => Err(format!("Cannot raise {:?} to {:?}", self, other)),
+        }
+    }
+
     pub fn blend(&self, other: &Value) -> Result<Value, String> {
+        self.blend_with(other, BlendMode::Srgb)
+    }
+
+    pub fn blend_with(&self, other: &Value, mode: BlendMode) -> Result<Value, String> {
         match (self, other) {
-            (Value::Color(a), Value::Color(b)) => Ok(Value::Color(a.blend(b))),
+            (Value::Color(a), Value::Color(b)) => Ok(Value::Color(a.blend_with(b, mode))),
             _ => Err(format!("Cannot blend {:?} and {:?}", self, other)),
         }
     }
@@ -188,23 +1165,72 @@ impl Value {
     pub fn less_than(&self, other: &Value) -> Result<Value, String> {
         match (self, other) {
             (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a < b)),
-            _ => Err(format!("Cannot compare {:?} and {:?}", self, other)),
+            _ => Ok(Value::Bool(self.compare(other)? == Ordering::Less)),
         }
     }
 
     pub fn greater_than(&self, other: &Value) -> Result<Value, String> {
         match (self, other) {
             (Value::Num(a), Value::Num(b)) => Ok(Value::Bool(a > b)),
+            _ => Ok(Value::Bool(self.compare(other)? == Ordering::Greater)),
+        }
+    }
+
+    // Order for sorting and < and >: numbers by value, strings
+    // lexicographically by character, arrays item by item with a prefix first.
+    // Numbers equal under == are equal here too, and NaN sorts above every
+    // other number. Other kinds, or different kinds, have no order.
+    pub fn compare(&self, other: &Value) -> Result<Ordering, String> {
+        match (self, other) {
+            (Value::Num(_), Value::Num(_)) if self == other => Ok(Ordering::Equal),
+            (Value::Num(a), Value::Num(b)) => Ok(a.partial_cmp(b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))),
+            (Value::Str(a), Value::Str(b)) => Ok(a.cmp(b)),
+            (Value::Array(a), Value::Array(b)) => {
+                for (x, y) in a.iter().zip(b.iter()) {
+                    match x.compare(y)? {
+                        Ordering::Equal => continue,
+                        order => return Ok(order),
+                    }
+                }
+                Ok(a.len().cmp(&b.len()))
+            }
             _ => Err(format!("Cannot compare {:?} and {:?}", self, other)),
         }
     }
 
+    // Structural equality, as PartialEq: arrays item by item, maps by their
+    // entries in any order, colors channel by channel. Handles such as
+    // functions and sockets are equal only to themselves.
     pub fn equals(&self, other: &Value) -> Result<Value, String> {
-        match (self, other) {
-            (Value::Num(a), Value::Num(b)) => Ok(Value::Bool((a - b).abs() < f64::EPSILON)),
-            (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a == b)),
-            (Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a == b)),
-            _ => Ok(Value::Bool(false)),
+        Ok(Value::Bool(self == other))
+    }
+
+    // What sort of value this is, for messages that name the kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Num(_) => "number",
+            Value::Str(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Unit => "unit",
+            Value::None => "none",
+            Value::Color(_) => "color",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Struct(_) => "struct",
+            Value::Range(_) => "range",
+            Value::Future(_) => "future",
+            Value::Continuation(_) => "continuation",
+            Value::Function(_) => "function",
+            Value::Mock(_) => "mock",
+            Value::Proxy(_) => "proxy",
+            Value::Generator(_) => "generator",
+            Value::Metric(_) => "metric",
+            Value::Socket(_) => "socket",
+            Value::Subscription(_) => "subscription",
+            Value::Stream(_) => "stream",
+            Value::Secret(_) => "secret",
+            Value::Mqtt(_) => "mqtt client",
+            Value::Serial(_) => "serial port",
         }
     }
 
@@ -212,7 +1238,7 @@ impl Value {
         match self {
             Value::Bool(b) => *b,
             Value::Num(n) => *n != 0.0,
-            Value::Unit => false,
+            Value::Unit | Value::None => false,
             _ => true,
         }
     }
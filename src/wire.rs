@@ -0,0 +1,224 @@
+// Binary wire format for values - a compact alternative to the Rho literals
+// of the line protocol (see transport.rs), for the Tau network layer.
+//
+//   encoded = version:u8 value
+//   value   = tag:u8 length:u32le payload[length]
+//
+//   tag  variant  payload
+//   0    unit     empty
+//   1    bool     0 or 1
+//   2    num      f64, little endian
+//   3    str      UTF-8 bytes
+//   4    color    r g b, then a unless opaque
+//   5    range    start:i64le end:i64le, end exclusive
+//   6    array    each item as a value
+//   7    map      key then value for each entry, in insertion order
+//   8    struct   name as a str, its enum as a str or unit, then each field's
+//                 name as a str followed by its value
+//   9    none     empty
+//
+// Every value carries its length, so a decoder checks each payload is
+// consumed exactly and never reads past the end of its parent.
+use crate::value::{Color, Map, Range, Struct, Value};
+use std::rc::Rc;
+
+pub const VERSION: u8 = 1;
+
+// How deep arrays and maps may nest before decoding gives up, so hostile
+// input cannot exhaust the stack
+const MAX_DEPTH: usize = 256;
+
+const UNIT: u8 = 0;
+const BOOL: u8 = 1;
+const NUM: u8 = 2;
+const STR: u8 = 3;
+const COLOR: u8 = 4;
+const RANGE: u8 = 5;
+const ARRAY: u8 = 6;
+const MAP: u8 = 7;
+const STRUCT: u8 = 8;
+const NONE: u8 = 9;
+
+pub fn encode(value: &Value) -> Result<Vec<u8>, String> {
+    let mut out = vec![VERSION];
+    write_value(&mut out, value)?;
+    Ok(out)
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) -> Result<(), String> {
+    let tag = match value {
+        Value::Unit => UNIT,
+        Value::None => NONE,
+        Value::Bool(_) => BOOL,
+        Value::Num(_) => NUM,
+        Value::Str(_) => STR,
+        Value::Color(_) => COLOR,
+        Value::Range(_) => RANGE,
+        Value::Array(_) => ARRAY,
+        Value::Map(_) => MAP,
+        Value::Struct(_) => STRUCT,
+        other => return Err(format!("Cannot send {:?} over a transport", other)),
+    };
+    out.push(tag);
+    let length_at = out.len();
+    out.extend_from_slice(&[0; 4]);
+    match value {
+        Value::Bool(b) => out.push(*b as u8),
+        Value::Num(n) => out.extend_from_slice(&n.to_le_bytes()),
+        Value::Str(s) => out.extend_from_slice(s.as_bytes()),
+        Value::Color(c) => {
+            out.extend_from_slice(&[c.r, c.g, c.b]);
+            if c.a != 255 {
+                out.push(c.a);
+            }
+        }
+        Value::Range(r) => {
+            out.extend_from_slice(&r.start.to_le_bytes());
+            out.extend_from_slice(&r.end.to_le_bytes());
+        }
+        Value::Array(items) => {
+            for item in items {
+                write_value(out, item)?;
+            }
+        }
+        Value::Map(map) => {
+            for (key, value) in map.iter() {
+                write_value(out, key)?;
+                write_value(out, value)?;
+            }
+        }
+        Value::Struct(record) => {
+            write_value(out, &Value::Str(record.name.clone()))?;
+            write_value(out, &record.enumeration.clone().map_or(Value::Unit, Value::Str))?;
+            for (name, value) in record.fields.iter() {
+                write_value(out, &Value::Str(name.as_str().into()))?;
+                write_value(out, value)?;
+            }
+        }
+        _ => {}
+    }
+    let length = u32::try_from(out.len() - length_at - 4).map_err(|_| "Value too large for the wire format".to_string())?;
+    out[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    Ok(())
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Value, String> {
+    let (&version, _) = bytes.split_first().ok_or("Empty wire data")?;
+    if version != VERSION {
+        return Err(format!("Unsupported wire version {} (expected {})", version, VERSION));
+    }
+    let mut reader = Reader { bytes, pos: 1, end: bytes.len() };
+    let value = reader.value(0)?;
+    if reader.pos != bytes.len() {
+        return Err(format!("Trailing bytes after wire value at byte {}", reader.pos));
+    }
+    Ok(value)
+}
+
+// Reads values from bytes[pos..end]; nested payloads narrow `end`
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.end - self.pos < n {
+            return Err(format!("Truncated wire value at byte {}", self.pos));
+        }
+        self.pos += n;
+        Ok(&self.bytes[self.pos - n..self.pos])
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        let start = self.pos;
+        let tag = self.take(1)?[0];
+        let length = u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize;
+        let payload = self.take(length)?;
+        let fixed = |size: usize| -> Result<&'a [u8], String> {
+            if payload.len() != size {
+                return Err(format!("Malformed wire value with tag {} at byte {}", tag, start));
+            }
+            Ok(payload)
+        };
+        Ok(match tag {
+            UNIT => {
+                fixed(0)?;
+                Value::Unit
+            }
+            NONE => {
+                fixed(0)?;
+                Value::None
+            }
+            BOOL => match fixed(1)?[0] {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err(format!("Malformed wire value with tag {} at byte {}", tag, start)),
+            },
+            NUM => Value::Num(f64::from_le_bytes(fixed(8)?.try_into().unwrap())),
+            STR => match std::str::from_utf8(payload) {
+                Ok(s) => Value::Str(s.into()),
+                Err(_) => return Err(format!("Invalid UTF-8 in wire string at byte {}", start)),
+            },
+            COLOR => match payload {
+                [r, g, b] => Value::Color(Color::new(*r, *g, *b)),
+                [r, g, b, a] => Value::Color(Color::rgba(*r, *g, *b, *a)),
+                _ => return Err(format!("Malformed wire value with tag {} at byte {}", tag, start)),
+            },
+            RANGE => {
+                let bytes = fixed(16)?;
+                let start = i64::from_le_bytes(bytes[..8].try_into().unwrap());
+                let end = i64::from_le_bytes(bytes[8..].try_into().unwrap());
+                Value::Range(Range { start, end })
+            }
+            ARRAY | MAP | STRUCT => {
+                if depth == MAX_DEPTH {
+                    return Err(format!("Wire value nested deeper than {} at byte {}", MAX_DEPTH, start));
+                }
+                let mut items = Vec::new();
+                let mut inner = Reader { bytes: self.bytes, pos: self.pos - length, end: self.pos };
+                while inner.pos < inner.end {
+                    items.push(inner.value(depth + 1)?);
+                }
+                match tag {
+                    ARRAY => Value::Array(items.into()),
+                    MAP => {
+                        if items.len() % 2 != 0 {
+                            return Err(format!("Map entry without a value at byte {}", start));
+                        }
+                        let mut pairs = Vec::with_capacity(items.len() / 2);
+                        let mut items = items.into_iter();
+                        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                            pairs.push((key, value));
+                        }
+                        Value::Map(Map::from_pairs(pairs)?)
+                    }
+                    _ => read_struct(items).ok_or_else(|| format!("Malformed wire struct at byte {}", start))?,
+                }
+            }
+            _ => return Err(format!("Unknown wire tag {} at byte {}", tag, start)),
+        })
+    }
+}
+
+// A struct from its payload's values: name, enum, then field names and values
+fn read_struct(items: Vec<Value>) -> Option<Value> {
+    let mut items = items.into_iter();
+    let Some(Value::Str(name)) = items.next() else {
+        return None;
+    };
+    let enumeration = match items.next()? {
+        Value::Str(enumeration) => Some(enumeration),
+        Value::Unit => None,
+        _ => return None,
+    };
+    let mut fields = Vec::new();
+    while let Some(field) = items.next() {
+        let Value::Str(field) = field else {
+            return None;
+        };
+        fields.push((field.to_string(), items.next()?));
+    }
+    Some(Value::Struct(Struct { name, fields: Rc::new(fields), enumeration }))
+}